@@ -0,0 +1,214 @@
+// Building emulated AFS archives from the original and the files replacing or added to it
+
+use crate::format::{AfsEntry, AfsHeader, AfsName, ALIGNMENT, MAGIC, NAME_ENTRY_SIZE};
+use archive_emulation_framework::{
+    EmulatorBuilder, FileSlice, FileSliceStream, MultiStream, OffsetRange, OpenRequest,
+    PaddingStream, Route, Source, StreamOffsetPair,
+};
+use std::collections::BTreeMap;
+use std::io;
+
+/// How far past the end of the original archive inputs can append files. Names starting with
+/// larger indices are more likely dates or other numbers, which would make huge archives.
+pub const MAX_APPENDED: usize = 4096;
+
+/// Builds one emulated AFS archive. See the [crate docs](crate) for how inputs are named.
+#[derive(Debug, Default)]
+pub struct AfsBuilder {
+    // Files to inject, by index.
+    custom_files: BTreeMap<u32, CustomFile>,
+}
+
+#[derive(Debug)]
+struct CustomFile {
+    path: String,
+    // Name for the name table, for slots which have none.
+    name: String,
+}
+
+impl EmulatorBuilder for AfsBuilder {
+    type Header = AfsHeader;
+
+    fn accepts(route: &Route) -> bool {
+        let name = route.as_file_name().as_bytes();
+        name.len() > 4 && name[name.len() - 4..].eq_ignore_ascii_case(b".afs")
+    }
+
+    fn parse_header(source: &dyn Source) -> io::Result<Option<AfsHeader>> {
+        AfsHeader::read(source)
+    }
+
+    /// Files not starting with an index are ignored.
+    fn add_file(&mut self, name: &str, path: &str) {
+        let digits = name.bytes().take_while(u8::is_ascii_digit).count();
+        let Ok(index) = name[..digits].parse() else {
+            return;
+        };
+
+        // `0_yahoo!.adx` is named `yahoo!.adx`; `00000.adx` keeps its name.
+        let rest = &name[digits..];
+        let name = rest.strip_prefix('_').unwrap_or(name);
+        let file = CustomFile {
+            path: path.to_owned(),
+            name: name.to_owned(),
+        };
+        self.custom_files.insert(index, file);
+    }
+
+    /// Inputs more than [`MAX_APPENDED`] past the end of the original are skipped and reported.
+    fn build(mut self, header: AfsHeader, request: &OpenRequest<'_>) -> io::Result<MultiStream> {
+        let limit = header.entries.len().saturating_add(MAX_APPENDED);
+        let limit = u32::try_from(limit).unwrap_or(u32::MAX);
+        for (index, custom) in self.custom_files.split_off(&limit) {
+            request.framework.report(format!(
+                "{}: index {index} is far past the {} files of {}, skipped",
+                custom.path,
+                header.entries.len(),
+                request.path
+            ));
+        }
+
+        let custom_count = self
+            .custom_files
+            .keys()
+            .next_back()
+            .map_or(0, |x| *x as usize + 1);
+        let count = custom_count.max(header.entries.len());
+
+        // Table of contents, and a slot for the name table pointer after it.
+        let header_length = (8 + (count as u64 + 1) * 8).next_multiple_of(ALIGNMENT);
+        let mut layout = Layout {
+            pairs: Vec::new(),
+            offset: header_length,
+        };
+
+        let mut entries = Vec::with_capacity(count);
+        let mut names = Vec::with_capacity(count);
+        for index in 0..count {
+            let original = header.entries.get(index).copied().unwrap_or_default();
+            let mut name = match &header.names {
+                Some(names) => names.get(index).copied().unwrap_or_default(),
+                None => AfsName::default(),
+            };
+
+            let entry = if let Some(custom) = self.custom_files.get(&(index as u32)) {
+                let source = request.framework.open_source(&custom.path)?;
+                if name.name_bytes().is_empty() {
+                    name = AfsName::new(&custom.name, 0);
+                }
+
+                let slice = FileSlice::new(0, source.size(), source);
+                layout.push_file(Box::new(FileSliceStream::new(slice)))?
+            } else if !original.is_empty() {
+                layout.push_original(original, index, request)?
+            } else {
+                AfsEntry::default()
+            };
+
+            name.length = entry.length;
+            entries.push(entry);
+            names.push(name);
+        }
+
+        let name_pointer = match header.names {
+            Some(_) => layout.push_names(&names)?,
+            None => AfsEntry::default(),
+        };
+
+        let mut toc = vec![0; header_length as usize];
+        toc[..4].copy_from_slice(&MAGIC);
+        toc[4..8].copy_from_slice(&(count as u32).to_le_bytes());
+        for (index, entry) in entries.iter().enumerate() {
+            entry.write(&mut toc[8 + index * 8..]);
+        }
+
+        let pointer_at = match header.name_pointer_before_data {
+            true => header_length as usize - 8,
+            false => 8 + count * 8,
+        };
+        name_pointer.write(&mut toc[pointer_at..]);
+
+        let range = OffsetRange::new(0, header_length);
+        layout
+            .pairs
+            .push(StreamOffsetPair::new(Box::new(toc), range));
+        MultiStream::new(layout.pairs)
+    }
+}
+
+/// The streams after the table of contents, as they are laid out.
+struct Layout {
+    pairs: Vec<StreamOffsetPair<Box<dyn Source>>>,
+    offset: u64,
+}
+
+impl Layout {
+    /// Adds a file, and padding up to the next file.
+    fn push_file(&mut self, stream: Box<dyn Source>) -> io::Result<AfsEntry> {
+        let entry = self.entry(stream.size())?;
+        self.push(stream);
+        self.pad();
+        Ok(entry)
+    }
+
+    /// Adds a file from the original archive, emulated if another emulator claims it.
+    fn push_original(
+        &mut self,
+        original: AfsEntry,
+        index: usize,
+        request: &OpenRequest<'_>,
+    ) -> io::Result<AfsEntry> {
+        let route = request.route.merge(&index.to_string());
+        let (offset, length) = (original.offset as u64, original.length as u64);
+        let framework = request.framework;
+        if let Ok(file) = framework.try_create_from_file_slice(request.path, offset, length, &route)
+        {
+            return self.push_file(Box::new(file));
+        }
+
+        // Take the original's padding too (when it is there), so neighbouring files merge
+        // into one slice.
+        let entry = self.entry(length)?;
+        let available = request.source.size() - offset;
+        let padded = length.next_multiple_of(ALIGNMENT).min(available);
+        let slice = FileSlice::new(offset, padded, request.source.clone());
+        self.push(Box::new(FileSliceStream::new(slice)));
+        self.pad();
+        Ok(entry)
+    }
+
+    /// Adds the name table, returning the pointer to it.
+    fn push_names(&mut self, names: &[AfsName]) -> io::Result<AfsEntry> {
+        let mut table = vec![0; names.len() * NAME_ENTRY_SIZE as usize];
+        for (name, bytes) in names
+            .iter()
+            .zip(table.chunks_exact_mut(NAME_ENTRY_SIZE as usize))
+        {
+            name.write(bytes);
+        }
+
+        self.push_file(Box::new(table))
+    }
+
+    /// The table of contents entry for a file of `length` bytes at the current offset.
+    fn entry(&self, length: u64) -> io::Result<AfsEntry> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidData, "archive exceeds 4 GiB");
+        Ok(AfsEntry {
+            offset: self.offset.try_into().map_err(|_| too_large())?,
+            length: length.try_into().map_err(|_| too_large())?,
+        })
+    }
+
+    fn push(&mut self, stream: Box<dyn Source>) {
+        let range = OffsetRange::from_start_and_length(self.offset, stream.size());
+        self.offset = range.end;
+        self.pairs.push(StreamOffsetPair::new(stream, range));
+    }
+
+    fn pad(&mut self) {
+        let padding = self.offset.next_multiple_of(ALIGNMENT) - self.offset;
+        if padding > 0 {
+            self.push(Box::new(PaddingStream::new(0, padding)));
+        }
+    }
+}
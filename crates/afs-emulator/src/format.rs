@@ -0,0 +1,181 @@
+// AFS table of contents and name table
+//
+// Layout, all little endian:
+//   magic `AFS\0`, u32 file count
+//   per file: u32 offset, u32 length (both 0 for an empty slot)
+//   u32 offset, u32 length of the name table (0 if none); some archives put this pointer
+//   just before the first file instead
+//   files, each aligned to 0x800
+//   name table: per file, 32 byte name, 6 x u16 timestamp, u32 length
+
+use archive_emulation_framework::Source;
+use std::io;
+
+pub const MAGIC: [u8; 4] = *b"AFS\0";
+
+/// Alignment of files (and the name table) within an archive.
+pub const ALIGNMENT: u64 = 0x800;
+
+/// Size of a name table entry.
+pub const NAME_ENTRY_SIZE: u64 = 0x30;
+
+const NAME_LENGTH: usize = 32;
+
+/// Where a file is within an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AfsEntry {
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl AfsEntry {
+    /// Empty slots have no data, and are skipped by games.
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0 && self.length == 0
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        Self {
+            offset: u32_at(bytes, 0),
+            length: u32_at(bytes, 4),
+        }
+    }
+
+    pub(crate) fn write(&self, bytes: &mut [u8]) {
+        bytes[0..4].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.length.to_le_bytes());
+    }
+}
+
+/// A file's entry in the name table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AfsName {
+    /// The name, padded with zeroes.
+    pub name: [u8; NAME_LENGTH],
+    /// Year, month, day, hour, minute, second.
+    pub timestamp: [u16; 6],
+    /// Length of the file; usually the same as in the table of contents.
+    pub length: u32,
+}
+
+impl AfsName {
+    /// Creates an entry without a timestamp. Names are cut off at 32 bytes.
+    pub fn new(name: &str, length: u32) -> Self {
+        let mut bytes = [0; NAME_LENGTH];
+        let count = name.len().min(NAME_LENGTH);
+        bytes[..count].copy_from_slice(&name.as_bytes()[..count]);
+        Self {
+            name: bytes,
+            timestamp: [0; 6],
+            length,
+        }
+    }
+
+    /// The name, without padding.
+    pub fn name_bytes(&self) -> &[u8] {
+        let length = self.name.iter().position(|x| *x == 0);
+        &self.name[..length.unwrap_or(NAME_LENGTH)]
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        let mut timestamp = [0; 6];
+        for (index, value) in timestamp.iter_mut().enumerate() {
+            let at = NAME_LENGTH + index * 2;
+            *value = u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        }
+
+        Self {
+            name: bytes[..NAME_LENGTH].try_into().unwrap(),
+            timestamp,
+            length: u32_at(bytes, NAME_LENGTH + 12),
+        }
+    }
+
+    pub(crate) fn write(&self, bytes: &mut [u8]) {
+        bytes[..NAME_LENGTH].copy_from_slice(&self.name);
+        for (index, value) in self.timestamp.iter().enumerate() {
+            let at = NAME_LENGTH + index * 2;
+            bytes[at..at + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        bytes[NAME_LENGTH + 12..NAME_LENGTH + 16].copy_from_slice(&self.length.to_le_bytes());
+    }
+}
+
+/// Everything read from an archive besides the files themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AfsHeader {
+    pub entries: Vec<AfsEntry>,
+    /// One per entry, if the archive has a name table.
+    pub names: Option<Vec<AfsName>>,
+    /// Whether the name table pointer sits just before the first file, rather than right
+    /// after the table of contents.
+    pub name_pointer_before_data: bool,
+}
+
+impl AfsHeader {
+    /// Reads the header of an archive. Returns `Ok(None)` if `source` isn't one, or its table
+    /// of contents points outside of it.
+    pub fn read(source: &dyn Source) -> io::Result<Option<Self>> {
+        let size = source.size();
+        let mut start = [0; 8];
+        if size < 8 {
+            return Ok(None);
+        }
+
+        source.read_exact_at(0, &mut start)?;
+        if start[..4] != MAGIC {
+            return Ok(None);
+        }
+
+        // Entries plus the name table pointer after them.
+        let count = u32_at(&start, 4) as u64;
+        let toc_length = (count + 1) * 8;
+        if 8 + toc_length > size {
+            return Ok(None);
+        }
+
+        let mut toc = vec![0; toc_length as usize];
+        source.read_exact_at(8, &mut toc)?;
+        let entries: Vec<AfsEntry> = toc.chunks_exact(8).map(AfsEntry::read).collect();
+        let (pointer, entries) = entries.split_last().unwrap();
+        let in_bounds = |x: &AfsEntry| x.offset as u64 + x.length as u64 <= size;
+        if !entries.iter().all(in_bounds) {
+            return Ok(None);
+        }
+
+        let mut header = Self {
+            entries: entries.to_vec(),
+            names: None,
+            name_pointer_before_data: false,
+        };
+
+        let mut pointer = *pointer;
+        let first_offset = header.entries.iter().find(|x| !x.is_empty());
+        let first_offset = first_offset.map(|x| x.offset as u64);
+        if let (true, Some(first_offset)) = (pointer.is_empty(), first_offset) {
+            if first_offset >= 8 + toc_length + 8 {
+                let mut bytes = [0; 8];
+                source.read_exact_at(first_offset - 8, &mut bytes)?;
+                pointer = AfsEntry::read(&bytes);
+                header.name_pointer_before_data = !pointer.is_empty();
+            }
+        }
+
+        let table_length = count * NAME_ENTRY_SIZE;
+        if !pointer.is_empty() && in_bounds(&pointer) && pointer.length as u64 >= table_length {
+            let mut table = vec![0; table_length as usize];
+            source.read_exact_at(pointer.offset as u64, &mut table)?;
+            let names = table.chunks_exact(NAME_ENTRY_SIZE as usize);
+            header.names = Some(names.map(AfsName::read).collect());
+        } else {
+            header.name_pointer_before_data = false;
+        }
+
+        Ok(Some(header))
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
@@ -0,0 +1,34 @@
+//! AFS archive emulator: the reference Layer 3 emulator.
+//!
+//! AFS is CRI Middleware's archive format: a table of contents giving the offset and length of
+//! each file, followed by the files, each aligned to [`ALIGNMENT`]. An optional table after the
+//! files holds their names and timestamps.
+//!
+//! Inputs go in `FileEmulationFramework/AFS/<archive>/`, named by the index of the file they
+//! replace, such as `0_yahoo!.adx` or `00007.adx`. Indices past the end of the archive append
+//! files, with empty entries filling any gap; inputs more than [`MAX_APPENDED`] past the end
+//! are skipped, and reported through the framework. Files which aren't replaced are kept as
+//! slices of the original, and are offered to the other emulators with the route
+//! `<archive>/<index>`.
+//!
+//! ```ignore
+//! let emulator = Arc::new(AfsEmulator::new());
+//! framework.register(Box::new(emulator.clone()));
+//! emulator.add_from_folders(&format!("{mod_folder}/FileEmulationFramework/{INPUT_FOLDER}"))?;
+//! ```
+
+mod builder;
+mod format;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+pub use builder::{AfsBuilder, MAX_APPENDED};
+pub use format::{AfsEntry, AfsHeader, AfsName, ALIGNMENT, MAGIC, NAME_ENTRY_SIZE};
+
+use archive_emulation_framework::BuilderFactory;
+
+/// The AFS emulator, to register with the framework.
+pub type AfsEmulator = BuilderFactory<AfsBuilder>;
+
+/// Folder for this emulator's inputs, in a mod's `FileEmulationFramework` folder.
+pub const INPUT_FOLDER: &str = "AFS";
@@ -0,0 +1,19 @@
+// Synthetic AFS archives for tests
+
+use crate::format::ALIGNMENT;
+
+/// Writes an AFS archive without a name table, holding `files` in order.
+pub fn write_archive(files: &[&[u8]]) -> Vec<u8> {
+    let alignment = ALIGNMENT as usize;
+    let mut data = vec![0; (8 + files.len() * 8).next_multiple_of(alignment)];
+    data[..4].copy_from_slice(b"AFS\0");
+    data[4..8].copy_from_slice(&(files.len() as u32).to_le_bytes());
+    for (index, content) in files.iter().enumerate() {
+        let (entry, offset) = (8 + index * 8, data.len() as u32);
+        data[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+        data[entry + 4..entry + 8].copy_from_slice(&(content.len() as u32).to_le_bytes());
+        data.extend(*content);
+        data.resize(data.len().next_multiple_of(alignment), 0);
+    }
+    data
+}
@@ -0,0 +1,261 @@
+// Emulated AFS archives built from synthetic originals and inputs, read back with the header
+// parser and checked entry by entry.
+
+use afs_emulator::{
+    AfsBuilder, AfsEntry, AfsHeader, AfsName, ALIGNMENT, INPUT_FOLDER, MAX_APPENDED,
+};
+use archive_emulation_framework::test_support::Setup;
+use archive_emulation_framework::{
+    EmulatorBuilder, IEmulatedFile, IEmulator, OpenRequest, Route, Source,
+};
+use std::io;
+
+const ARCHIVE: &str = "/game/Sound/SH_VOICE_E.afs";
+
+struct Original<'a> {
+    files: &'a [(&'a str, &'a [u8])],
+    names: bool,
+    name_pointer_before_data: bool,
+}
+
+impl Original<'_> {
+    fn new<'a>(files: &'a [(&'a str, &'a [u8])]) -> Original<'a> {
+        Original {
+            files,
+            names: true,
+            name_pointer_before_data: false,
+        }
+    }
+
+    /// Writes the archive as the usual packing tools do.
+    fn write(&self) -> Vec<u8> {
+        let count = self.files.len();
+        let header_length = (8 + (count + 1) * 8).next_multiple_of(ALIGNMENT as usize);
+        let mut data = vec![0; header_length];
+        data[..4].copy_from_slice(b"AFS\0");
+        data[4..8].copy_from_slice(&(count as u32).to_le_bytes());
+
+        let mut names = Vec::new();
+        for (index, (name, content)) in self.files.iter().enumerate() {
+            let mut name = AfsName::new(name, content.len() as u32);
+            name.timestamp = [2004, 1, 2, 3, 4, index as u16];
+            names.resize(names.len() + 0x30, 0);
+            let at = names.len() - 0x30;
+            write_name(&name, &mut names[at..]);
+
+            let entry = entry(data.len(), content.len());
+            write_entry(&entry, &mut data[8 + index * 8..]);
+            data.extend(*content);
+            data.resize(data.len().next_multiple_of(ALIGNMENT as usize), 0);
+        }
+
+        if self.names {
+            let pointer = entry(data.len(), names.len());
+            let at = match self.name_pointer_before_data {
+                true => header_length - 8,
+                false => 8 + count * 8,
+            };
+            write_entry(&pointer, &mut data[at..]);
+            data.extend(names);
+            data.resize(data.len().next_multiple_of(ALIGNMENT as usize), 0);
+        }
+
+        data
+    }
+}
+
+fn entry(offset: usize, length: usize) -> AfsEntry {
+    AfsEntry {
+        offset: offset as u32,
+        length: length as u32,
+    }
+}
+
+fn write_entry(entry: &AfsEntry, bytes: &mut [u8]) {
+    bytes[..4].copy_from_slice(&entry.offset.to_le_bytes());
+    bytes[4..8].copy_from_slice(&entry.length.to_le_bytes());
+}
+
+fn write_name(name: &AfsName, bytes: &mut [u8]) {
+    bytes[..32].copy_from_slice(&name.name);
+    for (index, value) in name.timestamp.iter().enumerate() {
+        bytes[32 + index * 2..34 + index * 2].copy_from_slice(&value.to_le_bytes());
+    }
+    bytes[44..48].copy_from_slice(&name.length.to_le_bytes());
+}
+
+/// The emulator, with `original` as the archive.
+fn with_original(original: Vec<u8>) -> Setup<AfsBuilder> {
+    Setup::new(INPUT_FOLDER, ARCHIVE, original)
+}
+
+/// The archive's files, and its header.
+fn unpack(archive: &[u8]) -> (Vec<Vec<u8>>, AfsHeader) {
+    let header = AfsHeader::read(&archive.to_vec()).unwrap().unwrap();
+    let files = header.entries.iter().map(|entry| {
+        assert_eq!(entry.offset as u64 % ALIGNMENT, 0);
+        let start = entry.offset as usize;
+        archive[start..start + entry.length as usize].to_vec()
+    });
+    (files.collect(), header)
+}
+
+fn names(header: &AfsHeader) -> Vec<String> {
+    let names = header.names.as_ref().unwrap().iter();
+    names
+        .map(|x| String::from_utf8(x.name_bytes().to_vec()).unwrap())
+        .collect()
+}
+
+const FILES: &[(&str, &[u8])] = &[
+    ("yahoo!.adx", b"yahoo"),
+    ("kick.adx", &[1; 0x900]),
+    ("jump.adx", b"jump"),
+    ("land.adx", &[2; 0x800]),
+];
+
+#[test]
+fn replacing_a_file_with_itself_round_trips() {
+    for (names, name_pointer_before_data) in [(true, false), (true, true), (false, false)] {
+        let original = Original {
+            files: FILES,
+            names,
+            name_pointer_before_data,
+        };
+        let original = original.write();
+        let setup = with_original(original.clone());
+        setup.add_inputs("a", &[("2_jump.adx", b"jump")]);
+        assert_eq!(
+            setup.emulate(),
+            original,
+            "{names} {name_pointer_before_data}"
+        );
+    }
+}
+
+#[test]
+fn inputs_replace_files_by_index() {
+    let setup = with_original(Original::new(FILES).write());
+    setup.add_inputs("a", &[("1_kick.adx", b"first mod"), ("00003.adx", b"land")]);
+    setup.add_inputs("b", &[("1.adx", &[3; 0x1234])]);
+
+    let (files, header) = unpack(&setup.emulate());
+    let expected: [&[u8]; 4] = [b"yahoo", &[3; 0x1234], b"jump", b"land"];
+    assert_eq!(files, expected);
+
+    // Names and timestamps are kept, with lengths updated.
+    assert_eq!(
+        names(&header),
+        ["yahoo!.adx", "kick.adx", "jump.adx", "land.adx"]
+    );
+    let table = header.names.unwrap();
+    assert_eq!(table[1].timestamp, [2004, 1, 2, 3, 4, 1]);
+    assert_eq!(table[1].length, 0x1234);
+    assert_eq!(table[3].length, 4);
+}
+
+#[test]
+fn inputs_past_the_end_are_appended() {
+    let setup = with_original(Original::new(FILES).write());
+    setup.add_inputs("a", &[("6_new.adx", b"appended"), ("x.adx", b"ignored")]);
+
+    let (files, header) = unpack(&setup.emulate());
+    assert_eq!(files.len(), 7);
+    assert_eq!(files[3], [2; 0x800]);
+    assert_eq!(files[6], b"appended");
+
+    // The slots in between are empty.
+    assert_eq!(header.entries[4], AfsEntry::default());
+    assert_eq!(header.entries[5], AfsEntry::default());
+    assert_eq!(names(&header)[4..], ["", "", "new.adx"]);
+}
+
+#[test]
+fn inputs_far_past_the_end_are_skipped_and_reported() {
+    let setup = with_original(Original::new(FILES).write());
+    let last = format!("{}_last.adx", FILES.len() - 1 + MAX_APPENDED);
+    let inputs = [("20240101_notes.txt", &b"notes"[..]), (&last, b"last")];
+    setup.add_inputs("a", &inputs);
+
+    let (files, _) = unpack(&setup.emulate());
+    assert_eq!(files.len(), FILES.len() + MAX_APPENDED);
+    assert_eq!(files.last().unwrap(), b"last");
+    assert_eq!(
+        setup.framework.take_reports(),
+        [format!(
+            "/mods/a/FileEmulationFramework/AFS/SH_VOICE_E.afs/20240101_notes.txt: index \
+             20240101 is far past the {} files of {ARCHIVE}, skipped",
+            FILES.len()
+        )]
+    );
+    assert!(setup.framework.take_reports().is_empty());
+}
+
+#[test]
+fn unchanged_files_are_one_slice_of_the_original() {
+    let setup = with_original(Original::new(FILES).write());
+    setup.add_inputs("a", &[("0_yahoo!.adx", b"replaced")]);
+
+    // Header, file 0, its padding, then files 1-3 in one slice, then the name table.
+    let stream = setup.build();
+    let slices: Vec<_> = stream
+        .streams()
+        .iter()
+        .filter_map(|x| x.stream.file_slice())
+        .map(|x| (x.offset(), x.length()))
+        .collect();
+    assert_eq!(slices, [(0, 8), (0x1000, 0x800 * 4)]);
+}
+
+#[test]
+fn other_files_are_left_alone() {
+    let setup = with_original(b"AFS\0 but not really".to_vec());
+    setup.add_inputs("a", &[("0.adx", b"data")]);
+    assert!(setup.framework.emulate(ARCHIVE).is_none());
+
+    // No inputs for this archive.
+    let setup = with_original(Original::new(FILES).write());
+    assert!(setup.framework.emulate(ARCHIVE).is_none());
+
+    assert!(AfsBuilder::accepts(&Route::new("/game/BGM.AFS")));
+    assert!(!AfsBuilder::accepts(&Route::new("/game/BGM.AFS.bak")));
+}
+
+/// Claims file 1 of the archive, doubling each byte.
+struct Doubler;
+
+impl IEmulator for Doubler {
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        if !request.route.as_str().ends_with("SH_VOICE_E.afs/1") {
+            return None;
+        }
+
+        let mut data = vec![0; request.source.size() as usize];
+        request.source.read_exact_at(0, &mut data).unwrap();
+        let doubled: Vec<u8> = data.iter().flat_map(|x| [*x, *x]).collect();
+        Some(Box::new(Memory(doubled)))
+    }
+}
+
+struct Memory(Vec<u8>);
+
+impl IEmulatedFile for Memory {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+#[test]
+fn files_inside_are_offered_to_other_emulators() {
+    let setup = with_original(Original::new(FILES).write());
+    setup.framework.register(Box::new(Doubler));
+    setup.add_inputs("a", &[("0.adx", b"zero")]);
+
+    let (files, _) = unpack(&setup.emulate());
+    assert_eq!(files[1], [1; 0x1200]);
+    assert!(setup.framework.is_emulated("/game/Sound/SH_VOICE_E.afs/1"));
+}
@@ -0,0 +1,93 @@
+// Reading an emulated file from temp files on disk, with and without a block cache.
+
+use archive_emulation_framework::{
+    BlockCache, DiskSources, FileSlice, FileSliceStream, FileSources, MultiStream, OffsetRange,
+    Source, StreamOffsetPair,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use std::io::Write;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+/// Files the emulated file is built from.
+const FILES: usize = 16;
+/// Size of each file.
+const FILE_SIZE: u64 = 1024 * 1024;
+/// Bytes at the start of each file read again and again, like a header.
+const HEADER_SIZE: u64 = 0x800;
+const READS: usize = 4096;
+const READ_SIZE: usize = 0x200;
+
+fn temp_files() -> Vec<NamedTempFile> {
+    let files = (0..FILES).map(|x| {
+        let mut file = NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..FILE_SIZE).map(|y| (x as u64 + y) as u8).collect();
+        file.write_all(&data).unwrap();
+        file
+    });
+    files.collect()
+}
+
+/// An emulated file with each temp file in full, one after another.
+fn emulated(files: &[NamedTempFile], cache: Option<&Arc<BlockCache>>) -> MultiStream {
+    let pairs = files.iter().enumerate().map(|(index, file)| {
+        let source = DiskSources.open(file.path().to_str().unwrap()).unwrap();
+        let stream: Box<dyn Source> =
+            Box::new(FileSliceStream::new(FileSlice::new(0, FILE_SIZE, source)));
+        let range = OffsetRange::from_start_and_length(index as u64 * FILE_SIZE, FILE_SIZE);
+        StreamOffsetPair::new(stream, range)
+    });
+
+    let pairs = pairs.collect();
+    match cache {
+        Some(cache) => MultiStream::with_cache(pairs, cache).unwrap(),
+        None => MultiStream::new(pairs).unwrap(),
+    }
+}
+
+/// `READS` offsets in no particular order, within the first `within` bytes of each file.
+fn offsets(within: u64) -> Vec<u64> {
+    let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+    (0..READS)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let file = state % FILES as u64;
+            file * FILE_SIZE + (state >> 32) % (within - READ_SIZE as u64)
+        })
+        .collect()
+}
+
+fn random_reads(c: &mut Criterion) {
+    let files = temp_files();
+    let mut group = c.benchmark_group("block_cache");
+    group.throughput(Throughput::Bytes((READS * READ_SIZE) as u64));
+
+    let patterns = [("headers", HEADER_SIZE), ("whole_files", FILE_SIZE)];
+    for (pattern, within) in patterns {
+        let offsets = offsets(within);
+        // A budget of a quarter of the data, plenty for the headers.
+        let cache = BlockCache::with_block_size(FILES as u64 * FILE_SIZE / 4, 0x1000);
+        let setups = [("uncached", None), ("cached", Some(&cache))];
+        for (name, cache) in setups {
+            let file = emulated(&files, cache);
+            let id = BenchmarkId::new(name, pattern);
+            group.bench_with_input(id, &offsets, |b, offsets| {
+                let mut buffer = [0; READ_SIZE];
+                b.iter(|| {
+                    for &offset in offsets {
+                        black_box(file.read_at(black_box(offset), &mut buffer).unwrap());
+                    }
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, random_reads);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+// Listing a large input tree: DirectorySearcher vs std::fs::read_dir and walkdir.
+
+use archive_emulation_framework::DirectorySearcher;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Top level folders, like emulator input folders.
+const FOLDERS: usize = 20;
+/// Archive folders inside each top level folder.
+const ARCHIVES: usize = 50;
+/// Files inside each archive folder.
+const FILES: usize = 200;
+/// 200,000 files in all.
+const FILE_COUNT: usize = FOLDERS * ARCHIVES * FILES;
+
+fn tree() -> TempDir {
+    let root = tempfile::tempdir().unwrap();
+    for folder in 0..FOLDERS {
+        for archive in 0..ARCHIVES {
+            let directory = root
+                .path()
+                .join(format!("FOLDER{folder}/archive_{archive}.afs"));
+            fs::create_dir_all(&directory).unwrap();
+            for file in 0..FILES {
+                fs::File::create(directory.join(format!("{file:05}.adx"))).unwrap();
+            }
+        }
+    }
+    root
+}
+
+/// The straightforward recursive search, with the standard library.
+fn read_dir(directory: &Path, files: &mut Vec<String>) {
+    for entry in fs::read_dir(directory).unwrap() {
+        let entry = entry.unwrap();
+        match entry.file_type().unwrap().is_dir() {
+            true => read_dir(&entry.path(), files),
+            false => files.push(entry.file_name().into_string().unwrap()),
+        }
+    }
+}
+
+fn search(c: &mut Criterion) {
+    let root = tree();
+    let folder = root.path().to_str().unwrap();
+    let mut group = c.benchmark_group("directory_searcher");
+    group.throughput(Throughput::Elements(FILE_COUNT as u64));
+    group.sample_size(10);
+
+    group.bench_function("std_read_dir", |b| {
+        b.iter(|| {
+            let mut files = Vec::new();
+            read_dir(root.path(), &mut files);
+            assert_eq!(black_box(files).len(), FILE_COUNT);
+        })
+    });
+
+    group.bench_function("walkdir", |b| {
+        b.iter(|| {
+            let files = walkdir::WalkDir::new(root.path())
+                .into_iter()
+                .map(Result::unwrap)
+                .filter(|x| !x.file_type().is_dir());
+            assert_eq!(black_box(files.count()), FILE_COUNT);
+        })
+    });
+
+    group.bench_function("grouped", |b| {
+        b.iter(|| {
+            let groups = DirectorySearcher::get_directory_contents_recursive_grouped(folder);
+            let files: usize = groups.unwrap().iter().map(|x| x.files.len()).sum();
+            assert_eq!(black_box(files), FILE_COUNT);
+        })
+    });
+
+    group.bench_function("grouped_parallel", |b| {
+        b.iter(|| {
+            let groups =
+                DirectorySearcher::get_directory_contents_recursive_grouped_parallel(folder, 0);
+            let files: usize = groups.unwrap().iter().map(|x| x.files.len()).sum();
+            assert_eq!(black_box(files), FILE_COUNT);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+// Finding the range an offset is in: binary search vs the Eytzinger layout.
+
+use archive_emulation_framework::{OffsetRange, OffsetRangeSelector, SearchLayout};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+
+const LOOKUPS: usize = 4096;
+
+/// `count` files aligned to 0x800, like an archive.
+fn aligned_ranges(count: u64) -> Vec<OffsetRange> {
+    (0..count)
+        .map(|x| OffsetRange::from_start_and_length(x * 0x800, 0x800 - (x % 0x100)))
+        .collect()
+}
+
+/// Offsets spread across the whole layout, in no particular order.
+fn offsets(end: u64) -> Vec<u64> {
+    let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+    (0..LOOKUPS)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % end
+        })
+        .collect()
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("offset_range_lookup");
+    group.throughput(Throughput::Elements(LOOKUPS as u64));
+
+    for count in [16, 256, 4096, 16384, 65536, 1 << 20] {
+        let ranges = aligned_ranges(count);
+        let offsets = offsets(ranges.last().unwrap().end);
+        for layout in [SearchLayout::Sorted, SearchLayout::Eytzinger] {
+            let selector = OffsetRangeSelector::with_gaps(&ranges).with_layout(layout);
+            let id = BenchmarkId::new(format!("{layout:?}"), count);
+            group.bench_with_input(id, &offsets, |b, offsets| {
+                b.iter(|| {
+                    for &offset in offsets {
+                        black_box(selector.lookup(black_box(offset)));
+                    }
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
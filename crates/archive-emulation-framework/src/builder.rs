@@ -0,0 +1,208 @@
+// Emulator inputs collected from mod folders, and building emulated files from them
+
+use crate::directory_searcher::{DirectorySearcher, FileGroup};
+use crate::emulator::{IEmulatedFile, IEmulator, OpenRequest};
+use crate::route::Route;
+use crate::source::Source;
+use crate::streams::MultiStream;
+use std::collections::HashMap;
+use std::io;
+use std::marker::PhantomData;
+use std::sync::{Mutex, RwLock};
+use virtual_filesystem::path::{self, normalize_path};
+
+/// Builds one emulated file of a format, from the original file and the inputs matching it.
+///
+/// Implemented by each emulator; [`BuilderFactory`] does the rest. A builder is created for
+/// every file in the format, given its inputs with [`add_file`](Self::add_file), then built
+/// once.
+///
+/// Files inside the emulated file which no input replaces should be offered to the other
+/// emulators with [`EmulationFramework::try_create_from_file_slice`](crate::EmulationFramework::try_create_from_file_slice).
+/// That is how emulated files nest: a file without inputs of its own is still emulated when
+/// one of the files inside it is, to any depth, without placeholder inputs.
+pub trait EmulatorBuilder: Default + Send + 'static {
+    /// What is read from the original file before building, usually its table of contents.
+    type Header;
+
+    /// Whether inputs can go in subfolders of the emulated file, e.g. `parent.bin/child/a.dds`
+    /// adding `child/a.dds` to `parent.bin`. Matching is slower with them.
+    const SUBFOLDERS: bool = false;
+
+    /// Quick check of the route before anything else is done, usually of the extension.
+    fn accepts(route: &Route) -> bool;
+
+    /// Reads the header of the original file. Returns `Ok(None)` if it isn't in this format.
+    fn parse_header(source: &dyn Source) -> io::Result<Option<Self::Header>>;
+
+    /// Adds an input file. `name` is its path inside the emulated file, such as `0_yahoo!.adx`,
+    /// or `child/a.dds` with subfolders; `path` is the file to read it from.
+    ///
+    /// Inputs are added in the order mods were loaded; later ones take priority.
+    fn add_file(&mut self, name: &str, path: &str);
+
+    /// Lays out the emulated file from the header and inputs.
+    ///
+    /// Inputs are opened with [`EmulationFramework::open_source`](crate::EmulationFramework::open_source).
+    fn build(self, header: Self::Header, request: &OpenRequest<'_>) -> io::Result<MultiStream>;
+}
+
+/// A folder of emulator inputs, and the route of the file they go in.
+#[derive(Debug, Clone)]
+pub struct RouteGroup {
+    pub route: Route,
+    pub files: FileGroup,
+}
+
+/// Collects the inputs for an [`EmulatorBuilder`] and builds emulated files from them.
+///
+/// Register it with the framework as the emulator (wrapped in an [`Arc`](std::sync::Arc) to keep adding inputs
+/// while mods load). When a file in the format is first opened, a builder is created and given
+/// the inputs matching its route, and the file is built from it. Files without inputs are kept
+/// only if a file nested in them was emulated while building; they aren't built at all when no
+/// registered input could be nested in them, nor again until the inputs change. Built files are
+/// kept until unregistered.
+pub struct BuilderFactory<B> {
+    groups: RwLock<Vec<RouteGroup>>,
+    // Built files by normalised path.
+    built: Mutex<HashMap<String, MultiStream>>,
+    // Files built without anything to emulate, by normalised path, with the nested input
+    // routes at the time. They are only built again once those change.
+    unmodded: Mutex<HashMap<String, Vec<String>>>,
+    builder: PhantomData<fn() -> B>,
+}
+
+impl<B: EmulatorBuilder> BuilderFactory<B> {
+    pub fn new() -> Self {
+        Self {
+            groups: RwLock::new(Vec::new()),
+            built: Mutex::new(HashMap::new()),
+            unmodded: Mutex::new(HashMap::new()),
+            builder: PhantomData,
+        }
+    }
+
+    /// Adds every folder below `input_folder` which contains files, e.g. for a mod's
+    /// `<Mod>/FileEmulationFramework/AFS`, `EVENT_ADX_E.AFS` with the files in it.
+    pub fn add_from_folders(&self, input_folder: &str) -> io::Result<()> {
+        let groups = DirectorySearcher::get_directory_contents_recursive_grouped(input_folder)?;
+        for group in groups {
+            if !group.has_files() {
+                continue;
+            }
+
+            if let Some(route) = Route::from_folder_and_full_path(input_folder, &group.directory) {
+                self.add_group(route, group);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the files of `files` as inputs for the file at `route`.
+    pub fn add_group(&self, route: Route, files: FileGroup) {
+        self.groups
+            .write()
+            .unwrap()
+            .push(RouteGroup { route, files });
+    }
+
+    /// Routes of all inputs added so far.
+    pub fn routes(&self) -> Vec<Route> {
+        let groups = self.groups.read().unwrap();
+        groups.iter().map(|x| x.route.clone()).collect()
+    }
+
+    /// Creates a builder given every input matching `route`, or `None` if there are none.
+    pub fn try_create_from_route(&self, route: &Route) -> Option<B> {
+        let mut builder = None;
+        for group in self.groups.read().unwrap().iter() {
+            let subfolder = match B::SUBFOLDERS {
+                true => route.match_subfolder(&group.route),
+                false => route.matches_no_subfolder(&group.route).then_some(""),
+            };
+
+            let Some(subfolder) = subfolder else {
+                continue;
+            };
+
+            let builder = builder.get_or_insert_with(B::default);
+            for (name, full_path) in group.files.files.iter().zip(group.files.file_paths()) {
+                match subfolder {
+                    "" => builder.add_file(name, &full_path),
+                    _ => builder.add_file(&path::join(subfolder, name), &full_path),
+                }
+            }
+        }
+
+        builder
+    }
+
+    /// Builds the emulated file for `request`, if it is in this format and has inputs or
+    /// emulated files nested in it.
+    ///
+    /// Returns the file built before if there is one; clones share their data.
+    pub fn try_create_emulated_file(
+        &self,
+        request: &OpenRequest<'_>,
+    ) -> io::Result<Option<MultiStream>> {
+        if !B::accepts(request.route) {
+            return Ok(None);
+        }
+
+        let key = normalize_path(request.path);
+        if let Some(file) = self.built.lock().unwrap().get(&key) {
+            return Ok(Some(file.clone()));
+        }
+
+        // Without inputs, the build only offers the files inside to the other emulators, so
+        // skip it if none of their inputs could be nested in this file, or the file was built
+        // for nothing with the same inputs before.
+        let builder = self.try_create_from_route(request.route);
+        let has_inputs = builder.is_some();
+        let mut nested_inputs = Vec::new();
+        if !has_inputs {
+            nested_inputs = request.framework.nested_input_routes(request.route);
+            let unmodded = self.unmodded.lock().unwrap().get(&key) == Some(&nested_inputs);
+            if nested_inputs.is_empty() || unmodded {
+                return Ok(None);
+            }
+        }
+
+        let Some(header) = B::parse_header(request.source.as_ref())? else {
+            return Ok(None);
+        };
+
+        let file = builder.unwrap_or_default().build(header, request)?;
+        if !has_inputs && !request.framework.has_nested_files(request.path) {
+            self.unmodded.lock().unwrap().insert(key, nested_inputs);
+            return Ok(None);
+        }
+
+        // The framework won't build the same path twice at once, so no one else inserts `key`.
+        self.built.lock().unwrap().insert(key, file.clone());
+        Ok(Some(file))
+    }
+}
+
+impl<B: EmulatorBuilder> Default for BuilderFactory<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: EmulatorBuilder> IEmulator for BuilderFactory<B> {
+    /// Files which fail to build are left to the next emulator, or the original file.
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        let file = self.try_create_emulated_file(request).ok()??;
+        Some(Box::new(file))
+    }
+
+    fn unregister_file(&self, path: &str) {
+        self.built.lock().unwrap().remove(&normalize_path(path));
+    }
+
+    fn input_routes(&self) -> Vec<Route> {
+        self.routes()
+    }
+}
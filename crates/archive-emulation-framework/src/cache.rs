@@ -0,0 +1,358 @@
+// Block cache for the sources emulated files read from
+
+use crate::source::{FileLength, Origin, Source};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Default size of the blocks sources are cached in.
+pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Keeps recently read blocks of sources in memory, within a byte budget shared by every
+/// source.
+///
+/// Emulators tend to re-read the same small regions, such as headers and tables of contents,
+/// which costs a read call (and on hard drives, a seek) each time without a cache. Sources are
+/// wrapped with [`source`](Self::source); [`MultiStream::with_cache`](crate::MultiStream::with_cache)
+/// does it for the slices of an emulated file, and pins the headers it generates.
+///
+/// Each source evicts its own least recently used blocks: when over budget, blocks are taken
+/// from the source holding the most, so one file being streamed through doesn't push out the
+/// headers of the others. [Pinned](CachedSource::pin) blocks are never evicted, but count
+/// towards the budget, and are only pinned while they fit in it.
+pub struct BlockCache {
+    block_size: u64,
+    budget: u64,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Hit and miss counts, and memory use, of a [`BlockCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Blocks read from the cache.
+    pub hits: u64,
+    /// Blocks read from their source.
+    pub misses: u64,
+    /// Bytes held in blocks, pinned ones included.
+    pub cached_bytes: u64,
+    pub pinned_bytes: u64,
+}
+
+impl CacheStats {
+    /// Fraction of block reads served from the cache; 0 before any reads.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    sources: HashMap<u64, SourceBlocks>,
+    // Live cached sources by the address of the source they wrap, to share one per source.
+    wrapped: HashMap<usize, Weak<CachedSource>>,
+    cached_bytes: u64,
+    pinned_bytes: u64,
+    next_id: u64,
+    // Incremented on every block access, to order blocks by when they were last used.
+    tick: u64,
+}
+
+#[derive(Default)]
+struct SourceBlocks {
+    blocks: HashMap<u64, Block>,
+    // Unpinned blocks by when they were last used, oldest first.
+    lru: BTreeMap<u64, u64>,
+    unpinned_bytes: u64,
+}
+
+struct Block {
+    data: Arc<[u8]>,
+    last_used: u64,
+    pinned: bool,
+}
+
+impl BlockCache {
+    /// Creates a cache holding up to `budget` bytes, in blocks of [`DEFAULT_BLOCK_SIZE`].
+    pub fn new(budget: u64) -> Arc<Self> {
+        Self::with_block_size(budget, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Creates a cache holding up to `budget` bytes, in blocks of `block_size` bytes.
+    pub fn with_block_size(budget: u64, block_size: u64) -> Arc<Self> {
+        assert!(block_size > 0, "block size must be positive");
+        Arc::new(Self {
+            block_size,
+            budget,
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            cached_bytes: state.cached_bytes,
+            pinned_bytes: state.pinned_bytes,
+        }
+    }
+
+    /// Wraps `source` so its reads go through the cache.
+    ///
+    /// Wrapping the same source again returns the same [`CachedSource`] while it is alive, so
+    /// emulated files built from one original share its blocks. Blocks are dropped along with
+    /// the last handle to the cached source.
+    pub fn source(self: &Arc<Self>, source: &Arc<dyn Source>) -> Arc<CachedSource> {
+        let address = Arc::as_ptr(source) as *const () as usize;
+        let mut state = self.state.lock().unwrap();
+        if let Some(cached) = state.wrapped.get(&address).and_then(Weak::upgrade) {
+            return cached;
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let cached = Arc::new(CachedSource {
+            id,
+            address,
+            source: source.clone(),
+            cache: self.clone(),
+        });
+
+        state.wrapped.insert(address, Arc::downgrade(&cached));
+        cached
+    }
+
+    /// The block at `index` of a source, if cached, marking it as just used.
+    fn get(&self, id: u64, index: u64) -> Option<Arc<[u8]>> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        state.tick += 1;
+        let source = state.sources.get_mut(&id)?;
+        let block = source.blocks.get_mut(&index)?;
+        if !block.pinned {
+            source.lru.remove(&block.last_used);
+            source.lru.insert(state.tick, index);
+        }
+
+        block.last_used = state.tick;
+        Some(block.data.clone())
+    }
+
+    /// Adds a block just read from its source, evicting others to stay within the budget.
+    ///
+    /// Pinned blocks count towards the budget too, so a block is only pinned if the pinned
+    /// blocks would still fit; returns false if it was asked to be pinned but wasn't.
+    fn insert(&self, id: u64, index: u64, data: Arc<[u8]>, pin: bool) -> bool {
+        let length = data.len() as u64;
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let pinned = pin && state.pinned_bytes + length <= self.budget;
+        state.tick += 1;
+        let tick = state.tick;
+        let source = state.sources.entry(id).or_default();
+        if let Some(block) = source.blocks.get_mut(&index) {
+            // Another thread read it too; keep the one already there, pinning it if asked.
+            if pinned && !block.pinned {
+                source.lru.remove(&block.last_used);
+                source.unpinned_bytes -= block.data.len() as u64;
+                state.pinned_bytes += block.data.len() as u64;
+                block.pinned = true;
+            }
+            return !pin || block.pinned;
+        }
+
+        if !pinned && length > self.budget {
+            return !pin;
+        }
+
+        let block = Block {
+            data,
+            last_used: tick,
+            pinned,
+        };
+        source.blocks.insert(index, block);
+        state.cached_bytes += length;
+        match pinned {
+            true => state.pinned_bytes += length,
+            false => {
+                source.lru.insert(tick, index);
+                source.unpinned_bytes += length;
+            }
+        }
+
+        state.evict(self.budget);
+        pinned == pin
+    }
+
+    /// Drops every block of a source.
+    fn remove(&self, id: u64, address: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(source) = state.sources.remove(&id) {
+            let pinned: u64 = source
+                .blocks
+                .values()
+                .filter(|x| x.pinned)
+                .map(|x| x.data.len() as u64)
+                .sum();
+            state.cached_bytes -= pinned + source.unpinned_bytes;
+            state.pinned_bytes -= pinned;
+        }
+
+        // Only if it is still this source's entry, and not a newer wrapper's.
+        let dead = state
+            .wrapped
+            .get(&address)
+            .is_some_and(|x| x.strong_count() == 0);
+        if dead {
+            state.wrapped.remove(&address);
+        }
+    }
+}
+
+impl CacheState {
+    /// Evicts the least recently used blocks of the sources holding the most, until within
+    /// `budget` or only pinned blocks are left.
+    fn evict(&mut self, budget: u64) {
+        while self.cached_bytes > budget {
+            let largest = self.sources.values_mut().max_by_key(|x| x.unpinned_bytes);
+            let Some(source) = largest.filter(|x| x.unpinned_bytes > 0) else {
+                return;
+            };
+
+            let (_, index) = source.lru.pop_first().unwrap();
+            let block = source.blocks.remove(&index).unwrap();
+            let length = block.data.len() as u64;
+            source.unpinned_bytes -= length;
+            self.cached_bytes -= length;
+        }
+    }
+}
+
+/// A source read through a [`BlockCache`]. Created with [`BlockCache::source`].
+pub struct CachedSource {
+    id: u64,
+    // Address of `source`, identifying it in the cache.
+    address: usize,
+    source: Arc<dyn Source>,
+    cache: Arc<BlockCache>,
+}
+
+impl CachedSource {
+    /// The source reads are cached for.
+    pub fn inner(&self) -> &Arc<dyn Source> {
+        &self.source
+    }
+
+    /// Reads the blocks covering `length` bytes at `offset` into the cache and keeps them there
+    /// until the source is dropped. Meant for headers and tables of contents emulators read
+    /// again and again.
+    ///
+    /// Pinned blocks count towards the cache's budget. Returns false if pinning them all would
+    /// go over it; the blocks which didn't fit are cached as usual instead.
+    pub fn pin(&self, offset: u64, length: FileLength) -> io::Result<bool> {
+        let end = offset.saturating_add(length).min(self.source.size());
+        if offset >= end {
+            return Ok(true);
+        }
+
+        let block_size = self.cache.block_size;
+        let mut pinned = true;
+        for index in offset / block_size..end.div_ceil(block_size) {
+            let data = match self.cache.get(self.id, index) {
+                Some(data) => data,
+                None => self.read_block(index)?,
+            };
+            pinned &= self.cache.insert(self.id, index, data, true);
+        }
+
+        Ok(pinned)
+    }
+
+    /// Reads a block from the source.
+    fn read_block(&self, index: u64) -> io::Result<Arc<[u8]>> {
+        let block_size = self.cache.block_size;
+        let start = index * block_size;
+        let length = block_size.min(self.source.size() - start);
+        let mut data = vec![0; length as usize];
+        self.source.read_exact_at(start, &mut data)?;
+        Ok(data.into())
+    }
+}
+
+impl Source for CachedSource {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let size = self.source.size();
+        let wanted = buffer
+            .len()
+            .min(size.saturating_sub(offset).try_into().unwrap_or(usize::MAX));
+        let block_size = self.cache.block_size;
+        let mut total = 0;
+        while total < wanted {
+            let position = offset + total as u64;
+            let index = position / block_size;
+            let data = match self.cache.get(self.id, index) {
+                Some(data) => {
+                    self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                    data
+                }
+                None => {
+                    self.cache.misses.fetch_add(1, Ordering::Relaxed);
+                    let data = self.read_block(index)?;
+                    self.cache.insert(self.id, index, data.clone(), false);
+                    data
+                }
+            };
+
+            let within = (position - index * block_size) as usize;
+            let count = (data.len() - within).min(wanted - total);
+            buffer[total..total + count].copy_from_slice(&data[within..within + count]);
+            total += count;
+        }
+
+        Ok(total)
+    }
+
+    fn size(&self) -> FileLength {
+        self.source.size()
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        self.source.origin()
+    }
+}
+
+impl Source for Arc<CachedSource> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        CachedSource::read_at(self, offset, buffer)
+    }
+
+    fn size(&self) -> FileLength {
+        self.source.size()
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        self.source.origin()
+    }
+}
+
+impl Drop for CachedSource {
+    fn drop(&mut self) {
+        self.cache.remove(self.id, self.address);
+    }
+}
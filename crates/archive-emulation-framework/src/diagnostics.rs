@@ -0,0 +1,175 @@
+// Diagnostics for input routes which override more files than intended
+
+use crate::route::Route;
+use std::fmt;
+use virtual_filesystem::path;
+
+/// A route which may override files it wasn't meant to.
+///
+/// A bare route such as `child.bin` matches every `child.bin`, in any folder. Each warning
+/// suggests routes qualified with the game folder's name (e.g. `GameFolder/data/file.afs`),
+/// which only match the files inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteWarning {
+    /// The route matches more than one real file.
+    Ambiguous {
+        route: Route,
+        files: Vec<String>,
+        suggestions: Vec<Route>,
+    },
+    /// The route matches a file outside the game folder.
+    OutsideGameFolder {
+        route: Route,
+        file: String,
+        suggestions: Vec<Route>,
+    },
+}
+
+impl RouteWarning {
+    /// The route the warning is about.
+    pub fn route(&self) -> &Route {
+        match self {
+            RouteWarning::Ambiguous { route, .. } => route,
+            RouteWarning::OutsideGameFolder { route, .. } => route,
+        }
+    }
+
+    /// More specific routes to use instead, one per file inside the game folder the route
+    /// matched.
+    pub fn suggestions(&self) -> &[Route] {
+        match self {
+            RouteWarning::Ambiguous { suggestions, .. } => suggestions,
+            RouteWarning::OutsideGameFolder { suggestions, .. } => suggestions,
+        }
+    }
+}
+
+impl fmt::Display for RouteWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteWarning::Ambiguous { route, files, .. } => {
+                write!(f, "route `{route}` matches {} files: ", files.len())?;
+                write_list(f, files)?;
+            }
+            RouteWarning::OutsideGameFolder { route, file, .. } => {
+                write!(
+                    f,
+                    "route `{route}` matches `{file}`, outside the game folder"
+                )?;
+            }
+        }
+
+        if !self.suggestions().is_empty() {
+            f.write_str("; use ")?;
+            write_list(f, self.suggestions())?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "`{item}`")?;
+    }
+
+    Ok(())
+}
+
+/// Checks emulator input routes against the real files they may override.
+///
+/// `files` are the full paths of real files: those in `game_folder`, plus any others the
+/// application may open (e.g. from its save or configuration folders). Routes are matched as
+/// [`Route::matches_with_subfolder`] does, so `Sound.afs/00000.adx` is checked against files
+/// named `Sound.afs`. Routes which match no real file (e.g. of files inside archives) are
+/// fine and not reported.
+pub fn diagnose_routes<S: AsRef<str>>(
+    routes: &[Route],
+    game_folder: &str,
+    files: &[S],
+) -> Vec<RouteWarning> {
+    let files: Vec<(&str, Route)> = files
+        .iter()
+        .map(|x| (x.as_ref(), Route::new(x.as_ref())))
+        .collect();
+
+    let mut warnings = Vec::new();
+    for route in routes {
+        // Which files the route matches, and the part of the route below each file.
+        let matches: Vec<(&str, String)> = files
+            .iter()
+            .filter_map(|(file, file_route)| Some((*file, inner_path(route, file_route)?)))
+            .collect();
+
+        let suggestions: Vec<Route> = matches
+            .iter()
+            .filter_map(|(file, inner)| suggest(game_folder, file, inner, &files))
+            .collect();
+
+        if matches.len() > 1 {
+            warnings.push(RouteWarning::Ambiguous {
+                route: route.clone(),
+                files: matches.iter().map(|(file, _)| file.to_string()).collect(),
+                suggestions: suggestions.clone(),
+            });
+        }
+
+        for (file, _) in &matches {
+            if path::strip_folder(file, game_folder).is_none() {
+                warnings.push(RouteWarning::OutsideGameFolder {
+                    route: route.clone(),
+                    file: file.to_string(),
+                    suggestions: suggestions.clone(),
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+/// If `route` matches the file at `file_route`, returns the rest of `route` (the path inside
+/// the file), possibly empty.
+fn inner_path(route: &Route, file_route: &Route) -> Option<String> {
+    let components: Vec<&str> = route
+        .as_str()
+        .split(is_separator)
+        .filter(|x| !x.is_empty())
+        .collect();
+
+    (1..=components.len()).find_map(|count| {
+        let prefix = Route::new(components[..count].join("/"));
+        match file_route.matches_no_subfolder(&prefix) {
+            true => Some(components[count..].join("/")),
+            false => None,
+        }
+    })
+}
+
+/// Returns the route of `inner` inside `file`, starting at the game folder's name, or further
+/// up if other files would still match that.
+fn suggest(game_folder: &str, file: &str, inner: &str, files: &[(&str, Route)]) -> Option<Route> {
+    let relative = path::strip_folder(file, game_folder)?;
+    let folders: Vec<&str> = game_folder
+        .split(is_separator)
+        .filter(|x| !x.is_empty())
+        .collect();
+
+    let mut route = Route::new(relative);
+    for folder in folders.iter().rev() {
+        route = Route::new(path::join(folder, route.as_str()));
+        let matching = files.iter().filter(|(_, x)| x.matches_no_subfolder(&route));
+        if matching.count() <= 1 {
+            break;
+        }
+    }
+
+    Some(route.merge(inner))
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == std::path::MAIN_SEPARATOR
+}
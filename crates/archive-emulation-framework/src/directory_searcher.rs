@@ -0,0 +1,280 @@
+// Listing emulator input folders
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use virtual_filesystem::path;
+
+/// The files directly inside one directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileGroup {
+    /// Full path of the directory.
+    pub directory: String,
+    /// Names of the files in the directory, sorted.
+    pub files: Vec<String>,
+}
+
+impl FileGroup {
+    pub fn has_files(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// Full paths of the files.
+    pub fn file_paths(&self) -> impl Iterator<Item = String> + '_ {
+        self.files.iter().map(|x| path::join(&self.directory, x))
+    }
+}
+
+/// Searches directories on disk, for emulator inputs.
+///
+/// On Linux, directories are read with `getdents64` into one buffer reused for every
+/// directory, and only the directories themselves, symbolic links (and entries on file systems
+/// which don't report types) need a `stat`. Elsewhere, the standard library is used.
+///
+/// Symbolic links are followed, except those back to a directory being listed or one above it.
+pub struct DirectorySearcher;
+
+impl DirectorySearcher {
+    /// Lists `folder` and every directory below it, returning the full paths of all files and
+    /// of all directories below `folder`.
+    pub fn get_directory_contents_recursive(
+        folder: &str,
+    ) -> io::Result<(Vec<String>, Vec<String>)> {
+        let groups = Self::get_directory_contents_recursive_grouped(folder)?;
+        let files = groups.iter().flat_map(FileGroup::file_paths).collect();
+        let directories = groups.into_iter().skip(1).map(|x| x.directory).collect();
+        Ok((files, directories))
+    }
+
+    /// Lists `folder` and every directory below it, one group per directory (including those
+    /// without files), parents before their subdirectories.
+    ///
+    /// Names which aren't valid UTF-8 are skipped, as they can't be routed.
+    pub fn get_directory_contents_recursive_grouped(folder: &str) -> io::Result<Vec<FileGroup>> {
+        let mut groups = Vec::new();
+        let mut pending = vec![Directory::root(folder)];
+        let mut lister = Lister::default();
+        while let Some(directory) = pending.pop() {
+            let (group, mut subdirectories) = lister.list(directory)?;
+            subdirectories.reverse();
+            pending.extend(subdirectories);
+            groups.push(group);
+        }
+
+        Ok(groups)
+    }
+
+    /// [`get_directory_contents_recursive_grouped`](Self::get_directory_contents_recursive_grouped),
+    /// listing directories on `threads` threads at once (or one per core, if 0).
+    ///
+    /// Returns the same groups in the same order. Worth it for large trees on storage which
+    /// serves many requests at once, such as SSDs, or when the tree isn't in the OS cache yet.
+    pub fn get_directory_contents_recursive_grouped_parallel(
+        folder: &str,
+        threads: usize,
+    ) -> io::Result<Vec<FileGroup>> {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |x| x.get()),
+            x => x,
+        };
+
+        let queue = WorkQueue {
+            state: Mutex::new(QueueState {
+                pending: VecDeque::from([Directory::root(folder)]),
+                listing: 0,
+                error: None,
+            }),
+            changed: Condvar::new(),
+        };
+
+        let mut groups: Vec<FileGroup> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| queue.work())).collect();
+            let results = workers.into_iter().map(|x| x.join().unwrap());
+            results.flatten().collect()
+        });
+
+        if let Some(error) = queue.state.into_inner().unwrap().error {
+            return Err(error);
+        }
+
+        // Ordering by components puts each directory right before those below it, with
+        // siblings sorted by name, as in the single-threaded search.
+        groups.sort_unstable_by(|a, b| Path::new(&a.directory).cmp(Path::new(&b.directory)));
+        Ok(groups)
+    }
+}
+
+/// Identifies a directory on disk, so links back to one being listed can be told apart.
+#[cfg(unix)]
+pub(crate) type DirectoryId = (u64, u64);
+#[cfg(not(unix))]
+pub(crate) type DirectoryId = std::path::PathBuf;
+
+/// A directory left to list.
+struct Directory {
+    path: String,
+    // The directories above it, which links inside it mustn't lead back to.
+    ancestors: Vec<DirectoryId>,
+}
+
+impl Directory {
+    fn root(folder: &str) -> Self {
+        Self {
+            path: folder.to_owned(),
+            ancestors: Vec::new(),
+        }
+    }
+}
+
+/// Lists one directory at a time, reusing its buffers.
+#[derive(Default)]
+struct Lister {
+    #[cfg(target_os = "linux")]
+    buffer: Vec<u8>,
+}
+
+impl Lister {
+    /// Lists `directory`, returning its group and its subdirectories, sorted.
+    ///
+    /// Symbolic links to the directory or those above it are skipped, so cycles end.
+    fn list(&mut self, directory: Directory) -> io::Result<(FileGroup, Vec<Directory>)> {
+        let Directory {
+            path: directory,
+            mut ancestors,
+        } = directory;
+        let mut files = Vec::new();
+        let mut subdirectories = Vec::new();
+        self.read(&directory, &mut ancestors, &mut files, &mut subdirectories)?;
+
+        files.sort_unstable();
+        subdirectories.sort_unstable();
+        let subdirectories = subdirectories
+            .iter()
+            .map(|x| Directory {
+                path: path::join(&directory, x),
+                ancestors: ancestors.clone(),
+            })
+            .collect();
+        Ok((FileGroup { directory, files }, subdirectories))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read(
+        &mut self,
+        directory: &str,
+        ancestors: &mut Vec<DirectoryId>,
+        files: &mut Vec<String>,
+        subdirectories: &mut Vec<String>,
+    ) -> io::Result<()> {
+        crate::getdents::list(
+            directory,
+            ancestors,
+            &mut self.buffer,
+            files,
+            subdirectories,
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read(
+        &mut self,
+        directory: &str,
+        ancestors: &mut Vec<DirectoryId>,
+        files: &mut Vec<String>,
+        subdirectories: &mut Vec<String>,
+    ) -> io::Result<()> {
+        ancestors.push(directory_id(Path::new(directory))?);
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+
+            // Follows symbolic links, as mod managers often link inputs in.
+            let file_type = entry.file_type()?;
+            let is_dir = match file_type.is_symlink() {
+                true => match std::fs::metadata(entry.path()).is_ok_and(|x| x.is_dir()) {
+                    true if ancestors.contains(&directory_id(&entry.path())?) => continue,
+                    x => x,
+                },
+                false => file_type.is_dir(),
+            };
+
+            match is_dir {
+                true => subdirectories.push(name),
+                false => files.push(name),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn directory_id(directory: &Path) -> io::Result<DirectoryId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(directory)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_id(directory: &Path) -> io::Result<DirectoryId> {
+    std::fs::canonicalize(directory)
+}
+
+/// Directories left to list, shared by the threads of a parallel search.
+struct WorkQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+struct QueueState {
+    pending: VecDeque<Directory>,
+    // Directories being listed; more may be found while any are.
+    listing: usize,
+    // The first error, which stops the search.
+    error: Option<io::Error>,
+}
+
+impl WorkQueue {
+    /// Lists directories until none are left, returning the groups listed by this thread.
+    fn work(&self) -> Vec<FileGroup> {
+        let mut groups = Vec::new();
+        let mut lister = Lister::default();
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.error.is_some() {
+                return groups;
+            }
+
+            let Some(directory) = state.pending.pop_front() else {
+                if state.listing == 0 {
+                    return groups;
+                }
+
+                state = self.changed.wait(state).unwrap();
+                continue;
+            };
+
+            state.listing += 1;
+            drop(state);
+            let result = lister.list(directory);
+
+            state = self.state.lock().unwrap();
+            state.listing -= 1;
+            match result {
+                Ok((group, subdirectories)) => {
+                    state.pending.extend(subdirectories);
+                    groups.push(group);
+                }
+                Err(error) => {
+                    state.error.get_or_insert(error);
+                }
+            }
+
+            self.changed.notify_all();
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Interfaces implemented by emulators and the files they produce
+
+use crate::framework::EmulationFramework;
+use crate::route::Route;
+use crate::source::{Origin, Source};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Creates emulated files of one kind (e.g. AFS archives).
+///
+/// Called from any thread that opens a file, so state (such as inputs collected from mod
+/// folders) lives behind interior mutability.
+pub trait IEmulator: Send + Sync {
+    /// Offered every file that is opened, until one emulator claims it by returning a file.
+    ///
+    /// Should return quickly for files it doesn't handle: check the extension or route first,
+    /// and only then read the header from [`OpenRequest::source`]. The returned file is built
+    /// once and served unchanged for the rest of the process.
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>>;
+
+    /// Called when a file this emulator created is unloaded, so it can drop anything it cached
+    /// for `path`.
+    fn unregister_file(&self, _path: &str) {}
+
+    /// Routes of the inputs collected from mod folders, e.g. `EVENT_ADX_E.AFS`.
+    ///
+    /// Used for diagnostics ([`EmulationFramework::diagnose_routes`]), and to skip building
+    /// files which no input could be nested in.
+    fn input_routes(&self) -> Vec<Route> {
+        Vec::new()
+    }
+}
+
+/// Lets an emulator be registered while keeping a handle to it, e.g. to add inputs later.
+impl<T: IEmulator + ?Sized> IEmulator for Arc<T> {
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        (**self).try_create_file(request)
+    }
+
+    fn unregister_file(&self, path: &str) {
+        (**self).unregister_file(path)
+    }
+
+    fn input_routes(&self) -> Vec<Route> {
+        (**self).input_routes()
+    }
+}
+
+/// Content of an emulated file.
+pub trait IEmulatedFile: Send + Sync {
+    /// Reads up to `buffer.len()` bytes at `offset`. Returns `0` at or past the end.
+    ///
+    /// Layer 2 calls again for the remainder of short reads.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Size of the file in bytes. Must not change once the file is built.
+    fn size(&self) -> u64;
+
+    /// Where the content comes from. See [`Source::origin`].
+    fn origin(&self) -> Origin<'_> {
+        Origin::Generated
+    }
+}
+
+/// Reads the whole of `file` in one read, which must return all of it, as the guidelines ask
+/// of every emulated file.
+pub fn read_emulated_file(file: &dyn IEmulatedFile) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; file.size() as usize];
+    match file.read_at(0, &mut data)? {
+        read if read == data.len() => Ok(data),
+        _ => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+/// A file offered to the emulators.
+pub struct OpenRequest<'a> {
+    /// Path of the file, or the route of a file slice.
+    pub path: &'a str,
+    pub route: &'a Route,
+    /// The original content of the file.
+    pub source: &'a Arc<dyn Source>,
+    /// The framework, for offering nested files to other emulators while building.
+    pub framework: &'a EmulationFramework,
+}
+
+/// Why [`EmulationFramework::try_create_from_file_slice`] didn't produce a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmulatedFileError {
+    /// The file the slice is taken from couldn't be opened.
+    CannotOpenFile,
+    /// The slice extends past the end of the file.
+    OutOfBounds,
+    /// The route is already being built on this thread.
+    Recursive,
+    /// No emulator claimed the slice.
+    NotClaimed,
+}
+
+/// Result of [`EmulationFramework::try_create_from_file_slice`].
+pub type EmulatedFileResult = Result<Arc<dyn IEmulatedFile>, EmulatedFileError>;
+
+impl fmt::Display for EmulatedFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            EmulatedFileError::CannotOpenFile => "cannot open file",
+            EmulatedFileError::OutOfBounds => "slice extends past the end of the file",
+            EmulatedFileError::Recursive => "route is already being built",
+            EmulatedFileError::NotClaimed => "no emulator claimed the file",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for EmulatedFileError {}
+
+impl Source for Arc<dyn IEmulatedFile> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        (**self).origin()
+    }
+}
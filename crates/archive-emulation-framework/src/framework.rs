@@ -0,0 +1,653 @@
+// Emulator registry, routing of opened files to emulators, and emulated file lifetimes
+
+use crate::diagnostics::{self, RouteWarning};
+use crate::emulator::{
+    EmulatedFileError, EmulatedFileResult, IEmulatedFile, IEmulator, OpenRequest,
+};
+use crate::paired::{IPairedEmulator, PairRequest, PairedEmulator};
+use crate::route::Route;
+use crate::slice::FileSlice;
+use crate::source::{FileLength, FileSources, Source};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, ThreadId};
+use virtual_file_framework::{
+    FileHandler, VfsError, VirtualFileFramework, VirtualFileHandle, VirtualFileMetadata,
+};
+use virtual_filesystem::path::normalize_path;
+
+/// Layer 3: Archive Emulation Framework.
+///
+/// Holds the registered emulators and every emulated file built so far. Files opened by the
+/// application are passed to [`emulate`](Self::emulate) before Layer 2's `open`; the first time
+/// a file is seen, it is offered to the emulators, and a file one of them claims is registered
+/// with Layer 2, which serves it from then on.
+///
+/// Files are keyed by their normalised path; nested files (see
+/// [`try_create_from_file_slice`](Self::try_create_from_file_slice)) by their normalised route.
+/// Files built together by a paired emulator (see [`register_paired`](Self::register_paired))
+/// share a lifetime.
+pub struct EmulationFramework {
+    files: Arc<VirtualFileFramework>,
+    sources: Box<dyn FileSources>,
+    emulators: RwLock<Vec<Arc<dyn IEmulator>>>,
+    paired: RwLock<Vec<Arc<dyn IPairedEmulator>>>,
+    nodes: Mutex<HashMap<String, Node>>,
+    // Signalled whenever a build finishes, for threads waiting on another thread's build.
+    built: Condvar,
+    reports: Mutex<Vec<String>>,
+}
+
+/// An emulated file, or one being offered to the emulators.
+struct Node {
+    // Path or route as given, before normalisation.
+    path: String,
+    state: State,
+    // Original content, for nested files to be sliced from. `None` until opened, and for
+    // files registered directly.
+    source: Option<Arc<dyn Source>>,
+    // Emulated file a nested file was sliced from, and the nested files sliced from this one.
+    parent: Option<String>,
+    children: Vec<String>,
+    // Other files of the set this file was built with by a paired emulator.
+    paired: Vec<String>,
+}
+
+enum State {
+    Building(ThreadId),
+    Built {
+        file: Arc<dyn IEmulatedFile>,
+        // Emulator which created the file; `None` for files registered directly.
+        emulator: Option<Arc<dyn IEmulator>>,
+        // Layer 2 registration; `None` for nested files.
+        handle: Option<VirtualFileHandle>,
+    },
+}
+
+enum Begin {
+    Built(Arc<dyn IEmulatedFile>),
+    Recursive,
+    Started,
+}
+
+impl EmulationFramework {
+    /// Creates the framework on top of Layer 2, reading original files from `sources`.
+    pub fn new(files: Arc<VirtualFileFramework>, sources: Box<dyn FileSources>) -> Self {
+        Self {
+            files,
+            sources,
+            emulators: RwLock::new(Vec::new()),
+            paired: RwLock::new(Vec::new()),
+            nodes: Mutex::new(HashMap::new()),
+            built: Condvar::new(),
+            reports: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The Layer 2 framework emulated files are served through.
+    pub fn virtual_file_framework(&self) -> &Arc<VirtualFileFramework> {
+        &self.files
+    }
+
+    /// Registers an emulator. Emulators are offered files in the order they were registered.
+    pub fn register(&self, emulator: Box<dyn IEmulator>) {
+        self.emulators.write().unwrap().push(Arc::from(emulator));
+    }
+
+    /// Registers an emulator for files which are built together. Paired emulators are offered
+    /// files before the others, in the order they were registered.
+    pub fn register_paired(&self, emulator: Box<dyn IPairedEmulator>) {
+        self.paired.write().unwrap().push(Arc::from(emulator));
+    }
+
+    /// Opens a file on disk, such as an emulator input, through the framework's
+    /// [`FileSources`], bypassing emulation.
+    pub fn open_source(&self, path: &str) -> io::Result<Arc<dyn Source>> {
+        self.sources.open(path)
+    }
+
+    /// Reports a problem found while building, such as an input an emulator had to skip.
+    ///
+    /// The framework runs inside the application, so it has nowhere to show reports itself;
+    /// hosts collect them with [`take_reports`](Self::take_reports).
+    pub fn report(&self, message: String) {
+        self.reports.lock().unwrap().push(message);
+    }
+
+    /// Returns the reports made since the last call, oldest first.
+    pub fn take_reports(&self) -> Vec<String> {
+        std::mem::take(&mut self.reports.lock().unwrap())
+    }
+
+    /// Called when the application opens `path`, before Layer 2's `open`.
+    ///
+    /// Returns the emulated file if `path` is (now) emulated, in which case it is registered as
+    /// a Layer 2 virtual file at `path`. Otherwise the original file should be opened.
+    ///
+    /// On the first open of a file, it is offered to the emulators. If another thread is
+    /// building the same file, waits for it to finish. Opens of the file from inside its own
+    /// build (e.g. an emulator reading the original through the OS) return `None`, so they
+    /// reach the original file.
+    ///
+    /// If a paired emulator claims `path`, the other files of its set are built and registered
+    /// along with it.
+    pub fn emulate(&self, path: &str) -> Option<Arc<dyn IEmulatedFile>> {
+        let key = normalize_path(path);
+        if let Some(file) = self.emulate_paired(&key, path) {
+            return file;
+        }
+
+        match self.begin(&key, path, None) {
+            Begin::Built(file) => return Some(file),
+            Begin::Recursive => return None,
+            Begin::Started => {}
+        }
+
+        let source = match self.sources.open(path) {
+            Ok(source) => source,
+            Err(_) => {
+                self.unload(&key);
+                return None;
+            }
+        };
+
+        let route = Route::new(path);
+        let built = self.build(&key, path, &route, source);
+        self.finish(&key, path, built, true)
+    }
+
+    /// Offers `length` bytes at `offset` of `file_path` to the emulators, as a file at `route`.
+    ///
+    /// Called by emulators while building, for each file inside an archive they don't replace,
+    /// so the other emulators can emulate those without them being extracted. Usually
+    /// `route` is the archive's route [merged](Route::merge) with the name of the inner file.
+    ///
+    /// If `file_path` is an emulated file (or one being built), the slice is taken from its
+    /// original content, and the result lives as long as it: unloading `file_path` unloads the
+    /// nested file too. Otherwise the slice is taken from the file on disk, and the result
+    /// lives until unregistered.
+    ///
+    /// Nested files are identified by `route`; a route which was already built returns the
+    /// same file.
+    pub fn try_create_from_file_slice(
+        &self,
+        file_path: &str,
+        offset: u64,
+        length: FileLength,
+        route: &Route,
+    ) -> EmulatedFileResult {
+        let parent_key = normalize_path(file_path);
+        let parent_source = self
+            .nodes
+            .lock()
+            .unwrap()
+            .get(&parent_key)
+            .and_then(|x| x.source.clone());
+
+        let (source, parent) = match parent_source {
+            Some(source) => (source, Some(parent_key)),
+            None => match self.sources.open(file_path) {
+                Ok(source) => (source, None),
+                Err(_) => return Err(EmulatedFileError::CannotOpenFile),
+            },
+        };
+
+        let slice = FileSlice::new(offset, length, source);
+        if !slice.in_bounds() {
+            return Err(EmulatedFileError::OutOfBounds);
+        }
+
+        let key = normalize_path(route.as_str());
+        match self.begin(&key, route.as_str(), parent) {
+            Begin::Built(file) => return Ok(file),
+            Begin::Recursive => return Err(EmulatedFileError::Recursive),
+            Begin::Started => {}
+        }
+
+        let built = self.build(&key, route.as_str(), route, Arc::new(slice));
+        self.finish(&key, route.as_str(), built, false)
+            .ok_or(EmulatedFileError::NotClaimed)
+    }
+
+    /// Makes `file` available at `path` without waiting for the application to open it.
+    ///
+    /// Registered with Layer 2 straight away. Slices taken from `path` come from the file on
+    /// disk, since there is no other original content.
+    pub fn register_virtual_file(
+        &self,
+        path: &str,
+        file: Box<dyn IEmulatedFile>,
+    ) -> Result<VirtualFileHandle, VfsError> {
+        let key = normalize_path(path);
+        let mut nodes = self.nodes.lock().unwrap();
+        if nodes.contains_key(&key) {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let file: Arc<dyn IEmulatedFile> = Arc::from(file);
+        let handle = self.register_with_layer2(path, &file)?;
+        nodes.insert(
+            key,
+            Node {
+                path: path.to_owned(),
+                state: State::Built {
+                    file,
+                    emulator: None,
+                    handle: Some(handle),
+                },
+                source: None,
+                parent: None,
+                children: Vec::new(),
+                paired: Vec::new(),
+            },
+        );
+
+        Ok(handle)
+    }
+
+    /// Unloads the emulated file at `path` (or route), every file nested in it, and the files
+    /// it was built with by a paired emulator.
+    ///
+    /// Emulators are told through [`IEmulator::unregister_file`]. Handles that are already
+    /// open remain valid until closed; the next open builds the file again. Meant for tools
+    /// such as hot reload; see the guidelines on keeping generated files immutable.
+    ///
+    /// A file still being built is unloaded too: its build is discarded when it finishes.
+    pub fn unregister_virtual_file(&self, path: &str) -> Result<(), VfsError> {
+        let key = normalize_path(path);
+        if !self.nodes.lock().unwrap().contains_key(&key) {
+            return Err(VfsError::NotFound);
+        }
+
+        self.unload(&key);
+        Ok(())
+    }
+
+    /// Checks the input routes of every registered emulator against the real files they may
+    /// override. See [`diagnose_routes`](crate::diagnose_routes).
+    pub fn diagnose_routes<S: AsRef<str>>(
+        &self,
+        game_folder: &str,
+        files: &[S],
+    ) -> Vec<RouteWarning> {
+        let mut routes = Vec::new();
+        let emulators = self.emulators.read().unwrap();
+        let paired = self.paired.read().unwrap();
+        let inputs = emulators.iter().map(|x| x.input_routes());
+        for route in inputs
+            .chain(paired.iter().map(|x| x.input_routes()))
+            .flatten()
+        {
+            if !routes.contains(&route) {
+                routes.push(route);
+            }
+        }
+
+        diagnostics::diagnose_routes(&routes, game_folder, files)
+    }
+
+    /// Returns the emulated file at `path` (or route), if it has been built.
+    pub fn emulated_file(&self, path: &str) -> Option<Arc<dyn IEmulatedFile>> {
+        let nodes = self.nodes.lock().unwrap();
+        match &nodes.get(&normalize_path(path))?.state {
+            State::Built { file, .. } => Some(file.clone()),
+            State::Building(_) => None,
+        }
+    }
+
+    /// Returns true if the file at `path` (or route) has been emulated.
+    pub fn is_emulated(&self, path: &str) -> bool {
+        self.emulated_file(path).is_some()
+    }
+
+    /// Returns true if files nested in the file at `path` (or route) have been emulated,
+    /// including while it is still being built.
+    pub fn has_nested_files(&self, path: &str) -> bool {
+        let nodes = self.nodes.lock().unwrap();
+        let node = nodes.get(&normalize_path(path));
+        node.is_some_and(|x| !x.children.is_empty())
+    }
+
+    /// Lists the input routes of the registered emulators which could match a file nested in
+    /// the file at `route`, i.e. those which don't match `route` itself, sorted.
+    ///
+    /// Routes such as `Sound.afs` match at the end of any route, so they may apply inside any
+    /// archive. When there are none, nothing nested in the file can be emulated.
+    pub fn nested_input_routes(&self, route: &Route) -> Vec<String> {
+        let emulators = self.emulators.read().unwrap();
+        let paired = self.paired.read().unwrap();
+        let inputs = emulators.iter().map(|x| x.input_routes());
+        let mut routes: Vec<String> = inputs
+            .chain(paired.iter().map(|x| x.input_routes()))
+            .flatten()
+            .filter(|x| !route.matches_no_subfolder(x))
+            .map(|x| x.as_str().to_owned())
+            .collect();
+        routes.sort_unstable();
+        routes
+    }
+
+    /// Starts building `key`, unless it is built or being built.
+    fn begin(&self, key: &str, path: &str, parent: Option<String>) -> Begin {
+        let current = thread::current().id();
+        let mut nodes = self.nodes.lock().unwrap();
+        loop {
+            match nodes.get(key).map(|x| &x.state) {
+                Some(State::Built { file, .. }) => return Begin::Built(file.clone()),
+                Some(State::Building(thread)) if *thread == current => return Begin::Recursive,
+                Some(State::Building(_)) => nodes = self.built.wait(nodes).unwrap(),
+                None => break,
+            }
+        }
+
+        let node = Node {
+            path: path.to_owned(),
+            state: State::Building(current),
+            source: None,
+            parent,
+            children: Vec::new(),
+            paired: Vec::new(),
+        };
+        nodes.insert(key.to_owned(), node);
+        Begin::Started
+    }
+
+    /// Offers `path` to the paired emulators, building the set it belongs to if one claims it.
+    /// Returns `None` if none did, for the other emulators to be offered it.
+    fn emulate_paired(&self, key: &str, path: &str) -> Option<Option<Arc<dyn IEmulatedFile>>> {
+        // Emulators may register others while building; don't hold the lock across them.
+        let emulators = self.paired.read().unwrap().clone();
+        for emulator in emulators {
+            let Some(paths) = emulator.paired_paths(path) else {
+                continue;
+            };
+
+            let keys: Vec<String> = paths.iter().map(|x| normalize_path(x)).collect();
+            if !keys.iter().any(|x| x == key) {
+                continue;
+            }
+
+            match self.begin_paired(&keys, key, &paths) {
+                Some(Begin::Built(file)) => return Some(Some(file)),
+                Some(Begin::Recursive) => return Some(None),
+                Some(Begin::Started) => {}
+                None => continue,
+            }
+
+            if self.build_paired(&keys, &paths, &emulator) {
+                return Some(self.emulated_file(key));
+            }
+        }
+
+        None
+    }
+
+    /// Starts building every file of a set, unless `key` is built or being built. Returns
+    /// `None` if another file of the set was emulated on its own.
+    ///
+    /// All files are claimed at once, so two threads opening different files of the same set
+    /// can't each wait for the other.
+    fn begin_paired(&self, keys: &[String], key: &str, paths: &[String]) -> Option<Begin> {
+        let current = thread::current().id();
+        let mut nodes = self.nodes.lock().unwrap();
+        loop {
+            let states = keys.iter().filter_map(|x| nodes.get(x)).map(|x| &x.state);
+            let mut waiting = false;
+            for state in states {
+                match state {
+                    State::Building(thread) if *thread == current => return Some(Begin::Recursive),
+                    State::Building(_) => waiting = true,
+                    State::Built { .. } => {}
+                }
+            }
+
+            if !waiting {
+                break;
+            }
+
+            nodes = self.built.wait(nodes).unwrap();
+        }
+
+        if let Some(State::Built { file, .. }) = nodes.get(key).map(|x| &x.state) {
+            return Some(Begin::Built(file.clone()));
+        }
+
+        if keys.iter().any(|x| nodes.contains_key(x)) {
+            return None;
+        }
+
+        for (key, path) in keys.iter().zip(paths) {
+            let node = Node {
+                path: path.to_owned(),
+                state: State::Building(current),
+                source: None,
+                parent: None,
+                children: Vec::new(),
+                paired: keys.iter().filter(|x| *x != key).cloned().collect(),
+            };
+            nodes.insert(key.to_owned(), node);
+        }
+
+        Some(Begin::Started)
+    }
+
+    /// Builds and registers every file of a set, or unloads all of them if any fails.
+    fn build_paired(
+        &self,
+        keys: &[String],
+        paths: &[String],
+        emulator: &Arc<dyn IPairedEmulator>,
+    ) -> bool {
+        let sources: io::Result<Vec<_>> = paths.iter().map(|x| self.sources.open(x)).collect();
+        let files = sources.ok().and_then(|sources| {
+            let mut nodes = self.nodes.lock().unwrap();
+            for (key, source) in keys.iter().zip(&sources) {
+                if let Some(node) = nodes.get_mut(key) {
+                    node.source = Some(source.clone());
+                }
+            }
+
+            drop(nodes);
+            let routes: Vec<Route> = paths.iter().map(Route::new).collect();
+            let request = PairRequest {
+                paths,
+                routes: &routes,
+                sources: &sources,
+                framework: self,
+            };
+
+            let files = emulator.try_create_files(&request)?;
+            (files.len() == paths.len()).then_some(files)
+        });
+
+        let Some(files) = files else {
+            self.unload(&keys[0]);
+            return false;
+        };
+
+        let files: Vec<Arc<dyn IEmulatedFile>> = files.into_iter().map(Arc::from).collect();
+        let mut handles = Vec::new();
+        for (path, file) in paths.iter().zip(&files) {
+            match self.register_with_layer2(path, file) {
+                Ok(handle) => handles.push(handle),
+                // Something else already serves this path through Layer 2.
+                Err(_) => {
+                    for handle in handles {
+                        let _ = self.files.unregister_virtual_file(handle);
+                    }
+                    self.unload(&keys[0]);
+                    return false;
+                }
+            }
+        }
+
+        let mut nodes = self.nodes.lock().unwrap();
+        if !keys.iter().all(|x| nodes.contains_key(x)) {
+            // Unloaded while building: undo what was set up for the whole set.
+            drop(nodes);
+            for (path, handle) in paths.iter().zip(handles) {
+                emulator.unregister_file(path);
+                let _ = self.files.unregister_virtual_file(handle);
+            }
+            self.unload(&keys[0]);
+            return false;
+        }
+
+        let emulator: Arc<dyn IEmulator> = Arc::new(PairedEmulator(emulator.clone()));
+        for ((key, file), handle) in keys.iter().zip(files).zip(handles) {
+            let node = nodes.get_mut(key).unwrap();
+            node.state = State::Built {
+                file,
+                emulator: Some(emulator.clone()),
+                handle: Some(handle),
+            };
+        }
+
+        drop(nodes);
+        self.built.notify_all();
+        true
+    }
+
+    /// Offers the file to each emulator until one claims it.
+    fn build(
+        &self,
+        key: &str,
+        path: &str,
+        route: &Route,
+        source: Arc<dyn Source>,
+    ) -> Option<(Arc<dyn IEmulatedFile>, Arc<dyn IEmulator>)> {
+        if let Some(node) = self.nodes.lock().unwrap().get_mut(key) {
+            node.source = Some(source.clone());
+        }
+
+        // Emulators may register others while building; don't hold the lock across them.
+        let emulators = self.emulators.read().unwrap().clone();
+        let request = OpenRequest {
+            path,
+            route,
+            source: &source,
+            framework: self,
+        };
+
+        emulators.into_iter().find_map(|emulator| {
+            let file = emulator.try_create_file(&request)?;
+            Some((Arc::from(file), emulator))
+        })
+    }
+
+    /// Records the outcome of a build and wakes any threads waiting for it.
+    fn finish(
+        &self,
+        key: &str,
+        path: &str,
+        built: Option<(Arc<dyn IEmulatedFile>, Arc<dyn IEmulator>)>,
+        register: bool,
+    ) -> Option<Arc<dyn IEmulatedFile>> {
+        let Some((file, emulator)) = built else {
+            self.unload(key);
+            return None;
+        };
+
+        let handle = match register {
+            true => match self.register_with_layer2(path, &file) {
+                Ok(handle) => Some(handle),
+                // Something else already serves this path through Layer 2.
+                Err(_) => {
+                    self.unload(key);
+                    return None;
+                }
+            },
+            false => None,
+        };
+
+        let mut nodes = self.nodes.lock().unwrap();
+        let Some(node) = nodes.get_mut(key) else {
+            // Unloaded while building: undo what was set up for it.
+            drop(nodes);
+            emulator.unregister_file(path);
+            if let Some(handle) = handle {
+                let _ = self.files.unregister_virtual_file(handle);
+            }
+            return None;
+        };
+
+        node.state = State::Built {
+            file: file.clone(),
+            emulator: Some(emulator),
+            handle,
+        };
+
+        if let Some(parent) = node.parent.clone() {
+            if let Some(parent) = nodes.get_mut(&parent) {
+                parent.children.push(key.to_owned());
+            }
+        }
+
+        drop(nodes);
+        self.built.notify_all();
+        Some(file)
+    }
+
+    /// Removes `key` and everything nested in it, then tells the emulators and Layer 2.
+    fn unload(&self, key: &str) {
+        let mut removed = Vec::new();
+        let mut nodes = self.nodes.lock().unwrap();
+        let mut pending = vec![key.to_owned()];
+        while let Some(key) = pending.pop() {
+            if let Some(node) = nodes.remove(&key) {
+                pending.extend(node.children.iter().cloned());
+                pending.extend(node.paired.iter().cloned());
+                removed.push(node);
+            }
+        }
+
+        if let Some(parent) = removed.first().and_then(|x| x.parent.as_ref()) {
+            if let Some(parent) = nodes.get_mut(parent) {
+                parent.children.retain(|x| x != key);
+            }
+        }
+
+        drop(nodes);
+        self.built.notify_all();
+
+        // Outside the lock: emulators may call back into the framework.
+        for node in removed {
+            if let State::Built {
+                emulator, handle, ..
+            } = node.state
+            {
+                if let Some(emulator) = emulator {
+                    emulator.unregister_file(&node.path);
+                }
+                if let Some(handle) = handle {
+                    let _ = self.files.unregister_virtual_file(handle);
+                }
+            }
+        }
+    }
+
+    fn register_with_layer2(
+        &self,
+        path: &str,
+        file: &Arc<dyn IEmulatedFile>,
+    ) -> Result<VirtualFileHandle, VfsError> {
+        let metadata = VirtualFileMetadata::with_size(file.size());
+        let handler = EmulatedFileHandler(file.clone());
+        self.files
+            .register_virtual_file(path, metadata, Box::new(handler))
+    }
+}
+
+/// Serves an emulated file through Layer 2.
+struct EmulatedFileHandler(Arc<dyn IEmulatedFile>);
+
+impl FileHandler for EmulatedFileHandler {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.0.size())
+    }
+}
@@ -0,0 +1,128 @@
+// Listing directories on Linux with getdents64
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use crate::directory_searcher::DirectoryId;
+
+/// Size of the buffer directory entries are read into; a few hundred entries per call.
+pub(crate) const BUFFER_SIZE: usize = 32 * 1024;
+
+/// Offset of `d_reclen` in `linux_dirent64`, after `d_ino` and `d_off`.
+const RECORD_LENGTH_OFFSET: usize = 16;
+const TYPE_OFFSET: usize = 18;
+const NAME_OFFSET: usize = 19;
+
+/// Lists `directory` into `files` and `subdirectories` (names only), reading entries through
+/// `buffer`, which is reused between calls.
+///
+/// Skips names which aren't valid UTF-8, and follows symbolic links like the standard library
+/// version. `ancestors` are the directories above `directory`, which `directory` is added to;
+/// links back to any of them are skipped, as they'd be listed forever.
+pub(crate) fn list(
+    directory: &str,
+    ancestors: &mut Vec<DirectoryId>,
+    buffer: &mut Vec<u8>,
+    files: &mut Vec<String>,
+    subdirectories: &mut Vec<String>,
+) -> io::Result<()> {
+    let path = CString::new(directory).map_err(|_| io::ErrorKind::InvalidInput)?;
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    // SAFETY: `path` is null terminated.
+    let fd = unsafe { libc::open(path.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `fd` was just opened, and is owned by nothing else.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: `stat` is only read if the call succeeded.
+    if unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: as above.
+    ancestors.push(id(unsafe { &stat.assume_init() }));
+    buffer.resize(BUFFER_SIZE, 0);
+    loop {
+        // SAFETY: the kernel writes at most `buffer.len()` bytes into `buffer`.
+        let read = unsafe {
+            libc::syscall(
+                libc::SYS_getdents64,
+                fd.as_raw_fd(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )
+        };
+
+        match read {
+            0 => return Ok(()),
+            x if x < 0 => return Err(io::Error::last_os_error()),
+            x => add_entries(&fd, &buffer[..x as usize], ancestors, files, subdirectories),
+        }
+    }
+}
+
+/// Sorts the entries in one `getdents64` result into files and subdirectories.
+fn add_entries(
+    fd: &OwnedFd,
+    mut entries: &[u8],
+    ancestors: &[DirectoryId],
+    files: &mut Vec<String>,
+    subdirectories: &mut Vec<String>,
+) {
+    while !entries.is_empty() {
+        let length = u16::from_ne_bytes([
+            entries[RECORD_LENGTH_OFFSET],
+            entries[RECORD_LENGTH_OFFSET + 1],
+        ]) as usize;
+        let entry_type = entries[TYPE_OFFSET];
+        let name = CStr::from_bytes_until_nul(&entries[NAME_OFFSET..length]).unwrap();
+        entries = &entries[length..];
+
+        let Ok(text) = name.to_str() else {
+            continue;
+        };
+
+        if text == "." || text == ".." {
+            continue;
+        }
+
+        // File systems without types in their entries report everything as unknown.
+        let is_dir = match entry_type {
+            libc::DT_DIR => true,
+            libc::DT_LNK | libc::DT_UNKNOWN => match directory_at(fd, name) {
+                Some(x) if ancestors.contains(&x) => continue,
+                Some(_) => true,
+                None => false,
+            },
+            _ => false,
+        };
+
+        match is_dir {
+            true => subdirectories.push(text.to_owned()),
+            false => files.push(text.to_owned()),
+        }
+    }
+}
+
+/// Identifies `name` in the directory `fd` if it's a directory, following symbolic links.
+fn directory_at(fd: &OwnedFd, name: &CStr) -> Option<DirectoryId> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: `name` is null terminated, and `stat` is only read if the call succeeded.
+    match unsafe { libc::fstatat(fd.as_raw_fd(), name.as_ptr(), stat.as_mut_ptr(), 0) } {
+        0 => Some(unsafe { stat.assume_init() })
+            .filter(|x| x.st_mode & libc::S_IFMT == libc::S_IFDIR)
+            .map(|x| id(&x)),
+        _ => None,
+    }
+}
+
+// The types are narrower on some 32-bit targets.
+#[allow(clippy::unnecessary_cast)]
+fn id(stat: &libc::stat) -> DirectoryId {
+    (stat.st_dev as u64, stat.st_ino as u64)
+}
@@ -0,0 +1,61 @@
+//! Layer 3: Archive Emulation Framework.
+//!
+//! Builds emulated files (e.g. archives with injected files) on top of Layer 2. Emulators
+//! ([`IEmulator`]) register with the [`EmulationFramework`]; when a file is opened, it is offered
+//! to each emulator in turn, and the first to claim it supplies an [`IEmulatedFile`] which is
+//! served through Layer 2 from then on.
+//!
+//! Emulated files are built lazily, on first open, and are immutable once built. Emulators can
+//! offer parts of the original file to the other emulators while building
+//! ([`EmulationFramework::try_create_from_file_slice`]), so emulated files can nest. Files which
+//! depend on each other, such as an index and its data, are built together by an
+//! [`IPairedEmulator`].
+//!
+//! Most emulated files are a [`MultiStream`]: new data such as headers, joined with
+//! [`FileSlice`]s of the original file and [`PaddingStream`]s. Reads of the slices can go
+//! through a [`BlockCache`], for emulated files whose original regions are read repeatedly.
+//!
+//! Emulator inputs are matched to files by [`Route`]; [`diagnose_routes`] finds input routes
+//! which would also override files they weren't meant to. [`BuilderFactory`] collects inputs
+//! from mod folders and matches them, so an emulator only implements an [`EmulatorBuilder`]:
+//! reading the original file's header, and laying out the emulated file.
+//!
+//! Original file data is read through [`FileSources`], so the framework runs entirely in memory
+//! when given in-memory sources such as [`MemorySources`].
+//! With the `test-support` feature, `test_support::Setup` registers an emulator with such a
+//! framework, for emulators' tests.
+
+mod builder;
+mod cache;
+mod diagnostics;
+mod directory_searcher;
+mod emulator;
+mod framework;
+#[cfg(target_os = "linux")]
+mod getdents;
+mod offset_range;
+mod paired;
+mod route;
+mod slice;
+mod source;
+mod streams;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+pub use builder::{BuilderFactory, EmulatorBuilder, RouteGroup};
+pub use cache::{BlockCache, CacheStats, CachedSource, DEFAULT_BLOCK_SIZE};
+pub use diagnostics::{diagnose_routes, RouteWarning};
+pub use directory_searcher::{DirectorySearcher, FileGroup};
+pub use emulator::{
+    read_emulated_file, EmulatedFileError, EmulatedFileResult, IEmulatedFile, IEmulator,
+    OpenRequest,
+};
+pub use framework::EmulationFramework;
+pub use offset_range::{OffsetRange, OffsetRangeSelector, RangeLookup, SearchLayout};
+pub use paired::{IPairedEmulator, PairRequest};
+pub use route::Route;
+pub use slice::FileSlice;
+pub use source::{
+    read_source, DiskSources, FileLength, FileSources, MemorySources, Origin, Source,
+};
+pub use streams::{FileSliceStream, MultiStream, PaddingStream, StreamOffsetPair};
@@ -0,0 +1,258 @@
+// Offset ranges, and finding which of a set of ranges an offset falls in
+
+/// A range of offsets, `start..end`. The end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct OffsetRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl OffsetRange {
+    pub fn new(start: u64, end: u64) -> Self {
+        debug_assert!(start <= end, "range ends before it starts");
+        Self { start, end }
+    }
+
+    pub fn from_start_and_length(start: u64, length: u64) -> Self {
+        Self::new(start, start + length)
+    }
+
+    pub fn length(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns true if `offset` is in the range.
+    pub fn contains_point(&self, offset: u64) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    /// Returns true if the ranges share at least one offset.
+    pub fn overlaps(&self, other: &OffsetRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Where an offset falls among the ranges of an [`OffsetRangeSelector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeLookup {
+    /// In the range at this index.
+    Range(usize),
+    /// Between two non-empty ranges, e.g. in alignment padding. Empty ranges in between are
+    /// skipped.
+    Gap {
+        /// The whole gap; the caller usually fills `offset..gap.end` with padding.
+        gap: OffsetRange,
+        /// Index of the non-empty range after the gap.
+        next: usize,
+    },
+    /// Before the first range or at/after the end of the last.
+    Outside,
+}
+
+/// How an [`OffsetRangeSelector`] lays out range starts for searching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchLayout {
+    /// Sorted, searched with a binary search.
+    Sorted,
+    /// Eytzinger (breadth-first tree) order, searched without branching on the data.
+    ///
+    /// The first few levels of the tree share cache lines, and the nodes a few levels down are
+    /// prefetched while searching. The standard binary search is branch-free too, so this only
+    /// pays off for tables too large for the cache (see `benches/offset_range.rs`).
+    Eytzinger,
+}
+
+impl SearchLayout {
+    /// Range count from which [`SearchLayout::Eytzinger`] is picked by default.
+    pub const EYTZINGER_THRESHOLD: usize = 32768;
+
+    fn for_count(count: usize) -> Self {
+        if count >= Self::EYTZINGER_THRESHOLD {
+            Self::Eytzinger
+        } else {
+            Self::Sorted
+        }
+    }
+}
+
+/// Finds the range an offset falls in, among sorted ranges.
+///
+/// Created with [`OffsetRangeSelector::new`] for ranges without gaps (e.g. the parts of a
+/// [`MultiStream`](crate::MultiStream)), or [`OffsetRangeSelector::with_gaps`] for layouts with
+/// holes in them (e.g. files aligned within an archive), where [`OffsetRangeSelector::lookup`]
+/// reports which gap an offset falls in.
+#[derive(Debug, Clone, Default)]
+pub struct OffsetRangeSelector {
+    starts: Vec<u64>,
+    ends: Vec<u64>,
+    // Starts in Eytzinger order, if using that layout.
+    eytzinger: Option<Eytzinger>,
+}
+
+impl OffsetRangeSelector {
+    /// `ranges` must be sorted, without gaps or overlaps. Empty ranges are never selected.
+    pub fn new(ranges: &[OffsetRange]) -> Self {
+        debug_assert!(
+            ranges.windows(2).all(|x| x[0].end == x[1].start),
+            "ranges must be sorted, without gaps"
+        );
+
+        Self::build(ranges)
+    }
+
+    /// `ranges` must be sorted, without overlaps; gaps between them are allowed.
+    pub fn with_gaps(ranges: &[OffsetRange]) -> Self {
+        debug_assert!(
+            ranges.windows(2).all(|x| x[0].end <= x[1].start),
+            "ranges must be sorted, without overlaps"
+        );
+
+        Self::build(ranges)
+    }
+
+    fn build(ranges: &[OffsetRange]) -> Self {
+        let selector = Self {
+            starts: ranges.iter().map(|x| x.start).collect(),
+            ends: ranges.iter().map(|x| x.end).collect(),
+            eytzinger: None,
+        };
+
+        selector.with_layout(SearchLayout::for_count(ranges.len()))
+    }
+
+    /// Switches to the given search layout. By default the layout is picked by range count.
+    pub fn with_layout(mut self, layout: SearchLayout) -> Self {
+        self.eytzinger = match layout {
+            SearchLayout::Sorted => None,
+            SearchLayout::Eytzinger => Some(Eytzinger::new(&self.starts)),
+        };
+
+        self
+    }
+
+    pub fn layout(&self) -> SearchLayout {
+        match self.eytzinger {
+            Some(_) => SearchLayout::Eytzinger,
+            None => SearchLayout::Sorted,
+        }
+    }
+
+    /// Number of ranges.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Returns the index of the range containing `offset`, or `None` if it is in a gap or
+    /// outside all of them.
+    pub fn select(&self, offset: u64) -> Option<usize> {
+        match self.lookup(offset) {
+            RangeLookup::Range(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Returns the range or gap containing `offset`.
+    pub fn lookup(&self, offset: u64) -> RangeLookup {
+        // The last non-empty range starting at or before `offset`.
+        let count = self.count_starting_at_or_before(offset);
+        let Some(index) = (0..count).rev().find(|x| !self.is_empty_range(*x)) else {
+            return RangeLookup::Outside;
+        };
+
+        if offset < self.ends[index] {
+            return RangeLookup::Range(index);
+        }
+
+        // Past the end of `index`, so before the start of the next non-empty range, if any.
+        match (index + 1..self.len()).find(|x| !self.is_empty_range(*x)) {
+            Some(next) => RangeLookup::Gap {
+                gap: OffsetRange::new(self.ends[index], self.starts[next]),
+                next,
+            },
+            None => RangeLookup::Outside,
+        }
+    }
+
+    fn is_empty_range(&self, index: usize) -> bool {
+        self.starts[index] == self.ends[index]
+    }
+
+    fn count_starting_at_or_before(&self, offset: u64) -> usize {
+        match &self.eytzinger {
+            Some(eytzinger) => eytzinger.count_at_or_before(offset),
+            None => self.starts.partition_point(|x| *x <= offset),
+        }
+    }
+}
+
+/// Sorted keys stored as an implicit binary tree: the children of node `k` are `2k` and `2k + 1`.
+#[derive(Debug, Clone)]
+struct Eytzinger {
+    // Node 0 is unused, so the tree starts at 1.
+    keys: Vec<u64>,
+    // Index of each node's key in sorted order; entry 0 is the key count, for "none found".
+    sorted_index: Vec<usize>,
+}
+
+impl Eytzinger {
+    fn new(sorted: &[u64]) -> Self {
+        let mut tree = Self {
+            keys: vec![0; sorted.len() + 1],
+            sorted_index: vec![sorted.len(); sorted.len() + 1],
+        };
+
+        let mut next = 0;
+        tree.fill(sorted, &mut next, 1);
+        tree
+    }
+
+    /// In-order walk of the tree, handing out sorted keys as nodes are visited.
+    fn fill(&mut self, sorted: &[u64], next: &mut usize, node: usize) {
+        if node >= self.keys.len() {
+            return;
+        }
+
+        self.fill(sorted, next, 2 * node);
+        self.keys[node] = sorted[*next];
+        self.sorted_index[node] = *next;
+        *next += 1;
+        self.fill(sorted, next, 2 * node + 1);
+    }
+
+    /// Number of keys `<= value`, i.e. the sorted index of the first key greater than it.
+    fn count_at_or_before(&self, value: u64) -> usize {
+        // Descend left or right by the comparison result rather than branching on it; the
+        // loop always runs tree-height times.
+        let mut node = 1;
+        while node < self.keys.len() {
+            // The 16 nodes four levels below `node` are contiguous: two cache lines.
+            prefetch(self.keys.as_ptr().wrapping_add(16 * node));
+            node = 2 * node + usize::from(self.keys[node] <= value);
+        }
+
+        // Undo the trailing right turns, and the left turn before them, to land on the last
+        // node we went left at: the first key greater than `value`. 0 if there was none.
+        node >>= node.trailing_ones() + 1;
+        self.sorted_index[node]
+    }
+}
+
+/// Hints that `key` will be read soon. The address may be past the end of the tree.
+#[inline(always)]
+fn prefetch(key: *const u64) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: prefetching is only a hint; it never faults, whatever the address.
+    unsafe {
+        std::arch::x86_64::_mm_prefetch::<{ std::arch::x86_64::_MM_HINT_T0 }>(key.cast())
+    };
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = key;
+}
@@ -0,0 +1,95 @@
+// Emulators for files which are only built together, such as an index and its data file
+
+use crate::emulator::{IEmulatedFile, IEmulator, OpenRequest};
+use crate::framework::EmulationFramework;
+use crate::route::Route;
+use crate::source::Source;
+use std::sync::Arc;
+
+/// Creates sets of files which depend on each other, such as an index and the data file its
+/// offsets point into (e.g. ACB + AWB, or `.dir` + `.bin`).
+///
+/// The first open of any file in a set builds all of them at once: the other files of the set
+/// can't be built by anyone else meanwhile, and either all of them are emulated or none are.
+/// They are unloaded together too, so offsets from one file into another always agree.
+///
+/// Registered with [`EmulationFramework::register_paired`], and offered files before the
+/// [`IEmulator`]s. Only files opened by path are paired, not nested files.
+pub trait IPairedEmulator: Send + Sync {
+    /// Paths of every file in the set `path` belongs to, `path` included, or `None` if this
+    /// emulator doesn't handle it.
+    ///
+    /// Called for every opened file, so should only look at the path, e.g. for
+    /// `music.dir` returning `music.dir` and `music.bin`.
+    fn paired_paths(&self, path: &str) -> Option<Vec<String>>;
+
+    /// Builds every file of the set, one per [`PairRequest::paths`] and in the same order.
+    ///
+    /// Returning `None` (or the wrong number of files) leaves the files to the other emulators.
+    fn try_create_files(&self, request: &PairRequest<'_>) -> Option<Vec<Box<dyn IEmulatedFile>>>;
+
+    /// Called for each file of a set when the set is unloaded.
+    fn unregister_file(&self, _path: &str) {}
+
+    /// Routes of the inputs collected from mod folders. See [`IEmulator::input_routes`].
+    fn input_routes(&self) -> Vec<Route> {
+        Vec::new()
+    }
+}
+
+/// Lets an emulator be registered while keeping a handle to it, e.g. to add inputs later.
+impl<T: IPairedEmulator + ?Sized> IPairedEmulator for Arc<T> {
+    fn paired_paths(&self, path: &str) -> Option<Vec<String>> {
+        (**self).paired_paths(path)
+    }
+
+    fn try_create_files(&self, request: &PairRequest<'_>) -> Option<Vec<Box<dyn IEmulatedFile>>> {
+        (**self).try_create_files(request)
+    }
+
+    fn unregister_file(&self, path: &str) {
+        (**self).unregister_file(path)
+    }
+
+    fn input_routes(&self) -> Vec<Route> {
+        (**self).input_routes()
+    }
+}
+
+/// A set of files offered to a paired emulator. Each list has one item per file.
+pub struct PairRequest<'a> {
+    /// Paths of the files, as returned by [`IPairedEmulator::paired_paths`].
+    pub paths: &'a [String],
+    pub routes: &'a [Route],
+    /// The original content of each file.
+    pub sources: &'a [Arc<dyn Source>],
+    /// The framework, for offering nested files to other emulators while building.
+    pub framework: &'a EmulationFramework,
+}
+
+impl PairRequest<'_> {
+    /// The request for one file of the set, e.g. to offer the files inside it to other
+    /// emulators.
+    pub fn request(&self, index: usize) -> OpenRequest<'_> {
+        OpenRequest {
+            path: &self.paths[index],
+            route: &self.routes[index],
+            source: &self.sources[index],
+            framework: self.framework,
+        }
+    }
+}
+
+/// Lets the framework keep paired emulators where it keeps the others, to tell them about
+/// unloaded files.
+pub(crate) struct PairedEmulator(pub(crate) Arc<dyn IPairedEmulator>);
+
+impl IEmulator for PairedEmulator {
+    fn try_create_file(&self, _request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        None
+    }
+
+    fn unregister_file(&self, path: &str) {
+        self.0.unregister_file(path)
+    }
+}
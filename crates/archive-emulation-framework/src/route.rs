@@ -0,0 +1,123 @@
+// Routes: the paths emulators match their inputs against
+
+use std::fmt;
+use virtual_filesystem::path::{self, normalize_path};
+
+/// Path of a file as seen by the emulators, including any archives it is nested in.
+///
+/// For a file opened by the application this is its full path. For a file inside an emulated
+/// archive, it is the archive's route followed by the name of the file inside, e.g.
+/// `<GameFolder>/English/Sound.afs/00000.adx`.
+///
+/// Routes are compared like Layer 1 compares paths: case-insensitively, and on Windows with
+/// `/` and `\` as the same separator. See `Emulator-Development/Routing.md` for the matching
+/// rules.
+#[derive(Debug, Clone)]
+pub struct Route {
+    path: String,
+    // Normalised form of `path`, which matching works on.
+    key: String,
+}
+
+impl Route {
+    pub fn new(path: impl Into<String>) -> Self {
+        let path = path.into();
+        let key = normalize_path(&path);
+        Self { path, key }
+    }
+
+    /// Creates the route of `full_path` relative to `folder`, e.g. the route of a folder in a
+    /// mod's emulator input folder.
+    ///
+    /// Returns `None` if `full_path` isn't inside `folder`, or is `folder` itself.
+    pub fn from_folder_and_full_path(folder: &str, full_path: &str) -> Option<Self> {
+        match path::strip_folder(full_path, folder)? {
+            "" => None,
+            route => Some(Self::new(route)),
+        }
+    }
+
+    /// The route as a path, as given.
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The last component of the route.
+    pub fn as_file_name(&self) -> &str {
+        path::file_name(&self.path)
+    }
+
+    /// Returns the route of `name` inside the file at this route.
+    pub fn merge(&self, name: &str) -> Route {
+        Route::new(path::join(&self.path, name))
+    }
+
+    /// Returns true if this route ends with `group`.
+    ///
+    /// `group` is the route of an emulator input, such as `Sound.afs` or `English/Sound.afs`.
+    /// Its first component may match partially (`nglish/Sound.afs`); the rest must match whole.
+    pub fn matches_no_subfolder(&self, group: &Route) -> bool {
+        debug_assert!(!group.key.is_empty(), "group route cannot be empty");
+        self.key.ends_with(&group.key)
+    }
+
+    /// Returns true if this route ends with `group`, or with `group` minus one or more trailing
+    /// components.
+    ///
+    /// For inputs which add files in subfolders of an archive: `parent.bin/child/child.dds`
+    /// matches the route of `parent.bin`.
+    pub fn matches_with_subfolder(&self, group: &Route) -> bool {
+        self.match_subfolder(group).is_some()
+    }
+
+    /// Like [`matches_with_subfolder`](Self::matches_with_subfolder), but returns the trailing
+    /// components of `group` which were left over: the subfolder inside this route's file that
+    /// the group's files go in.
+    ///
+    /// For `parent.bin/child` matched against the route of `parent.bin`, returns `child`, as
+    /// given in `group`. Returns an empty string if all of `group` matched.
+    pub fn match_subfolder<'a>(&self, group: &'a Route) -> Option<&'a str> {
+        debug_assert!(!group.key.is_empty(), "group route cannot be empty");
+        debug_assert!(
+            !group.key.starts_with(is_separator),
+            "group route cannot start with a separator"
+        );
+
+        if self.key.ends_with(&group.key) {
+            return Some("");
+        }
+
+        // Longest match first. Usually only one or two separators, so this stays close to
+        // linear.
+        let prefixes = group.key.rmatch_indices(is_separator).map(|(x, _)| x);
+        prefixes
+            .filter(|x| *x > 0)
+            .find(|x| self.key.ends_with(&group.key[..*x]))
+            .and_then(|x| path::strip_folder(&group.path, &group.key[..x]))
+    }
+}
+
+impl PartialEq for Route {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Route {}
+
+impl std::hash::Hash for Route {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+// Normalised routes only contain the platform's main separator.
+fn is_separator(c: char) -> bool {
+    c == std::path::MAIN_SEPARATOR
+}
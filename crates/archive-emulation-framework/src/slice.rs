@@ -0,0 +1,97 @@
+// File slices: regions of original files reused in emulated ones
+
+use crate::source::{FileLength, Origin, Source};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// `length` bytes at `offset` of a source, usually the original file.
+///
+/// Reads stop at the end of the slice, or at the end of the source if that comes first.
+#[derive(Clone)]
+pub struct FileSlice {
+    offset: u64,
+    length: FileLength,
+    source: Arc<dyn Source>,
+}
+
+impl FileSlice {
+    pub fn new(offset: u64, length: FileLength, source: Arc<dyn Source>) -> Self {
+        Self {
+            offset,
+            length,
+            source,
+        }
+    }
+
+    /// Offset of the slice within its source.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn length(&self) -> FileLength {
+        self.length
+    }
+
+    pub fn source(&self) -> &Arc<dyn Source> {
+        &self.source
+    }
+
+    /// Returns true if the slice lies within its source.
+    pub fn in_bounds(&self) -> bool {
+        self.offset
+            .checked_add(self.length)
+            .is_some_and(|end| end <= self.source.size())
+    }
+
+    /// Joins `first` and `second` if `second` starts where `first` ends, in the same source.
+    ///
+    /// e.g. `0-4095` and `4096-65535` make `0-65535`.
+    pub fn try_merge(first: &FileSlice, second: &FileSlice) -> Option<FileSlice> {
+        let same_source = Arc::ptr_eq(&first.source, &second.source);
+        if !same_source || first.offset.checked_add(first.length)? != second.offset {
+            return None;
+        }
+
+        Some(FileSlice::new(
+            first.offset,
+            first.length + second.length,
+            first.source.clone(),
+        ))
+    }
+}
+
+impl Source for FileSlice {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(offset);
+        let length = buffer.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        if length == 0 {
+            return Ok(0);
+        }
+
+        self.source
+            .read_at(self.offset + offset, &mut buffer[..length])
+    }
+
+    fn size(&self) -> FileLength {
+        self.length
+    }
+
+    fn file_slice(&self) -> Option<&FileSlice> {
+        Some(self)
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::Slice(self)
+    }
+}
+
+impl fmt::Debug for FileSlice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileSlice")
+            .field("offset", &self.offset)
+            .field("length", &self.length)
+            .field("source", &Arc::as_ptr(&self.source))
+            .finish()
+    }
+}
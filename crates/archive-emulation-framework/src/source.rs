@@ -0,0 +1,183 @@
+// Original file data emulators build from
+
+use crate::slice::FileSlice;
+use crate::streams::MultiStream;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::sync::{Arc, Mutex};
+use virtual_filesystem::path::normalize_path;
+
+/// Length of a file, or of a slice of one, in bytes.
+pub type FileLength = u64;
+
+/// Read-only data an emulated file is built from, such as the original file.
+///
+/// Reads are positional, so a source can be shared between emulated files and threads.
+pub trait Source: Send + Sync {
+    /// Reads up to `buffer.len()` bytes at `offset`. Returns `0` at or past the end.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Size of the data in bytes.
+    fn size(&self) -> FileLength;
+
+    /// Reads exactly `buffer.len()` bytes at `offset`.
+    fn read_exact_at(&self, mut offset: u64, mut buffer: &mut [u8]) -> io::Result<()> {
+        while !buffer.is_empty() {
+            match self.read_at(offset, buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => {
+                    buffer = &mut buffer[read..];
+                    offset += read as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the slice of another source this reads, if it is one.
+    ///
+    /// Lets [`MultiStream`](crate::MultiStream) merge slices which lie next to each other.
+    fn file_slice(&self) -> Option<&FileSlice> {
+        None
+    }
+
+    /// Where the data comes from, so generated data can be told apart from file data.
+    fn origin(&self) -> Origin<'_> {
+        Origin::Generated
+    }
+}
+
+/// Where the data of a [`Source`] comes from.
+#[derive(Clone, Copy)]
+pub enum Origin<'a> {
+    /// Made by the emulator, such as a header.
+    Generated,
+    /// One byte, repeated.
+    Padding(u8),
+    /// The file on disk at this path.
+    File(&'a str),
+    /// Part of another source.
+    Slice(&'a FileSlice),
+    /// Streams joined together, such as an emulated file.
+    Streams(&'a MultiStream),
+}
+
+impl Source for Vec<u8> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let start = offset.min(self.len() as u64) as usize;
+        let read = buffer.len().min(self.len() - start);
+        buffer[..read].copy_from_slice(&self[start..start + read]);
+        Ok(read)
+    }
+
+    fn size(&self) -> FileLength {
+        self.len() as u64
+    }
+}
+
+/// Opens the original files emulators build from.
+///
+/// Backends whose hooks would see the framework's own reads supply an implementation going
+/// around them; [`MemorySources`] serves files from memory.
+pub trait FileSources: Send + Sync {
+    fn open(&self, path: &str) -> io::Result<Arc<dyn Source>>;
+}
+
+/// Opens files on disk with the standard library.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiskSources;
+
+impl FileSources for DiskSources {
+    fn open(&self, path: &str) -> io::Result<Arc<dyn Source>> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let path = path.to_owned();
+        Ok(Arc::new(DiskFile { file, size, path }))
+    }
+}
+
+/// Serves files held in memory, for tests and tools which build emulated files without a
+/// filesystem. Paths are matched after normalising them.
+#[derive(Default, Clone)]
+pub struct MemorySources(Arc<Mutex<HashMap<String, Arc<Vec<u8>>>>>);
+
+impl MemorySources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the file at `path`, replacing any file already there. Clones share their files.
+    pub fn insert(&self, path: &str, data: impl Into<Vec<u8>>) {
+        let mut files = self.0.lock().unwrap();
+        files.insert(normalize_path(path), Arc::new(data.into()));
+    }
+}
+
+impl FileSources for MemorySources {
+    fn open(&self, path: &str) -> io::Result<Arc<dyn Source>> {
+        match self.0.lock().unwrap().get(&normalize_path(path)) {
+            Some(data) => Ok(Arc::new(MemoryFile {
+                data: data.clone(),
+                path: path.to_owned(),
+            })),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+struct MemoryFile {
+    data: Arc<Vec<u8>>,
+    path: String,
+}
+
+impl Source for MemoryFile {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.data.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> FileLength {
+        self.data.size()
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::File(&self.path)
+    }
+}
+
+/// Reads the whole of `source`.
+pub fn read_source(source: &dyn Source) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; source.size() as usize];
+    source.read_exact_at(0, &mut data)?;
+    Ok(data)
+}
+
+struct DiskFile {
+    file: File,
+    // Files are immutable while emulated, so the size is taken once.
+    size: FileLength,
+    path: String,
+}
+
+impl Source for DiskFile {
+    #[cfg(unix)]
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(&self.file, buffer, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(&self.file, buffer, offset)
+    }
+
+    fn size(&self) -> FileLength {
+        self.size
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::File(&self.path)
+    }
+}
@@ -0,0 +1,320 @@
+// Streams emulated files are assembled from
+
+use crate::cache::BlockCache;
+use crate::emulator::IEmulatedFile;
+use crate::offset_range::{OffsetRange, OffsetRangeSelector};
+use crate::slice::FileSlice;
+use crate::source::{FileLength, Origin, Source};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+// Every stream can be read positionally (through `Source`, shared between threads) or as a
+// `Read + Seek` cursor. Cloning a stream gives a new cursor over the same data.
+
+/// A stream, and the range of offsets it makes up in a [`MultiStream`].
+///
+/// Offset 0 of the stream is at `range.start`.
+pub struct StreamOffsetPair<S> {
+    pub stream: S,
+    pub range: OffsetRange,
+}
+
+impl<S> StreamOffsetPair<S> {
+    pub fn new(stream: S, range: OffsetRange) -> Self {
+        Self { stream, range }
+    }
+}
+
+/// Stream of a single repeated byte, e.g. alignment padding between files.
+#[derive(Debug, Clone)]
+pub struct PaddingStream {
+    byte: u8,
+    length: FileLength,
+    position: u64,
+}
+
+impl PaddingStream {
+    pub fn new(byte: u8, length: FileLength) -> Self {
+        Self {
+            byte,
+            length,
+            position: 0,
+        }
+    }
+}
+
+impl Source for PaddingStream {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(offset);
+        let length = buffer.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        buffer[..length].fill(self.byte);
+        Ok(length)
+    }
+
+    fn size(&self) -> FileLength {
+        self.length
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::Padding(self.byte)
+    }
+}
+
+/// Stream over a [`FileSlice`].
+#[derive(Debug, Clone)]
+pub struct FileSliceStream {
+    slice: FileSlice,
+    position: u64,
+}
+
+impl FileSliceStream {
+    pub fn new(slice: FileSlice) -> Self {
+        Self { slice, position: 0 }
+    }
+
+    pub fn slice(&self) -> &FileSlice {
+        &self.slice
+    }
+
+    /// Joins the streams if their slices can be merged ([`FileSlice::try_merge`]).
+    pub fn try_merge(first: &FileSliceStream, second: &FileSliceStream) -> Option<Self> {
+        FileSlice::try_merge(&first.slice, &second.slice).map(Self::new)
+    }
+}
+
+impl Source for FileSliceStream {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.slice.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> FileLength {
+        self.slice.size()
+    }
+
+    fn file_slice(&self) -> Option<&FileSlice> {
+        Some(&self.slice)
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::Slice(&self.slice)
+    }
+}
+
+/// Joins streams into one: the primary abstraction emulators build files with.
+///
+/// Built from [`StreamOffsetPair`]s which together cover the whole file, without gaps or
+/// overlaps. Neighbouring slices of the same source are merged into one, so a run of unmodified
+/// files from the original is read in one go.
+///
+/// Reads are served straight into the caller's buffer, one stream at a time, and are always
+/// complete: short reads from a stream are retried, and a stream ending before its range does
+/// is an error rather than a short read.
+#[derive(Clone)]
+pub struct MultiStream {
+    streams: Arc<[StreamOffsetPair<Box<dyn Source>>]>,
+    selector: Arc<OffsetRangeSelector>,
+    length: FileLength,
+    position: u64,
+}
+
+impl MultiStream {
+    /// Returns [`io::ErrorKind::InvalidInput`] if the streams don't cover `0..length` exactly.
+    pub fn new(mut streams: Vec<StreamOffsetPair<Box<dyn Source>>>) -> io::Result<Self> {
+        streams.retain(|x| !x.range.is_empty());
+        streams.sort_by_key(|x| x.range.start);
+
+        let mut expected_start = 0;
+        for pair in &streams {
+            if pair.range.start != expected_start {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("streams leave a gap or overlap at offset {expected_start}"),
+                ));
+            }
+            expected_start = pair.range.end;
+        }
+
+        let streams = merge_slices(streams);
+        let ranges: Vec<OffsetRange> = streams.iter().map(|x| x.range).collect();
+        Ok(Self {
+            streams: streams.into(),
+            selector: Arc::new(OffsetRangeSelector::new(&ranges)),
+            length: expected_start,
+            position: 0,
+        })
+    }
+
+    /// Like [`new`](Self::new), with the file slices read through `cache`.
+    ///
+    /// Slices of the same source share one [`CachedSource`](crate::CachedSource), so they
+    /// still merge. Generated streams, such as headers and tables of contents, are cached as
+    /// sources of their own and [pinned](crate::CachedSource::pin) whole, as far as the cache's
+    /// budget allows.
+    pub fn with_cache(
+        streams: Vec<StreamOffsetPair<Box<dyn Source>>>,
+        cache: &Arc<BlockCache>,
+    ) -> io::Result<Self> {
+        let mut cached: Vec<StreamOffsetPair<Box<dyn Source>>> = Vec::new();
+        for pair in streams {
+            if let Some(slice) = pair.stream.file_slice() {
+                let source: Arc<dyn Source> = cache.source(slice.source());
+                let slice = FileSlice::new(slice.offset(), slice.length(), source);
+                let stream = Box::new(FileSliceStream::new(slice));
+                cached.push(StreamOffsetPair::new(stream, pair.range));
+            } else if let Origin::Generated = pair.stream.origin() {
+                let source: Arc<dyn Source> = Arc::from(pair.stream);
+                let source = cache.source(&source);
+                // Left to be cached like the slices if the budget is taken.
+                source.pin(0, source.size())?;
+                cached.push(StreamOffsetPair::new(Box::new(source), pair.range));
+            } else {
+                cached.push(pair);
+            }
+        }
+
+        Self::new(cached)
+    }
+
+    /// The streams making up the file, in order, after merging.
+    pub fn streams(&self) -> &[StreamOffsetPair<Box<dyn Source>>] {
+        &self.streams
+    }
+
+    pub fn len(&self) -> FileLength {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Reads up to `buffer.len()` bytes at `offset`. Only short at the end of the file.
+    pub fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let Some(mut index) = self.selector.select(offset) else {
+            return Ok(0);
+        };
+
+        let remaining = self.length - offset;
+        let wanted = buffer.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let mut total = 0;
+        while total < wanted {
+            let pair = &self.streams[index];
+            let position = offset + total as u64;
+            let available = (pair.range.end - position).try_into().unwrap_or(usize::MAX);
+            let count = available.min(wanted - total);
+            let within = position - pair.range.start;
+            read_exact_at(&*pair.stream, within, &mut buffer[total..total + count])?;
+            total += count;
+            index += 1;
+        }
+
+        Ok(total)
+    }
+}
+
+/// Reads all of `buffer`, retrying short reads.
+fn read_exact_at(stream: &dyn Source, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    stream
+        .read_exact_at(offset, buffer)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended before the end of its range",
+            ),
+            _ => e,
+        })
+}
+
+/// Merges neighbouring slices of the same source, where each covers exactly its range.
+fn merge_slices(
+    streams: Vec<StreamOffsetPair<Box<dyn Source>>>,
+) -> Vec<StreamOffsetPair<Box<dyn Source>>> {
+    let mut merged: Vec<StreamOffsetPair<Box<dyn Source>>> = Vec::with_capacity(streams.len());
+    for pair in streams {
+        let joined = merged.last().and_then(|last| {
+            let first = exact_slice(last)?;
+            let second = exact_slice(&pair)?;
+            FileSlice::try_merge(first, second)
+        });
+
+        match joined {
+            Some(slice) => {
+                let last = merged.last_mut().unwrap();
+                last.range.end = pair.range.end;
+                last.stream = Box::new(FileSliceStream::new(slice));
+            }
+            None => merged.push(pair),
+        }
+    }
+
+    merged
+}
+
+fn exact_slice(pair: &StreamOffsetPair<Box<dyn Source>>) -> Option<&FileSlice> {
+    let slice = pair.stream.file_slice()?;
+    (slice.length() == pair.range.length()).then_some(slice)
+}
+
+impl Source for MultiStream {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        MultiStream::read_at(self, offset, buffer)
+    }
+
+    fn size(&self) -> FileLength {
+        self.length
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::Streams(self)
+    }
+}
+
+impl IEmulatedFile for MultiStream {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        MultiStream::read_at(self, offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        self.length
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::Streams(self)
+    }
+}
+
+/// Implements `Read + Seek` over a stream's positional reads and `position` field.
+macro_rules! impl_cursor {
+    ($stream:ty) => {
+        impl Read for $stream {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                let read = Source::read_at(self, self.position, buffer)?;
+                self.position += read as u64;
+                Ok(read)
+            }
+        }
+
+        impl Seek for $stream {
+            fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+                self.position = seek(self.position, Source::size(self), position)?;
+                Ok(self.position)
+            }
+        }
+    };
+}
+
+impl_cursor!(PaddingStream);
+impl_cursor!(FileSliceStream);
+impl_cursor!(MultiStream);
+
+/// Seeks like a file: past the end is allowed, before the start isn't.
+fn seek(current: u64, length: u64, position: SeekFrom) -> io::Result<u64> {
+    let new_position = match position {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(delta) => current.checked_add_signed(delta),
+        SeekFrom::End(delta) => length.checked_add_signed(delta),
+    };
+
+    new_position
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"))
+}
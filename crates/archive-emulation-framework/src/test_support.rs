@@ -0,0 +1,87 @@
+// An emulator registered with a framework reading from memory, for emulator tests
+
+use crate::builder::{BuilderFactory, EmulatorBuilder};
+use crate::directory_searcher::FileGroup;
+use crate::emulator::{read_emulated_file, OpenRequest};
+use crate::framework::EmulationFramework;
+use crate::route::Route;
+use crate::source::MemorySources;
+use crate::streams::MultiStream;
+use std::io;
+use std::sync::Arc;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::path;
+use virtual_filesystem::VirtualFiles;
+
+/// A framework with one emulator registered, and one original archive in memory.
+pub struct Setup<B> {
+    pub framework: EmulationFramework,
+    pub sources: MemorySources,
+    pub emulator: Arc<BuilderFactory<B>>,
+    input_folder: String,
+    archive: String,
+}
+
+impl<B: EmulatorBuilder> Setup<B> {
+    /// Puts `original` at the path `archive`. Mods' inputs for it go in
+    /// `FileEmulationFramework/<input_folder>`, as for the emulator.
+    pub fn new(input_folder: &str, archive: &str, original: Vec<u8>) -> Self {
+        let sources = MemorySources::new();
+        sources.insert(archive, original);
+        let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+        let framework = EmulationFramework::new(Arc::new(layer2), Box::new(sources.clone()));
+        let emulator = Arc::new(BuilderFactory::new());
+        framework.register(Box::new(emulator.clone()));
+        Self {
+            framework,
+            sources,
+            emulator,
+            input_folder: input_folder.to_owned(),
+            archive: archive.to_owned(),
+        }
+    }
+
+    /// Adds a mod's inputs for the archive.
+    pub fn add_inputs(&self, mod_name: &str, files: &[(&str, &[u8])]) {
+        let (folder, name) = (&self.input_folder, path::file_name(&self.archive));
+        let directory = format!("/mods/{mod_name}/FileEmulationFramework/{folder}/{name}");
+        let mut group = FileGroup {
+            directory,
+            files: Vec::new(),
+        };
+
+        for (name, data) in files {
+            group.files.push(name.to_string());
+            let path = group.file_paths().last().unwrap();
+            self.sources.insert(&path, data.to_vec());
+        }
+
+        self.emulator.add_group(Route::new(name), group);
+    }
+
+    /// Emulates the archive through the framework, and reads it whole.
+    pub fn emulate(&self) -> Vec<u8> {
+        let file = self
+            .framework
+            .emulate(&self.archive)
+            .expect("archive is emulated");
+        read_emulated_file(file.as_ref()).unwrap()
+    }
+
+    /// Builds the archive directly, to look at its streams.
+    pub fn build(&self) -> MultiStream {
+        self.try_build().unwrap().expect("archive is built")
+    }
+
+    pub fn try_build(&self) -> io::Result<Option<MultiStream>> {
+        let source = self.framework.open_source(&self.archive).unwrap();
+        let route = Route::new(self.archive.as_str());
+        let request = OpenRequest {
+            path: &self.archive,
+            route: &route,
+            source: &source,
+            framework: &self.framework,
+        };
+        self.emulator.try_create_emulated_file(&request)
+    }
+}
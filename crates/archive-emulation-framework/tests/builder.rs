@@ -0,0 +1,282 @@
+// BuilderFactory driving a toy format from mod folders on disk.
+
+use archive_emulation_framework::{
+    BuilderFactory, DiskSources, EmulationFramework, EmulatorBuilder, FileSlice, FileSliceStream,
+    IEmulatedFile, IEmulator, MultiStream, OffsetRange, OpenRequest, Route, Source,
+    StreamOffsetPair,
+};
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::VirtualFiles;
+
+thread_local! {
+    static BUILDS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// `.toy` files: `TOY\n`, then one `name=data\n` line per entry.
+#[derive(Default)]
+struct ToyBuilder<const SUBFOLDERS: bool> {
+    inputs: Vec<(String, String)>,
+}
+
+struct ToyEntry {
+    name: String,
+    offset: u64,
+    length: u64,
+}
+
+impl<const SUBFOLDERS: bool> EmulatorBuilder for ToyBuilder<SUBFOLDERS> {
+    type Header = Vec<ToyEntry>;
+
+    const SUBFOLDERS: bool = SUBFOLDERS;
+
+    fn accepts(route: &Route) -> bool {
+        route.as_str().ends_with(".toy")
+    }
+
+    fn parse_header(source: &dyn Source) -> io::Result<Option<Self::Header>> {
+        let mut data = vec![0; source.size() as usize];
+        source.read_exact_at(0, &mut data)?;
+        let Some(lines) = data.strip_prefix(b"TOY\n") else {
+            return Ok(None);
+        };
+
+        let mut entries = Vec::new();
+        let mut offset = 4;
+        for line in lines.split_inclusive(|x| *x == b'\n') {
+            let name_length = line.iter().position(|x| *x == b'=').unwrap();
+            entries.push(ToyEntry {
+                name: String::from_utf8(line[..name_length].to_vec()).unwrap(),
+                offset: offset + name_length as u64 + 1,
+                length: (line.len() - name_length - 2) as u64,
+            });
+            offset += line.len() as u64;
+        }
+
+        Ok(Some(entries))
+    }
+
+    fn add_file(&mut self, name: &str, path: &str) {
+        self.inputs.retain(|(x, _)| x != name);
+        self.inputs.push((name.to_owned(), path.to_owned()));
+    }
+
+    fn build(self, header: Self::Header, request: &OpenRequest<'_>) -> io::Result<MultiStream> {
+        BUILDS.with(|x| x.set(x.get() + 1));
+
+        let mut entries = Vec::new();
+        for entry in header {
+            let slice = FileSlice::new(entry.offset, entry.length, request.source.clone());
+            entries.push((entry.name, slice));
+        }
+
+        for (name, path) in self.inputs {
+            let source = request.framework.open_source(&path)?;
+            let slice = FileSlice::new(0, source.size(), source);
+            match entries.iter_mut().find(|(x, _)| *x == name) {
+                Some(entry) => entry.1 = slice,
+                None => entries.push((name, slice)),
+            }
+        }
+
+        let mut pairs = Vec::new();
+        push(&mut pairs, Box::new(b"TOY\n".to_vec()));
+        for (name, slice) in entries {
+            push(&mut pairs, Box::new(format!("{name}=").into_bytes()));
+            push(&mut pairs, Box::new(FileSliceStream::new(slice)));
+            push(&mut pairs, Box::new(b"\n".to_vec()));
+        }
+
+        MultiStream::new(pairs)
+    }
+}
+
+fn push(pairs: &mut Vec<StreamOffsetPair<Box<dyn Source>>>, stream: Box<dyn Source>) {
+    let start = pairs.last().map_or(0, |x| x.range.end);
+    let range = OffsetRange::from_start_and_length(start, stream.size());
+    pairs.push(StreamOffsetPair::new(stream, range));
+}
+
+fn write(root: &Path, path: &str, data: &[u8]) -> String {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, data).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+fn framework() -> EmulationFramework {
+    let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    EmulationFramework::new(Arc::new(layer2), Box::new(DiskSources))
+}
+
+fn read_all(file: &dyn IEmulatedFile) -> String {
+    let mut buffer = vec![0; file.size() as usize];
+    file.read_at(0, &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Two mods adding inputs for `game.toy`, and the game's files.
+fn setup(root: &Path) -> [String; 2] {
+    write(root, "mod1/TOY/game.toy/b", b"mod1");
+    write(root, "mod2/TOY/game.toy/b", b"mod2");
+    write(root, "mod2/TOY/game.toy/c", b"new");
+    write(root, "mod2/TOY/game.toy/sub/d", b"nested");
+    write(root, "mod2/TOY/broken.toy/a", b"unused");
+    write(root, "mod2/TOY/game.txt/a", b"unused");
+
+    write(root, "game/data/broken.toy", b"NOT A TOY");
+    write(root, "game/data/game.txt", b"TOY\na=1\n");
+    write(root, "game/data/other.toy", b"TOY\na=1\n");
+    let game = write(root, "game/data/game.toy", b"TOY\na=1\nb=2\n");
+    let mods = root.join("mod1/TOY").to_str().unwrap().to_owned();
+    [game, mods]
+}
+
+#[test]
+fn inputs_from_mod_folders_are_built_in() {
+    let root = tempfile::tempdir().unwrap();
+    let [game, _] = setup(root.path());
+    let framework = framework();
+    let factory = Arc::new(BuilderFactory::<ToyBuilder<false>>::new());
+    framework.register(Box::new(factory.clone()));
+
+    for name in ["mod1", "mod2"] {
+        let folder = root.path().join(name).join("TOY");
+        factory.add_from_folders(folder.to_str().unwrap()).unwrap();
+    }
+
+    let mut routes: Vec<String> = factory.routes().iter().map(|x| x.to_string()).collect();
+    routes.sort();
+    let separator = std::path::MAIN_SEPARATOR;
+    let subfolder = format!("game.toy{separator}sub");
+    assert_eq!(
+        routes,
+        ["broken.toy", "game.toy", "game.toy", &subfolder, "game.txt"]
+    );
+
+    // Later mods win; the subfolder isn't matched without subfolder support.
+    let file = framework.emulate(&game).unwrap();
+    assert_eq!(read_all(file.as_ref()), "TOY\na=1\nb=mod2\nc=new\n");
+
+    let data = root.path().join("game/data");
+    for name in ["broken.toy", "game.txt", "other.toy"] {
+        let path = data.join(name);
+        assert!(
+            framework.emulate(path.to_str().unwrap()).is_none(),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn subfolders_go_inside_the_file() {
+    let root = tempfile::tempdir().unwrap();
+    let [game, _] = setup(root.path());
+    let framework = framework();
+    let factory = Arc::new(BuilderFactory::<ToyBuilder<true>>::new());
+    framework.register(Box::new(factory.clone()));
+    let folder = root.path().join("mod2/TOY");
+    factory.add_from_folders(folder.to_str().unwrap()).unwrap();
+
+    let file = framework.emulate(&game).unwrap();
+    let separator = std::path::MAIN_SEPARATOR;
+    assert_eq!(
+        read_all(file.as_ref()),
+        format!("TOY\na=1\nb=mod2\nc=new\nsub{separator}d=nested\n")
+    );
+}
+
+#[test]
+fn built_lazily_and_cached_until_unregistered() {
+    let root = tempfile::tempdir().unwrap();
+    let [game, mods] = setup(root.path());
+    let framework = framework();
+    let factory = BuilderFactory::<ToyBuilder<false>>::new();
+    factory.add_from_folders(&mods).unwrap();
+
+    assert_eq!(BUILDS.with(Cell::get), 0);
+
+    let source = framework.open_source(&game).unwrap();
+    let route = Route::new(game.as_str());
+    let request = OpenRequest {
+        path: &game,
+        route: &route,
+        source: &source,
+        framework: &framework,
+    };
+
+    let first = factory.try_create_emulated_file(&request).unwrap().unwrap();
+    factory.try_create_emulated_file(&request).unwrap().unwrap();
+    assert_eq!(BUILDS.with(Cell::get), 1);
+    assert_eq!(read_all(&first), "TOY\na=1\nb=mod1\n");
+
+    factory.unregister_file(&game);
+    factory.try_create_emulated_file(&request).unwrap().unwrap();
+    assert_eq!(BUILDS.with(Cell::get), 2);
+}
+
+#[test]
+fn unmodded_files_are_built_once_per_set_of_inputs() {
+    let root = tempfile::tempdir().unwrap();
+    let [_, mods] = setup(root.path());
+    let other = root.path().join("game/data/other.toy");
+    let other = other.to_str().unwrap();
+    let framework = framework();
+    let factory = Arc::new(BuilderFactory::<ToyBuilder<false>>::new());
+    framework.register(Box::new(factory.clone()));
+
+    // No input could be nested in it.
+    assert!(framework.emulate(other).is_none());
+    assert_eq!(BUILDS.with(Cell::get), 0);
+
+    // `game.toy` could be, but isn't.
+    factory.add_from_folders(&mods).unwrap();
+    assert!(framework.emulate(other).is_none());
+    assert!(framework.emulate(other).is_none());
+    assert_eq!(BUILDS.with(Cell::get), 1);
+
+    let folder = root.path().join("mod2/TOY");
+    factory.add_from_folders(folder.to_str().unwrap()).unwrap();
+    assert!(framework.emulate(other).is_none());
+    assert!(framework.emulate(other).is_none());
+    assert_eq!(BUILDS.with(Cell::get), 2);
+}
+
+/// Inputs of another format, which may be nested in `.toy` files.
+struct OtherInputs(Mutex<Vec<Route>>);
+
+impl IEmulator for OtherInputs {
+    fn try_create_file(&self, _request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        None
+    }
+
+    fn input_routes(&self) -> Vec<Route> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[test]
+fn unmodded_files_are_built_again_when_an_input_is_swapped() {
+    let root = tempfile::tempdir().unwrap();
+    setup(root.path());
+    let other = root.path().join("game/data/other.toy");
+    let other = other.to_str().unwrap();
+    let framework = framework();
+    let factory = Arc::new(BuilderFactory::<ToyBuilder<false>>::new());
+    let inputs = Arc::new(OtherInputs(Mutex::new(vec![Route::new("a.bin")])));
+    framework.register(Box::new(factory.clone()));
+    framework.register(Box::new(inputs.clone()));
+
+    assert!(framework.emulate(other).is_none());
+    assert!(framework.emulate(other).is_none());
+    assert_eq!(BUILDS.with(Cell::get), 1);
+
+    // As many inputs as before, but not the same ones.
+    *inputs.0.lock().unwrap() = vec![Route::new("b.bin")];
+    assert!(framework.emulate(other).is_none());
+    assert_eq!(BUILDS.with(Cell::get), 2);
+}
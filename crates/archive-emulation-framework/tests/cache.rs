@@ -0,0 +1,286 @@
+// Block cache hits, eviction within the budget, pinning, and reads matching the source.
+
+use archive_emulation_framework::{
+    BlockCache, CacheStats, FileSlice, FileSliceStream, MultiStream, OffsetRange, Source,
+    StreamOffsetPair,
+};
+use proptest::prelude::*;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Source counting the reads which reach it.
+struct Counting {
+    data: Vec<u8>,
+    reads: AtomicUsize,
+}
+
+impl Counting {
+    fn new(length: usize) -> Arc<Self> {
+        let data = (0..length).map(|x| (x * 7 % 251) as u8).collect();
+        Arc::new(Self {
+            data,
+            reads: AtomicUsize::new(0),
+        })
+    }
+
+    fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+}
+
+impl Source for Counting {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.data.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+/// Generated stream, reading through to a shared [`Counting`].
+struct Generated(Arc<Counting>);
+
+impl Source for Generated {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+}
+
+fn read(source: &dyn Source, offset: u64, length: usize) -> Vec<u8> {
+    let mut buffer = vec![0; length];
+    source.read_exact_at(offset, &mut buffer).unwrap();
+    buffer
+}
+
+#[test]
+fn repeated_reads_are_hits() {
+    let cache = BlockCache::with_block_size(1024, 16);
+    let inner = Counting::new(100);
+    let source: Arc<dyn Source> = inner.clone();
+    let cached = cache.source(&source);
+
+    assert_eq!(read(&*cached, 4, 20), inner.data[4..24]);
+    assert_eq!(inner.reads(), 2);
+    assert_eq!(read(&*cached, 0, 32), inner.data[..32]);
+    assert_eq!(inner.reads(), 2);
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+    assert_eq!(stats.hit_rate(), 0.5);
+    assert_eq!(stats.cached_bytes, 32);
+
+    // The last block is short, and reads past the end are empty.
+    assert_eq!(read(&*cached, 96, 4), inner.data[96..]);
+    assert_eq!(cached.read_at(100, &mut [0; 8]).unwrap(), 0);
+    assert_eq!(cache.stats().cached_bytes, 36);
+}
+
+#[test]
+fn least_recently_used_blocks_are_evicted_first() {
+    let cache = BlockCache::with_block_size(48, 16);
+    let inner = Counting::new(100);
+    let source: Arc<dyn Source> = inner.clone();
+    let cached = cache.source(&source);
+
+    for block in [0, 1, 2, 0, 3] {
+        read(&*cached, block * 16, 16);
+    }
+
+    // Block 1 went to make room for block 3.
+    assert_eq!(cache.stats().cached_bytes, 48);
+    let reads = inner.reads();
+    read(&*cached, 0, 16);
+    assert_eq!(inner.reads(), reads);
+    read(&*cached, 16, 16);
+    assert_eq!(inner.reads(), reads + 1);
+}
+
+#[test]
+fn sources_streaming_through_evict_their_own_blocks() {
+    let cache = BlockCache::with_block_size(64, 16);
+    let header = Counting::new(100);
+    let streamed = Counting::new(1024);
+    let header_source: Arc<dyn Source> = header.clone();
+    let streamed_source: Arc<dyn Source> = streamed.clone();
+    let header_cached = cache.source(&header_source);
+    let streamed_cached = cache.source(&streamed_source);
+
+    read(&*header_cached, 0, 16);
+    for offset in (0..1024).step_by(16) {
+        read(&*streamed_cached, offset, 16);
+    }
+
+    assert!(cache.stats().cached_bytes <= 64);
+    read(&*header_cached, 0, 16);
+    assert_eq!(header.reads(), 1);
+}
+
+#[test]
+fn pinned_blocks_are_kept() {
+    let cache = BlockCache::with_block_size(32, 16);
+    let inner = Counting::new(208);
+    let source: Arc<dyn Source> = inner.clone();
+    let cached = cache.source(&source);
+    assert!(cached.pin(0, 20).unwrap());
+    assert_eq!(inner.reads(), 2);
+
+    for offset in (32..208).step_by(16) {
+        read(&*cached, offset, 16);
+    }
+
+    let stats = cache.stats();
+    assert_eq!(stats.pinned_bytes, 32);
+    assert_eq!(stats.cached_bytes, 32);
+    let reads = inner.reads();
+    assert_eq!(read(&*cached, 0, 32), inner.data[..32]);
+    assert_eq!(inner.reads(), reads);
+}
+
+#[test]
+fn pins_stay_within_the_budget() {
+    let cache = BlockCache::with_block_size(48, 16);
+    let inner = Counting::new(100);
+    let source: Arc<dyn Source> = inner.clone();
+    let cached = cache.source(&source);
+    assert!(cached.pin(0, 32).unwrap());
+
+    // Only one more block fits; the rest are cached as usual, and evicted.
+    assert!(!cached.pin(32, 48).unwrap());
+    let stats = cache.stats();
+    assert_eq!(stats.pinned_bytes, 48);
+    assert!(stats.cached_bytes <= 48);
+
+    read(&*cached, 64, 36);
+    assert!(cache.stats().cached_bytes <= 48);
+    let reads = inner.reads();
+    assert_eq!(read(&*cached, 0, 48), inner.data[..48]);
+    assert_eq!(inner.reads(), reads);
+
+    // Nor do generated streams go over it.
+    let header = Box::new(Generated(Counting::new(200))) as Box<dyn Source>;
+    let range = OffsetRange::from_start_and_length(0, 200);
+    let file = MultiStream::with_cache(vec![StreamOffsetPair::new(header, range)], &cache);
+    assert_eq!(read(&file.unwrap(), 0, 200).len(), 200);
+    assert!(cache.stats().cached_bytes <= 48);
+}
+
+#[test]
+fn sources_are_shared_and_their_blocks_dropped_with_them() {
+    let cache = BlockCache::with_block_size(1024, 16);
+    let inner = Counting::new(100);
+    let source: Arc<dyn Source> = inner.clone();
+    let first = cache.source(&source);
+    assert!(Arc::ptr_eq(&first, &cache.source(&source)));
+
+    read(&*first, 0, 64);
+    assert!(cache.source(&source).pin(64, 16).unwrap());
+    assert_eq!(cache.stats().cached_bytes, 80);
+    drop(first);
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 0,
+            misses: 4,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn cached_multi_streams_read_the_same_and_still_merge() {
+    let cache = BlockCache::with_block_size(256, 16);
+    let inner = Counting::new(300);
+    let source: Arc<dyn Source> = inner.clone();
+    let pairs = || {
+        let slice = |offset, length| FileSlice::new(offset, length, source.clone());
+        let streams: [(Box<dyn Source>, u64); 3] = [
+            (Box::new(b"header".to_vec()), 6),
+            (Box::new(FileSliceStream::new(slice(10, 100))), 100),
+            (Box::new(FileSliceStream::new(slice(110, 150))), 150),
+        ];
+
+        let mut start = 0;
+        let pairs = streams.into_iter().map(|(stream, length)| {
+            let range = OffsetRange::from_start_and_length(start, length);
+            start = range.end;
+            StreamOffsetPair::new(stream, range)
+        });
+        pairs.collect::<Vec<_>>()
+    };
+
+    let plain = MultiStream::new(pairs()).unwrap();
+    let cached = MultiStream::with_cache(pairs(), &cache).unwrap();
+    assert_eq!(cached.streams().len(), 2);
+    assert_eq!(read(&cached, 0, 256), read(&plain, 0, 256));
+
+    let reads = inner.reads();
+    assert_eq!(read(&cached, 50, 100), read(&plain, 50, 100));
+    assert_eq!(inner.reads(), reads + 1);
+    assert!(cache.stats().hits > 0);
+
+    drop(cached);
+    assert_eq!(cache.stats().cached_bytes, 0);
+}
+
+#[test]
+fn generated_streams_are_pinned() {
+    let cache = BlockCache::with_block_size(32, 16);
+    let header = Counting::new(20);
+    let inner = Counting::new(200);
+    let source: Arc<dyn Source> = inner.clone();
+    let slice = FileSlice::new(0, 200, source);
+    let pairs = vec![
+        StreamOffsetPair::new(
+            Box::new(Generated(header.clone())) as Box<dyn Source>,
+            OffsetRange::from_start_and_length(0, 20),
+        ),
+        StreamOffsetPair::new(
+            Box::new(FileSliceStream::new(slice)) as Box<dyn Source>,
+            OffsetRange::from_start_and_length(20, 200),
+        ),
+    ];
+
+    let file = MultiStream::with_cache(pairs, &cache).unwrap();
+    assert_eq!(header.reads(), 2);
+    assert_eq!(cache.stats().pinned_bytes, 20);
+
+    // Streaming through the slice doesn't push the header out.
+    let all = read(&file, 0, 220);
+    assert_eq!(all[..20], header.data);
+    assert_eq!(all[20..], inner.data);
+    assert_eq!(read(&file, 0, 20), header.data);
+    assert_eq!(header.reads(), 2);
+
+    drop(file);
+    assert_eq!(cache.stats().cached_bytes, 0);
+}
+
+proptest! {
+    #[test]
+    fn reads_match_the_source(
+        length in 1..2000usize,
+        block_size in 1..300u64,
+        budget in 0..2000u64,
+        reads in prop::collection::vec((0..2100u64, 0..400usize), 1..40),
+    ) {
+        let cache = BlockCache::with_block_size(budget, block_size);
+        let inner = Counting::new(length);
+        let source: Arc<dyn Source> = inner.clone();
+        let cached = cache.source(&source);
+        for (offset, count) in reads {
+            let mut expected = vec![0; count];
+            let mut actual = vec![0; count];
+            let read = inner.read_at(offset, &mut expected).unwrap();
+            prop_assert_eq!(cached.read_at(offset, &mut actual).unwrap(), read);
+            prop_assert_eq!(&actual[..read], &expected[..read]);
+            prop_assert!(cache.stats().cached_bytes <= budget);
+        }
+    }
+}
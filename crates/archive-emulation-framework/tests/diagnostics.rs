@@ -0,0 +1,120 @@
+// Collateral damage diagnostics: input routes checked against a game's real files.
+
+use archive_emulation_framework::{
+    diagnose_routes, EmulationFramework, FileSources, IEmulatedFile, IEmulator, OpenRequest, Route,
+    RouteWarning, Source,
+};
+use std::io;
+use std::sync::Arc;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::VirtualFiles;
+
+const GAME_FOLDER: &str = "/games/Sonic";
+
+const FILES: &[&str] = &[
+    "/games/Sonic/data/file.afs",
+    "/games/Sonic/other/file.afs",
+    "/games/Sonic/bgm/BGM.AFS",
+    "/games/Sonic/child.bin",
+    "/home/user/.config/Sonic/child.bin",
+];
+
+fn routes(routes: &[&str]) -> Vec<Route> {
+    routes.iter().map(|x| Route::new(*x)).collect()
+}
+
+#[test]
+fn specific_routes_are_fine() {
+    let routes = routes(&[
+        "data/file.afs",
+        "Sonic/other/file.afs",
+        "BGM.AFS",
+        "bgm/BGM.AFS/0_yahoo!.adx",
+        // Only exists inside an archive.
+        "textures.txd/texture_001.dds",
+    ]);
+    assert_eq!(diagnose_routes(&routes, GAME_FOLDER, FILES), []);
+}
+
+#[test]
+fn route_matching_several_files_suggests_each() {
+    let warnings = diagnose_routes(&routes(&["FILE.afs/5.adx"]), GAME_FOLDER, FILES);
+    let [RouteWarning::Ambiguous {
+        route,
+        files,
+        suggestions,
+    }] = warnings.as_slice()
+    else {
+        panic!("unexpected warnings: {warnings:?}");
+    };
+
+    assert_eq!(route.as_str(), "FILE.afs/5.adx");
+    assert_eq!(files, &FILES[..2]);
+    assert_eq!(
+        suggestions,
+        &routes(&["Sonic/data/file.afs/5.adx", "Sonic/other/file.afs/5.adx"])
+    );
+
+    // The suggestions resolve the ambiguity.
+    assert_eq!(diagnose_routes(suggestions, GAME_FOLDER, FILES), []);
+}
+
+#[test]
+fn route_matching_outside_game_folder() {
+    let warnings = diagnose_routes(&routes(&["child.bin"]), GAME_FOLDER, FILES);
+    // `Sonic/child.bin` would still match the other `Sonic` folder.
+    let expected_suggestions = routes(&["games/Sonic/child.bin"]);
+    assert_eq!(warnings.len(), 2);
+    assert!(matches!(&warnings[0], RouteWarning::Ambiguous { files, .. } if files.len() == 2));
+    assert!(matches!(
+        &warnings[1],
+        RouteWarning::OutsideGameFolder { file, .. } if file == FILES[4]
+    ));
+    assert!(warnings
+        .iter()
+        .all(|x| x.suggestions() == expected_suggestions));
+
+    assert_eq!(
+        diagnose_routes(&expected_suggestions, GAME_FOLDER, FILES),
+        []
+    );
+}
+
+#[test]
+fn framework_checks_routes_of_registered_emulators() {
+    struct Inputs(Vec<Route>);
+
+    impl IEmulator for Inputs {
+        fn try_create_file(&self, _: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+            None
+        }
+
+        fn input_routes(&self) -> Vec<Route> {
+            self.0.clone()
+        }
+    }
+
+    struct NoSources;
+
+    impl FileSources for NoSources {
+        fn open(&self, _: &str) -> io::Result<Arc<dyn Source>> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    let framework = EmulationFramework::new(Arc::new(layer2), Box::new(NoSources));
+    framework.register(Box::new(Inputs(routes(&["file.afs", "BGM.AFS"]))));
+    // The same input in two emulators is reported once.
+    framework.register(Box::new(Inputs(routes(&["FILE.AFS"]))));
+
+    let warnings = framework.diagnose_routes(GAME_FOLDER, FILES);
+    assert_eq!(warnings.len(), 1);
+    // Suggestions are joined with the platform's separator.
+    #[cfg(unix)]
+    assert_eq!(
+        warnings[0].to_string(),
+        "route `file.afs` matches 2 files: `/games/Sonic/data/file.afs`, \
+         `/games/Sonic/other/file.afs`; use `Sonic/data/file.afs`, `Sonic/other/file.afs`"
+    );
+}
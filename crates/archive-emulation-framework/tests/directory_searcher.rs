@@ -0,0 +1,158 @@
+// Listing input folders on disk, single-threaded and in parallel.
+
+use archive_emulation_framework::{DirectorySearcher, FileGroup};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+use virtual_filesystem::path;
+
+fn create(root: &Path, files: &[&str]) {
+    for file in files {
+        let file = root.join(file);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, b"").unwrap();
+    }
+}
+
+fn group(root: &str, directory: &str, files: &[&str]) -> FileGroup {
+    FileGroup {
+        directory: path::join(root, directory),
+        files: files.iter().map(|x| x.to_string()).collect(),
+    }
+}
+
+fn search(folder: &str) -> Vec<FileGroup> {
+    let groups = DirectorySearcher::get_directory_contents_recursive_grouped(folder).unwrap();
+    for threads in [1, 4, 0] {
+        let parallel =
+            DirectorySearcher::get_directory_contents_recursive_grouped_parallel(folder, threads);
+        assert_eq!(parallel.unwrap(), groups, "{threads} threads");
+    }
+    groups
+}
+
+#[test]
+fn groups_are_listed_parents_first_and_sorted() {
+    let root = TempDir::new().unwrap();
+    create(
+        root.path(),
+        &[
+            "b.txt",
+            "a.txt",
+            "AFS/EVENT.AFS/2.adx",
+            "AFS/EVENT.AFS/10.adx",
+            "AFS/BGM.AFS/0.adx",
+            "ONE/x.one/deep/file",
+        ],
+    );
+    fs::create_dir(root.path().join("empty")).unwrap();
+    let folder = root.path().to_str().unwrap();
+
+    assert_eq!(
+        search(folder),
+        [
+            group(folder, "", &["a.txt", "b.txt"]),
+            group(folder, "AFS", &[]),
+            group(folder, "AFS/BGM.AFS", &["0.adx"]),
+            group(folder, "AFS/EVENT.AFS", &["10.adx", "2.adx"]),
+            group(folder, "ONE", &[]),
+            group(folder, "ONE/x.one", &[]),
+            group(folder, "ONE/x.one/deep", &["file"]),
+            group(folder, "empty", &[]),
+        ]
+    );
+
+    let (files, directories) = DirectorySearcher::get_directory_contents_recursive(folder).unwrap();
+    assert_eq!(files.len(), 6);
+    assert_eq!(files[0], path::join(folder, "a.txt"));
+    assert_eq!(directories.len(), 7);
+    assert_eq!(directories[0], path::join(folder, "AFS"));
+}
+
+#[test]
+fn large_directories_are_read_in_full() {
+    // Long names, so the entries take several reads of the buffer.
+    let root = TempDir::new().unwrap();
+    let names: Vec<String> = (0..3000).map(|x| format!("{x:0200}")).collect();
+    for name in &names {
+        fs::write(root.path().join(name), b"").unwrap();
+    }
+
+    let groups = search(root.path().to_str().unwrap());
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].files, names);
+}
+
+#[test]
+fn missing_folders_are_errors() {
+    let root = TempDir::new().unwrap();
+    let missing = root.path().join("missing");
+    let missing = missing.to_str().unwrap();
+    assert!(DirectorySearcher::get_directory_contents_recursive_grouped(missing).is_err());
+    assert!(
+        DirectorySearcher::get_directory_contents_recursive_grouped_parallel(missing, 4).is_err()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_are_followed() {
+    use std::os::unix::fs::symlink;
+
+    let root = TempDir::new().unwrap();
+    let inputs = TempDir::new().unwrap();
+    create(inputs.path(), &["linked.adx"]);
+    create(root.path(), &["AFS/real.adx"]);
+    symlink(inputs.path(), root.path().join("AFS/LINKED.AFS")).unwrap();
+    symlink(
+        root.path().join("AFS/real.adx"),
+        root.path().join("AFS/alias.adx"),
+    )
+    .unwrap();
+    symlink(root.path().join("nowhere"), root.path().join("AFS/broken")).unwrap();
+    let folder = root.path().to_str().unwrap();
+
+    assert_eq!(
+        search(folder),
+        [
+            group(folder, "", &[]),
+            group(folder, "AFS", &["alias.adx", "broken", "real.adx"]),
+            group(folder, "AFS/LINKED.AFS", &["linked.adx"]),
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn links_back_up_the_tree_are_skipped() {
+    use std::os::unix::fs::symlink;
+
+    let root = TempDir::new().unwrap();
+    create(root.path(), &["AFS/a.adx"]);
+    symlink("..", root.path().join("AFS/loop")).unwrap();
+    symlink(".", root.path().join("AFS/self")).unwrap();
+    let folder = root.path().to_str().unwrap();
+
+    assert_eq!(
+        search(folder),
+        [group(folder, "", &[]), group(folder, "AFS", &["a.adx"])]
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn names_which_are_not_utf8_are_skipped() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let root = TempDir::new().unwrap();
+    create(root.path(), &["valid.adx"]);
+    let invalid = root.path().join(OsStr::from_bytes(b"invalid\xFF.adx"));
+    if fs::write(invalid, b"").is_err() {
+        // Some file systems only allow UTF-8 names.
+        return;
+    }
+
+    let folder = root.path().to_str().unwrap();
+    assert_eq!(search(folder), [group(folder, "", &["valid.adx"])]);
+}
@@ -0,0 +1,289 @@
+// Drives the framework as the open hooks would, with original files and Layer 2 in memory.
+
+use archive_emulation_framework::{
+    read_emulated_file, EmulatedFileError, EmulationFramework, IEmulatedFile, IEmulator,
+    MemorySources, OpenRequest, Route, Source,
+};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::Duration;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::VirtualFiles;
+
+fn framework(files: &[(&str, &[u8])]) -> EmulationFramework {
+    let sources = MemorySources::new();
+    for (path, data) in files {
+        sources.insert(path, *data);
+    }
+
+    let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    EmulationFramework::new(Arc::new(layer2), Box::new(sources))
+}
+
+struct MemoryFile(Vec<u8>);
+
+impl IEmulatedFile for MemoryFile {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+/// Claims `.up` files, serving the original uppercased.
+#[derive(Default)]
+struct UpperEmulator {
+    builds: Arc<AtomicUsize>,
+    unregistered: Arc<Mutex<Vec<String>>>,
+    delay: Duration,
+}
+
+impl IEmulator for UpperEmulator {
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        if !request.route.as_str().ends_with(".up") {
+            return None;
+        }
+
+        self.builds.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(self.delay);
+        let mut data = vec![0; request.source.size() as usize];
+        request.source.read_exact_at(0, &mut data).unwrap();
+        Some(Box::new(MemoryFile(data.to_ascii_uppercase())))
+    }
+
+    fn unregister_file(&self, path: &str) {
+        self.unregistered.lock().unwrap().push(path.to_owned());
+    }
+}
+
+/// Claims `.pack` files: a count byte, then one `(offset, length)` byte pair per entry.
+/// Emulates each entry through the framework and serves the results concatenated.
+#[derive(Default)]
+struct PackEmulator {
+    unregistered: Arc<Mutex<Vec<String>>>,
+}
+
+impl IEmulator for PackEmulator {
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        if !request.path.ends_with(".pack") {
+            return None;
+        }
+
+        let mut header = vec![0; request.source.size() as usize];
+        request.source.read_exact_at(0, &mut header).unwrap();
+        let mut content = Vec::new();
+        for (index, entry) in header[1..=header[0] as usize * 2].chunks(2).enumerate() {
+            let route = request.route.merge(&format!("{index}.up"));
+            let (offset, length) = (entry[0] as u64, entry[1] as u64);
+            match request
+                .framework
+                .try_create_from_file_slice(request.path, offset, length, &route)
+            {
+                Ok(file) => content.extend(read_emulated_file(file.as_ref()).unwrap()),
+                Err(_) => content.extend(&header[offset as usize..(offset + length) as usize]),
+            }
+        }
+
+        Some(Box::new(MemoryFile(content)))
+    }
+
+    fn unregister_file(&self, path: &str) {
+        self.unregistered.lock().unwrap().push(path.to_owned());
+    }
+}
+
+#[test]
+fn builds_on_first_open_and_serves_through_layer2() {
+    let framework = framework(&[("game/hello.up", b"hello"), ("game/other.txt", b"other")]);
+    let emulator = UpperEmulator::default();
+    let builds = emulator.builds.clone();
+    framework.register(Box::new(emulator));
+    assert_eq!(builds.load(Ordering::SeqCst), 0);
+
+    // Unclaimed files are left to the OS.
+    assert!(framework.emulate("game/other.txt").is_none());
+    assert!(!framework.is_emulated("game/other.txt"));
+    assert!(framework.emulate("game/missing.up").is_none());
+
+    let file = framework.emulate("game/hello.up").unwrap();
+    assert_eq!(read_emulated_file(file.as_ref()).unwrap(), b"HELLO");
+    let again = framework.emulate("GAME/Hello.up").unwrap();
+    assert!(Arc::ptr_eq(&file, &again));
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+    let layer2 = framework.virtual_file_framework();
+    layer2.open("game/hello.up", 3).unwrap();
+    let mut buffer = [0; 16];
+    assert_eq!(layer2.read(3, &mut buffer).unwrap(), 5);
+    assert_eq!(&buffer[..5], b"HELLO");
+    assert_eq!(layer2.metadata(3).unwrap().size(), 5);
+}
+
+#[test]
+fn open_during_own_build_reaches_original() {
+    struct ReopeningEmulator(Arc<Mutex<Vec<bool>>>);
+
+    impl IEmulator for ReopeningEmulator {
+        fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+            let reopened = request.framework.emulate(request.path);
+            self.0.lock().unwrap().push(reopened.is_some());
+            Some(Box::new(MemoryFile(b"emulated".to_vec())))
+        }
+    }
+
+    let framework = framework(&[("file.bin", b"original")]);
+    let reopened = Arc::new(Mutex::new(Vec::new()));
+    framework.register(Box::new(ReopeningEmulator(reopened.clone())));
+    let file = framework.emulate("file.bin").unwrap();
+    assert_eq!(read_emulated_file(file.as_ref()).unwrap(), b"emulated");
+    assert_eq!(*reopened.lock().unwrap(), [false]);
+}
+
+#[test]
+fn files_unregistered_while_building_are_discarded() {
+    struct UnregisteringEmulator(Arc<Mutex<Vec<String>>>);
+
+    impl IEmulator for UnregisteringEmulator {
+        fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+            request
+                .framework
+                .unregister_virtual_file(request.path)
+                .unwrap();
+            Some(Box::new(MemoryFile(b"emulated".to_vec())))
+        }
+
+        fn unregister_file(&self, path: &str) {
+            self.0.lock().unwrap().push(path.to_owned());
+        }
+    }
+
+    let framework = framework(&[("file.bin", b"original")]);
+    let unregistered = Arc::new(Mutex::new(Vec::new()));
+    framework.register(Box::new(UnregisteringEmulator(unregistered.clone())));
+    assert!(framework.emulate("file.bin").is_none());
+    assert!(!framework.is_emulated("file.bin"));
+    assert!(!framework
+        .virtual_file_framework()
+        .is_virtual_file("file.bin"));
+    assert_eq!(*unregistered.lock().unwrap(), ["file.bin"]);
+}
+
+#[test]
+fn concurrent_first_opens_build_once() {
+    let framework = Arc::new(framework(&[("slow.up", b"slow")]));
+    let emulator = UpperEmulator {
+        delay: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let builds = emulator.builds.clone();
+    framework.register(Box::new(emulator));
+
+    let barrier = Arc::new(Barrier::new(4));
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let framework = framework.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                framework.emulate("slow.up").unwrap()
+            })
+        })
+        .collect();
+
+    let files: Vec<_> = threads.into_iter().map(|x| x.join().unwrap()).collect();
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+    assert!(files.iter().all(|x| Arc::ptr_eq(x, &files[0])));
+}
+
+#[test]
+fn nested_files_are_emulated_and_unloaded_with_parent() {
+    // Entries: "abc" at 5 and "de" at 8.
+    let framework = framework(&[("data/archive.pack", b"\x02\x05\x03\x08\x02abcde")]);
+    let upper = UpperEmulator::default();
+    let upper_unregistered = upper.unregistered.clone();
+    let pack = PackEmulator::default();
+    let pack_unregistered = pack.unregistered.clone();
+    framework.register(Box::new(pack));
+    framework.register(Box::new(upper));
+
+    let file = framework.emulate("data/archive.pack").unwrap();
+    assert_eq!(read_emulated_file(file.as_ref()).unwrap(), b"ABCDE");
+    assert!(framework.is_emulated("data/archive.pack/0.up"));
+    assert!(framework.is_emulated("data/archive.pack/1.up"));
+
+    // Nested files aren't visible to the application.
+    let layer2 = framework.virtual_file_framework();
+    assert!(layer2.is_virtual_file("data/archive.pack"));
+    assert!(!layer2.is_virtual_file("data/archive.pack/0.up"));
+
+    framework
+        .unregister_virtual_file("data/archive.pack")
+        .unwrap();
+    assert!(!framework.is_emulated("data/archive.pack/0.up"));
+    assert!(!layer2.is_virtual_file("data/archive.pack"));
+    assert_eq!(*pack_unregistered.lock().unwrap(), ["data/archive.pack"]);
+    let mut nested = upper_unregistered.lock().unwrap().clone();
+    nested.sort();
+    assert_eq!(nested, ["data/archive.pack/0.up", "data/archive.pack/1.up"]);
+
+    // The next open builds it again.
+    assert!(framework.emulate("data/archive.pack").is_some());
+    assert!(framework.is_emulated("data/archive.pack/1.up"));
+}
+
+#[test]
+fn file_slice_errors() {
+    let framework = framework(&[("data.bin", b"0123456789")]);
+    framework.register(Box::new(UpperEmulator::default()));
+    let route = Route::new("data.bin/inner.up");
+
+    let result = framework.try_create_from_file_slice("missing.bin", 0, 1, &route);
+    assert_eq!(result.err(), Some(EmulatedFileError::CannotOpenFile));
+    let result = framework.try_create_from_file_slice("data.bin", 8, 3, &route);
+    assert_eq!(result.err(), Some(EmulatedFileError::OutOfBounds));
+    let result = framework.try_create_from_file_slice("data.bin", 0, 3, &Route::new("a.txt"));
+    assert_eq!(result.err(), Some(EmulatedFileError::NotClaimed));
+
+    // Slices of files which aren't emulated live until unregistered.
+    let file = framework
+        .try_create_from_file_slice("data.bin", 8, 2, &route)
+        .unwrap();
+    assert_eq!(read_emulated_file(file.as_ref()).unwrap(), b"89");
+    assert!(framework.is_emulated("data.bin/inner.up"));
+    framework
+        .unregister_virtual_file("data.bin/inner.up")
+        .unwrap();
+    assert!(!framework.is_emulated("data.bin/inner.up"));
+}
+
+#[test]
+fn registered_virtual_files_are_served_until_unregistered() {
+    let framework = framework(&[]);
+    let file = Box::new(MemoryFile(b"generated".to_vec()));
+    framework
+        .register_virtual_file("mods/extra.bin", file)
+        .unwrap();
+
+    let duplicate = Box::new(MemoryFile(Vec::new()));
+    let result = framework.register_virtual_file("mods/EXTRA.bin", duplicate);
+    assert_eq!(
+        result.err(),
+        Some(virtual_file_framework::VfsError::AlreadyExists)
+    );
+
+    let file = framework.emulate("mods/extra.bin").unwrap();
+    assert_eq!(read_emulated_file(file.as_ref()).unwrap(), b"generated");
+    assert!(framework
+        .virtual_file_framework()
+        .is_virtual_file("mods/extra.bin"));
+
+    framework.unregister_virtual_file("mods/extra.bin").unwrap();
+    assert!(!framework
+        .virtual_file_framework()
+        .is_virtual_file("mods/extra.bin"));
+    assert!(framework.unregister_virtual_file("mods/extra.bin").is_err());
+}
@@ -0,0 +1,299 @@
+// Emulated files nested inside each other, three levels deep, with inputs only for the
+// innermost and no placeholder inputs for the files around it.
+
+use archive_emulation_framework::{
+    read_emulated_file, read_source, BuilderFactory, EmulationFramework, EmulatorBuilder,
+    FileGroup, FileSlice, FileSliceStream, MemorySources, MultiStream, OffsetRange, OpenRequest,
+    Route, Source, StreamOffsetPair,
+};
+use std::io;
+use std::sync::Arc;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::VirtualFiles;
+
+struct Entry {
+    name: String,
+    offset: u64,
+    length: u64,
+}
+
+/// `.pak` files: `PAK\0`, u32 count, then per entry u32 offset, u32 length and a 16 byte name,
+/// then the data.
+#[derive(Default)]
+struct PakBuilder {
+    inputs: Vec<(String, String)>,
+}
+
+impl EmulatorBuilder for PakBuilder {
+    type Header = Vec<Entry>;
+
+    fn accepts(route: &Route) -> bool {
+        route.as_str().ends_with(".pak")
+    }
+
+    fn parse_header(source: &dyn Source) -> io::Result<Option<Vec<Entry>>> {
+        let data = read_source(source)?;
+        if !data.starts_with(b"PAK\0") {
+            return Ok(None);
+        }
+
+        let count = u32_at(&data, 4) as usize;
+        let entries = data[8..8 + count * 24].chunks(24).map(|x| Entry {
+            name: String::from_utf8_lossy(&x[8..]).trim_end().to_owned(),
+            offset: u32_at(x, 0) as u64,
+            length: u32_at(x, 4) as u64,
+        });
+        Ok(Some(entries.collect()))
+    }
+
+    fn add_file(&mut self, name: &str, path: &str) {
+        self.inputs.push((name.to_owned(), path.to_owned()));
+    }
+
+    fn build(self, header: Vec<Entry>, request: &OpenRequest<'_>) -> io::Result<MultiStream> {
+        let files = entry_sources(header, &self.inputs, request)?;
+        let mut offset = 8 + files.len() * 24;
+        let mut toc = b"PAK\0".to_vec();
+        toc.extend((files.len() as u32).to_le_bytes());
+        for (name, source) in &files {
+            toc.extend((offset as u32).to_le_bytes());
+            toc.extend((source.size() as u32).to_le_bytes());
+            toc.extend(pak_name(name));
+            offset += source.size() as usize;
+        }
+
+        let mut streams: Vec<Box<dyn Source>> = vec![Box::new(toc)];
+        streams.extend(files.into_iter().map(|(_, x)| x));
+        concatenate(streams)
+    }
+}
+
+/// `.bun` files: per entry, a u8 name length, the name, a u32 length and the data.
+#[derive(Default)]
+struct BunBuilder {
+    inputs: Vec<(String, String)>,
+}
+
+impl EmulatorBuilder for BunBuilder {
+    type Header = Vec<Entry>;
+
+    fn accepts(route: &Route) -> bool {
+        route.as_str().ends_with(".bun")
+    }
+
+    fn parse_header(source: &dyn Source) -> io::Result<Option<Vec<Entry>>> {
+        let data = read_source(source)?;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let name_length = data[offset] as usize;
+            let name = &data[offset + 1..offset + 1 + name_length];
+            let start = offset + 1 + name_length + 4;
+            let length = u32_at(&data, start - 4) as usize;
+            entries.push(Entry {
+                name: String::from_utf8(name.to_vec()).unwrap(),
+                offset: start as u64,
+                length: length as u64,
+            });
+            offset = start + length;
+        }
+
+        Ok(Some(entries))
+    }
+
+    fn add_file(&mut self, name: &str, path: &str) {
+        self.inputs.push((name.to_owned(), path.to_owned()));
+    }
+
+    fn build(self, header: Vec<Entry>, request: &OpenRequest<'_>) -> io::Result<MultiStream> {
+        let mut streams: Vec<Box<dyn Source>> = Vec::new();
+        for (name, source) in entry_sources(header, &self.inputs, request)? {
+            let mut entry_header = vec![name.len() as u8];
+            entry_header.extend(name.as_bytes());
+            entry_header.extend((source.size() as u32).to_le_bytes());
+            streams.push(Box::new(entry_header));
+            streams.push(source);
+        }
+
+        concatenate(streams)
+    }
+}
+
+/// Each entry's data: the input replacing it, the emulated file if another emulator claims it,
+/// or the original.
+fn entry_sources(
+    entries: Vec<Entry>,
+    inputs: &[(String, String)],
+    request: &OpenRequest<'_>,
+) -> io::Result<Vec<(String, Box<dyn Source>)>> {
+    let mut sources = Vec::new();
+    for entry in entries {
+        let input = inputs.iter().rev().find(|(name, _)| *name == entry.name);
+        let source: Box<dyn Source> = if let Some((_, path)) = input {
+            let source = request.framework.open_source(path)?;
+            let slice = FileSlice::new(0, source.size(), source);
+            Box::new(FileSliceStream::new(slice))
+        } else {
+            let route = request.route.merge(&entry.name);
+            let framework = request.framework;
+            match framework.try_create_from_file_slice(
+                request.path,
+                entry.offset,
+                entry.length,
+                &route,
+            ) {
+                Ok(file) => Box::new(file),
+                Err(_) => {
+                    let slice = FileSlice::new(entry.offset, entry.length, request.source.clone());
+                    Box::new(FileSliceStream::new(slice))
+                }
+            }
+        };
+
+        sources.push((entry.name, source));
+    }
+
+    Ok(sources)
+}
+
+fn concatenate(streams: Vec<Box<dyn Source>>) -> io::Result<MultiStream> {
+    let mut pairs = Vec::new();
+    let mut offset = 0;
+    for stream in streams {
+        let range = OffsetRange::from_start_and_length(offset, stream.size());
+        offset = range.end;
+        pairs.push(StreamOffsetPair::new(stream, range));
+    }
+
+    MultiStream::new(pairs)
+}
+
+fn pak(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut data = b"PAK\0".to_vec();
+    data.extend((files.len() as u32).to_le_bytes());
+    let mut offset = 8 + files.len() * 24;
+    for (name, content) in files {
+        data.extend((offset as u32).to_le_bytes());
+        data.extend((content.len() as u32).to_le_bytes());
+        data.extend(pak_name(name));
+        offset += content.len();
+    }
+
+    for (_, content) in files {
+        data.extend(*content);
+    }
+    data
+}
+
+fn bun(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (name, content) in files {
+        data.push(name.len() as u8);
+        data.extend(name.as_bytes());
+        data.extend((content.len() as u32).to_le_bytes());
+        data.extend(*content);
+    }
+    data
+}
+
+fn pak_name(name: &str) -> [u8; 16] {
+    let mut bytes = [b' '; 16];
+    bytes[..name.len()].copy_from_slice(name.as_bytes());
+    bytes
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+const OUTER: &str = "/game/outer.pak";
+
+/// `outer.pak` holding `middle.bun`, holding `inner.pak`, holding `leaf.txt`, with `leaf`
+/// being the given data.
+fn game(leaf: &[u8]) -> Vec<u8> {
+    let inner = pak(&[("leaf.txt", leaf), ("other.txt", b"other")]);
+    let middle = bun(&[("a.txt", b"aaa"), ("inner.pak", &inner)]);
+    pak(&[("readme.txt", b"hi"), ("middle.bun", &middle)])
+}
+
+struct Setup {
+    framework: EmulationFramework,
+    sources: MemorySources,
+    paks: Arc<BuilderFactory<PakBuilder>>,
+}
+
+impl Setup {
+    fn new() -> Self {
+        let sources = MemorySources::new();
+        sources.insert(OUTER, game(b"original leaf"));
+
+        let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+        let framework = EmulationFramework::new(Arc::new(layer2), Box::new(sources.clone()));
+        let paks = Arc::new(BuilderFactory::<PakBuilder>::new());
+        framework.register(Box::new(paks.clone()));
+        framework.register(Box::new(BuilderFactory::<BunBuilder>::new()));
+        Self {
+            framework,
+            sources,
+            paks,
+        }
+    }
+
+    fn replace_leaf(&self, data: &[u8]) {
+        let directory = "/mods/a/PAK/inner.pak";
+        let path = format!("{directory}/leaf.txt");
+        self.sources.insert(&path, data);
+
+        let group = FileGroup {
+            directory: directory.to_owned(),
+            files: vec!["leaf.txt".to_owned()],
+        };
+        self.paks.add_group(Route::new("inner.pak"), group);
+    }
+}
+
+#[test]
+fn innermost_inputs_emulate_every_file_around_them() {
+    let setup = Setup::new();
+    setup.replace_leaf(b"replaced leaf");
+
+    let file = setup.framework.emulate(OUTER).unwrap();
+    assert_eq!(
+        read_emulated_file(file.as_ref()).unwrap(),
+        game(b"replaced leaf")
+    );
+
+    let framework = &setup.framework;
+    assert!(framework.is_emulated("/game/outer.pak/middle.bun"));
+    assert!(framework.is_emulated("/game/outer.pak/middle.bun/inner.pak"));
+    assert!(framework.has_nested_files(OUTER));
+
+    // Siblings nobody claims stay as they are.
+    assert!(!framework.is_emulated("/game/outer.pak/readme.txt"));
+    assert!(!framework.is_emulated("/game/outer.pak/middle.bun/inner.pak/other.txt"));
+}
+
+#[test]
+fn files_without_inputs_inside_are_not_emulated() {
+    let setup = Setup::new();
+    assert!(setup.framework.emulate(OUTER).is_none());
+    assert!(!setup.framework.is_emulated("/game/outer.pak/middle.bun"));
+    assert!(!setup.framework.has_nested_files(OUTER));
+}
+
+#[test]
+fn unloading_the_outer_file_unloads_the_chain() {
+    let setup = Setup::new();
+    setup.replace_leaf(b"first");
+    setup.framework.emulate(OUTER).unwrap();
+
+    let innermost = "/game/outer.pak/middle.bun/inner.pak";
+    setup.framework.unregister_virtual_file(OUTER).unwrap();
+    assert!(!setup.framework.is_emulated(innermost));
+
+    // Rebuilt with the newer input on the next open.
+    setup.replace_leaf(b"second");
+    let file = setup.framework.emulate(OUTER).unwrap();
+    assert_eq!(read_emulated_file(file.as_ref()).unwrap(), game(b"second"));
+    assert!(setup.framework.is_emulated(innermost));
+}
@@ -0,0 +1,158 @@
+// OffsetRangeSelector in both search layouts, with and without gaps between ranges.
+
+use archive_emulation_framework::{OffsetRange, OffsetRangeSelector, RangeLookup, SearchLayout};
+use proptest::prelude::*;
+use rstest::rstest;
+
+/// An archive's files aligned to 0x800: a header, then three files with padding between them.
+fn aligned() -> Vec<OffsetRange> {
+    vec![
+        OffsetRange::new(0, 0x20),
+        OffsetRange::new(0x800, 0x900),
+        OffsetRange::new(0x1000, 0x1000),
+        OffsetRange::new(0x1000, 0x1800),
+        OffsetRange::new(0x1800, 0x1801),
+    ]
+}
+
+#[rstest]
+#[case(0, RangeLookup::Range(0))]
+#[case(0x1F, RangeLookup::Range(0))]
+#[case(0x20, RangeLookup::Gap { gap: OffsetRange::new(0x20, 0x800), next: 1 })]
+#[case(0x7FF, RangeLookup::Gap { gap: OffsetRange::new(0x20, 0x800), next: 1 })]
+#[case(0x800, RangeLookup::Range(1))]
+#[case(0x900, RangeLookup::Gap { gap: OffsetRange::new(0x900, 0x1000), next: 3 })]
+#[case(0x1000, RangeLookup::Range(3))]
+#[case(0x1800, RangeLookup::Range(4))]
+#[case(0x1801, RangeLookup::Outside)]
+#[case(u64::MAX, RangeLookup::Outside)]
+fn gaps_are_reported(
+    #[case] offset: u64,
+    #[case] expected: RangeLookup,
+    #[values(SearchLayout::Sorted, SearchLayout::Eytzinger)] layout: SearchLayout,
+) {
+    let selector = OffsetRangeSelector::with_gaps(&aligned()).with_layout(layout);
+    assert_eq!(selector.lookup(offset), expected);
+
+    let index = match expected {
+        RangeLookup::Range(index) => Some(index),
+        _ => None,
+    };
+    assert_eq!(selector.select(offset), index);
+}
+
+/// An empty range inside a gap, which the gap spans as if it weren't there.
+#[rstest]
+#[case(15, RangeLookup::Gap { gap: OffsetRange::new(10, 30), next: 2 })]
+#[case(20, RangeLookup::Gap { gap: OffsetRange::new(10, 30), next: 2 })]
+#[case(25, RangeLookup::Gap { gap: OffsetRange::new(10, 30), next: 2 })]
+#[case(30, RangeLookup::Range(2))]
+#[case(40, RangeLookup::Outside)]
+fn empty_ranges_in_gaps_are_skipped(
+    #[case] offset: u64,
+    #[case] expected: RangeLookup,
+    #[values(SearchLayout::Sorted, SearchLayout::Eytzinger)] layout: SearchLayout,
+) {
+    let ranges = [
+        OffsetRange::new(0, 10),
+        OffsetRange::new(20, 20),
+        OffsetRange::new(30, 40),
+        OffsetRange::new(50, 50),
+    ];
+    let selector = OffsetRangeSelector::with_gaps(&ranges).with_layout(layout);
+    assert_eq!(selector.lookup(offset), expected);
+}
+
+#[rstest]
+fn nothing_before_the_first_range(
+    #[values(SearchLayout::Sorted, SearchLayout::Eytzinger)] layout: SearchLayout,
+) {
+    let selector = OffsetRangeSelector::with_gaps(&[OffsetRange::new(10, 20)]).with_layout(layout);
+    assert_eq!(selector.lookup(9), RangeLookup::Outside);
+    assert_eq!(selector.lookup(10), RangeLookup::Range(0));
+
+    let empty = OffsetRangeSelector::with_gaps(&[]).with_layout(layout);
+    assert_eq!(empty.lookup(0), RangeLookup::Outside);
+
+    let ranges = [OffsetRange::new(5, 5), OffsetRange::new(10, 20)];
+    let selector = OffsetRangeSelector::with_gaps(&ranges).with_layout(layout);
+    assert_eq!(selector.lookup(7), RangeLookup::Outside);
+}
+
+#[test]
+fn layout_is_picked_by_range_count() {
+    let ranges = |count: u64| -> Vec<OffsetRange> {
+        (0..count)
+            .map(|x| OffsetRange::from_start_and_length(x * 16, 16))
+            .collect()
+    };
+
+    let small = OffsetRangeSelector::new(&ranges(16));
+    assert_eq!(small.layout(), SearchLayout::Sorted);
+
+    let large = ranges(SearchLayout::EYTZINGER_THRESHOLD as u64);
+    let large = OffsetRangeSelector::new(&large);
+    assert_eq!(large.layout(), SearchLayout::Eytzinger);
+    assert_eq!(large.select(16 * 1000 + 5), Some(1000));
+}
+
+#[test]
+fn overlaps() {
+    let range = OffsetRange::new(10, 20);
+    assert!(range.overlaps(&OffsetRange::new(19, 30)));
+    assert!(range.overlaps(&OffsetRange::new(0, 11)));
+    assert!(!range.overlaps(&OffsetRange::new(20, 30)));
+    assert!(!range.overlaps(&OffsetRange::new(0, 10)));
+}
+
+/// Sorted ranges with random gaps (possibly none) and lengths (possibly empty).
+fn ranges_with_gaps() -> impl Strategy<Value = Vec<OffsetRange>> {
+    prop::collection::vec((0..64u64, 0..64u64), 0..300).prop_map(|parts| {
+        let mut end = 0;
+        parts
+            .into_iter()
+            .map(|(gap, length)| {
+                let range = OffsetRange::from_start_and_length(end + gap, length);
+                end = range.end;
+                range
+            })
+            .collect()
+    })
+}
+
+/// What `lookup` should return, by checking every range. Gaps run between non-empty ranges.
+fn linear_lookup(ranges: &[OffsetRange], offset: u64) -> RangeLookup {
+    if let Some(index) = ranges.iter().position(|x| x.contains_point(offset)) {
+        return RangeLookup::Range(index);
+    }
+
+    let next = ranges
+        .iter()
+        .position(|x| x.start > offset && x.start < x.end);
+    let previous = ranges
+        .iter()
+        .rposition(|x| x.end <= offset && x.start < x.end);
+    match (previous, next) {
+        (Some(previous), Some(next)) => RangeLookup::Gap {
+            gap: OffsetRange::new(ranges[previous].end, ranges[next].start),
+            next,
+        },
+        _ => RangeLookup::Outside,
+    }
+}
+
+proptest! {
+    #[test]
+    fn layouts_match_linear_search(
+        ranges in ranges_with_gaps(),
+        offsets in prop::collection::vec(0..20_000u64, 32),
+    ) {
+        let sorted = OffsetRangeSelector::with_gaps(&ranges).with_layout(SearchLayout::Sorted);
+        let eytzinger = sorted.clone().with_layout(SearchLayout::Eytzinger);
+        for offset in offsets {
+            let expected = linear_lookup(&ranges, offset);
+            prop_assert_eq!(sorted.lookup(offset), expected);
+            prop_assert_eq!(eytzinger.lookup(offset), expected);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+// Paired emulators building a toy index and data file together.
+
+use archive_emulation_framework::{
+    read_emulated_file, read_source, EmulationFramework, FileSlice, FileSliceStream, IEmulatedFile,
+    IPairedEmulator, MemorySources, MultiStream, OffsetRange, PaddingStream, PairRequest, Source,
+    StreamOffsetPair,
+};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::Duration;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::VirtualFiles;
+
+struct MemoryFile(Vec<u8>);
+
+impl IEmulatedFile for MemoryFile {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read_at(offset, buffer)
+    }
+
+    fn size(&self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+/// Alignment of entries in a `.bin`.
+const ALIGNMENT: u64 = 16;
+
+/// `.dir` files list the entries of the `.bin` next to them, one `name offset length` line
+/// each. Entries in the `.bin` are aligned to [`ALIGNMENT`].
+#[derive(Default)]
+struct DirEmulator {
+    // Entries to replace or add, by the path of the `.dir` without its extension.
+    inputs: HashMap<String, Vec<(String, Vec<u8>)>>,
+    builds: Arc<AtomicUsize>,
+    unregistered: Arc<Mutex<Vec<String>>>,
+    delay: Duration,
+    // Unregisters the `.bin` of the set while building it.
+    unregister_during_build: bool,
+}
+
+struct Entry {
+    name: String,
+    offset: u64,
+    length: u64,
+}
+
+impl IPairedEmulator for DirEmulator {
+    fn paired_paths(&self, path: &str) -> Option<Vec<String>> {
+        let stem = path
+            .strip_suffix(".dir")
+            .or_else(|| path.strip_suffix(".bin"))?;
+        Some(vec![format!("{stem}.dir"), format!("{stem}.bin")])
+    }
+
+    fn try_create_files(&self, request: &PairRequest<'_>) -> Option<Vec<Box<dyn IEmulatedFile>>> {
+        let stem = request.paths[0].strip_suffix(".dir")?;
+        let inputs = self.inputs.get(stem)?;
+        self.builds.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(self.delay);
+        if self.unregister_during_build {
+            let framework = request.framework;
+            framework
+                .unregister_virtual_file(&request.paths[1])
+                .unwrap();
+        }
+
+        let mut files: Vec<(String, Box<dyn Source>)> = Vec::new();
+        for entry in parse_index(&read_source(request.sources[0].as_ref()).ok()?) {
+            let slice = FileSlice::new(entry.offset, entry.length, request.sources[1].clone());
+            files.push((entry.name, Box::new(FileSliceStream::new(slice))));
+        }
+
+        for (name, data) in inputs {
+            let data = Box::new(data.clone());
+            match files.iter_mut().find(|(x, _)| x == name) {
+                Some(file) => file.1 = data,
+                None => files.push((name.clone(), data)),
+            }
+        }
+
+        let mut index = String::new();
+        let mut pairs = Vec::new();
+        let mut offset = 0;
+        for (name, stream) in files {
+            index += &format!("{name} {offset} {}\n", stream.size());
+            let padding = stream.size().next_multiple_of(ALIGNMENT) - stream.size();
+            for stream in [stream, Box::new(PaddingStream::new(0, padding))] {
+                let range = OffsetRange::from_start_and_length(offset, stream.size());
+                offset = range.end;
+                pairs.push(StreamOffsetPair::new(stream, range));
+            }
+        }
+
+        let data = MultiStream::new(pairs).ok()?;
+        Some(vec![
+            Box::new(MemoryFile(index.into_bytes())),
+            Box::new(data),
+        ])
+    }
+
+    fn unregister_file(&self, path: &str) {
+        self.unregistered.lock().unwrap().push(path.to_owned());
+    }
+}
+
+fn parse_index(index: &[u8]) -> Vec<Entry> {
+    let lines = String::from_utf8(index.to_vec()).unwrap();
+    let entries = lines.lines().map(|line| {
+        let [name, offset, length] = line.split(' ').collect::<Vec<_>>().try_into().unwrap();
+        Entry {
+            name: name.to_owned(),
+            offset: offset.parse().unwrap(),
+            length: length.parse().unwrap(),
+        }
+    });
+    entries.collect()
+}
+
+/// Writes a `.dir` and `.bin` pair.
+fn pair(files: &[(&str, &[u8])]) -> (Vec<u8>, Vec<u8>) {
+    let mut index = String::new();
+    let mut data = Vec::new();
+    for (name, content) in files {
+        index += &format!("{name} {} {}\n", data.len(), content.len());
+        data.extend(*content);
+        data.resize(data.len().next_multiple_of(ALIGNMENT as usize), 0xFF);
+    }
+    (index.into_bytes(), data)
+}
+
+/// Reads every entry of the `.bin` through the offsets in the `.dir`.
+fn unpack(index: &[u8], data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let entries = parse_index(index).into_iter().map(|x| {
+        let range = x.offset as usize..(x.offset + x.length) as usize;
+        assert_eq!(x.offset % ALIGNMENT, 0);
+        (x.name, data[range].to_vec())
+    });
+    entries.collect()
+}
+
+const ORIGINAL: &[(&str, &[u8])] = &[("a", b"first"), ("b", &[2; 20]), ("c", b"end")];
+
+fn framework(emulator: DirEmulator) -> EmulationFramework {
+    let (index, data) = pair(ORIGINAL);
+    let sources = MemorySources::new();
+    sources.insert("music.dir", index.clone());
+    sources.insert("music.bin", data);
+    sources.insert("lonely.dir", index);
+
+    let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    let framework = EmulationFramework::new(Arc::new(layer2), Box::new(sources));
+    framework.register_paired(Box::new(emulator));
+    framework
+}
+
+fn emulator() -> DirEmulator {
+    let inputs = vec![
+        ("b".to_owned(), vec![3; 40]),
+        ("d".to_owned(), b"added".to_vec()),
+    ];
+    let mut emulator = DirEmulator::default();
+    emulator.inputs.insert("music".to_owned(), inputs.clone());
+    emulator.inputs.insert("lonely".to_owned(), inputs);
+    emulator
+}
+
+fn expected() -> Vec<(String, Vec<u8>)> {
+    let files: [(&str, &[u8]); 4] = [
+        ("a", b"first"),
+        ("b", &[3; 40]),
+        ("c", b"end"),
+        ("d", b"added"),
+    ];
+    files
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_vec()))
+        .collect()
+}
+
+#[test]
+fn opening_either_file_builds_both() {
+    for (first, second) in [("music.dir", "music.bin"), ("music.bin", "music.dir")] {
+        let emulator = emulator();
+        let builds = emulator.builds.clone();
+        let framework = framework(emulator);
+
+        let file = framework.emulate(first).unwrap();
+        assert!(framework.is_emulated(second));
+        let layer2 = framework.virtual_file_framework();
+        assert!(layer2.is_virtual_file(first) && layer2.is_virtual_file(second));
+
+        let other = framework.emulate(second).unwrap();
+        assert!(Arc::ptr_eq(
+            &other,
+            &framework.emulated_file(second).unwrap()
+        ));
+        assert_eq!(builds.load(Ordering::SeqCst), 1, "{first}");
+
+        // Offsets in the index point at the right data.
+        let [index, data] = match first.ends_with(".dir") {
+            true => [file, other],
+            false => [other, file],
+        };
+        let files = unpack(
+            &read_emulated_file(index.as_ref()).unwrap(),
+            &read_emulated_file(data.as_ref()).unwrap(),
+        );
+        assert_eq!(files, expected(), "{first}");
+    }
+}
+
+#[test]
+fn concurrent_opens_of_both_files_build_once() {
+    let emulator = DirEmulator {
+        delay: Duration::from_millis(100),
+        ..emulator()
+    };
+    let builds = emulator.builds.clone();
+    let framework = Arc::new(framework(emulator));
+
+    let barrier = Arc::new(Barrier::new(4));
+    let threads: Vec<_> = ["music.dir", "music.bin", "music.bin", "music.dir"]
+        .into_iter()
+        .map(|path| {
+            let framework = framework.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                framework.emulate(path).unwrap()
+            })
+        })
+        .collect();
+
+    let files: Vec<_> = threads.into_iter().map(|x| x.join().unwrap()).collect();
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+    assert!(Arc::ptr_eq(&files[0], &files[3]));
+    assert!(Arc::ptr_eq(&files[1], &files[2]));
+    let files = unpack(
+        &read_emulated_file(files[0].as_ref()).unwrap(),
+        &read_emulated_file(files[1].as_ref()).unwrap(),
+    );
+    assert_eq!(files, expected());
+}
+
+#[test]
+fn unloading_either_file_unloads_both() {
+    let emulator = emulator();
+    let builds = emulator.builds.clone();
+    let unregistered = emulator.unregistered.clone();
+    let framework = framework(emulator);
+    framework.emulate("music.dir").unwrap();
+
+    framework.unregister_virtual_file("music.bin").unwrap();
+    assert!(!framework.is_emulated("music.dir"));
+    assert!(!framework
+        .virtual_file_framework()
+        .is_virtual_file("music.dir"));
+    let mut paths = unregistered.lock().unwrap().clone();
+    paths.sort();
+    assert_eq!(paths, ["music.bin", "music.dir"]);
+
+    // The next open builds both again.
+    framework.emulate("music.bin").unwrap();
+    assert!(framework.is_emulated("music.dir"));
+    assert_eq!(builds.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn sets_unregistered_while_building_are_discarded() {
+    let mut emulator = emulator();
+    emulator.unregister_during_build = true;
+    let builds = emulator.builds.clone();
+    let unregistered = emulator.unregistered.clone();
+    let framework = framework(emulator);
+
+    assert!(framework.emulate("music.dir").is_none());
+    assert_eq!(builds.load(Ordering::SeqCst), 1);
+    for path in ["music.dir", "music.bin"] {
+        assert!(!framework.is_emulated(path), "{path}");
+        let layer2 = framework.virtual_file_framework();
+        assert!(!layer2.is_virtual_file(path), "{path}");
+    }
+
+    let mut paths = unregistered.lock().unwrap().clone();
+    paths.sort();
+    assert_eq!(paths, ["music.bin", "music.dir"]);
+}
+
+#[test]
+fn incomplete_sets_are_left_alone() {
+    let emulator = emulator();
+    let builds = emulator.builds.clone();
+    let framework = framework(emulator);
+
+    // `lonely.bin` doesn't exist, and `other.dir` has no inputs.
+    assert!(framework.emulate("lonely.dir").is_none());
+    assert!(!framework.is_emulated("lonely.dir"));
+    assert!(framework.emulate("other.dir").is_none());
+    assert_eq!(builds.load(Ordering::SeqCst), 0);
+
+    // A file of the set emulated on its own isn't rebuilt as part of it.
+    let file = Box::new(MemoryFile(b"standalone".to_vec()));
+    framework.register_virtual_file("music.bin", file).unwrap();
+    assert!(framework.emulate("music.dir").is_none());
+    assert_eq!(builds.load(Ordering::SeqCst), 0);
+}
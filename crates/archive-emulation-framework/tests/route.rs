@@ -0,0 +1,306 @@
+// Route matching, one case per row of the truth tables in `Emulator-Development/Routing.md`.
+// Each table is also checked with full path routes, as the framework passes them.
+
+use archive_emulation_framework::Route;
+use rstest::rstest;
+
+fn no_subfolder(route: &str, group: &str) -> bool {
+    Route::new(route).matches_no_subfolder(&Route::new(group))
+}
+
+fn with_subfolder(route: &str, group: &str) -> bool {
+    Route::new(route).matches_with_subfolder(&Route::new(group))
+}
+
+#[rstest]
+#[case("a.bin", "b.bin", false, "`b.bin` not at end of `a.bin`")]
+#[case("b.bin", "b.bin", true, "Direct match")]
+#[case("folder/a.bin", "a.bin", true, "Matches `a.bin` at end")]
+#[case("folder/a.bin", "b.bin", false, "`b.bin` not at end of `folder/a.bin`")]
+fn standard_routes(
+    #[values("", "/full/path/to/")] prefix: &str,
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+    #[case] description: &str,
+) {
+    let route = format!("{prefix}{route}");
+    assert_eq!(no_subfolder(&route, group), expected, "{description}");
+}
+
+#[rstest]
+#[case(
+    "parent.bin/child.bin",
+    "child.bin",
+    true,
+    "Matches `child.bin` at end"
+)]
+#[case(
+    "parent.bin/child.bin",
+    "parent.bin/child.bin",
+    true,
+    "Matches `parent.bin/child.bin` at end"
+)]
+#[case(
+    "parent.bin/parentSubfolder/child.bin",
+    "parent.bin/child.bin",
+    false,
+    "Not direct descendant of `parent.bin`"
+)]
+#[case(
+    "parent.bin/parentSubfolder/child.bin",
+    "parentSubfolder/child.bin",
+    true,
+    "Matches `child.bin` at end. May match multiple parent folders/archives"
+)]
+fn nested_files_of_same_type(
+    #[values("", "/full/path/to/")] prefix: &str,
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+    #[case] description: &str,
+) {
+    let route = format!("{prefix}{route}");
+    assert_eq!(no_subfolder(&route, group), expected, "{description}");
+}
+
+#[rstest]
+#[case(
+    "parent.bin/child.dds",
+    "child.dds",
+    true,
+    "Matches `child.dds` at end"
+)]
+#[case(
+    "parent.bin/child.dds",
+    "parent.bin/child.dds",
+    true,
+    "Matches `parent.bin/child.dds` at end"
+)]
+#[case(
+    "parent.bin/parentSubfolder/child.dds",
+    "parent.bin/child.dds",
+    false,
+    "Not direct descendant of `parent.bin`"
+)]
+#[case(
+    "parent.bin/parentSubfolder/child.dds",
+    "parentSubfolder/child.dds",
+    true,
+    "Matches `child.dds` at end. May match multiple parent folders/archives"
+)]
+fn nested_files_of_different_type(
+    #[values("", "/full/path/to/")] prefix: &str,
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+    #[case] description: &str,
+) {
+    let route = format!("{prefix}{route}");
+    assert_eq!(no_subfolder(&route, group), expected, "{description}");
+}
+
+#[rstest]
+#[case(
+    "ModBFolder/child.bin",
+    "child.bin",
+    true,
+    "Overrides file `child.bin` in another folder. Potentially undesirable"
+)]
+#[case(
+    "child.bin/.../ModBFolder/...",
+    "child.bin",
+    false,
+    "ModBFolder doesn't end with `child.bin`"
+)]
+fn collateral_damage(
+    #[values("", "/full/path/to/")] prefix: &str,
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+    #[case] description: &str,
+) {
+    let route = format!("{prefix}{route}");
+    assert_eq!(no_subfolder(&route, group), expected, "{description}");
+}
+
+#[rstest]
+#[case(
+    "parent.bin",
+    "parent.bin/child",
+    true,
+    "Matched via `parent.bin` in front"
+)]
+#[case(
+    "parent.bin",
+    "parent.bin/child/child2",
+    true,
+    "Matched via `parent.bin` in front"
+)]
+#[case(
+    "folder/parent.bin",
+    "parent.bin/child",
+    true,
+    "Matched via `parent.bin` in front"
+)]
+#[case(
+    "folder/parent.bin",
+    "parent.bin/child/child2",
+    true,
+    "Matched via `parent.bin` in front"
+)]
+#[case(
+    "folder/parent.bin",
+    "folder/parent.bin/child",
+    true,
+    "Matched via `folder/parent.bin` in front"
+)]
+#[case(
+    "folder/parent.bin",
+    "der/parent.bin/child",
+    true,
+    "Matched via `der/parent.bin` in front"
+)]
+#[case(
+    "folder/parent.bin",
+    "folder/parent.bin/child/child2",
+    true,
+    "Matched via `folder/parent.bin` in front"
+)]
+#[case(
+    "folder/parent.bin",
+    "folder/other/parent.bin/child",
+    false,
+    "`folder/parent.bin` != `folder/other`"
+)]
+#[case(
+    "parent.bin",
+    "parent.bin_suffix/child",
+    false,
+    "`parent.bin` is not a prefix of `parent.bin_suffix`"
+)]
+#[case(
+    "folder/parent.bin",
+    "folder/parent.bin_suffix/child",
+    false,
+    "`folder/parent.bin` is not a prefix of `folder/parent.bin_suffix`"
+)]
+#[case(
+    "parent.bin",
+    "parent.bin/otherFolder/parent.bin/child",
+    true,
+    "Recursive `parent.bin` folders should not throw the logic off"
+)]
+fn handling_subfolders(
+    #[values("", "/full/path/to/")] prefix: &str,
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+    #[case] description: &str,
+) {
+    let route = format!("{prefix}{route}");
+    assert_eq!(with_subfolder(&route, group), expected, "{description}");
+}
+
+#[rstest]
+#[case(
+    "/full/path/to/folder/parent.bin",
+    "full/path/to/folder/parent.bin/child",
+    true,
+    "Matched via `full/path/to/folder/parent.bin` in front"
+)]
+#[case(
+    "/full/path/to/folder/parent.bin",
+    "other/full/path/to/folder/parent.bin/child",
+    false,
+    "Does not match because of `other` folder at front"
+)]
+fn handling_subfolders_with_full_path_groups(
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+    #[case] description: &str,
+) {
+    assert_eq!(with_subfolder(route, group), expected, "{description}");
+}
+
+#[rstest]
+#[case("GameFolder/English/SOUND.AFS", "sound.afs", true)]
+#[case("GameFolder/English/Sound.afs", "ENGLISH/Sound.afs", true)]
+#[case("GameFolder/English//Sound.afs/", "English/Sound.afs", true)]
+#[case("GameFolder/Japanese/Sound.afs", "English/Sound.afs", false)]
+fn matching_ignores_case_and_repeated_separators(
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+) {
+    assert_eq!(no_subfolder(route, group), expected);
+    assert_eq!(with_subfolder(route, group), expected);
+}
+
+#[cfg(windows)]
+#[rstest]
+#[case(r"C:\Game\English\Sound.afs", "English/Sound.afs", true)]
+#[case("C:/Game/English/Sound.afs", r"English\Sound.afs", true)]
+#[case(r"C:\Game\English\Sound.afs", r"Sound.afs\00000.adx", true)]
+fn either_separator_matches_on_windows(
+    #[case] route: &str,
+    #[case] group: &str,
+    #[case] expected: bool,
+) {
+    assert_eq!(with_subfolder(route, group), expected);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn backslash_is_part_of_file_name_elsewhere() {
+    assert!(!no_subfolder(
+        "/game/English/Sound.afs",
+        r"English\Sound.afs"
+    ));
+    assert!(no_subfolder(
+        r"/game/English\Sound.afs",
+        r"English\Sound.afs"
+    ));
+}
+
+#[test]
+fn route_from_folder_and_full_path() {
+    let folder = "/mods/a/FileEmulationFramework/AFS";
+    let route = Route::from_folder_and_full_path(
+        folder,
+        "/mods/a/FileEmulationFramework/AFS/EVENT_ADX_E.AFS/EVENT_MUSIC.AFS",
+    );
+    let route = route.unwrap();
+    assert_eq!(route.as_str(), "EVENT_ADX_E.AFS/EVENT_MUSIC.AFS");
+    assert_eq!(route.as_file_name(), "EVENT_MUSIC.AFS");
+
+    // Folder components compare like routes do.
+    let route =
+        Route::from_folder_and_full_path(folder, "/mods/a/fileemulationframework/afs/BGM.AFS");
+    assert_eq!(route.unwrap().as_str(), "BGM.AFS");
+
+    assert!(Route::from_folder_and_full_path(folder, folder).is_none());
+    assert!(Route::from_folder_and_full_path(folder, "/mods/b/BGM.AFS").is_none());
+}
+
+#[test]
+fn merged_routes_compare_normalised() {
+    let route = Route::new("/game/BGM.AFS").merge("0");
+    assert_eq!(route.as_str(), "/game/BGM.AFS/0");
+    assert_eq!(route.as_file_name(), "0");
+    assert_eq!(route, Route::new("/GAME//bgm.afs/0"));
+}
+
+#[test]
+fn subfolder_match_returns_leftover_components() {
+    let route = Route::new("/game/Parent.bin");
+    let group = Route::new("parent.bin/Child/Grandchild");
+    assert_eq!(route.match_subfolder(&group), Some("Child/Grandchild"));
+    assert_eq!(route.match_subfolder(&Route::new("Parent.bin")), Some(""));
+    assert_eq!(route.match_subfolder(&Route::new("other.bin/Child")), None);
+
+    // The longest match wins.
+    let route = Route::new("/game/a/a");
+    assert_eq!(route.match_subfolder(&Route::new("a/a/b")), Some("b"));
+}
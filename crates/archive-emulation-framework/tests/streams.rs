@@ -0,0 +1,263 @@
+// MultiStream and the streams it is built from, checked against the same bytes concatenated
+// into a plain buffer.
+
+use archive_emulation_framework::{
+    FileSlice, FileSliceStream, MultiStream, OffsetRange, OffsetRangeSelector, PaddingStream,
+    Source, StreamOffsetPair,
+};
+use proptest::prelude::*;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// Returns at most `chunk` bytes per read, like a pipe or a slow disk.
+struct ShortReads {
+    data: Vec<u8>,
+    chunk: usize,
+}
+
+impl Source for ShortReads {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = buffer.len().min(self.chunk);
+        self.data.read_at(offset, &mut buffer[..length])
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Memory(Vec<u8>),
+    Padding(u8, u64),
+    /// Slice of the original, at an offset and length within it.
+    Slice(u64, u64),
+    /// Slice of the original continuing the previous part's slice, so they can be merged.
+    NextSlice(u64),
+}
+
+fn part() -> impl Strategy<Value = Part> {
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..64).prop_map(Part::Memory),
+        (any::<u8>(), 0..64u64).prop_map(|(byte, length)| Part::Padding(byte, length)),
+        (0..ORIGINAL_SIZE, 0..256u64).prop_map(|(offset, length)| Part::Slice(offset, length)),
+        (0..256u64).prop_map(Part::NextSlice),
+    ]
+}
+
+const ORIGINAL_SIZE: u64 = 1024;
+
+/// Builds a MultiStream from `parts`, passed in shuffled order, and the equivalent buffer.
+fn build(parts: &[Part], short_reads: usize, shuffle: u64) -> (MultiStream, Vec<u8>) {
+    let original_data: Vec<u8> = (0..ORIGINAL_SIZE).map(|x| (x * 7 % 251) as u8).collect();
+    let original: Arc<dyn Source> = Arc::new(ShortReads {
+        data: original_data.clone(),
+        chunk: short_reads,
+    });
+
+    let mut expected = Vec::new();
+    let mut pairs: Vec<StreamOffsetPair<Box<dyn Source>>> = Vec::new();
+    let mut slice_end = 0;
+    for part in parts {
+        let stream: Box<dyn Source> = match *part {
+            Part::Memory(ref data) => {
+                expected.extend(data);
+                Box::new(data.clone())
+            }
+            Part::Padding(byte, length) => {
+                expected.extend(std::iter::repeat_n(byte, length as usize));
+                Box::new(PaddingStream::new(byte, length))
+            }
+            Part::Slice(..) | Part::NextSlice(..) => {
+                let (offset, length) = match *part {
+                    Part::Slice(offset, length) => (offset, length),
+                    Part::NextSlice(length) => (slice_end, length),
+                    _ => unreachable!(),
+                };
+                let length = length.min(ORIGINAL_SIZE - offset);
+                slice_end = offset + length;
+                expected.extend(&original_data[offset as usize..slice_end as usize]);
+                let slice = FileSlice::new(offset, length, original.clone());
+                Box::new(FileSliceStream::new(slice))
+            }
+        };
+
+        let start = pairs.last().map_or(0, |x| x.range.end);
+        let range = OffsetRange::from_start_and_length(start, stream.size());
+        pairs.push(StreamOffsetPair::new(stream, range));
+    }
+
+    // Order doesn't matter to MultiStream.
+    let count = pairs.len().max(1);
+    pairs.rotate_left(shuffle as usize % count);
+    (MultiStream::new(pairs).unwrap(), expected)
+}
+
+proptest! {
+    #[test]
+    fn positional_reads_match_buffer(
+        parts in prop::collection::vec(part(), 0..24),
+        short_reads in 1..16usize,
+        shuffle in any::<u64>(),
+        reads in prop::collection::vec((0..4096u64, 0..512usize), 1..16),
+    ) {
+        let (stream, expected) = build(&parts, short_reads, shuffle);
+        prop_assert_eq!(stream.len(), expected.len() as u64);
+
+        for (offset, length) in reads {
+            let offset = offset % (expected.len() as u64 + 8);
+            let mut buffer = vec![0xCD; length];
+            let read = stream.read_at(offset, &mut buffer).unwrap();
+
+            let start = (offset as usize).min(expected.len());
+            let end = (start + length).min(expected.len());
+            prop_assert_eq!(read, end - start);
+            prop_assert_eq!(&buffer[..read], &expected[start..end]);
+        }
+    }
+
+    #[test]
+    fn cursor_reads_match_buffer(
+        parts in prop::collection::vec(part(), 0..24),
+        short_reads in 1..16usize,
+        chunks in prop::collection::vec(1..300usize, 1..8),
+        seek_to in any::<u64>(),
+    ) {
+        let (mut stream, expected) = build(&parts, short_reads, 0);
+
+        let mut all = Vec::new();
+        stream.read_to_end(&mut all).unwrap();
+        prop_assert_eq!(&all, &expected);
+
+        let seek_to = seek_to % (expected.len() as u64 + 1);
+        prop_assert_eq!(stream.seek(SeekFrom::Start(seek_to)).unwrap(), seek_to);
+        let mut position = seek_to as usize;
+        for chunk in chunks {
+            let mut buffer = vec![0; chunk];
+            let read = stream.read(&mut buffer).unwrap();
+            let end = (position + chunk).min(expected.len());
+            prop_assert_eq!(&buffer[..read], &expected[position..end]);
+            position = end;
+        }
+        prop_assert_eq!(stream.stream_position().unwrap(), position as u64);
+    }
+}
+
+fn original(size: u64) -> Arc<dyn Source> {
+    Arc::new((0..size).map(|x| x as u8).collect::<Vec<u8>>())
+}
+
+fn slice_pair(
+    source: &Arc<dyn Source>,
+    offset: u64,
+    length: u64,
+    at: u64,
+) -> StreamOffsetPair<Box<dyn Source>> {
+    let stream = FileSliceStream::new(FileSlice::new(offset, length, source.clone()));
+    StreamOffsetPair::new(
+        Box::new(stream),
+        OffsetRange::from_start_and_length(at, length),
+    )
+}
+
+#[test]
+fn neighbouring_slices_are_merged() {
+    let first = original(100);
+    let second = original(100);
+    let stream = MultiStream::new(vec![
+        slice_pair(&first, 0, 10, 0),
+        slice_pair(&first, 10, 20, 10),
+        slice_pair(&first, 30, 5, 30),
+        // Not adjacent in the source.
+        slice_pair(&first, 50, 5, 35),
+        // Adjacent, but in a different source.
+        slice_pair(&second, 55, 5, 40),
+    ])
+    .unwrap();
+
+    let slices: Vec<(u64, u64)> = stream
+        .streams()
+        .iter()
+        .map(|x| {
+            let slice = x.stream.file_slice().unwrap();
+            (slice.offset(), slice.length())
+        })
+        .collect();
+    assert_eq!(slices, [(0, 35), (50, 5), (55, 5)]);
+    assert_eq!(stream.streams()[0].range, OffsetRange::new(0, 35));
+
+    let mut buffer = [0; 45];
+    assert_eq!(stream.read_at(0, &mut buffer).unwrap(), 45);
+    assert_eq!(buffer[34..37], [34, 50, 51]);
+}
+
+#[test]
+fn gaps_and_overlaps_are_rejected() {
+    let source = original(100);
+    let gap = MultiStream::new(vec![
+        slice_pair(&source, 0, 10, 0),
+        slice_pair(&source, 20, 10, 20),
+    ]);
+    assert_eq!(gap.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+    let overlap = MultiStream::new(vec![
+        slice_pair(&source, 0, 10, 0),
+        slice_pair(&source, 20, 10, 5),
+    ]);
+    assert_eq!(overlap.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+    let late_start = MultiStream::new(vec![slice_pair(&source, 0, 10, 1)]);
+    assert!(late_start.is_err());
+}
+
+#[test]
+fn stream_ending_early_is_an_error_not_a_short_read() {
+    // The slice claims 50 bytes of a 20 byte source.
+    let stream = MultiStream::new(vec![
+        StreamOffsetPair::new(
+            Box::new(b"header".to_vec()) as Box<dyn Source>,
+            OffsetRange::new(0, 6),
+        ),
+        slice_pair(&original(20), 0, 50, 6),
+    ])
+    .unwrap();
+
+    let mut buffer = [0; 32];
+    assert_eq!(
+        stream.read_at(0, &mut buffer).unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+    assert_eq!(stream.read_at(0, &mut buffer[..26]).unwrap(), 26);
+}
+
+#[test]
+fn padding_and_slice_streams_are_cursors() {
+    let mut padding = PaddingStream::new(0xAA, 5);
+    let mut buffer = [0; 8];
+    assert_eq!(padding.read(&mut buffer).unwrap(), 5);
+    assert_eq!(buffer[..5], [0xAA; 5]);
+    assert_eq!(padding.read(&mut buffer).unwrap(), 0);
+
+    let mut slice = FileSliceStream::new(FileSlice::new(10, 20, original(100)));
+    assert_eq!(slice.seek(SeekFrom::End(-2)).unwrap(), 18);
+    assert_eq!(slice.read(&mut buffer).unwrap(), 2);
+    assert_eq!(buffer[..2], [28, 29]);
+    assert!(slice.seek(SeekFrom::Current(-21)).is_err());
+}
+
+#[test]
+fn selector_finds_range() {
+    let selector = OffsetRangeSelector::new(&[
+        OffsetRange::from_start_and_length(0, 1024),
+        OffsetRange::from_start_and_length(1024, 0),
+        OffsetRange::from_start_and_length(1024, 1024),
+        OffsetRange::from_start_and_length(2048, 2048),
+    ]);
+
+    assert_eq!(selector.select(0), Some(0));
+    assert_eq!(selector.select(1023), Some(0));
+    assert_eq!(selector.select(1500), Some(2));
+    assert_eq!(selector.select(4095), Some(3));
+    assert_eq!(selector.select(4096), None);
+    assert_eq!(OffsetRangeSelector::new(&[]).select(0), None);
+}
@@ -0,0 +1,338 @@
+//! Checks emulators against the rules of `Guidelines.md`.
+//!
+//! A [`ConformanceTest`] builds a file with an emulator, from original files and inputs held in
+//! memory, and reads it back the ways an application might:
+//!
+//! - **Generated files are immutable**: the file is built several times, each time with a new
+//!   emulator, and the hashes of the builds must match. The first build is read again at the
+//!   end, and must not have changed.
+//! - **Always return all requested bytes**: every read must return everything requested, up
+//!   to the end of the file, even when the sources the emulator reads from return short reads.
+//! - **Don't assume any read pattern**: reads at random offsets, of random lengths (including
+//!   empty reads, and reads crossing or past the end) must match one full sequential read.
+//! - **No partial results**: when the sources fail, a read must fail too, rather than succeed
+//!   with some of the bytes, or the wrong ones.
+//!
+//! Reads are picked from a seed, so a [`Violation`] can be reproduced by running the test again
+//! with the same seed.
+//!
+//! ```ignore
+//! let report = ConformanceTest::new("/game/voice.afs", move || {
+//!     let emulator = AfsEmulator::new();
+//!     emulator.add_group(Route::new("voice.afs"), inputs.clone());
+//!     Box::new(emulator)
+//! })
+//! .file("/game/voice.afs", original)
+//! .file("/mods/a/FileEmulationFramework/AFS/voice.afs/1.adx", replacement)
+//! .run()?;
+//! ```
+
+mod sources;
+mod violation;
+
+pub use violation::{Build, Violation};
+
+use archive_emulation_framework::{EmulationFramework, IEmulatedFile, IEmulator};
+use sources::{Fault, Faults, InjectedSources, Random};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::path::normalize_path;
+use virtual_filesystem::VirtualFiles;
+
+/// Random reads made of each build, by default.
+pub const DEFAULT_READS: usize = 1000;
+
+/// Longest random read, other than reads to the end of the file.
+const MAX_READ: u64 = 64 * 1024;
+
+/// One emulated file, and how to build it.
+pub struct ConformanceTest {
+    path: String,
+    emulator: Box<dyn Fn() -> Box<dyn IEmulator>>,
+    files: Arc<HashMap<String, Arc<Vec<u8>>>>,
+    seed: u64,
+    reads: usize,
+}
+
+/// What a passing [`ConformanceTest`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// Size of the emulated file.
+    pub size: u64,
+    /// Hash of the emulated file's content, the same for every build.
+    pub hash: u64,
+    /// Random reads checked, over every build.
+    pub reads: usize,
+    /// Short reads the sources returned.
+    pub short_reads: u64,
+    /// Reads of the sources which failed.
+    pub failures: u64,
+}
+
+/// A build of the file, with the framework it was built by.
+struct Built {
+    build: Build,
+    framework: EmulationFramework,
+    file: Arc<dyn IEmulatedFile>,
+    faults: Arc<Faults>,
+}
+
+impl ConformanceTest {
+    /// Tests the file at `path`, built by emulators `emulator` creates. Each build gets a new
+    /// emulator, which should be given its inputs as the mod loader would.
+    pub fn new(
+        path: impl Into<String>,
+        emulator: impl Fn() -> Box<dyn IEmulator> + 'static,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            emulator: Box::new(emulator),
+            files: Arc::default(),
+            seed: 0,
+            reads: DEFAULT_READS,
+        }
+    }
+
+    /// Adds a file the emulator can open: the original file, or an input.
+    pub fn file(mut self, path: &str, data: impl Into<Vec<u8>>) -> Self {
+        let files = Arc::make_mut(&mut self.files);
+        files.insert(normalize_path(path), Arc::new(data.into()));
+        self
+    }
+
+    /// Seed the reads, and the short reads and failures of the sources, are picked with.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Random reads to make of each build. Defaults to [`DEFAULT_READS`].
+    pub fn reads(mut self, reads: usize) -> Self {
+        self.reads = reads;
+        self
+    }
+
+    /// Runs the test, returning the first rule the emulator breaks.
+    pub fn run(&self) -> Result<Report, Violation> {
+        let mut random = Random::new(self.seed);
+        let first = self.build(Build::First, Fault::None)?;
+        let content = read_whole(&first)?;
+        let hash = hash(&content);
+        let mut reads = self.read_randomly(&first, &content, &mut random)?;
+
+        let second = self.build(Build::Second, Fault::None)?;
+        compare(&second, &read_whole(&second)?, &content)?;
+
+        let short = self.build(Build::ShortReads, Fault::ShortReads)?;
+        compare(&short, &read_whole(&short)?, &content)?;
+        reads += self.read_randomly(&short, &content, &mut random)?;
+
+        let failing = self.build(Build::Failures, Fault::None)?;
+        compare(&failing, &read_whole(&failing)?, &content)?;
+        failing.faults.set(Fault::Failures);
+        reads += self.read_failing(&failing, &content, &mut random)?;
+
+        // After all that, the first build, opened again, must still have the same content.
+        let reopened = first.framework.emulate(&self.path);
+        let reopened = reopened.ok_or(Violation::NotEmulated {
+            build: Build::First,
+        })?;
+        let again = read_whole(&Built {
+            file: reopened,
+            ..first
+        })?;
+        if again != content {
+            return Err(Violation::ContentChanged {
+                first_difference: first_difference(&again, &content),
+            });
+        }
+
+        Ok(Report {
+            size: content.len() as u64,
+            hash,
+            reads,
+            short_reads: short.faults.short_reads.load(Ordering::Relaxed),
+            failures: failing.faults.failures.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Builds the file with a new framework and emulator, its sources injecting `fault`.
+    fn build(&self, build: Build, fault: Fault) -> Result<Built, Violation> {
+        let faults = Arc::new(Faults::new(fault, self.seed ^ build as u64));
+        let sources = InjectedSources {
+            files: self.files.clone(),
+            faults: faults.clone(),
+        };
+
+        let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+        let framework = EmulationFramework::new(Arc::new(layer2), Box::new(sources));
+        framework.register((self.emulator)());
+        let file = framework.emulate(&self.path);
+        let file = file.ok_or(Violation::NotEmulated { build })?;
+        Ok(Built {
+            build,
+            framework,
+            file,
+            faults,
+        })
+    }
+
+    /// Makes random reads of `built`, each of which must match `content`.
+    fn read_randomly(
+        &self,
+        built: &Built,
+        content: &[u8],
+        random: &mut Random,
+    ) -> Result<usize, Violation> {
+        let mut buffer = Vec::new();
+        for _ in 0..self.reads {
+            let (offset, length) = pick_read(random, content.len() as u64);
+            buffer.resize(length, 0);
+            let result = built.file.read_at(offset, &mut buffer);
+            let read = result.map_err(|error| Violation::ReadFailed {
+                build: built.build,
+                offset,
+                length,
+                error: error.to_string(),
+            })?;
+
+            check_read(built, content, offset, &buffer, read)?;
+        }
+
+        Ok(self.reads)
+    }
+
+    /// Makes random reads of `built` while its sources fail. Reads may fail, but those which
+    /// succeed must match `content` in full.
+    fn read_failing(
+        &self,
+        built: &Built,
+        content: &[u8],
+        random: &mut Random,
+    ) -> Result<usize, Violation> {
+        let mut buffer = Vec::new();
+        for _ in 0..self.reads {
+            let (offset, length) = pick_read(random, content.len() as u64);
+            buffer.resize(length, 0);
+            let Ok(read) = built.file.read_at(offset, &mut buffer) else {
+                continue;
+            };
+
+            let result = check_read(built, content, offset, &buffer, read);
+            result.map_err(|_| Violation::PartialResult {
+                offset,
+                length,
+                returned: read,
+            })?;
+        }
+
+        Ok(self.reads)
+    }
+}
+
+/// Reads the whole of `built` in one read, which must return all of it.
+fn read_whole(built: &Built) -> Result<Vec<u8>, Violation> {
+    let size = built.file.size();
+    let mut content = vec![0; size as usize];
+    let result = built.file.read_at(0, &mut content);
+    let read = result.map_err(|error| Violation::ReadFailed {
+        build: built.build,
+        offset: 0,
+        length: size as usize,
+        error: error.to_string(),
+    })?;
+
+    if read as u64 != size {
+        return Err(Violation::WrongLength {
+            build: built.build,
+            offset: 0,
+            requested: size as usize,
+            returned: read,
+            expected: size as usize,
+        });
+    }
+
+    Ok(content)
+}
+
+/// Checks `buffer`, which a read at `offset` returned `read` bytes into, against `content`.
+fn check_read(
+    built: &Built,
+    content: &[u8],
+    offset: u64,
+    buffer: &[u8],
+    read: usize,
+) -> Result<(), Violation> {
+    let length = buffer.len();
+    let start = offset.min(content.len() as u64) as usize;
+    let expected = &content[start..start + length.min(content.len() - start)];
+    if read != expected.len() {
+        return Err(Violation::WrongLength {
+            build: built.build,
+            offset,
+            requested: length,
+            returned: read,
+            expected: expected.len(),
+        });
+    }
+
+    match first_difference(&buffer[..read], expected) {
+        Some(difference) => Err(Violation::WrongData {
+            build: built.build,
+            offset,
+            length,
+            first_difference: offset + difference,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks the content of another build against that of the first.
+fn compare(built: &Built, content: &[u8], expected: &[u8]) -> Result<(), Violation> {
+    let (hash, expected_hash) = (hash(content), hash(expected));
+    match hash == expected_hash {
+        true => Ok(()),
+        false => Err(Violation::BuildsDiffer {
+            build: built.build,
+            size: content.len() as u64,
+            expected_size: expected.len() as u64,
+            hash,
+            expected_hash,
+        }),
+    }
+}
+
+/// Picks an offset and length to read, favouring small reads, and the start and end of the
+/// file.
+fn pick_read(random: &mut Random, size: u64) -> (u64, usize) {
+    let offset = match random.below(8) {
+        0 => size + random.below(64),
+        1 => size.saturating_sub(1 + random.below(64)),
+        2 => 0,
+        _ => random.below(size.max(1)),
+    };
+
+    let length = match random.below(8) {
+        0 => 0,
+        1..=4 => 1 + random.below(64),
+        5 | 6 => 1 + random.below(MAX_READ),
+        _ => size.saturating_sub(offset) + random.below(64),
+    };
+
+    (offset, length as usize)
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<u64> {
+    let differing = a.iter().zip(b).position(|(a, b)| a != b);
+    let shorter = (a.len() != b.len()).then(|| a.len().min(b.len()));
+    differing.or(shorter).map(|x| x as u64)
+}
+
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
@@ -0,0 +1,130 @@
+// In-memory original files and inputs, with short reads and failures injected into their reads
+
+use archive_emulation_framework::{FileLength, FileSources, Origin, Source};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use virtual_filesystem::path::normalize_path;
+
+/// What reads of the sources do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Fault {
+    /// Return everything requested.
+    None,
+    /// Return a random amount of what was requested, at least one byte.
+    ShortReads,
+    /// Fail a quarter of reads.
+    Failures,
+}
+
+/// Fault injection shared by every source of one build.
+pub(crate) struct Faults {
+    fault: AtomicU8,
+    random: Mutex<Random>,
+    pub(crate) short_reads: AtomicU64,
+    pub(crate) failures: AtomicU64,
+}
+
+impl Faults {
+    pub(crate) fn new(fault: Fault, seed: u64) -> Self {
+        Self {
+            fault: AtomicU8::new(fault as u8),
+            random: Mutex::new(Random::new(seed)),
+            short_reads: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn set(&self, fault: Fault) {
+        self.fault.store(fault as u8, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> Fault {
+        match self.fault.load(Ordering::Relaxed) {
+            x if x == Fault::ShortReads as u8 => Fault::ShortReads,
+            x if x == Fault::Failures as u8 => Fault::Failures,
+            _ => Fault::None,
+        }
+    }
+}
+
+/// Serves files from memory, through [`Faults`].
+pub(crate) struct InjectedSources {
+    pub(crate) files: Arc<HashMap<String, Arc<Vec<u8>>>>,
+    pub(crate) faults: Arc<Faults>,
+}
+
+impl FileSources for InjectedSources {
+    fn open(&self, path: &str) -> io::Result<Arc<dyn Source>> {
+        match self.files.get(&normalize_path(path)) {
+            Some(data) => Ok(Arc::new(InjectedSource {
+                data: data.clone(),
+                path: path.to_owned(),
+                faults: self.faults.clone(),
+            })),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+struct InjectedSource {
+    data: Arc<Vec<u8>>,
+    path: String,
+    faults: Arc<Faults>,
+}
+
+impl Source for InjectedSource {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let start = offset.min(self.data.len() as u64) as usize;
+        let mut read = buffer.len().min(self.data.len() - start);
+        match self.faults.get() {
+            Fault::None => {}
+            Fault::ShortReads if read > 1 => {
+                read = 1 + self.faults.random.lock().unwrap().below(read as u64) as usize;
+                self.faults.short_reads.fetch_add(1, Ordering::Relaxed);
+            }
+            Fault::ShortReads => {}
+            Fault::Failures => {
+                if self.faults.random.lock().unwrap().below(4) == 0 {
+                    self.faults.failures.fetch_add(1, Ordering::Relaxed);
+                    return Err(io::Error::other("injected failure"));
+                }
+            }
+        }
+
+        buffer[..read].copy_from_slice(&self.data[start..start + read]);
+        Ok(read)
+    }
+
+    fn size(&self) -> FileLength {
+        self.data.len() as u64
+    }
+
+    fn origin(&self) -> Origin<'_> {
+        Origin::File(&self.path)
+    }
+}
+
+/// SplitMix64: reproducible from the seed, and good enough for picking reads.
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut x = self.0;
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^ (x >> 31)
+    }
+
+    /// A number in `0..bound`; `bound` must not be 0.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
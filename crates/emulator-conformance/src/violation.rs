@@ -0,0 +1,142 @@
+// Rules an emulator broke, as found by a conformance test
+
+use std::error::Error;
+use std::fmt;
+
+/// Which build of the file a [`Violation`] was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Build {
+    First,
+    /// Built again, to compare with the first.
+    Second,
+    /// Built and read with sources returning short reads.
+    ShortReads,
+    /// Built normally, then read with sources which fail.
+    Failures,
+}
+
+/// A rule of `Guidelines.md` an emulator broke.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// No emulator claimed the file.
+    NotEmulated { build: Build },
+    /// A build's content differs from the first build's.
+    BuildsDiffer {
+        build: Build,
+        size: u64,
+        expected_size: u64,
+        hash: u64,
+        expected_hash: u64,
+    },
+    /// The first build's content changed after it was read.
+    ContentChanged {
+        /// Offset of the first byte which changed, or the old size if only the size changed.
+        first_difference: Option<u64>,
+    },
+    /// A read returned more or fewer bytes than there are up to the end of the file.
+    WrongLength {
+        build: Build,
+        offset: u64,
+        requested: usize,
+        returned: usize,
+        expected: usize,
+    },
+    /// A read returned bytes which differ from a full sequential read.
+    WrongData {
+        build: Build,
+        offset: u64,
+        length: usize,
+        first_difference: u64,
+    },
+    /// A read failed, though the sources didn't.
+    ReadFailed {
+        build: Build,
+        offset: u64,
+        length: usize,
+        error: String,
+    },
+    /// A read succeeded while the sources failed, with only some of the bytes, or the wrong
+    /// ones.
+    PartialResult {
+        offset: u64,
+        length: usize,
+        returned: usize,
+    },
+}
+
+impl fmt::Display for Build {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Build::First => "first build",
+            Build::Second => "second build",
+            Build::ShortReads => "build with short source reads",
+            Build::Failures => "build with failing source reads",
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::NotEmulated { build } => write!(f, "{build}: file isn't emulated"),
+            Violation::BuildsDiffer {
+                build,
+                size,
+                expected_size,
+                hash,
+                expected_hash,
+            } => write!(
+                f,
+                "{build}: content differs from the first build \
+                 ({size} bytes, hash {hash:#018x}; expected {expected_size} bytes, hash \
+                 {expected_hash:#018x})"
+            ),
+            Violation::ContentChanged { first_difference } => match first_difference {
+                Some(offset) => write!(f, "first build: content changed at {offset:#x}"),
+                None => write!(f, "first build: content changed"),
+            },
+            Violation::WrongLength {
+                build,
+                offset,
+                requested,
+                returned,
+                expected,
+            } => write!(
+                f,
+                "{build}: reading {requested} bytes at {offset:#x} returned {returned}, \
+                 expected {expected}"
+            ),
+            Violation::WrongData {
+                build,
+                offset,
+                length,
+                first_difference,
+            } => write!(
+                f,
+                "{build}: reading {length} bytes at {offset:#x} returned the wrong data, \
+                 from {first_difference:#x}"
+            ),
+            Violation::ReadFailed {
+                build,
+                offset,
+                length,
+                error,
+            } => write!(
+                f,
+                "{build}: reading {length} bytes at {offset:#x} failed: {error}"
+            ),
+            Violation::PartialResult {
+                offset,
+                length,
+                returned,
+            } => write!(
+                f,
+                "{}: reading {length} bytes at {offset:#x} returned {returned} bytes of partial \
+                 or wrong data, instead of failing",
+                Build::Failures
+            ),
+        }
+    }
+}
+
+impl Error for Violation {}
@@ -0,0 +1,295 @@
+// The AFS and ONE emulators checked against the guidelines, and toy emulators breaking each
+// rule, which the checks must catch.
+
+use afs_emulator::test_support::write_archive as afs;
+use afs_emulator::AfsEmulator;
+use archive_emulation_framework::{
+    FileGroup, IEmulatedFile, IEmulator, OpenRequest, Route, Source,
+};
+use emulator_conformance::{Build, ConformanceTest, Violation};
+use one_emulator::test_support::write_archive as one;
+use one_emulator::OneEmulator;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+const READS: usize = 500;
+
+/// Bytes which differ from offset to offset, so misplaced data is noticed.
+fn pattern(length: usize, seed: u8) -> Vec<u8> {
+    (0..length)
+        .map(|x| (x as u8).wrapping_mul(31).wrapping_add(seed) ^ (x >> 8) as u8)
+        .collect()
+}
+
+/// Inputs in a mod's folder for `archive`, with the paths to give the test.
+fn inputs(input_folder: &str, archive: &str, files: &[(&str, Vec<u8>)]) -> FileGroup {
+    let directory = format!("/mods/a/FileEmulationFramework/{input_folder}/{archive}");
+    let files = files.iter().map(|(name, _)| name.to_string()).collect();
+    FileGroup { directory, files }
+}
+
+#[test]
+fn afs_archives_conform() {
+    const ARCHIVE: &str = "/game/Sound/voice.afs";
+    let original = afs(&[&pattern(3000, 1), &pattern(5000, 2), &pattern(100, 3)]);
+    let replaced = [("1.adx", pattern(9000, 4)), ("4.adx", pattern(2500, 5))];
+    let group = inputs("AFS", "voice.afs", &replaced);
+
+    let mut test = ConformanceTest::new(ARCHIVE, {
+        let group = group.clone();
+        move || {
+            let emulator = AfsEmulator::new();
+            emulator.add_group(Route::new("voice.afs"), group.clone());
+            Box::new(emulator)
+        }
+    });
+    test = test.file(ARCHIVE, original.clone());
+    for (path, (_, data)) in group.file_paths().zip(&replaced) {
+        test = test.file(&path, data.clone());
+    }
+
+    let report = test.reads(READS).run().unwrap();
+    assert!(report.size > original.len() as u64);
+    assert_eq!(report.reads, 3 * READS);
+    assert!(report.short_reads > 0);
+    assert!(report.failures > 0);
+}
+
+#[test]
+fn one_archives_conform() {
+    const ARCHIVE: &str = "/game/dvdroot/stg01_light.one";
+    let original = one(&[
+        ("GAME_DISP.TXD", &pattern(4000, 1)),
+        ("STG01.BSP", &pattern(6000, 2)),
+    ]);
+    let replaced = [
+        ("STG01.BSP", pattern(7000, 3)),
+        ("NEW.DFF", pattern(1500, 4)),
+    ];
+    let group = inputs("ONE", "stg01_light.one", &replaced);
+
+    let mut test = ConformanceTest::new(ARCHIVE, {
+        let group = group.clone();
+        move || {
+            let emulator = OneEmulator::new();
+            emulator.add_group(Route::new("stg01_light.one"), group.clone());
+            Box::new(emulator)
+        }
+    });
+    test = test.file(ARCHIVE, original);
+    for (path, (_, data)) in group.file_paths().zip(&replaced) {
+        test = test.file(&path, data.clone());
+    }
+
+    let report = test.reads(READS).run().unwrap();
+    assert!(report.short_reads > 0);
+    assert!(report.failures > 0);
+}
+
+/// A rule a [`ToyEmulator`] breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flaw {
+    None,
+    /// Returns at most 1000 bytes per read.
+    Truncates,
+    /// Returns what one read of the source returns.
+    PassesShortReads,
+    /// Reads the source in pieces, returning the pieces read so far if one fails.
+    LeaksPartialReads,
+    /// Serves a different first byte each build.
+    ChangesPerBuild,
+    /// Serves a different first byte each read.
+    ChangesPerRead,
+}
+
+/// Emulates `.toy` files as their original content, with a flaw.
+struct ToyEmulator {
+    flaw: Flaw,
+    builds: Arc<AtomicU64>,
+}
+
+struct ToyFile {
+    source: Arc<dyn Source>,
+    flaw: Flaw,
+    build: u64,
+    reads: AtomicU64,
+}
+
+impl IEmulator for ToyEmulator {
+    fn try_create_file(&self, request: &OpenRequest<'_>) -> Option<Box<dyn IEmulatedFile>> {
+        if !request.path.ends_with(".toy") {
+            return None;
+        }
+
+        Some(Box::new(ToyFile {
+            source: request.source.clone(),
+            flaw: self.flaw,
+            build: self.builds.fetch_add(1, Ordering::Relaxed),
+            reads: AtomicU64::new(0),
+        }))
+    }
+}
+
+impl IEmulatedFile for ToyFile {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = buffer
+            .len()
+            .min(self.size().saturating_sub(offset) as usize);
+        let buffer = &mut buffer[..length];
+        let read = match self.flaw {
+            Flaw::Truncates => {
+                let length = length.min(1000);
+                self.source.read_exact_at(offset, &mut buffer[..length])?;
+                length
+            }
+            Flaw::PassesShortReads => self.source.read_at(offset, buffer)?,
+            Flaw::LeaksPartialReads => {
+                let mut read = 0;
+                for piece in buffer.chunks_mut(16) {
+                    if self.source.read_exact_at(offset + read, piece).is_err() {
+                        return Ok(read as usize);
+                    }
+                    read += piece.len() as u64;
+                }
+                length
+            }
+            _ => {
+                self.source.read_exact_at(offset, buffer)?;
+                length
+            }
+        };
+
+        let reads = self.reads.fetch_add(1, Ordering::Relaxed);
+        if offset == 0 && read > 0 {
+            match self.flaw {
+                Flaw::ChangesPerBuild => buffer[0] ^= self.build as u8,
+                Flaw::ChangesPerRead => buffer[0] ^= reads as u8,
+                _ => {}
+            }
+        }
+        Ok(read)
+    }
+
+    fn size(&self) -> u64 {
+        self.source.size()
+    }
+}
+
+fn toy(flaw: Flaw) -> ConformanceTest {
+    const FILE: &str = "/game/data.toy";
+    let builds = Arc::new(AtomicU64::new(0));
+    ConformanceTest::new(FILE, move || {
+        let builds = builds.clone();
+        Box::new(ToyEmulator { flaw, builds })
+    })
+    .file(FILE, pattern(10_000, 7))
+    .reads(READS)
+}
+
+#[test]
+fn toys_without_flaws_conform() {
+    let report = toy(Flaw::None).run().unwrap();
+    assert_eq!(report.size, 10_000);
+    assert_eq!(report.reads, 3 * READS);
+}
+
+#[test]
+fn truncated_reads_are_caught() {
+    let violation = toy(Flaw::Truncates).run().unwrap_err();
+    assert_eq!(
+        violation,
+        Violation::WrongLength {
+            build: Build::First,
+            offset: 0,
+            requested: 10_000,
+            returned: 1000,
+            expected: 10_000,
+        }
+    );
+}
+
+#[test]
+fn passing_on_short_source_reads_is_caught() {
+    let violation = toy(Flaw::PassesShortReads).run().unwrap_err();
+    assert!(
+        matches!(
+            violation,
+            Violation::WrongLength {
+                build: Build::ShortReads,
+                ..
+            }
+        ),
+        "{violation}"
+    );
+}
+
+#[test]
+fn partial_results_are_caught() {
+    let violation = toy(Flaw::LeaksPartialReads).run().unwrap_err();
+    assert!(
+        matches!(violation, Violation::PartialResult { returned, length, .. } if returned < length),
+        "{violation}"
+    );
+
+    // Violations are reproducible from the seed.
+    assert_eq!(toy(Flaw::LeaksPartialReads).run().unwrap_err(), violation);
+    let other = toy(Flaw::LeaksPartialReads).seed(1).run().unwrap_err();
+    assert_ne!(other, violation);
+}
+
+#[test]
+fn builds_which_differ_are_caught() {
+    let violation = toy(Flaw::ChangesPerBuild).run().unwrap_err();
+    assert!(
+        matches!(
+            violation,
+            Violation::BuildsDiffer {
+                build: Build::Second,
+                size: 10_000,
+                expected_size: 10_000,
+                ..
+            }
+        ),
+        "{violation}"
+    );
+}
+
+#[test]
+fn content_changing_between_reads_is_caught() {
+    let violation = toy(Flaw::ChangesPerRead).run().unwrap_err();
+    assert!(
+        matches!(
+            violation,
+            Violation::WrongData {
+                build: Build::First,
+                first_difference: 0,
+                ..
+            }
+        ),
+        "{violation}"
+    );
+}
+
+#[test]
+fn files_no_emulator_claims_are_reported() {
+    let builds = Arc::new(AtomicU64::new(0));
+    let test = ConformanceTest::new("/game/data.bin", move || {
+        let builds = builds.clone();
+        Box::new(ToyEmulator {
+            flaw: Flaw::None,
+            builds,
+        })
+    });
+    let violation = test
+        .file("/game/data.bin", vec![1, 2, 3])
+        .run()
+        .unwrap_err();
+    assert_eq!(
+        violation,
+        Violation::NotEmulated {
+            build: Build::First
+        }
+    );
+    assert_eq!(violation.to_string(), "first build: file isn't emulated");
+}
@@ -0,0 +1,90 @@
+// Comparing emulated files with the files they are expected to match
+
+use archive_emulation_framework::IEmulatedFile;
+use std::io::{self, Read};
+
+/// Size of the chunks files are compared in.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How an emulated file differs from the expected one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Comparison {
+    pub size: u64,
+    pub expected_size: u64,
+    /// Offset of the first byte which differs, within the shorter of the two files.
+    pub first_difference: Option<u64>,
+    /// Bytes which differ, within the shorter of the two files.
+    pub differing_bytes: u64,
+}
+
+impl Comparison {
+    pub fn matches(&self) -> bool {
+        self.size == self.expected_size && self.differing_bytes == 0
+    }
+}
+
+/// Compares `file` with `expected`, read to its end.
+pub fn compare(file: &dyn IEmulatedFile, expected: &mut dyn Read) -> io::Result<Comparison> {
+    let mut comparison = Comparison {
+        size: file.size(),
+        ..Default::default()
+    };
+
+    let mut actual = vec![0; CHUNK_SIZE];
+    let mut wanted = vec![0; CHUNK_SIZE];
+    loop {
+        let read = read_up_to(expected, &mut wanted)?;
+        if read == 0 {
+            return Ok(comparison);
+        }
+
+        let offset = comparison.expected_size;
+        comparison.expected_size += read as u64;
+        let length = (read as u64).min(file.size().saturating_sub(offset)) as usize;
+        if length == 0 {
+            continue;
+        }
+
+        read_exact_at(file, offset, &mut actual[..length])?;
+        let pairs = actual[..length].iter().zip(&wanted[..length]);
+        for (index, _) in pairs.enumerate().filter(|(_, (a, b))| a != b) {
+            comparison.differing_bytes += 1;
+            comparison
+                .first_difference
+                .get_or_insert(offset + index as u64);
+        }
+    }
+}
+
+/// Fills as much of `buffer` as `reader` has left.
+fn read_up_to(reader: &mut dyn Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        match reader.read(&mut buffer[total..]) {
+            Ok(0) => break,
+            Ok(read) => total += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(total)
+}
+
+fn read_exact_at(
+    file: &dyn IEmulatedFile,
+    mut offset: u64,
+    mut buffer: &mut [u8],
+) -> io::Result<()> {
+    while !buffer.is_empty() {
+        match file.read_at(offset, buffer)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+// Breaking emulated files down into the ranges each source makes up
+
+use archive_emulation_framework::{FileSlice, IEmulatedFile, MultiStream, OffsetRange, Origin};
+use std::fmt;
+
+/// A range of an emulated file and where its data comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Offsets within the emulated file.
+    pub range: OffsetRange,
+    /// How many [`MultiStream`]s deep the range is; nested emulated files list their own
+    /// segments one level deeper, after a segment for the whole nested file.
+    pub depth: usize,
+    /// Where the data comes from, e.g. `/game/data.afs @ 0x800` or `generated`.
+    pub source: String,
+}
+
+/// Lists the segments of `file`, in order. Files which aren't a [`MultiStream`] are one
+/// segment.
+pub fn layout(file: &dyn IEmulatedFile) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let range = OffsetRange::new(0, file.size());
+    match file.origin() {
+        Origin::Streams(streams) => add_streams(streams, 0, 0, &mut segments),
+        origin => segments.push(Segment {
+            range,
+            depth: 0,
+            source: describe(origin),
+        }),
+    }
+    segments
+}
+
+/// Adds a segment per stream of `streams`, placed at `base` in the emulated file.
+fn add_streams(streams: &MultiStream, base: u64, depth: usize, segments: &mut Vec<Segment>) {
+    for pair in streams.streams() {
+        let range = OffsetRange::new(base + pair.range.start, base + pair.range.end);
+        let origin = pair.stream.origin();
+        segments.push(Segment {
+            range,
+            depth,
+            source: describe(origin),
+        });
+
+        if let Origin::Streams(nested) = origin {
+            add_streams(nested, range.start, depth + 1, segments);
+        }
+    }
+}
+
+fn describe(origin: Origin<'_>) -> String {
+    match origin {
+        Origin::Generated => "generated".to_owned(),
+        Origin::Padding(byte) => format!("padding {byte:#04x}"),
+        Origin::File(path) => format!("{path} @ 0x0"),
+        Origin::Slice(slice) => describe_slice(slice, 0),
+        Origin::Streams(_) => "emulated file".to_owned(),
+    }
+}
+
+/// Describes `slice`, `offset` bytes in, following slices of slices to where the data is.
+fn describe_slice(slice: &FileSlice, offset: u64) -> String {
+    let offset = slice.offset() + offset;
+    match slice.source().origin() {
+        Origin::File(path) => format!("{path} @ {offset:#x}"),
+        Origin::Slice(inner) => describe_slice(inner, offset),
+        Origin::Streams(_) => format!("emulated file @ {offset:#x}"),
+        Origin::Generated => format!("generated @ {offset:#x}"),
+        Origin::Padding(byte) => format!("padding {byte:#04x}"),
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}..{:#010x} {:>10}  {:indent$}{}",
+            self.range.start,
+            self.range.end,
+            self.range.length(),
+            "",
+            self.source,
+            indent = self.depth * 2,
+        )
+    }
+}
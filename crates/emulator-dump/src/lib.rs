@@ -0,0 +1,114 @@
+//! Builds emulated files offline, for debugging emulators without running the game.
+//!
+//! A [`Session`] loads the emulators with the inputs of a set of mod folders, and emulates
+//! files of a game folder as if the game had opened them. The resulting file can be written
+//! out ([`dump`]), compared with the file it is expected to be ([`compare`]), or broken down
+//! into the ranges each source makes up ([`layout`]).
+//!
+//! The `emulator-dump` binary wraps these for the command line.
+
+mod compare;
+mod layout;
+
+pub use compare::{compare, Comparison};
+pub use layout::{layout, Segment};
+
+use afs_emulator::AfsEmulator;
+use archive_emulation_framework::{DiskSources, EmulationFramework, IEmulatedFile};
+use one_emulator::OneEmulator;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use virtual_file_framework::VirtualFileFramework;
+use virtual_filesystem::{path, VirtualFiles};
+
+/// Size of the reads [`dump`] copies the file in.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The emulators, loaded with the inputs of some mods, and a game folder to emulate files of.
+pub struct Session {
+    framework: EmulationFramework,
+    game: String,
+}
+
+impl Session {
+    /// Registers every emulator, adding the inputs in each mod's `FileEmulationFramework`
+    /// folder. Mods are given in load order.
+    pub fn new(game: &str, mods: &[String]) -> io::Result<Self> {
+        let layer2 = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+        let framework = EmulationFramework::new(Arc::new(layer2), Box::new(DiskSources));
+
+        let afs = Arc::new(AfsEmulator::new());
+        let one = Arc::new(OneEmulator::new());
+        framework.register(Box::new(afs.clone()));
+        framework.register(Box::new(one.clone()));
+        for folder in mods {
+            let inputs = path::join(folder, "FileEmulationFramework");
+            let afs_inputs = path::join(&inputs, afs_emulator::INPUT_FOLDER);
+            if Path::new(&afs_inputs).is_dir() {
+                afs.add_from_folders(&afs_inputs)?;
+            }
+
+            let one_inputs = path::join(&inputs, one_emulator::INPUT_FOLDER);
+            if Path::new(&one_inputs).is_dir() {
+                one.add_from_folders(&one_inputs)?;
+            }
+        }
+
+        Ok(Self {
+            framework,
+            game: game.to_owned(),
+        })
+    }
+
+    pub fn framework(&self) -> &EmulationFramework {
+        &self.framework
+    }
+
+    /// Full path of `file`, which may be relative to the game folder.
+    pub fn path(&self, file: &str) -> String {
+        match Path::new(file).is_absolute() {
+            true => file.to_owned(),
+            false => path::join(&self.game, file),
+        }
+    }
+
+    /// Emulates `file` (relative to the game folder, or absolute) as if the game opened it.
+    ///
+    /// Files inside other files, such as `data.afs/voice.afs`, are emulated by opening the
+    /// file on disk containing them. Fails with [`io::ErrorKind::NotFound`] if no emulator
+    /// claims the file, e.g. as no mod has inputs for it.
+    pub fn emulate(&self, file: &str) -> io::Result<Arc<dyn IEmulatedFile>> {
+        let full_path = self.path(file);
+        let on_disk = Path::new(&full_path).ancestors().find(|x| x.is_file());
+        if let Some(on_disk) = on_disk.and_then(Path::to_str) {
+            if let Some(emulated) = self.framework.emulate(on_disk) {
+                if on_disk == full_path {
+                    return Ok(emulated);
+                }
+            }
+        }
+
+        self.framework.emulated_file(&full_path).ok_or_else(|| {
+            let message = format!("{full_path} isn't emulated: no emulator has inputs for it");
+            io::Error::new(io::ErrorKind::NotFound, message)
+        })
+    }
+}
+
+/// Writes the whole of `file` to `output`.
+pub fn dump(file: &dyn IEmulatedFile, output: &mut dyn Write) -> io::Result<()> {
+    let mut buffer = vec![0; CHUNK_SIZE.min(file.size() as usize)];
+    let mut offset = 0;
+    while offset < file.size() {
+        let read = file.read_at(offset, &mut buffer)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        output.write_all(&buffer[..read])?;
+        offset += read as u64;
+    }
+
+    output.flush()
+}
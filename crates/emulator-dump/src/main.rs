@@ -0,0 +1,120 @@
+// Command line front end: builds an emulated file and dumps, verifies or lays it out
+
+use archive_emulation_framework::IEmulatedFile;
+use emulator_dump::{compare, dump, layout, Session};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::process::exit;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: emulator-dump --game <folder> [--mod <folder>]... <command>
+
+commands:
+  dump <file> [<output>]     write the emulated file to <output>, or to stdout
+  verify <file> <expected>   compare the emulated file with <expected>
+  layout <file>              list which byte ranges come from which source
+
+<file> is relative to the game folder, and may be inside another file (e.g. data.afs/voice.afs).
+Mods are given in load order.";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut game = None;
+    let mut mods = Vec::new();
+    let mut command = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--game" => game = Some(value(&mut args)),
+            "--mod" => mods.push(value(&mut args)),
+            "-h" | "--help" => usage(0),
+            _ => command.push(arg),
+        }
+    }
+
+    let Some(game) = game else {
+        usage(2);
+    };
+
+    let session = match Session::new(&game, &mods) {
+        Ok(x) => x,
+        Err(error) => fail(&format!("cannot load mods: {error}")),
+    };
+
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    let file = match command.get(1) {
+        Some(file) => emulate(&session, file),
+        None => usage(2),
+    };
+
+    match command[..] {
+        ["dump", _] => {
+            let result = dump(file.as_ref(), &mut BufWriter::new(io::stdout().lock()));
+            result.unwrap_or_else(|error| fail(&format!("cannot write to stdout: {error}")));
+        }
+        ["dump", _, output] => {
+            let result =
+                File::create(output).and_then(|x| dump(file.as_ref(), &mut BufWriter::new(x)));
+            result.unwrap_or_else(|error| fail(&format!("cannot write {output}: {error}")));
+        }
+        ["verify", _, expected] => {
+            let comparison = File::open(expected).and_then(|mut x| compare(file.as_ref(), &mut x));
+            let comparison = comparison
+                .unwrap_or_else(|error| fail(&format!("cannot compare with {expected}: {error}")));
+            if comparison.matches() {
+                println!("matches {expected} ({} bytes)", comparison.size);
+                return;
+            }
+
+            if comparison.size != comparison.expected_size {
+                println!(
+                    "size is {} bytes, expected {}",
+                    comparison.size, comparison.expected_size
+                );
+            }
+
+            if let Some(offset) = comparison.first_difference {
+                println!(
+                    "{} differing bytes, the first at {offset:#x}",
+                    comparison.differing_bytes
+                );
+                let segments = layout(file.as_ref());
+                let containing = segments.iter().filter(|x| x.range.contains_point(offset));
+                for segment in containing {
+                    println!("  in {segment}");
+                }
+            }
+
+            exit(1);
+        }
+        ["layout", _] => {
+            for segment in layout(file.as_ref()) {
+                println!("{segment}");
+            }
+        }
+        _ => usage(2),
+    }
+}
+
+fn emulate(session: &Session, file: &str) -> Arc<dyn IEmulatedFile> {
+    let emulated = session.emulate(file);
+    for report in session.framework().take_reports() {
+        eprintln!("emulator-dump: {report}");
+    }
+
+    emulated.unwrap_or_else(|error| fail(&error.to_string()))
+}
+
+/// The value following an option, which must be there.
+fn value(args: &mut impl Iterator<Item = String>) -> String {
+    args.next().unwrap_or_else(|| usage(2))
+}
+
+fn usage(code: i32) -> ! {
+    eprintln!("{USAGE}");
+    exit(code)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("emulator-dump: {message}");
+    exit(1)
+}
@@ -0,0 +1,189 @@
+// Emulated AFS archives built from folders on disk, dumped, verified and laid out, through the
+// library and the binary.
+
+use afs_emulator::test_support::write_archive as afs;
+use emulator_dump::{compare, dump, layout, Session};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+use virtual_filesystem::path;
+
+const ARCHIVE: &str = "Sound/voice.afs";
+
+/// Offset and length of each entry in an archive.
+fn entries(archive: &[u8]) -> Vec<(usize, usize)> {
+    let u32_at = |at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap()) as usize;
+    let count = u32_at(4);
+    (0..count)
+        .map(|x| (u32_at(8 + x * 8), u32_at(12 + x * 8)))
+        .collect()
+}
+
+struct Setup {
+    root: TempDir,
+    game: String,
+    mod_folder: String,
+}
+
+impl Setup {
+    /// A game with one archive, and a mod replacing its second file.
+    fn new() -> Self {
+        let root = TempDir::new().unwrap();
+        let game = path::join(root.path().to_str().unwrap(), "game");
+        let mod_folder = path::join(root.path().to_str().unwrap(), "mod");
+
+        let archive = Path::new(&game).join(ARCHIVE);
+        fs::create_dir_all(archive.parent().unwrap()).unwrap();
+        fs::write(&archive, afs(&[b"first", b"second", b"third"])).unwrap();
+
+        let inputs = Path::new(&mod_folder).join("FileEmulationFramework/AFS/voice.afs");
+        fs::create_dir_all(&inputs).unwrap();
+        fs::write(inputs.join("1.adx"), b"replaced").unwrap();
+        Self {
+            root,
+            game,
+            mod_folder,
+        }
+    }
+
+    fn session(&self) -> Session {
+        Session::new(&self.game, std::slice::from_ref(&self.mod_folder)).unwrap()
+    }
+
+    fn input(&self) -> String {
+        path::join(
+            &self.mod_folder,
+            "FileEmulationFramework/AFS/voice.afs/1.adx",
+        )
+    }
+
+    fn dumped(&self) -> Vec<u8> {
+        let file = self.session().emulate(ARCHIVE).unwrap();
+        let mut data = Vec::new();
+        dump(file.as_ref(), &mut data).unwrap();
+        data
+    }
+
+    fn run(&self, command: &[&str]) -> std::process::Output {
+        Command::new(env!("CARGO_BIN_EXE_emulator-dump"))
+            .args(["--game", &self.game, "--mod", &self.mod_folder])
+            .args(command)
+            .output()
+            .unwrap()
+    }
+}
+
+#[test]
+fn dumps_contain_the_inputs() {
+    let setup = Setup::new();
+    let data = setup.dumped();
+    let files: Vec<&[u8]> = entries(&data)
+        .into_iter()
+        .map(|(offset, length)| &data[offset..offset + length])
+        .collect();
+    assert_eq!(files, [&b"first"[..], b"replaced", b"third"]);
+}
+
+#[test]
+fn verifying_finds_the_first_difference() {
+    let setup = Setup::new();
+    let session = setup.session();
+    let file = session.emulate(ARCHIVE).unwrap();
+    let data = setup.dumped();
+    let same = compare(file.as_ref(), &mut &data[..]).unwrap();
+    assert!(same.matches());
+    assert_eq!(same.size, data.len() as u64);
+
+    let (third, _) = entries(&data)[2];
+    let mut changed = data.clone();
+    changed[third + 1] ^= 0xFF;
+    changed[third + 3] ^= 0xFF;
+    let different = compare(file.as_ref(), &mut &changed[..]).unwrap();
+    assert!(!different.matches());
+    assert_eq!(different.first_difference, Some(third as u64 + 1));
+    assert_eq!(different.differing_bytes, 2);
+
+    let shorter = compare(file.as_ref(), &mut &data[..third]).unwrap();
+    assert!(!shorter.matches());
+    assert_eq!(shorter.expected_size, third as u64);
+    assert_eq!(shorter.differing_bytes, 0);
+}
+
+#[test]
+fn layout_shows_where_each_range_comes_from() {
+    let setup = Setup::new();
+    let session = setup.session();
+    let file = session.emulate(ARCHIVE).unwrap();
+    let segments = layout(file.as_ref());
+
+    // The segments cover the whole file, in order.
+    assert_eq!(segments[0].range.start, 0);
+    assert_eq!(segments.last().unwrap().range.end, file.size());
+    for pair in segments.windows(2) {
+        assert_eq!(pair[0].range.end, pair[1].range.start);
+    }
+
+    assert_eq!(segments[0].source, "generated");
+    let archive = session.path(ARCHIVE);
+    let sources: Vec<&str> = segments.iter().map(|x| x.source.as_str()).collect();
+    assert!(sources.contains(&format!("{archive} @ 0x800").as_str()));
+    assert!(sources.contains(&format!("{} @ 0x0", setup.input()).as_str()));
+    assert!(sources.iter().any(|x| x.starts_with("padding")));
+}
+
+#[test]
+fn files_without_inputs_are_not_emulated() {
+    let setup = Setup::new();
+    let other = Path::new(&setup.game).join("Sound/other.afs");
+    fs::write(other, afs(&[b"data"])).unwrap();
+
+    let session = setup.session();
+    let error = session.emulate("Sound/other.afs").err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert!(session.emulate("Sound/missing.afs").is_err());
+    drop(setup.root);
+}
+
+#[test]
+fn binary_dumps_verifies_and_lays_out() {
+    let setup = Setup::new();
+    let data = setup.dumped();
+
+    let output = setup.run(&["dump", ARCHIVE]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, data);
+
+    let dumped = path::join(setup.root.path().to_str().unwrap(), "dumped.afs");
+    assert!(setup.run(&["dump", ARCHIVE, &dumped]).status.success());
+    assert_eq!(fs::read(&dumped).unwrap(), data);
+
+    let output = setup.run(&["verify", ARCHIVE, &dumped]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with("matches"));
+
+    let (third, _) = entries(&data)[2];
+    let mut changed = data.clone();
+    changed[third] ^= 0xFF;
+    fs::write(&dumped, changed).unwrap();
+    let output = setup.run(&["verify", ARCHIVE, &dumped]);
+    assert_eq!(output.status.code(), Some(1));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains(&format!("1 differing bytes, the first at {third:#x}")));
+    let archive = path::join(&setup.game, ARCHIVE);
+    assert!(report.contains(&format!("{archive} @ 0x1800")), "{report}");
+
+    let output = setup.run(&["layout", ARCHIVE]);
+    assert!(output.status.success());
+    let lines = String::from_utf8(output.stdout).unwrap();
+    assert!(lines.contains(&setup.input()));
+
+    assert_eq!(setup.run(&["layout"]).status.code(), Some(2));
+    for option in ["--game", "--mod"] {
+        let output = setup.run(&["layout", ARCHIVE, option]);
+        assert_eq!(output.status.code(), Some(2), "{option}");
+    }
+}
@@ -0,0 +1,98 @@
+// The file API emulators read through, and helpers built on it
+
+use std::io;
+use zerocopy::FromBytes;
+
+/// Raw OS handle value: a `HANDLE` on Windows, a file descriptor on POSIX. Backends not
+/// backed by the OS hand out their own values.
+pub type RawHandle = usize;
+
+/// Where [`FileApi::seek_file`] offsets are counted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekOrigin {
+    Start,
+    Current,
+    End,
+}
+
+/// Opening and reading files by handle, each handle having its own file pointer.
+///
+/// Implemented by [`NativeFiles`](crate::NativeFiles), over the platform's file APIs, and by
+/// [`MemoryFiles`](crate::MemoryFiles) for tests.
+pub trait FileApi: Send + Sync {
+    /// Opens a file for reading.
+    fn open_file(&self, path: &str) -> io::Result<RawHandle>;
+
+    fn close_file(&self, handle: RawHandle) -> io::Result<()>;
+
+    /// Reads into `buffer` from the file pointer, advancing it by the number of bytes read.
+    /// May read less than asked for; 0 at the end of the file.
+    fn read_file(&self, handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Moves the file pointer, returning its new position. Positions past the end are allowed,
+    /// and read nothing.
+    fn seek_file(&self, handle: RawHandle, offset: i64, origin: SeekOrigin) -> io::Result<u64>;
+
+    fn get_file_size(&self, handle: RawHandle) -> io::Result<u64>;
+
+    fn get_file_pointer(&self, handle: RawHandle) -> io::Result<u64> {
+        self.seek_file(handle, 0, SeekOrigin::Current)
+    }
+}
+
+/// Helpers for every [`FileApi`].
+pub trait FileApiExt: FileApi {
+    /// Fills `buffer` from the file pointer, failing with [`io::ErrorKind::UnexpectedEof`] if
+    /// the file ends first.
+    fn read_exact(&self, handle: RawHandle, mut buffer: &mut [u8]) -> io::Result<()> {
+        while !buffer.is_empty() {
+            match self.read_file(handle, buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => buffer = &mut buffer[read..],
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a `T` from the file pointer, advancing it past the `T`.
+    ///
+    /// Multi-byte fields are read in the platform's byte order, unless declared with the
+    /// explicit endian types, e.g. [`u32le`](crate::u32le).
+    fn read_struct<T: FromBytes>(&self, handle: RawHandle) -> io::Result<T> {
+        let mut bytes = vec![0; size_of::<T>()];
+        self.read_exact(handle, &mut bytes)?;
+        Ok(T::read_from_bytes(&bytes).unwrap())
+    }
+}
+
+impl<A: FileApi + ?Sized> FileApiExt for A {}
+
+/// Lets one backend be shared between emulators.
+impl<A: FileApi + ?Sized> FileApi for std::sync::Arc<A> {
+    fn open_file(&self, path: &str) -> io::Result<RawHandle> {
+        (**self).open_file(path)
+    }
+
+    fn close_file(&self, handle: RawHandle) -> io::Result<()> {
+        (**self).close_file(handle)
+    }
+
+    fn read_file(&self, handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize> {
+        (**self).read_file(handle, buffer)
+    }
+
+    fn seek_file(&self, handle: RawHandle, offset: i64, origin: SeekOrigin) -> io::Result<u64> {
+        (**self).seek_file(handle, offset, origin)
+    }
+
+    fn get_file_size(&self, handle: RawHandle) -> io::Result<u64> {
+        (**self).get_file_size(handle)
+    }
+
+    fn get_file_pointer(&self, handle: RawHandle) -> io::Result<u64> {
+        (**self).get_file_pointer(handle)
+    }
+}
@@ -0,0 +1,77 @@
+//! File I/O for emulators.
+//!
+//! A minimal, handle-based API for reading existing file content, mapped to the native calls
+//! of each platform: [`read_file`] to `ReadFile` / `read`, [`seek_file`] to
+//! `SetFilePointerEx` / `lseek`, and so on. Emulator code written against it works the same
+//! on Windows and POSIX systems.
+//!
+//! The free functions use the [`NativeFiles`] backend. Code which takes a [`FileApi`] instead
+//! can also be given [`MemoryFiles`], so emulators can be unit tested without touching disk.
+//!
+//! [`read_struct`] reads any type implementing zerocopy's [`FromBytes`]. Fields declared with
+//! the explicit endian types ([`u32le`], [`u32be`], ...) read the same on every platform.
+
+#![allow(non_camel_case_types)]
+
+mod api;
+mod memory;
+#[cfg(unix)]
+mod posix;
+#[cfg(windows)]
+mod windows;
+
+pub use api::{FileApi, FileApiExt, RawHandle, SeekOrigin};
+pub use memory::MemoryFiles;
+#[cfg(unix)]
+pub use posix::NativeFiles;
+#[cfg(windows)]
+pub use windows::NativeFiles;
+pub use zerocopy::{FromBytes, Immutable, KnownLayout, Unaligned};
+
+use std::io;
+use zerocopy::byteorder::{BigEndian, LittleEndian, I16, I32, I64, U16, U32, U64};
+
+pub type u16le = U16<LittleEndian>;
+pub type u32le = U32<LittleEndian>;
+pub type u64le = U64<LittleEndian>;
+pub type i16le = I16<LittleEndian>;
+pub type i32le = I32<LittleEndian>;
+pub type i64le = I64<LittleEndian>;
+pub type u16be = U16<BigEndian>;
+pub type u32be = U32<BigEndian>;
+pub type u64be = U64<BigEndian>;
+pub type i16be = I16<BigEndian>;
+pub type i32be = I32<BigEndian>;
+pub type i64be = I64<BigEndian>;
+
+/// Opens a file for reading.
+pub fn open_file(path: &str) -> io::Result<RawHandle> {
+    NativeFiles.open_file(path)
+}
+
+pub fn close_file(handle: RawHandle) -> io::Result<()> {
+    NativeFiles.close_file(handle)
+}
+
+/// Reads into `buffer` from the file pointer, advancing it by the number of bytes read.
+pub fn read_file(handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize> {
+    NativeFiles.read_file(handle, buffer)
+}
+
+/// Reads a `T` from the file pointer, advancing it past the `T`.
+pub fn read_struct<T: FromBytes>(handle: RawHandle) -> io::Result<T> {
+    NativeFiles.read_struct(handle)
+}
+
+/// Moves the file pointer, returning its new position.
+pub fn seek_file(handle: RawHandle, offset: i64, origin: SeekOrigin) -> io::Result<u64> {
+    NativeFiles.seek_file(handle, offset, origin)
+}
+
+pub fn get_file_pointer(handle: RawHandle) -> io::Result<u64> {
+    NativeFiles.get_file_pointer(handle)
+}
+
+pub fn get_file_size(handle: RawHandle) -> io::Result<u64> {
+    NativeFiles.get_file_size(handle)
+}
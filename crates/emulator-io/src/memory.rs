@@ -0,0 +1,118 @@
+// In-memory files, for testing emulators without touching disk
+
+use crate::api::{FileApi, RawHandle, SeekOrigin};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Files held in memory, opened by the exact path they were added under.
+///
+/// Handles behave like native ones: each has its own file pointer, seeking past the end is
+/// allowed, and using a closed handle is an error.
+#[derive(Default)]
+pub struct MemoryFiles {
+    files: RwLock<HashMap<String, Arc<[u8]>>>,
+    handles: Mutex<Handles>,
+}
+
+#[derive(Default)]
+struct Handles {
+    open: HashMap<RawHandle, OpenFile>,
+    next: RawHandle,
+}
+
+struct OpenFile {
+    data: Arc<[u8]>,
+    position: u64,
+}
+
+impl MemoryFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds (or replaces) the file at `path`. Handles already open keep the old content.
+    pub fn insert(&self, path: &str, data: impl Into<Arc<[u8]>>) {
+        let mut files = self.files.write().unwrap();
+        files.insert(path.to_owned(), data.into());
+    }
+
+    /// Removes the file at `path`, returning whether it existed.
+    pub fn remove(&self, path: &str) -> bool {
+        self.files.write().unwrap().remove(path).is_some()
+    }
+
+    /// Number of handles not closed yet, e.g. to check an emulator closes what it opens.
+    pub fn open_handles(&self) -> usize {
+        self.handles.lock().unwrap().open.len()
+    }
+
+    /// Runs `action` on the open file behind `handle`.
+    fn with_file<T>(
+        &self,
+        handle: RawHandle,
+        action: impl FnOnce(&mut OpenFile) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut handles = self.handles.lock().unwrap();
+        match handles.open.get_mut(&handle) {
+            Some(file) => action(file),
+            None => Err(invalid_handle()),
+        }
+    }
+}
+
+fn invalid_handle() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "invalid handle")
+}
+
+impl FileApi for MemoryFiles {
+    fn open_file(&self, path: &str) -> io::Result<RawHandle> {
+        let data = match self.files.read().unwrap().get(path) {
+            Some(data) => data.clone(),
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+
+        let mut handles = self.handles.lock().unwrap();
+        // Starts at 1, so 0 is never a valid handle.
+        handles.next += 1;
+        let handle = handles.next;
+        handles.open.insert(handle, OpenFile { data, position: 0 });
+        Ok(handle)
+    }
+
+    fn close_file(&self, handle: RawHandle) -> io::Result<()> {
+        match self.handles.lock().unwrap().open.remove(&handle) {
+            Some(_) => Ok(()),
+            None => Err(invalid_handle()),
+        }
+    }
+
+    fn read_file(&self, handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize> {
+        self.with_file(handle, |file| {
+            let start = file.position.min(file.data.len() as u64) as usize;
+            let count = buffer.len().min(file.data.len() - start);
+            buffer[..count].copy_from_slice(&file.data[start..start + count]);
+            file.position += count as u64;
+            Ok(count)
+        })
+    }
+
+    fn seek_file(&self, handle: RawHandle, offset: i64, origin: SeekOrigin) -> io::Result<u64> {
+        self.with_file(handle, |file| {
+            let base = match origin {
+                SeekOrigin::Start => 0,
+                SeekOrigin::Current => file.position,
+                SeekOrigin::End => file.data.len() as u64,
+            };
+
+            file.position = base.checked_add_signed(offset).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+            })?;
+            Ok(file.position)
+        })
+    }
+
+    fn get_file_size(&self, handle: RawHandle) -> io::Result<u64> {
+        self.with_file(handle, |file| Ok(file.data.len() as u64))
+    }
+}
@@ -0,0 +1,70 @@
+// Native file API on POSIX systems (open, read, lseek, fstat)
+
+use crate::api::{FileApi, RawHandle, SeekOrigin};
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+
+/// Files on disk, through the platform's file APIs. Handles are file descriptors.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeFiles;
+
+/// Turns a `-1` result into the error in `errno`.
+fn check<T: PartialOrd + Default>(result: T) -> io::Result<T> {
+    match result < T::default() {
+        true => Err(io::Error::last_os_error()),
+        false => Ok(result),
+    }
+}
+
+impl FileApi for NativeFiles {
+    fn open_file(&self, path: &str) -> io::Result<RawHandle> {
+        let path = CString::new(path).map_err(|_| io::ErrorKind::InvalidInput)?;
+        // SAFETY: `path` is null terminated.
+        let fd = check(unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) })?;
+        Ok(fd as RawHandle)
+    }
+
+    fn close_file(&self, handle: RawHandle) -> io::Result<()> {
+        // SAFETY: closing a descriptor the caller owns.
+        check(unsafe { libc::close(handle as libc::c_int) })?;
+        Ok(())
+    }
+
+    fn read_file(&self, handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // SAFETY: the kernel writes at most `buffer.len()` bytes into `buffer`.
+            let read = unsafe {
+                libc::read(
+                    handle as libc::c_int,
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+
+            match check(read) {
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result.map(|x| x as usize),
+            }
+        }
+    }
+
+    fn seek_file(&self, handle: RawHandle, offset: i64, origin: SeekOrigin) -> io::Result<u64> {
+        let whence = match origin {
+            SeekOrigin::Start => libc::SEEK_SET,
+            SeekOrigin::Current => libc::SEEK_CUR,
+            SeekOrigin::End => libc::SEEK_END,
+        };
+
+        // SAFETY: only changes the descriptor's position.
+        let position = check(unsafe { libc::lseek(handle as libc::c_int, offset, whence) })?;
+        Ok(position as u64)
+    }
+
+    fn get_file_size(&self, handle: RawHandle) -> io::Result<u64> {
+        let mut stat = MaybeUninit::<libc::stat>::uninit();
+        // SAFETY: `stat` is only read if the call succeeded.
+        check(unsafe { libc::fstat(handle as libc::c_int, stat.as_mut_ptr()) })?;
+        Ok(unsafe { stat.assume_init() }.st_size as u64)
+    }
+}
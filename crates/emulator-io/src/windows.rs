@@ -0,0 +1,114 @@
+// Native file API on Windows (CreateFileW, ReadFile, SetFilePointerEx, GetFileSizeEx)
+
+use crate::api::{FileApi, RawHandle, SeekOrigin};
+use std::ffi::{c_void, OsStr};
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::null_mut;
+
+type Handle = isize;
+
+const GENERIC_READ: u32 = 0x8000_0000;
+const FILE_SHARE_ALL: u32 = 0x1 | 0x2 | 0x4;
+const OPEN_EXISTING: u32 = 3;
+const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+const INVALID_HANDLE_VALUE: Handle = -1;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn CreateFileW(
+        name: *const u16,
+        access: u32,
+        share_mode: u32,
+        security_attributes: *mut c_void,
+        creation_disposition: u32,
+        flags_and_attributes: u32,
+        template: Handle,
+    ) -> Handle;
+    fn CloseHandle(handle: Handle) -> i32;
+    fn ReadFile(
+        handle: Handle,
+        buffer: *mut u8,
+        length: u32,
+        read: *mut u32,
+        overlapped: *mut c_void,
+    ) -> i32;
+    fn SetFilePointerEx(handle: Handle, distance: i64, position: *mut i64, method: u32) -> i32;
+    fn GetFileSizeEx(handle: Handle, size: *mut i64) -> i32;
+}
+
+/// Files on disk, through the platform's file APIs. Handles are `HANDLE`s.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeFiles;
+
+/// Turns a `FALSE` result into the error from `GetLastError`.
+fn check(result: i32) -> io::Result<()> {
+    match result {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+impl FileApi for NativeFiles {
+    fn open_file(&self, path: &str) -> io::Result<RawHandle> {
+        let path: Vec<u16> = OsStr::new(path).encode_wide().chain([0]).collect();
+        // SAFETY: `path` is null terminated.
+        let handle = unsafe {
+            CreateFileW(
+                path.as_ptr(),
+                GENERIC_READ,
+                FILE_SHARE_ALL,
+                null_mut(),
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                0,
+            )
+        };
+
+        match handle {
+            INVALID_HANDLE_VALUE => Err(io::Error::last_os_error()),
+            handle => Ok(handle as RawHandle),
+        }
+    }
+
+    fn close_file(&self, handle: RawHandle) -> io::Result<()> {
+        // SAFETY: closing a handle the caller owns.
+        check(unsafe { CloseHandle(handle as Handle) })
+    }
+
+    fn read_file(&self, handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = buffer.len().min(u32::MAX as usize) as u32;
+        let mut read = 0;
+        // SAFETY: Windows writes at most `length` bytes into `buffer`.
+        check(unsafe {
+            ReadFile(
+                handle as Handle,
+                buffer.as_mut_ptr(),
+                length,
+                &mut read,
+                null_mut(),
+            )
+        })?;
+        Ok(read as usize)
+    }
+
+    fn seek_file(&self, handle: RawHandle, offset: i64, origin: SeekOrigin) -> io::Result<u64> {
+        let method = match origin {
+            SeekOrigin::Start => 0,
+            SeekOrigin::Current => 1,
+            SeekOrigin::End => 2,
+        };
+
+        let mut position = 0;
+        // SAFETY: only changes the handle's position.
+        check(unsafe { SetFilePointerEx(handle as Handle, offset, &mut position, method) })?;
+        Ok(position as u64)
+    }
+
+    fn get_file_size(&self, handle: RawHandle) -> io::Result<u64> {
+        let mut size = 0;
+        // SAFETY: writes the size only.
+        check(unsafe { GetFileSizeEx(handle as Handle, &mut size) })?;
+        Ok(size as u64)
+    }
+}
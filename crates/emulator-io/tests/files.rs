@@ -0,0 +1,158 @@
+// The same reads through the native and in-memory backends, and endian-aware structs.
+
+use emulator_io::{
+    get_file_pointer, get_file_size, open_file, read_file, read_struct, seek_file, u16be, u32be,
+    u32le, FileApi, FileApiExt, FromBytes, Immutable, KnownLayout, MemoryFiles, NativeFiles,
+    SeekOrigin, Unaligned,
+};
+use std::io::{self, Write};
+use tempfile::NamedTempFile;
+
+/// Start of an AFS archive: magic, file count, then the first entry's offset and length.
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned, Debug, PartialEq)]
+#[repr(C)]
+struct AfsHeader {
+    magic: [u8; 4],
+    count: u32le,
+    offset: u32le,
+    length: u32le,
+}
+
+/// The same fields, big endian.
+#[derive(FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct BigHeader {
+    magic: [u8; 4],
+    count: u32be,
+    offset: u32be,
+    length: u32be,
+}
+
+fn archive() -> Vec<u8> {
+    let mut data = b"AFS\0".to_vec();
+    for value in [1u32, 0x800, 3] {
+        data.extend(value.to_le_bytes());
+    }
+    data.resize(0x800, 0);
+    data.extend(b"end");
+    data
+}
+
+/// Reads `archive()` through `files`, as an emulator checking a header would.
+fn read_archive(files: &dyn FileApi, path: &str) {
+    let handle = files.open_file(path).unwrap();
+    assert_eq!(files.get_file_size(handle).unwrap(), 0x803);
+
+    let header: AfsHeader = files.read_struct(handle).unwrap();
+    assert_eq!(&header.magic, b"AFS\0");
+    assert_eq!(
+        (header.count.get(), header.offset.get(), header.length.get()),
+        (1, 0x800, 3)
+    );
+    assert_eq!(files.get_file_pointer(handle).unwrap(), 16);
+
+    // Reset after peeking at the magic, as emulators must.
+    files.seek_file(handle, -16, SeekOrigin::Current).unwrap();
+    assert_eq!(files.read_struct::<u32le>(handle).unwrap().get(), 0x534641);
+
+    let position = files
+        .seek_file(handle, header.offset.get().into(), SeekOrigin::Start)
+        .unwrap();
+    assert_eq!(position, 0x800);
+    let mut buffer = [0; 8];
+    assert_eq!(files.read_file(handle, &mut buffer).unwrap(), 3);
+    assert_eq!(&buffer[..3], b"end");
+    assert_eq!(files.read_file(handle, &mut buffer).unwrap(), 0);
+
+    // Past the end reads nothing, and structs which don't fit fail.
+    assert_eq!(files.seek_file(handle, 5, SeekOrigin::End).unwrap(), 0x808);
+    assert_eq!(files.read_file(handle, &mut buffer).unwrap(), 0);
+    files.seek_file(handle, -2, SeekOrigin::End).unwrap();
+    let error = files.read_struct::<u32le>(handle).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(files.seek_file(handle, -1, SeekOrigin::Start).is_err());
+
+    files.close_file(handle).unwrap();
+}
+
+fn temp_archive() -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(&archive()).unwrap();
+    file
+}
+
+#[test]
+fn backends_read_the_same() {
+    let file = temp_archive();
+    read_archive(&NativeFiles, file.path().to_str().unwrap());
+
+    let files = MemoryFiles::new();
+    files.insert("game/data.afs", archive());
+    read_archive(&files, "game/data.afs");
+    assert_eq!(files.open_handles(), 0);
+}
+
+#[test]
+fn free_functions_use_native_files() {
+    let file = temp_archive();
+    let handle = open_file(file.path().to_str().unwrap()).unwrap();
+    assert_eq!(get_file_size(handle).unwrap(), 0x803);
+    assert_eq!(read_struct::<[u8; 4]>(handle).unwrap(), *b"AFS\0");
+    assert_eq!(seek_file(handle, 4, SeekOrigin::Current).unwrap(), 8);
+    assert_eq!(get_file_pointer(handle).unwrap(), 8);
+    let mut buffer = [0; 4];
+    assert_eq!(read_file(handle, &mut buffer).unwrap(), 4);
+    assert_eq!(u32::from_le_bytes(buffer), 0x800);
+    emulator_io::close_file(handle).unwrap();
+
+    assert!(open_file(&format!("{}.missing", file.path().display())).is_err());
+}
+
+#[test]
+fn byte_order_is_part_of_the_struct() {
+    let files = MemoryFiles::new();
+    let mut data = b"BIG\0".to_vec();
+    for value in [1u32, 0x800, 3] {
+        data.extend(value.to_be_bytes());
+    }
+    files.insert("big", data);
+
+    let handle = files.open_file("big").unwrap();
+    let header: BigHeader = files.read_struct(handle).unwrap();
+    assert_eq!(&header.magic, b"BIG\0");
+    assert_eq!(
+        (header.count.get(), header.offset.get(), header.length.get()),
+        (1, 0x800, 3)
+    );
+
+    files.seek_file(handle, 4, SeekOrigin::Start).unwrap();
+    assert_eq!(files.read_struct::<u16be>(handle).unwrap().get(), 0);
+    assert_eq!(files.read_struct::<u16be>(handle).unwrap().get(), 1);
+}
+
+#[test]
+fn memory_handles_are_independent() {
+    let files = MemoryFiles::new();
+    files.insert("a", &b"abcdef"[..]);
+    let first = files.open_file("a").unwrap();
+    let second = files.open_file("a").unwrap();
+    assert_ne!(first, second);
+
+    files.seek_file(first, 4, SeekOrigin::Start).unwrap();
+    assert_eq!(files.read_struct::<[u8; 2]>(first).unwrap(), *b"ef");
+    assert_eq!(files.read_struct::<[u8; 2]>(second).unwrap(), *b"ab");
+
+    // Open handles keep what they opened.
+    files.insert("a", &b"replaced"[..]);
+    assert!(files.remove("a"));
+    assert_eq!(files.get_file_size(second).unwrap(), 6);
+    assert_eq!(
+        files.open_file("a").unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+
+    files.close_file(first).unwrap();
+    assert!(files.close_file(first).is_err());
+    assert!(files.read_file(first, &mut [0; 1]).is_err());
+    assert_eq!(files.open_handles(), 1);
+}
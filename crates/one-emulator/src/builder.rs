@@ -0,0 +1,224 @@
+// Building emulated ONE archives from the original and the files replacing, adding to or
+// deleting from it
+
+use crate::format::{
+    encode_name, name_bytes, write_section_header, OneHeader, FIRST_NAME_INDEX, MAX_NAMES,
+    NAME_SIZE, SECTION_HEADER_SIZE,
+};
+use crate::prs;
+use archive_emulation_framework::{
+    EmulatorBuilder, FileSlice, FileSliceStream, MultiStream, OffsetRange, OpenRequest, Route,
+    Source, StreamOffsetPair,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+
+/// Suffix of inputs which delete the file they are named after.
+pub const DELETE_SUFFIX: &str = ".DEL";
+
+/// Suffix of inputs which are already PRS compressed, and are stored as they are.
+pub const COMPRESSED_SUFFIX: &str = ".PRS";
+
+/// Builds one emulated ONE archive. See the [crate docs](crate) for how inputs are named.
+#[derive(Debug, Default)]
+pub struct OneBuilder {
+    // What to do with each file, by lowercase name.
+    custom_files: BTreeMap<String, CustomFile>,
+}
+
+#[derive(Debug)]
+struct CustomFile {
+    // Name for files which are added.
+    name: String,
+    action: Action,
+}
+
+#[derive(Debug)]
+enum Action {
+    Replace { path: String, compressed: bool },
+    Delete,
+}
+
+impl EmulatorBuilder for OneBuilder {
+    type Header = OneHeader;
+
+    fn accepts(route: &Route) -> bool {
+        let name = route.as_file_name().as_bytes();
+        name.len() > 4 && name[name.len() - 4..].eq_ignore_ascii_case(b".one")
+    }
+
+    fn parse_header(source: &dyn Source) -> io::Result<Option<OneHeader>> {
+        OneHeader::read(source)
+    }
+
+    fn add_file(&mut self, name: &str, path: &str) {
+        if let Some(name) = strip_suffix(name, DELETE_SUFFIX) {
+            self.insert(name, Action::Delete);
+            return;
+        }
+
+        let compressed = strip_suffix(name, COMPRESSED_SUFFIX);
+        let action = Action::Replace {
+            path: path.to_owned(),
+            compressed: compressed.is_some(),
+        };
+        self.insert(compressed.unwrap_or(name), action);
+    }
+
+    fn build(self, header: OneHeader, request: &OpenRequest<'_>) -> io::Result<MultiStream> {
+        let mut names = header.names.clone();
+        let mut used = HashSet::new();
+        let mut sections = Vec::new();
+        for entry in &header.entries {
+            let name = String::from_utf8_lossy(header.name(entry)).to_ascii_lowercase();
+            match self.custom_files.get(&name).map(|x| &x.action) {
+                Some(Action::Delete) => {}
+                Some(Action::Replace { path, compressed }) => {
+                    let data = open_data(path, *compressed, request)?;
+                    sections.push(Section::Custom(entry.name_index, entry.rw_version, data));
+                }
+                None => {
+                    let slice =
+                        FileSlice::new(entry.offset, entry.total_length(), request.source.clone());
+                    sections.push(Section::Original(slice));
+                }
+            }
+
+            used.insert(name);
+        }
+
+        // Files not in the archive are added, taking the first unused name. Fails if none is left
+        // and the table is at its largest.
+        for (key, file) in &self.custom_files {
+            let Action::Replace { path, compressed } = &file.action else {
+                continue;
+            };
+
+            if used.contains(key) {
+                continue;
+            }
+
+            let free = names
+                .iter()
+                .skip(FIRST_NAME_INDEX as usize)
+                .position(|x| name_bytes(x).is_empty());
+            let name_index = match free {
+                Some(index) => index + FIRST_NAME_INDEX as usize,
+                None if names.len() < MAX_NAMES => {
+                    names.resize(names.len().max(FIRST_NAME_INDEX as usize), [0; NAME_SIZE]);
+                    names.push([0; NAME_SIZE]);
+                    names.len() - 1
+                }
+                None => {
+                    let message = format!("{}: all {MAX_NAMES} names are taken", file.name);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+            };
+
+            names[name_index] = encode_name(&file.name)?;
+            let data = open_data(path, *compressed, request)?;
+            sections.push(Section::Custom(name_index as u32, header.rw_version, data));
+        }
+
+        let names_length = names.len() * NAME_SIZE;
+        let mut start = vec![0; SECTION_HEADER_SIZE as usize * 2 + names_length];
+        write_section_header(
+            &mut start[SECTION_HEADER_SIZE as usize..],
+            1,
+            names_length as u32,
+            header.rw_version,
+        );
+        for (name, bytes) in names
+            .iter()
+            .zip(start[SECTION_HEADER_SIZE as usize * 2..].chunks_exact_mut(NAME_SIZE))
+        {
+            bytes.copy_from_slice(name);
+        }
+
+        let mut layout = Layout {
+            pairs: Vec::new(),
+            offset: start.len() as u64,
+        };
+
+        for section in sections {
+            match section {
+                Section::Original(slice) => layout.push(Box::new(FileSliceStream::new(slice))),
+                Section::Custom(name_index, rw_version, data) => {
+                    let mut section_header = vec![0; SECTION_HEADER_SIZE as usize];
+                    let length = to_u32(data.size())?;
+                    write_section_header(&mut section_header, name_index, length, rw_version);
+                    layout.push(Box::new(section_header));
+                    layout.push(data);
+                }
+            }
+        }
+
+        let length = to_u32(layout.offset - SECTION_HEADER_SIZE)?;
+        write_section_header(&mut start, 0, length, header.rw_version);
+        let range = OffsetRange::from_start_and_length(0, start.len() as u64);
+        layout
+            .pairs
+            .push(StreamOffsetPair::new(Box::new(start), range));
+        MultiStream::new(layout.pairs)
+    }
+}
+
+impl OneBuilder {
+    fn insert(&mut self, name: &str, action: Action) {
+        let file = CustomFile {
+            name: name.to_owned(),
+            action,
+        };
+        self.custom_files.insert(name.to_ascii_lowercase(), file);
+    }
+}
+
+/// A file's section in the emulated archive.
+enum Section {
+    /// Header and data of a file kept from the original.
+    Original(FileSlice),
+    /// Name index, RenderWare version and compressed data of an injected file.
+    Custom(u32, u32, Box<dyn Source>),
+}
+
+/// The streams after the name section, as they are laid out.
+struct Layout {
+    pairs: Vec<StreamOffsetPair<Box<dyn Source>>>,
+    offset: u64,
+}
+
+impl Layout {
+    fn push(&mut self, stream: Box<dyn Source>) {
+        let range = OffsetRange::from_start_and_length(self.offset, stream.size());
+        self.offset = range.end;
+        self.pairs.push(StreamOffsetPair::new(stream, range));
+    }
+}
+
+/// The compressed data of an input, compressing it if it isn't already.
+fn open_data(
+    path: &str,
+    compressed: bool,
+    request: &OpenRequest<'_>,
+) -> io::Result<Box<dyn Source>> {
+    let source = request.framework.open_source(path)?;
+    if compressed {
+        let slice = FileSlice::new(0, source.size(), source);
+        return Ok(Box::new(FileSliceStream::new(slice)));
+    }
+
+    let mut data = vec![0; source.size() as usize];
+    source.read_exact_at(0, &mut data)?;
+    Ok(Box::new(prs::compress(&data)))
+}
+
+fn strip_suffix<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(suffix.len())?;
+    let matches = name.is_char_boundary(split) && name[split..].eq_ignore_ascii_case(suffix);
+    (matches && split > 0).then(|| &name[..split])
+}
+
+fn to_u32(length: u64) -> io::Result<u32> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidData, "archive exceeds 4 GiB");
+    length.try_into().map_err(|_| too_large())
+}
@@ -0,0 +1,151 @@
+// Sonic Heroes ONE archive layout
+//
+// All little endian, each section starting with a u32 type, u32 length of what follows and a
+// u32 RenderWare version:
+//   archive:   type 0, length of the rest of the file
+//   names:     type 1, then 64 byte names padded with zeroes; the first 2 are unused
+//   per file:  type is the index of the file's name, then its PRS compressed data
+
+use archive_emulation_framework::Source;
+use std::io;
+
+/// Size of each section header.
+pub const SECTION_HEADER_SIZE: u64 = 0xC;
+
+/// Size of a name in the name section.
+pub const NAME_SIZE: usize = 0x40;
+
+/// Most names the name section holds.
+pub const MAX_NAMES: usize = 256;
+
+/// Index of the first name files may use.
+pub const FIRST_NAME_INDEX: u32 = 2;
+
+const NAMES_TYPE: u32 = 1;
+
+/// A file within an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OneEntry {
+    /// Index into [`OneHeader::names`].
+    pub name_index: u32,
+    /// Offset of the file's section header.
+    pub offset: u64,
+    /// Length of the compressed data after the section header.
+    pub length: u32,
+    pub rw_version: u32,
+}
+
+impl OneEntry {
+    /// Length of the entry, including its section header.
+    pub fn total_length(&self) -> u64 {
+        SECTION_HEADER_SIZE + self.length as u64
+    }
+}
+
+/// Everything read from an archive besides the files' data.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OneHeader {
+    pub rw_version: u32,
+    pub names: Vec<[u8; NAME_SIZE]>,
+    pub entries: Vec<OneEntry>,
+}
+
+impl OneHeader {
+    /// Reads the sections of an archive. Returns `Ok(None)` if `source` isn't one, or its
+    /// sections don't add up to its size.
+    pub fn read(source: &dyn Source) -> io::Result<Option<Self>> {
+        let size = source.size();
+        if size < SECTION_HEADER_SIZE * 2 {
+            return Ok(None);
+        }
+
+        let [kind, length, rw_version] = read_section_header(source, 0)?;
+        if kind != 0 || length as u64 != size - SECTION_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let [kind, names_length, _] = read_section_header(source, SECTION_HEADER_SIZE)?;
+        let names_start = SECTION_HEADER_SIZE * 2;
+        let names_end = names_start + names_length as u64;
+        if kind != NAMES_TYPE
+            || !(names_length as usize).is_multiple_of(NAME_SIZE)
+            || names_end > size
+        {
+            return Ok(None);
+        }
+
+        let mut names = vec![0; names_length as usize];
+        source.read_exact_at(names_start, &mut names)?;
+        let names: Vec<[u8; NAME_SIZE]> = names
+            .chunks_exact(NAME_SIZE)
+            .map(|x| x.try_into().unwrap())
+            .collect();
+
+        let mut entries = Vec::new();
+        let mut offset = names_end;
+        while offset < size {
+            if offset + SECTION_HEADER_SIZE > size {
+                return Ok(None);
+            }
+
+            let [name_index, length, rw_version] = read_section_header(source, offset)?;
+            let entry = OneEntry {
+                name_index,
+                offset,
+                length,
+                rw_version,
+            };
+
+            if name_index as usize >= names.len() || offset + entry.total_length() > size {
+                return Ok(None);
+            }
+
+            offset += entry.total_length();
+            entries.push(entry);
+        }
+
+        Ok(Some(Self {
+            rw_version,
+            names,
+            entries,
+        }))
+    }
+
+    /// The name of an entry, without padding.
+    pub fn name(&self, entry: &OneEntry) -> &[u8] {
+        name_bytes(&self.names[entry.name_index as usize])
+    }
+}
+
+/// A name for the name section. Fails with [`io::ErrorKind::InvalidData`] for names longer
+/// than 63 bytes, which leave no room for the terminator.
+pub fn encode_name(name: &str) -> io::Result<[u8; NAME_SIZE]> {
+    if name.len() >= NAME_SIZE {
+        let message = format!("{name}: names can be at most {} bytes", NAME_SIZE - 1);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+
+    let mut bytes = [0; NAME_SIZE];
+    bytes[..name.len()].copy_from_slice(name.as_bytes());
+    Ok(bytes)
+}
+
+/// A name from the name section, without padding.
+pub fn name_bytes(name: &[u8; NAME_SIZE]) -> &[u8] {
+    let length = name.iter().position(|x| *x == 0);
+    &name[..length.unwrap_or(NAME_SIZE)]
+}
+
+/// Writes a section header to the start of `bytes`.
+pub(crate) fn write_section_header(bytes: &mut [u8], kind: u32, length: u32, rw_version: u32) {
+    for (index, value) in [kind, length, rw_version].into_iter().enumerate() {
+        bytes[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn read_section_header(source: &dyn Source, offset: u64) -> io::Result<[u32; 3]> {
+    let mut bytes = [0; SECTION_HEADER_SIZE as usize];
+    source.read_exact_at(offset, &mut bytes)?;
+    let value = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    Ok([value(0), value(4), value(8)])
+}
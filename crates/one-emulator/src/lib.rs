@@ -0,0 +1,44 @@
+//! Sonic Heroes ONE archive emulator.
+//!
+//! ONE is the archive format of Sonic Heroes (and Shadow the Hedgehog): a table of up to 256
+//! names, followed by each file's section, which refers to its name by index and holds the
+//! file [PRS](prs) compressed. See [`OneHeader`] for the layout.
+//!
+//! Inputs go in `FileEmulationFramework/ONE/<archive>/`, named after the file inside the
+//! archive, such as `GAME_DISP.TXD`; names are matched ignoring case.
+//!
+//! - A file with the name of one in the archive replaces it; any other file is added to the
+//!   end of the archive. Archives can't have more than [`MAX_NAMES`] names, of at most 63
+//!   bytes; the archive isn't emulated if the inputs don't fit.
+//! - `GAME_DISP.TXD.DEL` removes `GAME_DISP.TXD`. Its contents don't matter.
+//! - Inputs are compressed when the archive is built, unless their name ends in `.PRS`, in
+//!   which case they are already compressed and stored as is: `GAME_DISP.TXD.PRS`.
+//!
+//! Files which aren't replaced or removed are kept as slices of the original, compressed data
+//! and all. Being compressed, they aren't offered to other emulators to nest in.
+//!
+//! ```ignore
+//! let emulator = Arc::new(OneEmulator::new());
+//! framework.register(Box::new(emulator.clone()));
+//! emulator.add_from_folders(&format!("{mod_folder}/FileEmulationFramework/{INPUT_FOLDER}"))?;
+//! ```
+
+mod builder;
+mod format;
+pub mod prs;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+pub use builder::{OneBuilder, COMPRESSED_SUFFIX, DELETE_SUFFIX};
+pub use format::{
+    encode_name, name_bytes, OneEntry, OneHeader, FIRST_NAME_INDEX, MAX_NAMES, NAME_SIZE,
+    SECTION_HEADER_SIZE,
+};
+
+use archive_emulation_framework::BuilderFactory;
+
+/// The ONE emulator, to register with the framework.
+pub type OneEmulator = BuilderFactory<OneBuilder>;
+
+/// Folder for this emulator's inputs, in a mod's `FileEmulationFramework` folder.
+pub const INPUT_FOLDER: &str = "ONE";
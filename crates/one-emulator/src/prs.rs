@@ -0,0 +1,226 @@
+// PRS: the LZ77 variant Sega games compress data with
+//
+// A stream of operations, each introduced by flag bits read from control bytes (least
+// significant bit first; a control byte is read whenever the previous one runs out):
+//   1               literal: copy the next byte
+//   0 0 x y         short copy: (xy + 2) bytes from distance 256 - next byte
+//   0 1             long copy: u16 `d` follows; (d & 7) + 2 bytes, or next byte + 1 if
+//                   (d & 7) is 0, from distance 8192 - (d >> 3). `d` of 0 ends the stream
+
+use std::io;
+
+const SHORT_DISTANCE: usize = 0x100;
+const LONG_DISTANCE: usize = 0x1FFF;
+const MAX_LENGTH: usize = 0x100;
+
+// Candidates checked per position when looking for a match.
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: u32 = 15;
+
+/// Compresses `data`.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = Writer::default();
+    let mut matcher = Matcher::new(data.len());
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = matcher.find(data, position);
+        let length = match (length, distance) {
+            (2..=5, ..=SHORT_DISTANCE) => {
+                let size = length - 2;
+                writer.bits(&[false, false, size & 2 != 0, size & 1 != 0]);
+                writer.byte((SHORT_DISTANCE - distance) as u8);
+                length
+            }
+            (3.., _) => {
+                writer.bits(&[false, true]);
+                let offset = ((0x2000 - distance) << 3) as u16;
+                match length {
+                    ..=9 => writer.u16(offset | (length - 2) as u16),
+                    _ => {
+                        writer.u16(offset);
+                        writer.byte((length - 1) as u8);
+                    }
+                }
+                length
+            }
+            _ => {
+                writer.bits(&[true]);
+                writer.byte(data[position]);
+                1
+            }
+        };
+
+        for x in position..position + length {
+            matcher.insert(data, x);
+        }
+        position += length;
+    }
+
+    writer.bits(&[false, true]);
+    writer.u16(0);
+    writer.output
+}
+
+/// Decompresses `data`. Fails if it is malformed.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut reader = Reader {
+        data,
+        position: 0,
+        control: 0,
+        bits: 0,
+    };
+
+    let mut output = Vec::with_capacity(data.len() * 2);
+    loop {
+        if reader.bit()? {
+            output.push(reader.byte()?);
+            continue;
+        }
+
+        let (length, distance) = if reader.bit()? {
+            let value = u16::from_le_bytes([reader.byte()?, reader.byte()?]) as usize;
+            if value == 0 {
+                return Ok(output);
+            }
+
+            let length = match value & 7 {
+                0 => reader.byte()? as usize + 1,
+                size => size + 2,
+            };
+            (length, 0x2000 - (value >> 3))
+        } else {
+            let size = (reader.bit()? as usize) << 1 | reader.bit()? as usize;
+            (size + 2, SHORT_DISTANCE - reader.byte()? as usize)
+        };
+
+        let start = output
+            .len()
+            .checked_sub(distance)
+            .ok_or_else(|| invalid("copy from before the start of the data"))?;
+
+        // Copies may overlap what they produce, so byte by byte.
+        for x in start..start + length {
+            output.push(output[x]);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    output: Vec<u8>,
+    // Position of the control byte being filled, and how many of its bits are used.
+    control: usize,
+    used: u32,
+}
+
+impl Writer {
+    fn bits(&mut self, bits: &[bool]) {
+        for bit in bits {
+            if self.used.is_multiple_of(8) {
+                self.control = self.output.len();
+                self.output.push(0);
+                self.used = 0;
+            }
+
+            self.output[self.control] |= (*bit as u8) << self.used;
+            self.used += 1;
+        }
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.output.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.output.extend(value.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    control: u8,
+    bits: u32,
+}
+
+impl Reader<'_> {
+    fn bit(&mut self) -> io::Result<bool> {
+        if self.bits == 0 {
+            self.control = self.byte()?;
+            self.bits = 8;
+        }
+
+        let bit = self.control & 1 != 0;
+        self.control >>= 1;
+        self.bits -= 1;
+        Ok(bit)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = self.data.get(self.position).copied();
+        self.position += 1;
+        byte.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "PRS data ends early"))
+    }
+}
+
+/// Finds earlier occurrences of the data at a position, through chains of positions with the
+/// same 3 byte hash.
+struct Matcher {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl Matcher {
+    fn new(length: usize) -> Self {
+        Self {
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            previous: vec![usize::MAX; length],
+        }
+    }
+
+    fn hash(data: &[u8], position: usize) -> Option<usize> {
+        let bytes = data.get(position..position + 3)?;
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+        Some((value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize)
+    }
+
+    fn insert(&mut self, data: &[u8], position: usize) {
+        if let Some(hash) = Self::hash(data, position) {
+            self.previous[position] = self.head[hash];
+            self.head[hash] = position;
+        }
+    }
+
+    /// Longest match for `position` as (length, distance); length 0 if there is none.
+    fn find(&self, data: &[u8], position: usize) -> (usize, usize) {
+        let Some(hash) = Self::hash(data, position) else {
+            return (0, 0);
+        };
+
+        let limit = MAX_LENGTH.min(data.len() - position);
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash];
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || position - candidate > LONG_DISTANCE {
+                break;
+            }
+
+            let length = data[candidate..]
+                .iter()
+                .zip(&data[position..position + limit])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.0 {
+                best = (length, position - candidate);
+                if length == limit {
+                    break;
+                }
+            }
+
+            candidate = self.previous[candidate];
+        }
+
+        best
+    }
+}
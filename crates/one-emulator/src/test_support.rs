@@ -0,0 +1,35 @@
+// Synthetic ONE archives for tests
+
+use crate::format::{encode_name, FIRST_NAME_INDEX, MAX_NAMES, NAME_SIZE};
+use crate::prs::compress;
+
+/// RenderWare version the game's tools stamp every section with.
+pub const RW_VERSION: u32 = 0x1400FFFF;
+
+/// Writes an archive as the game's tools do: 256 names, files in name order.
+pub fn write_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut names = vec![[0; NAME_SIZE]; MAX_NAMES];
+    let mut sections = Vec::new();
+    for (index, (name, content)) in files.iter().enumerate() {
+        let name_index = index + FIRST_NAME_INDEX as usize;
+        names[name_index] = encode_name(name).unwrap();
+        let compressed = compress(content);
+        sections.extend(section(name_index as u32, compressed.len()));
+        sections.extend(compressed);
+    }
+
+    let names: Vec<u8> = names.concat();
+    let mut data = section(0, 0xC + names.len() + sections.len());
+    data.extend(section(1, names.len()));
+    data.extend(names);
+    data.extend(sections);
+    data
+}
+
+/// The header of a section of `kind` with `length` bytes after it.
+pub fn section(kind: u32, length: usize) -> Vec<u8> {
+    [kind, length as u32, RW_VERSION]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect()
+}
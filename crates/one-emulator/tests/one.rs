@@ -0,0 +1,202 @@
+// Emulated ONE archives built from synthetic originals and inputs, read back with the header
+// parser and checked file by file.
+
+use archive_emulation_framework::test_support::Setup;
+use archive_emulation_framework::{EmulatorBuilder, Route};
+use one_emulator::prs::{compress, decompress};
+use one_emulator::test_support::{section, write_archive, RW_VERSION};
+use one_emulator::{encode_name, OneBuilder, OneHeader, INPUT_FOLDER, NAME_SIZE};
+use std::io;
+
+const ARCHIVE: &str = "/game/dvdroot/stg01_light.one";
+
+/// The emulator, with `original` as the archive.
+fn with_original(original: Vec<u8>) -> Setup<OneBuilder> {
+    Setup::new(INPUT_FOLDER, ARCHIVE, original)
+}
+
+/// The archive's files as (name, decompressed data), and its header.
+fn unpack(archive: &[u8]) -> (Vec<(String, Vec<u8>)>, OneHeader) {
+    let header = OneHeader::read(&archive.to_vec()).unwrap().unwrap();
+    let files = header.entries.iter().map(|entry| {
+        let name = String::from_utf8(header.name(entry).to_vec()).unwrap();
+        let start = (entry.offset + 0xC) as usize;
+        let data = &archive[start..start + entry.length as usize];
+        (name, decompress(data).unwrap())
+    });
+    (files.collect(), header)
+}
+
+fn owned(files: &[(&str, &[u8])]) -> Vec<(String, Vec<u8>)> {
+    let files = files
+        .iter()
+        .map(|(name, data)| (name.to_string(), data.to_vec()));
+    files.collect()
+}
+
+const FILES: &[(&str, &[u8])] = &[
+    ("GAME_DISP.TXD", b"textures textures textures"),
+    ("stg01_blk.bin", &[1; 0x500]),
+    ("STG01_CAM.BIN", b"camera"),
+];
+
+#[test]
+fn inputs_replace_files_by_name() {
+    let setup = with_original(write_archive(FILES));
+    setup.add_inputs(
+        "a",
+        &[("game_disp.txd", b"first mod"), ("STG01_CAM.BIN", b"cam")],
+    );
+    setup.add_inputs("b", &[("GAME_DISP.TXD", &[3; 0x1234])]);
+
+    let (files, header) = unpack(&setup.emulate());
+    let expected: &[(&str, &[u8])] = &[
+        ("GAME_DISP.TXD", &[3; 0x1234]),
+        ("stg01_blk.bin", &[1; 0x500]),
+        ("STG01_CAM.BIN", b"cam"),
+    ];
+    assert_eq!(files, owned(expected));
+    assert_eq!(header.names.len(), 256);
+    assert!(header.entries.iter().all(|x| x.rw_version == RW_VERSION));
+}
+
+#[test]
+fn other_inputs_are_added() {
+    let setup = with_original(write_archive(FILES));
+    setup.add_inputs("a", &[("new.dff", b"model"), ("other.dff", b"another")]);
+
+    let (files, header) = unpack(&setup.emulate());
+    assert_eq!(files[..3], owned(FILES));
+    assert_eq!(
+        files[3..],
+        owned(&[("new.dff", b"model"), ("other.dff", b"another")])
+    );
+
+    // They take the next free names.
+    assert_eq!(header.entries[3].name_index, 5);
+    assert_eq!(header.entries[4].name_index, 6);
+}
+
+#[test]
+fn files_cant_be_added_when_the_table_is_full() {
+    let names: Vec<String> = (0..254).map(|x| format!("{x}.bin")).collect();
+    let files: Vec<(&str, &[u8])> = names.iter().map(|x| (x.as_str(), &b"x"[..])).collect();
+    let setup = with_original(write_archive(&files));
+    setup.add_inputs("a", &[("extra.bin", b"extra")]);
+
+    let error = setup.try_build().err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "extra.bin: all 256 names are taken");
+    assert!(setup.framework.emulate(ARCHIVE).is_none());
+}
+
+#[test]
+fn names_are_added_to_short_tables() {
+    // A table with only the 2 unused names, and no files.
+    let names = [[0; NAME_SIZE]; 2].concat();
+    let mut archive = section(0, 0xC + names.len());
+    archive.extend(section(1, names.len()));
+    archive.extend(names);
+    let setup = with_original(archive);
+    setup.add_inputs("a", &[("extra.bin", b"extra")]);
+
+    let (files, header) = unpack(&setup.emulate());
+    assert_eq!(header.names.len(), 3);
+    assert_eq!(header.entries[0].name_index, 2);
+    assert_eq!(files, [("extra.bin".to_string(), b"extra".to_vec())]);
+}
+
+#[test]
+fn names_longer_than_63_bytes_are_rejected() {
+    let name = format!("{}.bin", "a".repeat(60));
+    let longest = &name[1..];
+    assert_eq!(encode_name(longest).unwrap()[..63], *longest.as_bytes());
+    assert_eq!(
+        encode_name(&name).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+
+    let setup = with_original(write_archive(FILES));
+    setup.add_inputs("a", &[(&name, b"long")]);
+    assert_eq!(
+        setup.try_build().err().unwrap().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn delete_markers_remove_files() {
+    let setup = with_original(write_archive(FILES));
+    setup.add_inputs(
+        "a",
+        &[("GAME_DISP.TXD.DEL", b""), ("stg01_cam.bin.del", b"")],
+    );
+
+    let (files, _) = unpack(&setup.emulate());
+    assert_eq!(files, owned(&FILES[1..2]));
+}
+
+#[test]
+fn later_mods_override_deletions() {
+    let setup = with_original(write_archive(FILES));
+    setup.add_inputs("a", &[("GAME_DISP.TXD.DEL", b"")]);
+    setup.add_inputs("b", &[("GAME_DISP.TXD", b"back again")]);
+
+    let (files, _) = unpack(&setup.emulate());
+    assert_eq!(
+        files[0],
+        ("GAME_DISP.TXD".to_string(), b"back again".to_vec())
+    );
+    assert_eq!(files.len(), 3);
+}
+
+#[test]
+fn compressed_inputs_are_stored_as_is() {
+    let setup = with_original(write_archive(FILES));
+    let compressed = compress(b"already compressed");
+    setup.add_inputs("a", &[("STG01_CAM.BIN.PRS", &compressed)]);
+
+    let archive = setup.emulate();
+    let (files, header) = unpack(&archive);
+    assert_eq!(files[2].1, b"already compressed");
+
+    let start = (header.entries[2].offset + 0xC) as usize;
+    assert_eq!(archive[start..], compressed);
+}
+
+#[test]
+fn unchanged_files_are_one_slice_of_the_original() {
+    let original = write_archive(FILES);
+    let header = OneHeader::read(&original).unwrap().unwrap();
+    let setup = with_original(original);
+    setup.add_inputs("a", &[("GAME_DISP.TXD", b"replaced")]);
+
+    // Both untouched files, section headers and compressed data, in one slice.
+    let stream = setup.build();
+    let slices: Vec<_> = stream
+        .streams()
+        .iter()
+        .filter_map(|x| x.stream.file_slice())
+        .map(|x| (x.offset(), x.length()))
+        .collect();
+    let start = header.entries[1].offset;
+    let end = header.entries[2].offset + header.entries[2].total_length();
+    assert_eq!(slices, [(start, end - start)]);
+}
+
+#[test]
+fn other_files_are_left_alone() {
+    let setup = with_original(b"not an archive, but a .one all the same".to_vec());
+    setup.add_inputs("a", &[("GAME_DISP.TXD", b"data")]);
+    assert!(setup.framework.emulate(ARCHIVE).is_none());
+
+    // Sections which don't add up to the size of the file.
+    let mut truncated = write_archive(FILES);
+    truncated.pop();
+    let setup = with_original(truncated);
+    setup.add_inputs("a", &[("GAME_DISP.TXD", b"data")]);
+    assert!(setup.framework.emulate(ARCHIVE).is_none());
+
+    assert!(OneBuilder::accepts(&Route::new("/game/dvdroot/STG01.ONE")));
+    assert!(!OneBuilder::accepts(&Route::new("/game/dvdroot/one")));
+}
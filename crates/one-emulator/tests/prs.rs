@@ -0,0 +1,59 @@
+// PRS compression round trips, and decompression of hand written streams.
+
+use one_emulator::prs::{compress, decompress};
+use proptest::prelude::*;
+
+#[test]
+fn empty_data_round_trips() {
+    assert_eq!(compress(&[]), [0x02, 0x00, 0x00]);
+    assert_eq!(decompress(&compress(&[])).unwrap(), []);
+}
+
+#[test]
+fn decompresses_each_kind_of_copy() {
+    // Literals `ab`, a short copy of 4 from distance 2, a long copy of 3 from distance 6, a
+    // long copy of 20 from distance 1 (with its length in an extra byte), then the end.
+    let long = |distance: u16, size: u16| ((0x2000 - distance) << 3 | size).to_le_bytes();
+    let mut data = vec![0b1001_0011, b'a', b'b', 0xFE];
+    data.extend(long(6, 1));
+    data.push(0b1010);
+    data.extend(long(1, 0));
+    data.extend([19, 0, 0]);
+
+    let expected = format!("ababababa{}", "a".repeat(20));
+    assert_eq!(decompress(&data).unwrap(), expected.as_bytes());
+}
+
+#[test]
+fn repetitive_data_compresses() {
+    let data: Vec<u8> = (0..0x10000).map(|x| (x % 7) as u8).collect();
+    let compressed = compress(&data);
+    assert!(compressed.len() < data.len() / 50, "{}", compressed.len());
+    assert_eq!(decompress(&compressed).unwrap(), data);
+}
+
+#[test]
+fn malformed_data_is_an_error() {
+    // Ends early, and copies from before the start.
+    assert!(decompress(&[0b1]).is_err());
+    assert!(decompress(&[0b00, 0xFF]).is_err());
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_data_round_trips(data in prop::collection::vec(any::<u8>(), 0..0x3000)) {
+        prop_assert_eq!(decompress(&compress(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn data_with_repeats_round_trips(
+        parts in prop::collection::vec((prop::collection::vec(0..4u8, 1..40), 1..300usize), 1..40)
+    ) {
+        let data: Vec<u8> = parts
+            .iter()
+            .flat_map(|(part, times)| part.iter().cycle().take(part.len() * times))
+            .copied()
+            .collect();
+        prop_assert_eq!(decompress(&compress(&data)).unwrap(), data);
+    }
+}
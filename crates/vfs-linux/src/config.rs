@@ -0,0 +1,93 @@
+// Parsing of the `VFS_CONFIG` file
+
+use std::path::Path;
+
+/// A single line of the config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Entry {
+    File {
+        source: String,
+        target: String,
+    },
+    FolderAsFiles {
+        source: String,
+        target: String,
+    },
+    Folder {
+        source: String,
+        target: String,
+    },
+    Virtual {
+        path: String,
+        source: String,
+    },
+    /// Whether child processes inherit the VFS at all.
+    Propagate(bool),
+    /// Only child processes running this executable inherit the VFS.
+    PropagateAllow(String),
+    /// Child processes running this executable don't inherit the VFS.
+    PropagateDeny(String),
+}
+
+/// Parses the config file. Invalid lines are reported as `(line number, message)` and skipped.
+pub(crate) fn parse(text: &str, base: &Path) -> (Vec<Entry>, Vec<(usize, String)>) {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_line(line, base) {
+            Ok(entry) => entries.push(entry),
+            Err(message) => errors.push((index + 1, message)),
+        }
+    }
+
+    (entries, errors)
+}
+
+fn parse_line(line: &str, base: &Path) -> Result<Entry, String> {
+    let (kind, rest) = line
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("expected '<kind> <source> => <target>', got '{line}'"))?;
+    let rest = rest.trim();
+    match kind {
+        "propagate" => {
+            return match rest {
+                "on" => Ok(Entry::Propagate(true)),
+                "off" => Ok(Entry::Propagate(false)),
+                _ => Err(format!("expected 'on' or 'off', got '{rest}'")),
+            }
+        }
+        "propagate-allow" => return Ok(Entry::PropagateAllow(rest.to_owned())),
+        "propagate-deny" => return Ok(Entry::PropagateDeny(rest.to_owned())),
+        _ => {}
+    }
+
+    let (left, right) = rest
+        .split_once("=>")
+        .ok_or_else(|| format!("missing '=>' in '{line}'"))?;
+    let (left, right) = (left.trim(), right.trim());
+    if left.is_empty() || right.is_empty() {
+        return Err(format!("empty path in '{line}'"));
+    }
+
+    let source = absolute(left, base);
+    let target = absolute(right, base);
+    Ok(match kind {
+        "file" => Entry::File { source, target },
+        "folder-as-files" => Entry::FolderAsFiles { source, target },
+        "folder" => Entry::Folder { source, target },
+        "virtual" => Entry::Virtual {
+            path: source,
+            source: target,
+        },
+        _ => return Err(format!("unknown entry kind '{kind}'")),
+    })
+}
+
+fn absolute(path: &str, base: &Path) -> String {
+    crate::paths::normalize_lexically(&base.join(path).to_string_lossy())
+}
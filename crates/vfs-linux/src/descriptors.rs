@@ -0,0 +1,47 @@
+// Descriptors the backends opened for virtual and redirected files
+
+use libc::c_int;
+use std::sync::{Arc, LazyLock};
+use virtual_file_framework::{DescriptorTable, RawHandle};
+
+/// What an open file description tracked by the VFS refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Description {
+    /// A virtual file registered at this path, served as a memory file.
+    Virtual(String),
+    /// A real file, opened in place of the one at this path.
+    Redirected(String),
+}
+
+static DESCRIPTORS: LazyLock<DescriptorTable<Description>> = LazyLock::new(DescriptorTable::new);
+
+/// The process wide table of descriptors opened through the VFS.
+///
+/// Hooks which open files record the descriptors here; hooks for `close`, `dup`, `fcntl` and
+/// `fork` keep the table in step with the kernel's. Descriptors inherited through `exec` start
+/// out untracked.
+pub fn descriptors() -> &'static DescriptorTable<Description> {
+    &DESCRIPTORS
+}
+
+/// Records `fd`, just returned by an `open` called with `flags`, if it is valid.
+pub fn track(fd: c_int, description: Description, flags: c_int) {
+    if let Some(fd) = handle(fd) {
+        descriptors().insert(fd, description, flags & libc::O_CLOEXEC != 0);
+    }
+}
+
+/// Returns what `fd` refers to, if the VFS opened it.
+pub fn get(fd: c_int) -> Option<Arc<Description>> {
+    descriptors().get(handle(fd)?)
+}
+
+/// Whether `fd` is a virtual file opened through the VFS.
+pub fn is_virtual(fd: c_int) -> bool {
+    get(fd).is_some_and(|x| matches!(*x, Description::Virtual(_)))
+}
+
+/// Converts a descriptor to a table key; `None` for invalid (negative) descriptors.
+pub fn handle(fd: c_int) -> Option<RawHandle> {
+    RawHandle::try_from(fd).ok()
+}
@@ -0,0 +1,30 @@
+// Re-entrancy guard for hooks
+
+use std::cell::Cell;
+
+thread_local! {
+    // Const initialised so accessing it never allocates or registers a destructor,
+    // which makes it safe to touch from any hook, at any point in the process lifetime.
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as running VFS code until dropped.
+///
+/// The hooks themselves use std and libc, which call back into the hooked functions
+/// (`std::fs` calls `open64`, `statx`, ...). Those nested calls must reach the real
+/// functions directly. Every hook enters the guard before doing any work of its own; the
+/// VFS's own file access avoids the hooks entirely through [`sys`](crate::sys).
+pub struct HookGuard(());
+
+impl HookGuard {
+    /// Returns `None` if this thread is already inside a hook.
+    pub fn enter() -> Option<Self> {
+        IN_HOOK.with(|x| (!x.replace(true)).then_some(HookGuard(())))
+    }
+}
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        IN_HOOK.with(|x| x.set(false));
+    }
+}
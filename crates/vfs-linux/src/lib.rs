@@ -0,0 +1,44 @@
+//! Shared support for the Linux backends (`vfs-preload`, `vfs-syscall-patch`, `vfs-seccomp`).
+//!
+//! Loads the configuration, resolves hooked paths through the Layer 1
+//! [`Redirector`](virtual_filesystem::Redirector) and serves virtual files from Layer 2.
+//! Backends only translate their calling convention (libc function, raw syscall, seccomp
+//! notification) to these.
+//!
+//! Configuration is read from the file named by the `VFS_CONFIG` environment variable.
+//! Without it, backends pass every call through unchanged. One entry per line:
+//!
+//! ```text
+//! # Tier 1: individual file redirect
+//! file /game/data/player.png => /mods/my-mod/player.png
+//! # Tier 1: redirect every file currently inside a folder
+//! folder-as-files /game/data => /mods/my-mod/data
+//! # Tier 2: folder fallback redirect
+//! folder /game/saves => /mods/my-mod/saves
+//! # Virtual file, with content served through Layer 2 from a host file
+//! virtual /game/data/extra.bin => /mods/my-mod/extra.bin
+//! # Child processes
+//! propagate on
+//! propagate-allow game.x86_64
+//! propagate-deny crash-reporter
+//! ```
+//!
+//! The VFS's own file access inside hooks goes through [`sys`], which makes raw syscalls
+//! instead of calling the libc functions the backends hook. Every hook also holds a
+//! [`HookGuard`](guard::HookGuard), so anything which still reaches a hook from inside one
+//! passes straight through.
+//!
+//! Relative paths are relative to the directory containing the config file.
+//! Child processes inherit the VFS unless `propagate off` is set; allow/deny entries name
+//! executables by file name, one per line.
+#![cfg(all(target_os = "linux", target_pointer_width = "64"))]
+
+mod config;
+pub mod descriptors;
+pub mod guard;
+pub mod paths;
+pub mod propagation;
+pub mod state;
+pub use vfs_sys as sys;
+pub mod syscalls;
+pub mod virtual_file;
@@ -0,0 +1,113 @@
+// Turning hooked path arguments into absolute paths, and resolving where they should point
+
+use crate::state::Vfs;
+use crate::sys;
+use libc::{c_char, c_int};
+use std::ffi::{CStr, CString};
+use virtual_filesystem::Resolution;
+
+/// Where a hooked call should operate.
+pub enum Target {
+    /// Not handled by the VFS; call the real function with the original arguments.
+    Original,
+    /// A virtual file registered at this (absolute) path.
+    Virtual(String),
+    /// Redirected to this absolute path.
+    Redirected(CString),
+}
+
+/// Resolves the path argument of a hooked call.
+///
+/// `create` is set for calls which may create the file; for Tier 2 folder redirects new files
+/// are created at the redirected location.
+///
+/// # Safety
+///
+/// `path` must be null or a valid C string.
+pub unsafe fn resolve(vfs: &Vfs, dirfd: c_int, path: *const c_char, create: bool) -> Target {
+    match absolute(dirfd, path) {
+        Some(path) => resolve_absolute(vfs, path, create),
+        None => Target::Original,
+    }
+}
+
+/// Resolves an absolute, lexically normalised path. See [`resolve`].
+pub fn resolve_absolute(vfs: &Vfs, path: String, create: bool) -> Target {
+    if vfs.framework.is_virtual_file(&path) {
+        return Target::Virtual(path);
+    }
+
+    let target = match vfs.redirector.resolve(&path) {
+        None => return Target::Original,
+        Some(Resolution::File(target)) => target,
+        Some(Resolution::Folder(target)) => {
+            let use_target = sys::exists(&target) || (create && !sys::exists(&path));
+            if !use_target {
+                return Target::Original;
+            }
+
+            target
+        }
+    };
+
+    CString::new(target).map_or(Target::Original, Target::Redirected)
+}
+
+/// Converts a path argument, relative to `dirfd` or the working directory, into an absolute path.
+///
+/// Returns `None` for null or non UTF-8 paths, which are never handled by the VFS.
+///
+/// # Safety
+///
+/// `path` must be null or a valid C string.
+pub unsafe fn absolute(dirfd: c_int, path: *const c_char) -> Option<String> {
+    if path.is_null() {
+        return None;
+    }
+
+    let path = CStr::from_ptr(path).to_str().ok()?;
+    if path.is_empty() {
+        return None;
+    }
+
+    if path.starts_with('/') {
+        return Some(normalize_lexically(path));
+    }
+
+    let base = if dirfd == libc::AT_FDCWD {
+        sys::current_dir().ok()?
+    } else {
+        let link = CString::new(format!("/proc/self/fd/{dirfd}")).ok()?;
+        sys::read_link(&link).ok()?
+    };
+
+    Some(normalize_lexically(&format!("{base}/{path}")))
+}
+
+/// Removes `.`, `..` and repeated separators from an absolute path without touching the disk.
+///
+/// Symbolic links are not resolved; a redirect only applies to the path it was registered with.
+pub fn normalize_lexically(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let mut result = String::with_capacity(path.len());
+    for component in &components {
+        result.push('/');
+        result.push_str(component);
+    }
+
+    if result.is_empty() {
+        result.push('/');
+    }
+
+    result
+}
@@ -0,0 +1,190 @@
+// Carrying the VFS into child processes started with `exec` or `posix_spawn`
+
+use crate::sys;
+use libc::{c_char, c_int};
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use virtual_filesystem::path::file_name;
+
+const PRELOAD: &str = "LD_PRELOAD";
+const CONFIG: &str = "VFS_CONFIG";
+
+/// Decides which child processes inherit the VFS, and builds their environment.
+///
+/// The VFS reaches a child through `LD_PRELOAD` (the backend library) and `VFS_CONFIG`.
+/// Both are put back into the environment passed to `exec`, so children which are started
+/// with a scrubbed environment are covered too. Children which shouldn't inherit the VFS
+/// get both removed instead.
+#[derive(Debug)]
+pub struct Propagation {
+    enabled: bool,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    /// Absolute path of the config file.
+    config: String,
+    /// Absolute path of the backend's shared library; `None` if it's linked into the program.
+    library: Option<String>,
+}
+
+/// A replacement `envp` for `exec`.
+pub struct Environment {
+    _strings: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+impl Propagation {
+    pub(crate) fn new(config: String) -> Self {
+        Self {
+            enabled: true,
+            allow: Vec::new(),
+            deny: Vec::new(),
+            config,
+            library: library_path(),
+        }
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(crate) fn allow(&mut self, name: String) {
+        self.allow.push(name);
+    }
+
+    pub(crate) fn deny(&mut self, name: String) {
+        self.deny.push(name);
+    }
+
+    /// Whether every child process inherits the VFS, i.e. no lists and no opt-out.
+    pub fn is_unrestricted(&self) -> bool {
+        self.enabled && self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Whether a child running `program` (a path or file name) should inherit the VFS.
+    ///
+    /// Executables are matched by file name. The deny list wins over the allow list; an empty
+    /// allow list allows everything.
+    pub fn applies_to(&self, program: &str) -> bool {
+        let name = file_name(program);
+        self.enabled
+            && !self.deny.iter().any(|x| x == name)
+            && (self.allow.is_empty() || self.allow.iter().any(|x| x == name))
+    }
+
+    /// Builds the environment for a child running `program`, from the one the caller passed.
+    ///
+    /// # Safety
+    ///
+    /// `environment` must be null or a null terminated array of C strings.
+    pub unsafe fn child_environment(
+        &self,
+        program: &str,
+        environment: *const *const c_char,
+    ) -> Environment {
+        let propagate = self.applies_to(program);
+        let mut strings = Vec::new();
+        let mut preload = None;
+        let mut current = environment;
+        while !current.is_null() && !(*current).is_null() {
+            let entry = CStr::from_ptr(*current);
+            current = current.add(1);
+            if let Some(value) = value_of(entry.to_bytes(), PRELOAD) {
+                preload = Some(String::from_utf8_lossy(value).into_owned());
+            } else if value_of(entry.to_bytes(), CONFIG).is_none() {
+                strings.push(entry.to_owned());
+            }
+        }
+
+        if let Some(preload) = self.preload(preload.as_deref().unwrap_or(""), propagate) {
+            strings.push(variable(PRELOAD, &preload));
+        }
+
+        if propagate {
+            strings.push(variable(CONFIG, &self.config));
+        }
+
+        let mut pointers: Vec<_> = strings.iter().map(|x| x.as_ptr()).collect();
+        pointers.push(std::ptr::null());
+        Environment {
+            _strings: strings,
+            pointers,
+        }
+    }
+
+    /// The child's `LD_PRELOAD`: the existing entries without the backend library, then the
+    /// library if propagating. `None` if that leaves it empty.
+    fn preload(&self, existing: &str, propagate: bool) -> Option<String> {
+        let ours = self.library.as_deref().map(file_name);
+        let mut entries: Vec<&str> = existing
+            .split([':', ' '])
+            .filter(|x| !x.is_empty() && Some(file_name(x)) != ours)
+            .collect();
+        if propagate {
+            entries.extend(self.library.as_deref());
+        }
+
+        (!entries.is_empty()).then(|| entries.join(":"))
+    }
+}
+
+impl Environment {
+    /// The `envp` array to pass to `exec`. Valid while `self` is.
+    pub fn as_ptr(&self) -> *const *const c_char {
+        self.pointers.as_ptr()
+    }
+}
+
+/// Returns the path of the program about to run for `execveat`/`fexecve`-style calls, where
+/// an empty path means the program is the file open as `dirfd`.
+///
+/// # Safety
+///
+/// `path` must be null or a valid C string.
+pub unsafe fn program_path(dirfd: c_int, path: *const c_char) -> String {
+    let path = match path.is_null() {
+        true => "",
+        false => CStr::from_ptr(path).to_str().unwrap_or(""),
+    };
+
+    if !path.is_empty() {
+        return path.to_owned();
+    }
+
+    let link = CString::new(format!("/proc/self/fd/{dirfd}")).unwrap();
+    sys::read_link(&link).unwrap_or_default()
+}
+
+fn value_of<'a>(entry: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    entry
+        .strip_prefix(name.as_bytes())
+        .and_then(|x| x.strip_prefix(b"="))
+}
+
+fn variable(name: &str, value: &str) -> CString {
+    CString::new(format!("{name}={value}")).unwrap_or_default()
+}
+
+/// Finds the shared library this code was loaded from.
+///
+/// Returns `None` when linked into the executable itself: a static program, or a program
+/// which embeds the VFS (like the seccomp supervisor).
+fn library_path() -> Option<String> {
+    // SAFETY: `dladdr` only fills `info`; the address is a function in this module.
+    let name = unsafe {
+        let mut info: libc::Dl_info = std::mem::zeroed();
+        let address = library_path as *const () as *const libc::c_void;
+        if libc::dladdr(address, &mut info) == 0 || info.dli_fname.is_null() {
+            return None;
+        }
+
+        CStr::from_ptr(info.dli_fname).to_str().ok()?.to_owned()
+    };
+
+    let path = Path::new(&name).canonicalize().ok()?;
+    let exe = std::env::current_exe().and_then(|x| x.canonicalize()).ok();
+    if exe.as_deref() == Some(path.as_path()) {
+        return None;
+    }
+
+    path.to_str().map(str::to_owned)
+}
@@ -0,0 +1,130 @@
+// Global VFS state and initialisation from the config file
+
+use crate::config::{self, Entry};
+use crate::guard::HookGuard;
+use crate::paths::normalize_lexically;
+use crate::propagation::Propagation;
+use crate::sys;
+use crate::virtual_file::HostFile;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use virtual_file_framework::{VirtualFileFramework, VirtualFileMetadata};
+use virtual_filesystem::{Redirector, VirtualFiles};
+
+/// Layer 1 redirector plus the Layer 2 framework holding virtual files.
+pub struct Vfs {
+    pub redirector: Redirector,
+    pub framework: VirtualFileFramework,
+    /// Which child processes inherit the VFS.
+    pub propagation: Propagation,
+}
+
+// Set once by `initialise`; stays unset (all calls pass through) if there is no config
+static VFS: OnceLock<Vfs> = OnceLock::new();
+
+/// Returns the VFS if the calling hook should handle this call.
+///
+/// Returns `None` if the VFS is not configured, or if this thread is already inside a hook;
+/// the hook should then call the real function with the original arguments.
+/// The returned guard must be held for as long as the hook runs.
+pub fn enter() -> Option<(&'static Vfs, HookGuard)> {
+    let vfs = VFS.get()?;
+    let guard = HookGuard::enter()?;
+    Some((vfs, guard))
+}
+
+/// Loads the config file named by `VFS_CONFIG`, making it the process wide VFS.
+pub extern "C" fn initialise() {
+    let Some(_guard) = HookGuard::enter() else {
+        return;
+    };
+
+    let Some(config_path) = std::env::var_os("VFS_CONFIG") else {
+        return;
+    };
+
+    match Vfs::load(Path::new(&config_path)) {
+        Ok(vfs) => {
+            let _ = VFS.set(vfs);
+        }
+        Err(error) => sys::report(&format!(
+            "vfs: cannot read {}: {error}",
+            config_path.to_string_lossy()
+        )),
+    }
+}
+
+impl Vfs {
+    /// Loads a config file. Invalid entries are reported on stderr and skipped.
+    pub fn load(config_path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(config_path)?;
+        let config_path = std::env::current_dir()
+            .unwrap_or_default()
+            .join(config_path);
+        let base = config_path.parent().unwrap_or(Path::new("/"));
+        let (entries, errors) = config::parse(&text, base);
+        for (line, message) in errors {
+            sys::report(&format!("vfs: {}:{line}: {message}", config_path.display()));
+        }
+
+        let config = normalize_lexically(&config_path.to_string_lossy());
+        let mut vfs = Vfs {
+            redirector: Redirector::new(),
+            framework: VirtualFileFramework::new(Arc::new(VirtualFiles::new())),
+            propagation: Propagation::new(config),
+        };
+
+        for entry in entries {
+            if let Err(message) = apply(&mut vfs, &entry) {
+                sys::report(&format!("vfs: {entry:?}: {message}"));
+            }
+        }
+
+        Ok(vfs)
+    }
+}
+
+fn apply(vfs: &mut Vfs, entry: &Entry) -> Result<(), String> {
+    match entry {
+        Entry::File { source, target } => {
+            vfs.redirector
+                .add_file(source, target)
+                .map_err(|x| x.to_string())?;
+        }
+        Entry::FolderAsFiles { source, target } => {
+            vfs.redirector
+                .add_folder_as_files(source, target)
+                .map_err(|x| x.to_string())?;
+        }
+        Entry::Folder { source, target } => {
+            vfs.redirector
+                .add_folder(source, target)
+                .map_err(|x| x.to_string())?;
+        }
+        Entry::Virtual { path, source } => {
+            let file = File::open(source).map_err(|x| x.to_string())?;
+            let host_metadata = file.metadata().map_err(|x| x.to_string())?;
+            let mut metadata = VirtualFileMetadata::with_size(host_metadata.len());
+            if let Ok(modified) = host_metadata.modified() {
+                let nanos = modified
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |x| x.as_nanos() as i64);
+                metadata.creation_time = nanos;
+                metadata.last_access_time = nanos;
+                metadata.last_write_time = nanos;
+                metadata.change_time = nanos;
+            }
+
+            vfs.framework
+                .register_virtual_file(path, metadata, Box::new(HostFile(file)))
+                .map_err(|x| x.to_string())?;
+        }
+        Entry::Propagate(enabled) => vfs.propagation.set_enabled(*enabled),
+        Entry::PropagateAllow(name) => vfs.propagation.allow(name.clone()),
+        Entry::PropagateDeny(name) => vfs.propagation.deny(name.clone()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+// Argument layout of the file syscalls handled by syscall level backends
+
+/// What a path syscall does, with the indices of its relevant arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallKind {
+    /// `open`, `openat`.
+    Open { flags: usize, mode: usize },
+    /// `openat2`: flags and mode are in the `struct open_how` argument.
+    Openat2 { how: usize },
+    /// `stat`, `lstat`, `newfstatat`: fills the `struct stat` argument.
+    Stat {
+        buf: usize,
+        flags: Option<usize>,
+        no_follow: bool,
+    },
+    /// `statx`: fills the `struct statx` argument.
+    Statx {
+        flags: usize,
+        mask: usize,
+        buf: usize,
+    },
+    /// `access`, `faccessat`, `faccessat2`.
+    Access { mode: usize, flags: Option<usize> },
+}
+
+/// A syscall taking a path which is routed through the VFS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathSyscall {
+    /// Index of the directory descriptor argument the path is relative to, if any.
+    pub dirfd: Option<usize>,
+    /// Index of the path argument.
+    pub path: usize,
+    pub kind: SyscallKind,
+}
+
+/// A syscall starting a new program, whose environment carries the VFS to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecSyscall {
+    /// Index of the directory descriptor argument the path is relative to, if any.
+    pub dirfd: Option<usize>,
+    /// Index of the path argument.
+    pub path: usize,
+    /// Index of the `envp` argument.
+    pub environment: usize,
+}
+
+/// Syscalls starting a new program.
+pub const EXEC_SYSCALLS: &[i64] = &[libc::SYS_execve, libc::SYS_execveat];
+
+/// Syscalls routed through the VFS.
+#[cfg(target_arch = "x86_64")]
+pub const PATH_SYSCALLS: &[i64] = &[
+    libc::SYS_open,
+    libc::SYS_openat,
+    libc::SYS_openat2,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_access,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+];
+
+/// Syscalls routed through the VFS.
+#[cfg(not(target_arch = "x86_64"))]
+pub const PATH_SYSCALLS: &[i64] = &[
+    libc::SYS_openat,
+    libc::SYS_openat2,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+];
+
+/// Returns the argument layout of `number`, or `None` if it isn't routed through the VFS.
+pub fn path_syscall(number: i64) -> Option<PathSyscall> {
+    let at = |kind| PathSyscall {
+        dirfd: Some(0),
+        path: 1,
+        kind,
+    };
+    Some(match number {
+        libc::SYS_openat => at(SyscallKind::Open { flags: 2, mode: 3 }),
+        libc::SYS_openat2 => at(SyscallKind::Openat2 { how: 2 }),
+        libc::SYS_newfstatat => at(SyscallKind::Stat {
+            buf: 2,
+            flags: Some(3),
+            no_follow: false,
+        }),
+        libc::SYS_statx => at(SyscallKind::Statx {
+            flags: 2,
+            mask: 3,
+            buf: 4,
+        }),
+        libc::SYS_faccessat => at(SyscallKind::Access {
+            mode: 2,
+            flags: None,
+        }),
+        libc::SYS_faccessat2 => at(SyscallKind::Access {
+            mode: 2,
+            flags: Some(3),
+        }),
+        _ => return legacy_path_syscall(number),
+    })
+}
+
+/// Returns the argument layout of `number`, or `None` if it doesn't start a new program.
+pub fn exec_syscall(number: i64) -> Option<ExecSyscall> {
+    match number {
+        libc::SYS_execve => Some(ExecSyscall {
+            dirfd: None,
+            path: 0,
+            environment: 2,
+        }),
+        libc::SYS_execveat => Some(ExecSyscall {
+            dirfd: Some(0),
+            path: 1,
+            environment: 3,
+        }),
+        _ => None,
+    }
+}
+
+// Syscalls without a directory argument, which newer architectures don't have
+#[cfg(target_arch = "x86_64")]
+fn legacy_path_syscall(number: i64) -> Option<PathSyscall> {
+    let kind = match number {
+        libc::SYS_open => SyscallKind::Open { flags: 1, mode: 2 },
+        libc::SYS_stat | libc::SYS_lstat => SyscallKind::Stat {
+            buf: 1,
+            flags: None,
+            no_follow: number == libc::SYS_lstat,
+        },
+        libc::SYS_access => SyscallKind::Access {
+            mode: 1,
+            flags: None,
+        },
+        _ => return None,
+    };
+
+    Some(PathSyscall {
+        dirfd: None,
+        path: 0,
+        kind,
+    })
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn legacy_path_syscall(_number: i64) -> Option<PathSyscall> {
+    None
+}
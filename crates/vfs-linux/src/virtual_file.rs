@@ -0,0 +1,134 @@
+// Serving virtual files to the program: file descriptors and metadata
+
+use crate::state::Vfs;
+use crate::sys;
+use libc::c_int;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, IntoRawFd};
+use virtual_file_framework::FileHandler;
+use virtual_filesystem::VirtualFileEntry;
+
+pub use virtual_file_framework::{
+    populate_memfd as populate, register_memfd_mapping as register_mapping, MemfdOptions,
+};
+
+/// Serves a virtual file's content from a file on the host.
+pub(crate) struct HostFile(pub(crate) File);
+
+impl FileHandler for HostFile {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        sys::pread(self.0.as_fd(), buffer, offset)
+    }
+}
+
+/// Opens a virtual file, returning a read-only descriptor or an `errno` value.
+///
+/// The content is served through Layer 2 as a sealed memory file, so the descriptor works with
+/// every API, including `mmap`. Files are filled when opened, unless `options` opt in to
+/// filling large files on demand; backends which do must call [`populate`] and
+/// [`register_mapping`] before passing reads and mappings of the descriptor through. Virtual
+/// files can't be written to.
+pub fn open(vfs: &Vfs, path: &str, flags: c_int, options: &MemfdOptions) -> Result<c_int, c_int> {
+    if flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0 {
+        return Err(libc::EACCES);
+    }
+
+    if flags & (libc::O_CREAT | libc::O_EXCL) == libc::O_CREAT | libc::O_EXCL {
+        return Err(libc::EEXIST);
+    }
+
+    if flags & libc::O_DIRECTORY != 0 {
+        return Err(libc::ENOTDIR);
+    }
+
+    let fd = vfs
+        .framework
+        .open_memfd(path, options)
+        .map_err(|x| errno_of(&x))?
+        .into_raw_fd();
+    if flags & libc::O_CLOEXEC == 0 {
+        // SAFETY: `fd` is the descriptor we just opened.
+        unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+    }
+
+    Ok(fd)
+}
+
+/// Checks `access`/`faccessat` permissions for a virtual file.
+///
+/// Virtual files are read-only and not executable.
+pub fn check_access(mode: c_int) -> Result<(), c_int> {
+    if mode & (libc::W_OK | libc::X_OK) != 0 {
+        return Err(libc::EACCES);
+    }
+
+    Ok(())
+}
+
+/// Fills `stat` with the metadata of a virtual file.
+pub fn fill_stat(entry: &VirtualFileEntry, stat: &mut libc::stat) {
+    let metadata = &entry.metadata;
+    // SAFETY: `stat` is plain old data.
+    *stat = unsafe { std::mem::zeroed() };
+    stat.st_ino = inode(entry);
+    stat.st_mode = libc::S_IFREG | 0o444;
+    stat.st_nlink = 1;
+    stat.st_uid = unsafe { libc::getuid() };
+    stat.st_gid = unsafe { libc::getgid() };
+    stat.st_size = metadata.end_of_file;
+    stat.st_blksize = 4096;
+    stat.st_blocks = (metadata.allocation_size as u64).div_ceil(512) as i64;
+    (stat.st_atime, stat.st_atime_nsec) = split_time(metadata.last_access_time);
+    (stat.st_mtime, stat.st_mtime_nsec) = split_time(metadata.last_write_time);
+    (stat.st_ctime, stat.st_ctime_nsec) = split_time(metadata.change_time);
+}
+
+/// Fills `statx` with the metadata of a virtual file.
+pub fn fill_statx(entry: &VirtualFileEntry, statx: &mut libc::statx) {
+    let metadata = &entry.metadata;
+    // SAFETY: `statx` is plain old data.
+    *statx = unsafe { std::mem::zeroed() };
+    statx.stx_mask = libc::STATX_BASIC_STATS | libc::STATX_BTIME;
+    statx.stx_blksize = 4096;
+    statx.stx_nlink = 1;
+    statx.stx_uid = unsafe { libc::getuid() };
+    statx.stx_gid = unsafe { libc::getgid() };
+    statx.stx_mode = (libc::S_IFREG | 0o444) as u16;
+    statx.stx_ino = inode(entry);
+    statx.stx_size = metadata.end_of_file as u64;
+    statx.stx_blocks = (metadata.allocation_size as u64).div_ceil(512);
+    statx.stx_atime = statx_time(metadata.last_access_time);
+    statx.stx_btime = statx_time(metadata.creation_time);
+    statx.stx_ctime = statx_time(metadata.change_time);
+    statx.stx_mtime = statx_time(metadata.last_write_time);
+}
+
+fn inode(entry: &VirtualFileEntry) -> u64 {
+    // Keep clear of real inode numbers on common filesystems.
+    entry.handle.as_raw() | (1 << 63)
+}
+
+fn split_time(nanos: i64) -> (i64, i64) {
+    (
+        nanos.div_euclid(1_000_000_000),
+        nanos.rem_euclid(1_000_000_000),
+    )
+}
+
+fn statx_time(nanos: i64) -> libc::statx_timestamp {
+    let (seconds, nanos) = split_time(nanos);
+    // SAFETY: `statx_timestamp` is plain old data.
+    let mut timestamp: libc::statx_timestamp = unsafe { std::mem::zeroed() };
+    timestamp.tv_sec = seconds;
+    timestamp.tv_nsec = nanos as u32;
+    timestamp
+}
+
+fn errno_of(error: &io::Error) -> c_int {
+    error.raw_os_error().unwrap_or(match error.kind() {
+        io::ErrorKind::NotFound => libc::ENOENT,
+        io::ErrorKind::PermissionDenied => libc::EACCES,
+        _ => libc::EIO,
+    })
+}
@@ -0,0 +1,39 @@
+#![cfg(all(target_os = "linux", target_pointer_width = "64"))]
+
+// Checks that the re-entrancy guard hooks rely on is held per thread. Re-entering the hooks
+// themselves is checked by vfs-preload's tests, with the real library loaded.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use vfs_linux::state;
+
+/// Loads a VFS redirecting `game/player.png` to `mod/player.png`; returns the root folder.
+fn root() -> &'static PathBuf {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = tempfile::tempdir().unwrap().keep();
+        fs::create_dir_all(root.join("game")).unwrap();
+        fs::create_dir_all(root.join("mod")).unwrap();
+        fs::write(root.join("game/player.png"), "original").unwrap();
+        fs::write(root.join("mod/player.png"), "modded").unwrap();
+        fs::write(
+            root.join("vfs.conf"),
+            "file game/player.png => mod/player.png\n",
+        )
+        .unwrap();
+
+        std::env::set_var("VFS_CONFIG", root.join("vfs.conf"));
+        state::initialise();
+        root
+    })
+}
+
+#[test]
+fn guard_is_per_thread() {
+    root();
+    let (_, _guard) = state::enter().unwrap();
+    assert!(state::enter().is_none());
+    let other_thread = std::thread::spawn(|| state::enter().is_some());
+    assert!(other_thread.join().unwrap());
+}
@@ -0,0 +1,169 @@
+// close, dup, fcntl and fork: keeping the descriptor table in step with the kernel's
+
+use crate::real::{real, try_real};
+use libc::{c_int, c_uint, c_ulong, pid_t, FILE};
+use vfs_linux::descriptors::{self, descriptors};
+use vfs_linux::state;
+
+type CloseFn = unsafe extern "C" fn(c_int) -> c_int;
+type DupFn = unsafe extern "C" fn(c_int) -> c_int;
+type Dup2Fn = unsafe extern "C" fn(c_int, c_int) -> c_int;
+type Dup3Fn = unsafe extern "C" fn(c_int, c_int, c_int) -> c_int;
+type FcntlFn = unsafe extern "C" fn(c_int, c_int, ...) -> c_int;
+type CloseRangeFn = unsafe extern "C" fn(c_uint, c_uint, c_int) -> c_int;
+type ForkFn = unsafe extern "C" fn() -> pid_t;
+type FcloseFn = unsafe extern "C" fn(*mut FILE) -> c_int;
+
+// The table is only updated once the real call succeeded, with the descriptor it returned.
+// Calls made from inside a hook never involve tracked descriptors, so they pass through.
+
+#[no_mangle]
+pub unsafe extern "C" fn close(fd: c_int) -> c_int {
+    // The descriptor is released even if `close` fails (e.g. `EINTR`).
+    if let Some((_, _guard)) = state::enter() {
+        forget(fd);
+    }
+
+    real!(close: CloseFn)(fd)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fclose(stream: *mut FILE) -> c_int {
+    // glibc closes the stream's descriptor internally, where `close` can't see it.
+    if let Some((_, _guard)) = state::enter() {
+        forget(libc::fileno(stream));
+    }
+
+    real!(fclose: FcloseFn)(stream)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn close_range(first: c_uint, last: c_uint, flags: c_int) -> c_int {
+    let Some(real_close_range) = try_real!(close_range: CloseRangeFn) else {
+        return super::fail(libc::ENOSYS);
+    };
+
+    let result = real_close_range(first, last, flags);
+    if result == 0 {
+        if let Some((_, _guard)) = state::enter() {
+            let range = first as usize..=last as usize;
+            for fd in descriptors()
+                .handles()
+                .into_iter()
+                .filter(|x| range.contains(x))
+            {
+                match flags & libc::CLOSE_RANGE_CLOEXEC as c_int != 0 {
+                    true => descriptors().set_close_on_exec(fd, true),
+                    false => descriptors().remove(fd).is_some(),
+                };
+            }
+        }
+    }
+
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dup(fd: c_int) -> c_int {
+    let new_fd = real!(dup: DupFn)(fd);
+    duplicated(fd, new_fd, false);
+    new_fd
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dup2(fd: c_int, new_fd: c_int) -> c_int {
+    let result = real!(dup2: Dup2Fn)(fd, new_fd);
+    // `dup2` onto itself changes nothing, not even the close-on-exec flag.
+    if result >= 0 && fd != new_fd {
+        closed_by_dup(result);
+        duplicated(fd, result, false);
+    }
+
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dup3(fd: c_int, new_fd: c_int, flags: c_int) -> c_int {
+    let result = real!(dup3: Dup3Fn)(fd, new_fd, flags);
+    if result >= 0 {
+        closed_by_dup(result);
+        duplicated(fd, result, flags & libc::O_CLOEXEC != 0);
+    }
+
+    result
+}
+
+// `arg` is an `int` or a pointer depending on `cmd`; both are passed in the same register.
+
+#[no_mangle]
+pub unsafe extern "C" fn fcntl(fd: c_int, cmd: c_int, arg: c_ulong) -> c_int {
+    fcntl_with(fd, cmd, arg, real!(fcntl: FcntlFn))
+}
+
+/// Exported since glibc 2.28, for `_FILE_OFFSET_BITS=64` builds.
+#[no_mangle]
+pub unsafe extern "C" fn fcntl64(fd: c_int, cmd: c_int, arg: c_ulong) -> c_int {
+    let Some(real_fcntl64) = try_real!(fcntl64: FcntlFn) else {
+        return super::fail(libc::ENOSYS);
+    };
+
+    fcntl_with(fd, cmd, arg, real_fcntl64)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fork() -> pid_t {
+    let real_fork = real!(fork: ForkFn);
+    match state::enter() {
+        Some((_, _guard)) => descriptors().fork(|| real_fork()),
+        None => real_fork(),
+    }
+}
+
+unsafe fn fcntl_with(fd: c_int, cmd: c_int, arg: c_ulong, real_fcntl: FcntlFn) -> c_int {
+    let result = real_fcntl(fd, cmd, arg);
+    if result < 0 {
+        return result;
+    }
+
+    match cmd {
+        libc::F_DUPFD => duplicated(fd, result, false),
+        libc::F_DUPFD_CLOEXEC => duplicated(fd, result, true),
+        libc::F_SETFD => {
+            if let (Some((_, _guard)), Some(fd)) = (state::enter(), descriptors::handle(fd)) {
+                descriptors().set_close_on_exec(fd, arg as c_int & libc::FD_CLOEXEC != 0);
+            }
+        }
+        _ => {}
+    }
+
+    result
+}
+
+/// Records `new_fd` (if valid) as a duplicate of `fd`.
+unsafe fn duplicated(fd: c_int, new_fd: c_int, close_on_exec: bool) {
+    let Some((_, _guard)) = state::enter() else {
+        return;
+    };
+
+    if let (Some(fd), Some(new_fd)) = (descriptors::handle(fd), descriptors::handle(new_fd)) {
+        descriptors().duplicate(fd, new_fd, close_on_exec);
+    }
+}
+
+/// Forgets whatever `new_fd` referred to before `dup2`/`dup3` closed it, in case `fd` itself
+/// isn't tracked.
+unsafe fn closed_by_dup(new_fd: c_int) {
+    let Some((_, _guard)) = state::enter() else {
+        return;
+    };
+
+    forget(new_fd);
+}
+
+/// Stops tracking `fd`. Most descriptors aren't tracked, so this avoids taking the table's
+/// write lock for them.
+fn forget(fd: c_int) {
+    if let Some(fd) = descriptors::handle(fd).filter(|x| descriptors().contains(*x)) {
+        descriptors().remove(fd);
+    }
+}
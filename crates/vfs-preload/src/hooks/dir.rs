@@ -0,0 +1,145 @@
+// Directory listing: appends virtual and redirected files to the real entries
+
+use crate::real::real;
+use libc::{c_char, c_int, DIR};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
+use std::sync::{Mutex, MutexGuard};
+use vfs_linux::{paths, state};
+use virtual_filesystem::path::file_name;
+
+type OpendirFn = unsafe extern "C" fn(*const c_char) -> *mut DIR;
+type ReaddirFn = unsafe extern "C" fn(*mut DIR) -> *mut libc::dirent64;
+type ClosedirFn = unsafe extern "C" fn(*mut DIR) -> c_int;
+type RewinddirFn = unsafe extern "C" fn(*mut DIR);
+
+// Listing state of a directory stream which has extra entries to inject
+struct DirectoryState {
+    // Names of virtual and redirected files to return after the real entries
+    extra: Vec<CString>,
+    // Index of the next entry in `extra`
+    next_extra: usize,
+    // Names returned so far (lowercased), so extra entries don't repeat real ones
+    seen: HashSet<String>,
+    // Storage for the entry returned to the program, valid until the next call
+    entry: Box<libc::dirent64>,
+}
+
+// Maps DIR pointer -> listing state. Streams without extra entries aren't tracked.
+static DIRECTORIES: Mutex<Option<HashMap<usize, DirectoryState>>> = Mutex::new(None);
+
+fn directories() -> MutexGuard<'static, Option<HashMap<usize, DirectoryState>>> {
+    let mut guard = DIRECTORIES.lock().unwrap_or_else(|x| x.into_inner());
+    guard.get_or_insert_with(HashMap::new);
+    guard
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn opendir(path: *const c_char) -> *mut DIR {
+    let dir = real!(opendir: OpendirFn)(path);
+    if dir.is_null() {
+        return dir;
+    }
+
+    if let Some((vfs, _guard)) = state::enter() {
+        let Some(path) = paths::absolute(libc::AT_FDCWD, path) else {
+            return dir;
+        };
+
+        let mut extra: Vec<CString> = (vfs.framework.virtual_files())
+            .entries_in_directory(&path)
+            .iter()
+            .map(|x| file_name(&x.path).to_owned())
+            .chain(vfs.redirector.file_names_in_directory(&path))
+            .filter_map(|x| CString::new(x).ok())
+            .collect();
+        extra.sort();
+        extra.dedup();
+        if !extra.is_empty() {
+            let state = DirectoryState {
+                extra,
+                next_extra: 0,
+                seen: HashSet::new(),
+                entry: Box::new(std::mem::zeroed()),
+            };
+            directories().as_mut().unwrap().insert(dir as usize, state);
+        }
+    }
+
+    dir
+}
+
+// `dirent` and `dirent64` share a layout on the supported 64-bit targets.
+
+#[no_mangle]
+pub unsafe extern "C" fn readdir(dir: *mut DIR) -> *mut libc::dirent {
+    read_directory(dir, real!(readdir: ReaddirFn)).cast()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readdir64(dir: *mut DIR) -> *mut libc::dirent64 {
+    read_directory(dir, real!(readdir64: ReaddirFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rewinddir(dir: *mut DIR) {
+    if let Some(_guard) = state::enter() {
+        if let Some(state) = directories().as_mut().unwrap().get_mut(&(dir as usize)) {
+            state.next_extra = 0;
+            state.seen.clear();
+        }
+    }
+
+    real!(rewinddir: RewinddirFn)(dir)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn closedir(dir: *mut DIR) -> c_int {
+    if let Some(_guard) = state::enter() {
+        directories().as_mut().unwrap().remove(&(dir as usize));
+    }
+    real!(closedir: ClosedirFn)(dir)
+}
+
+unsafe fn read_directory(dir: *mut DIR, real_readdir: ReaddirFn) -> *mut libc::dirent64 {
+    let entry = real_readdir(dir);
+    let Some(_guard) = state::enter() else {
+        return entry;
+    };
+
+    let mut directories = directories();
+    let Some(state) = directories.as_mut().unwrap().get_mut(&(dir as usize)) else {
+        return entry;
+    };
+
+    if !entry.is_null() {
+        let name = CStr::from_ptr((*entry).d_name.as_ptr()).to_string_lossy();
+        state.seen.insert(name.to_lowercase());
+        return entry;
+    }
+
+    while let Some(name) = state.extra.get(state.next_extra) {
+        state.next_extra += 1;
+        if !state.seen.insert(name.to_string_lossy().to_lowercase()) {
+            continue;
+        }
+
+        let entry = &mut *state.entry;
+        let bytes = name.as_bytes_with_nul();
+        if bytes.len() > entry.d_name.len() {
+            continue;
+        }
+
+        entry.d_ino = u64::MAX - state.next_extra as u64;
+        entry.d_off = 0;
+        entry.d_reclen = size_of::<libc::dirent64>() as u16;
+        entry.d_type = libc::DT_REG;
+        for (target, source) in entry.d_name.iter_mut().zip(bytes) {
+            *target = *source as c_char;
+        }
+
+        return entry;
+    }
+
+    std::ptr::null_mut()
+}
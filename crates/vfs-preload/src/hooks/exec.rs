@@ -0,0 +1,142 @@
+// exec and posix_spawn families: carrying the VFS into child processes
+
+use crate::real::{real, try_real};
+use libc::{c_char, c_int, pid_t, posix_spawn_file_actions_t, posix_spawnattr_t};
+use std::os::fd::BorrowedFd;
+use vfs_linux::descriptors::{self, descriptors};
+use vfs_linux::state;
+use vfs_linux::{propagation, virtual_file};
+
+type Argv = *const *const c_char;
+type ExecveFn = unsafe extern "C" fn(*const c_char, Argv, Argv) -> c_int;
+type ExecveatFn = unsafe extern "C" fn(c_int, *const c_char, Argv, Argv, c_int) -> c_int;
+type FexecveFn = unsafe extern "C" fn(c_int, Argv, Argv) -> c_int;
+type PosixSpawnFn = unsafe extern "C" fn(
+    *mut pid_t,
+    *const c_char,
+    *const posix_spawn_file_actions_t,
+    *const posix_spawnattr_t,
+    Argv,
+    Argv,
+) -> c_int;
+
+// `execl`, `execlp` and `execle` are variadic, which can't be defined in stable Rust, and
+// glibc's `system` and `popen` call its internal `posix_spawn`. These reach children through
+// the inherited `environ` only, which still carries the VFS unless the program scrubbed it.
+
+extern "C" {
+    static environ: Argv;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execve(path: *const c_char, argv: Argv, envp: Argv) -> c_int {
+    with_environment(libc::AT_FDCWD, path, envp, |envp| {
+        real!(execve: ExecveFn)(path, argv, envp)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execv(path: *const c_char, argv: Argv) -> c_int {
+    execve(path, argv, environ)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execvpe(file: *const c_char, argv: Argv, envp: Argv) -> c_int {
+    with_environment(libc::AT_FDCWD, file, envp, |envp| {
+        real!(execvpe: ExecveFn)(file, argv, envp)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn execvp(file: *const c_char, argv: Argv) -> c_int {
+    execvpe(file, argv, environ)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fexecve(fd: c_int, argv: Argv, envp: Argv) -> c_int {
+    with_environment(fd, c"".as_ptr(), envp, |envp| {
+        real!(fexecve: FexecveFn)(fd, argv, envp)
+    })
+}
+
+/// Exported since glibc 2.34.
+#[no_mangle]
+pub unsafe extern "C" fn execveat(
+    dirfd: c_int,
+    path: *const c_char,
+    argv: Argv,
+    envp: Argv,
+    flags: c_int,
+) -> c_int {
+    let Some(real) = try_real!(execveat: ExecveatFn) else {
+        return super::fail(libc::ENOSYS);
+    };
+
+    with_environment(dirfd, path, envp, |envp| {
+        real(dirfd, path, argv, envp, flags)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn posix_spawn(
+    pid: *mut pid_t,
+    path: *const c_char,
+    file_actions: *const posix_spawn_file_actions_t,
+    attributes: *const posix_spawnattr_t,
+    argv: Argv,
+    envp: Argv,
+) -> c_int {
+    with_environment(libc::AT_FDCWD, path, envp, |envp| {
+        real!(posix_spawn: PosixSpawnFn)(pid, path, file_actions, attributes, argv, envp)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn posix_spawnp(
+    pid: *mut pid_t,
+    file: *const c_char,
+    file_actions: *const posix_spawn_file_actions_t,
+    attributes: *const posix_spawnattr_t,
+    argv: Argv,
+    envp: Argv,
+) -> c_int {
+    with_environment(libc::AT_FDCWD, file, envp, |envp| {
+        real!(posix_spawnp: PosixSpawnFn)(pid, file, file_actions, attributes, argv, envp)
+    })
+}
+
+/// Calls `start` with the environment a child running the program at `path` (relative to
+/// `dirfd`) should get.
+///
+/// Passes `envp` through unchanged if the VFS isn't active.
+unsafe fn with_environment(
+    dirfd: c_int,
+    path: *const c_char,
+    envp: Argv,
+    start: impl FnOnce(Argv) -> c_int,
+) -> c_int {
+    let Some((vfs, _guard)) = state::enter() else {
+        return start(envp);
+    };
+
+    fill_inherited_virtual_files();
+    let program = propagation::program_path(dirfd, path);
+    let environment = vfs.propagation.child_environment(&program, envp);
+    start(environment.as_ptr())
+}
+
+/// Fills every lazily served virtual file the child inherits; nothing fills them once another
+/// program has the descriptor.
+fn fill_inherited_virtual_files() {
+    for fd in descriptors().inherited().handles() {
+        let Ok(fd) = c_int::try_from(fd) else {
+            continue;
+        };
+
+        if descriptors::is_virtual(fd) {
+            // SAFETY: `fd` is tracked, so it is open.
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            let _ = virtual_file::populate(fd, Some(0), u64::MAX);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+// Exported replacements for libc functions
+//
+// These are called by C code under the contract of the libc function they replace,
+// so they carry no safety docs of their own.
+#![allow(clippy::missing_safety_doc)]
+
+use libc::c_int;
+
+mod descriptor;
+mod dir;
+mod exec;
+mod open;
+mod read;
+mod stat;
+
+/// Sets `errno` and returns `-1`.
+fn fail(errno: c_int) -> c_int {
+    // SAFETY: `__errno_location` always returns a valid pointer for the calling thread.
+    unsafe { *libc::__errno_location() = errno };
+    -1
+}
@@ -0,0 +1,168 @@
+// open and fopen families
+
+use super::fail;
+use crate::real::real;
+use libc::{c_char, c_int, c_uint, mode_t, FILE};
+use std::ffi::CStr;
+use vfs_linux::descriptors::{self, Description};
+use vfs_linux::paths::{self, Target};
+use vfs_linux::state;
+use vfs_linux::virtual_file::{self, MemfdOptions};
+
+type OpenatFn = unsafe extern "C" fn(c_int, *const c_char, c_int, ...) -> c_int;
+type FopenFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut FILE;
+
+// `open` and `openat` are variadic in C, with `mode` only passed when creating a file.
+// The supported ABIs (x86_64 SysV, AArch64 Linux) pass variadic arguments the same way as
+// regular ones, so declaring `mode` as a regular parameter reads the same register.
+// When the caller didn't pass it, the value is garbage, but it is then also ignored.
+
+#[no_mangle]
+pub unsafe extern "C" fn open(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    open_at(libc::AT_FDCWD, path, flags, mode)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn open64(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    open_at(libc::AT_FDCWD, path, flags, mode)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openat(
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    open_at(dirfd, path, flags, mode)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn openat64(
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    open_at(dirfd, path, flags, mode)
+}
+
+// Called instead of `open`/`openat` in programs built with `_FORTIFY_SOURCE`, when `flags`
+// is known at compile time not to need a mode.
+
+#[no_mangle]
+pub unsafe extern "C" fn __open_2(path: *const c_char, flags: c_int) -> c_int {
+    open_at(libc::AT_FDCWD, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __open64_2(path: *const c_char, flags: c_int) -> c_int {
+    open_at(libc::AT_FDCWD, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __openat_2(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    open_at(dirfd, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __openat64_2(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    open_at(dirfd, path, flags, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fopen(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    fopen_with(path, mode, real!(fopen: FopenFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fopen64(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    fopen_with(path, mode, real!(fopen64: FopenFn))
+}
+
+unsafe fn open_at(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    let real_openat = real!(openat: OpenatFn);
+    if let Some((vfs, _guard)) = state::enter() {
+        match paths::resolve(vfs, dirfd, path, flags & libc::O_CREAT != 0) {
+            Target::Virtual(path) => {
+                let options = MemfdOptions::default();
+                return match virtual_file::open(vfs, &path, flags, &options) {
+                    Ok(fd) => {
+                        descriptors::track(fd, Description::Virtual(path), flags);
+                        fd
+                    }
+                    Err(errno) => fail(errno),
+                };
+            }
+            Target::Redirected(target) => {
+                let fd = real_openat(libc::AT_FDCWD, target.as_ptr(), flags, mode as c_uint);
+                let path = paths::absolute(dirfd, path).unwrap_or_default();
+                descriptors::track(fd, Description::Redirected(path), flags);
+                return fd;
+            }
+            Target::Original => {}
+        }
+    }
+
+    real_openat(dirfd, path, flags, mode as c_uint)
+}
+
+unsafe fn fopen_with(path: *const c_char, mode: *const c_char, real_fopen: FopenFn) -> *mut FILE {
+    if let Some((vfs, _guard)) = state::enter() {
+        let flags = open_flags(CStr::from_ptr(mode).to_bytes());
+        match paths::resolve(vfs, libc::AT_FDCWD, path, flags & libc::O_CREAT != 0) {
+            Target::Virtual(path) => {
+                // Streams read through glibc internals we can't intercept, so fill up front.
+                let fd = match virtual_file::open(vfs, &path, flags, &MemfdOptions::eager()) {
+                    Ok(fd) => fd,
+                    Err(errno) => {
+                        fail(errno);
+                        return std::ptr::null_mut();
+                    }
+                };
+
+                let file = libc::fdopen(fd, mode);
+                if file.is_null() {
+                    libc::close(fd);
+                    return file;
+                }
+
+                descriptors::track(fd, Description::Virtual(path), flags);
+                return file;
+            }
+            Target::Redirected(target) => {
+                let file = real_fopen(target.as_ptr(), mode);
+                if !file.is_null() {
+                    let path = paths::absolute(libc::AT_FDCWD, path).unwrap_or_default();
+                    descriptors::track(libc::fileno(file), Description::Redirected(path), flags);
+                }
+
+                return file;
+            }
+            Target::Original => {}
+        }
+    }
+
+    real_fopen(path, mode)
+}
+
+/// Converts an `fopen` mode string into the `open` flags glibc would use for it.
+fn open_flags(mode: &[u8]) -> c_int {
+    let mut flags = match mode.first() {
+        Some(b'w') => libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+        Some(b'a') => libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+        _ => libc::O_RDONLY,
+    };
+
+    for c in mode.iter().skip(1) {
+        match c {
+            b'+' => flags = (flags & !libc::O_ACCMODE) | libc::O_RDWR,
+            b'e' => flags |= libc::O_CLOEXEC,
+            b'x' => flags |= libc::O_EXCL,
+            b',' => break,
+            _ => {}
+        }
+    }
+
+    flags
+}
@@ -0,0 +1,283 @@
+// read, mmap and friends: filling lazily served virtual files before the kernel reads them
+
+use super::fail;
+use crate::real::real;
+use libc::{c_int, c_uint, c_void, iovec, loff_t, off_t, size_t, ssize_t};
+use std::os::fd::BorrowedFd;
+use vfs_linux::{descriptors, state, virtual_file};
+
+type ReadFn = unsafe extern "C" fn(c_int, *mut c_void, size_t) -> ssize_t;
+type PreadFn = unsafe extern "C" fn(c_int, *mut c_void, size_t, off_t) -> ssize_t;
+type ReadvFn = unsafe extern "C" fn(c_int, *const iovec, c_int) -> ssize_t;
+type PreadvFn = unsafe extern "C" fn(c_int, *const iovec, c_int, off_t) -> ssize_t;
+type SendfileFn = unsafe extern "C" fn(c_int, c_int, *mut off_t, size_t) -> ssize_t;
+type SpliceFn =
+    unsafe extern "C" fn(c_int, *mut loff_t, c_int, *mut loff_t, size_t, c_uint) -> ssize_t;
+type MmapFn = unsafe extern "C" fn(*mut c_void, size_t, c_int, c_int, c_int, off_t) -> *mut c_void;
+
+// Large virtual files are served as memory files which start out empty (see
+// `virtual_file::open`). The kernel reads holes as zeros rather than asking for content, so
+// every call which reads the file in the kernel fills the range it covers first. Reads through
+// glibc internals (`fread` and co.) can't be seen here; streams are filled up front instead.
+
+#[no_mangle]
+pub unsafe extern "C" fn read(fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t {
+    if !populate(fd, None, count) {
+        return -1;
+    }
+
+    real!(read: ReadFn)(fd, buf, count)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pread(
+    fd: c_int,
+    buf: *mut c_void,
+    count: size_t,
+    offset: off_t,
+) -> ssize_t {
+    pread_with(fd, buf, count, offset, real!(pread: PreadFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pread64(
+    fd: c_int,
+    buf: *mut c_void,
+    count: size_t,
+    offset: off_t,
+) -> ssize_t {
+    pread_with(fd, buf, count, offset, real!(pread64: PreadFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readv(fd: c_int, iov: *const iovec, iovcnt: c_int) -> ssize_t {
+    if !populate(fd, None, total_length(iov, iovcnt)) {
+        return -1;
+    }
+
+    real!(readv: ReadvFn)(fd, iov, iovcnt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn preadv(
+    fd: c_int,
+    iov: *const iovec,
+    iovcnt: c_int,
+    offset: off_t,
+) -> ssize_t {
+    preadv_with(fd, iov, iovcnt, offset, real!(preadv: PreadvFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn preadv64(
+    fd: c_int,
+    iov: *const iovec,
+    iovcnt: c_int,
+    offset: off_t,
+) -> ssize_t {
+    preadv_with(fd, iov, iovcnt, offset, real!(preadv64: PreadvFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut off_t,
+    count: size_t,
+) -> ssize_t {
+    sendfile_with(out_fd, in_fd, offset, count, real!(sendfile: SendfileFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sendfile64(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut off_t,
+    count: size_t,
+) -> ssize_t {
+    sendfile_with(out_fd, in_fd, offset, count, real!(sendfile64: SendfileFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn splice(
+    fd_in: c_int,
+    off_in: *mut loff_t,
+    fd_out: c_int,
+    off_out: *mut loff_t,
+    len: size_t,
+    flags: c_uint,
+) -> ssize_t {
+    if !populate(fd_in, offset_at(off_in), len) {
+        return -1;
+    }
+
+    real!(splice: SpliceFn)(fd_in, off_in, fd_out, off_out, len, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn copy_file_range(
+    fd_in: c_int,
+    off_in: *mut loff_t,
+    fd_out: c_int,
+    off_out: *mut loff_t,
+    len: size_t,
+    flags: c_uint,
+) -> ssize_t {
+    if !populate(fd_in, offset_at(off_in), len) {
+        return -1;
+    }
+
+    real!(copy_file_range: SpliceFn)(fd_in, off_in, fd_out, off_out, len, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mmap(
+    addr: *mut c_void,
+    length: size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+) -> *mut c_void {
+    mmap_with(addr, length, prot, flags, fd, offset, real!(mmap: MmapFn))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mmap64(
+    addr: *mut c_void,
+    length: size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+) -> *mut c_void {
+    mmap_with(addr, length, prot, flags, fd, offset, real!(mmap64: MmapFn))
+}
+
+unsafe fn pread_with(
+    fd: c_int,
+    buf: *mut c_void,
+    count: size_t,
+    offset: off_t,
+    real_pread: PreadFn,
+) -> ssize_t {
+    if !populate(fd, Some(offset), count) {
+        return -1;
+    }
+
+    real_pread(fd, buf, count, offset)
+}
+
+unsafe fn preadv_with(
+    fd: c_int,
+    iov: *const iovec,
+    iovcnt: c_int,
+    offset: off_t,
+    real_preadv: PreadvFn,
+) -> ssize_t {
+    if !populate(fd, Some(offset), total_length(iov, iovcnt)) {
+        return -1;
+    }
+
+    real_preadv(fd, iov, iovcnt, offset)
+}
+
+unsafe fn sendfile_with(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut off_t,
+    count: size_t,
+    real_sendfile: SendfileFn,
+) -> ssize_t {
+    if !populate(in_fd, offset_at(offset), count) {
+        return -1;
+    }
+
+    real_sendfile(out_fd, in_fd, offset, count)
+}
+
+unsafe fn mmap_with(
+    addr: *mut c_void,
+    length: size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+    real_mmap: MmapFn,
+) -> *mut c_void {
+    if fd < 0 || flags & libc::MAP_ANONYMOUS != 0 {
+        return real_mmap(addr, length, prot, flags, fd, offset);
+    }
+
+    // These fault the whole mapping in before we can register it; fill it first instead.
+    if flags & (libc::MAP_POPULATE | libc::MAP_LOCKED) != 0 && !populate(fd, Some(offset), length) {
+        return libc::MAP_FAILED;
+    }
+
+    let address = real_mmap(addr, length, prot, flags, fd, offset);
+    if address == libc::MAP_FAILED {
+        return address;
+    }
+
+    let Some((_, _guard)) = state::enter() else {
+        return address;
+    };
+
+    if !descriptors::is_virtual(fd) {
+        return address;
+    }
+
+    let fd = BorrowedFd::borrow_raw(fd);
+    if let Err(error) =
+        virtual_file::register_mapping(fd, address as usize, length, offset as u64, prot)
+    {
+        libc::munmap(address, length);
+        fail(error.raw_os_error().unwrap_or(libc::EIO));
+        return libc::MAP_FAILED;
+    }
+
+    address
+}
+
+/// Fills the range a read of `fd` is about to access, if it's a lazily served virtual file.
+/// `None` reads from the file position.
+///
+/// Returns `false` with `errno` set on failure.
+unsafe fn populate(fd: c_int, offset: Option<off_t>, length: size_t) -> bool {
+    // Invalid arguments are left for the real function to reject.
+    if offset.is_some_and(|x| x < 0) {
+        return true;
+    }
+
+    let Some((_, _guard)) = state::enter() else {
+        return true;
+    };
+
+    if !descriptors::is_virtual(fd) {
+        return true;
+    }
+
+    let fd = BorrowedFd::borrow_raw(fd);
+    match virtual_file::populate(fd, offset.map(|x| x as u64), length as u64) {
+        Ok(()) => true,
+        Err(error) => {
+            fail(error.raw_os_error().unwrap_or(libc::EIO));
+            false
+        }
+    }
+}
+
+/// The offset an optional offset pointer stands for; `None` (the file position) if null.
+unsafe fn offset_at(offset: *const off_t) -> Option<off_t> {
+    (!offset.is_null()).then(|| *offset)
+}
+
+unsafe fn total_length(iov: *const iovec, iovcnt: c_int) -> size_t {
+    if iov.is_null() || iovcnt <= 0 {
+        return 0;
+    }
+
+    std::slice::from_raw_parts(iov, iovcnt as usize)
+        .iter()
+        .fold(0, |total: size_t, x| total.saturating_add(x.iov_len))
+}
@@ -0,0 +1,205 @@
+// stat, statx and access families
+
+use super::fail;
+use crate::real::{real, try_real};
+use libc::{c_char, c_int, c_uint};
+use vfs_linux::paths::{self, Target};
+use vfs_linux::{state, virtual_file};
+
+type FstatatFn = unsafe extern "C" fn(c_int, *const c_char, *mut libc::stat, c_int) -> c_int;
+type FxstatatFn =
+    unsafe extern "C" fn(c_int, c_int, *const c_char, *mut libc::stat, c_int) -> c_int;
+type StatxFn = unsafe extern "C" fn(c_int, *const c_char, c_int, c_uint, *mut libc::statx) -> c_int;
+type FaccessatFn = unsafe extern "C" fn(c_int, *const c_char, c_int, c_int) -> c_int;
+
+// `_STAT_VER` passed to the `__xstat` family on the supported 64-bit targets.
+const STAT_VERSION: c_int = if cfg!(target_arch = "x86_64") { 1 } else { 0 };
+
+// glibc 2.33+ exports `stat`, `lstat` and `fstatat` directly; programs built against older
+// versions call the `__xstat` family instead. Both are handled the same way.
+
+#[no_mangle]
+pub unsafe extern "C" fn stat(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn stat64(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lstat(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, libc::AT_SYMLINK_NOFOLLOW)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lstat64(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, libc::AT_SYMLINK_NOFOLLOW)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fstatat(
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    stat_at(dirfd, path, buf, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fstatat64(
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    stat_at(dirfd, path, buf, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __xstat(
+    _version: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __xstat64(
+    _version: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __lxstat(
+    _version: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, libc::AT_SYMLINK_NOFOLLOW)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __lxstat64(
+    _version: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+) -> c_int {
+    stat_at(libc::AT_FDCWD, path, buf, libc::AT_SYMLINK_NOFOLLOW)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __fxstatat(
+    _version: c_int,
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    stat_at(dirfd, path, buf, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __fxstatat64(
+    _version: c_int,
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    stat_at(dirfd, path, buf, flags)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn statx(
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mask: c_uint,
+    buf: *mut libc::statx,
+) -> c_int {
+    let real_statx = real!(statx: StatxFn);
+    if let Some((vfs, _guard)) = state::enter() {
+        match paths::resolve(vfs, dirfd, path, false) {
+            Target::Virtual(path) => {
+                if let Some(entry) = vfs.framework.virtual_files().get(&path) {
+                    virtual_file::fill_statx(&entry, &mut *buf);
+                    return 0;
+                }
+            }
+            Target::Redirected(target) => {
+                return real_statx(libc::AT_FDCWD, target.as_ptr(), flags, mask, buf)
+            }
+            Target::Original => {}
+        }
+    }
+
+    real_statx(dirfd, path, flags, mask, buf)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn access(path: *const c_char, mode: c_int) -> c_int {
+    access_at(libc::AT_FDCWD, path, mode, 0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn faccessat(
+    dirfd: c_int,
+    path: *const c_char,
+    mode: c_int,
+    flags: c_int,
+) -> c_int {
+    access_at(dirfd, path, mode, flags)
+}
+
+unsafe fn stat_at(dirfd: c_int, path: *const c_char, buf: *mut libc::stat, flags: c_int) -> c_int {
+    if let Some((vfs, _guard)) = state::enter() {
+        match paths::resolve(vfs, dirfd, path, false) {
+            Target::Virtual(path) => {
+                if let Some(entry) = vfs.framework.virtual_files().get(&path) {
+                    virtual_file::fill_stat(&entry, &mut *buf);
+                    return 0;
+                }
+            }
+            Target::Redirected(target) => {
+                return real_fstatat(libc::AT_FDCWD, target.as_ptr(), buf, flags)
+            }
+            Target::Original => {}
+        }
+    }
+
+    real_fstatat(dirfd, path, buf, flags)
+}
+
+unsafe fn real_fstatat(
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+    flags: c_int,
+) -> c_int {
+    match try_real!(fstatat: FstatatFn) {
+        Some(real_fstatat) => real_fstatat(dirfd, path, buf, flags),
+        None => real!(__fxstatat: FxstatatFn)(STAT_VERSION, dirfd, path, buf, flags),
+    }
+}
+
+unsafe fn access_at(dirfd: c_int, path: *const c_char, mode: c_int, flags: c_int) -> c_int {
+    let real_faccessat = real!(faccessat: FaccessatFn);
+    if let Some((vfs, _guard)) = state::enter() {
+        match paths::resolve(vfs, dirfd, path, false) {
+            Target::Virtual(_) => return virtual_file::check_access(mode).map_or_else(fail, |_| 0),
+            Target::Redirected(target) => {
+                return real_faccessat(libc::AT_FDCWD, target.as_ptr(), mode, flags)
+            }
+            Target::Original => {}
+        }
+    }
+
+    real_faccessat(dirfd, path, mode, flags)
+}
@@ -0,0 +1,21 @@
+//! `LD_PRELOAD` shim providing Layer 1 redirection for dynamically linked (glibc) programs.
+//!
+//! Interposes the libc file APIs, consults the [`Redirector`](virtual_filesystem::Redirector)
+//! for path rewrites and the Layer 2 registry for virtual files. Programs which make syscalls
+//! directly (statically linked `musl`, Zig, etc.) are not covered; see `vfs-syscall-patch`.
+//!
+//! Configuration is read from the file named by the `VFS_CONFIG` environment variable,
+//! see `vfs-linux` for the format.
+//!
+//! Only 64-bit Linux targets are supported: hooks rely on `stat`/`stat64` and
+//! `dirent`/`dirent64` sharing a layout, and on variadic arguments being passed like
+//! regular ones.
+#![cfg(all(target_os = "linux", target_pointer_width = "64"))]
+
+mod hooks;
+mod real;
+
+// Runs when the library is loaded, before the program's `main`.
+#[used]
+#[link_section = ".init_array"]
+static INITIALISE: extern "C" fn() = vfs_linux::state::initialise;
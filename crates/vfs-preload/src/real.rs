@@ -0,0 +1,55 @@
+// Lookup of the original (next in search order) libc functions
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Resolves the next definition of a libc function with `dlsym(RTLD_NEXT)`.
+///
+/// Evaluates to an `Option` of the function pointer of type `$ty`. `None` if this libc doesn't
+/// export the function, e.g. `fstatat` before glibc 2.33. The lookup is cached.
+macro_rules! try_real {
+    ($name:ident: $ty:ty) => {{
+        static ADDRESS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        match $crate::real::lookup(concat!(stringify!($name), "\0"), &ADDRESS) {
+            0 => None,
+            address => Some(std::mem::transmute::<usize, $ty>(address)),
+        }
+    }};
+}
+
+/// Like [`try_real`], but for functions every supported libc exports.
+macro_rules! real {
+    ($name:ident: $ty:ty) => {
+        match $crate::real::try_real!($name: $ty) {
+            Some(function) => function,
+            None => $crate::real::missing(stringify!($name)),
+        }
+    };
+}
+
+pub(crate) use {real, try_real};
+
+// `name` must be nul terminated. Cached in `cache`: 0 = not looked up yet, 1 = not found.
+pub(crate) fn lookup(name: &str, cache: &AtomicUsize) -> usize {
+    let address = match cache.load(Ordering::Relaxed) {
+        0 => {
+            debug_assert!(name.ends_with('\0'));
+            // SAFETY: `name` is nul terminated.
+            let address = unsafe { libc::dlsym(libc::RTLD_NEXT, name.as_ptr().cast()) } as usize;
+            let address = address.max(1);
+            cache.store(address, Ordering::Relaxed);
+            address
+        }
+        address => address,
+    };
+
+    if address == 1 {
+        0
+    } else {
+        address
+    }
+}
+
+pub(crate) fn missing(name: &str) -> ! {
+    vfs_linux::sys::report(&format!("vfs-preload: libc does not export {name}"));
+    std::process::abort();
+}
@@ -0,0 +1,325 @@
+#![cfg(all(target_os = "linux", target_pointer_width = "64"))]
+
+// Runs a small C program under the shim with LD_PRELOAD and checks what it sees.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+struct Fixture {
+    root: TempDir,
+}
+
+impl Fixture {
+    /// Creates `game/` and `mod/` folders and a config with the given lines.
+    /// `{root}` in the config is replaced with the fixture's root folder.
+    fn new(config: &str) -> Self {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("game/data")).unwrap();
+        fs::create_dir_all(root.path().join("game/saves")).unwrap();
+        fs::create_dir_all(root.path().join("mod/data/sub")).unwrap();
+        fs::create_dir_all(root.path().join("mod/saves")).unwrap();
+        fs::write(root.path().join("game/data/player.png"), "original").unwrap();
+        fs::write(root.path().join("game/data/untouched.txt"), "untouched").unwrap();
+        fs::write(root.path().join("mod/player.png"), "modded").unwrap();
+        fs::write(root.path().join("mod/extra.bin"), "virtual content").unwrap();
+        fs::write(root.path().join("mod/data/new.txt"), "new file").unwrap();
+        fs::write(root.path().join("mod/data/sub/deep.txt"), "deep").unwrap();
+        fs::write(root.path().join("game/saves/original.sav"), "old save").unwrap();
+        fs::write(root.path().join("mod/saves/override.sav"), "override save").unwrap();
+
+        let config = config.replace("{root}", &root.path().to_string_lossy());
+        fs::write(root.path().join("vfs.conf"), config).unwrap();
+        Self { root }
+    }
+
+    fn path(&self, relative: &str) -> String {
+        self.root
+            .path()
+            .join(relative)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// The probe, with the shim loaded.
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(probe());
+        command
+            .args(args)
+            .env("LD_PRELOAD", library())
+            .env("VFS_CONFIG", self.root.path().join("vfs.conf"));
+        command
+    }
+
+    /// Runs the probe with the shim loaded; returns its output and whether it succeeded.
+    fn run(&self, args: &[&str]) -> (String, bool) {
+        run(self.command(args))
+    }
+
+    fn output(&self, args: &[&str]) -> String {
+        let (output, success) = self.run(args);
+        assert!(success, "{args:?} failed: {output}");
+        output
+    }
+}
+
+/// Runs `command`, which must not print errors; returns its output and whether it succeeded.
+fn run(mut command: Command) -> (String, bool) {
+    let output = command.output().unwrap();
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.success(),
+    )
+}
+
+fn library() -> &'static Path {
+    // `cargo test` doesn't build cdylibs, so build it here. Test binaries live in
+    // target/<profile>/deps, the library in target/<profile>.
+    static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let exe = std::env::current_exe().unwrap();
+        let profile_dir = exe.parent().unwrap().parent().unwrap();
+        let mut command = Command::new(env!("CARGO"));
+        command
+            .args(["build", "--lib", "--package", env!("CARGO_PKG_NAME")])
+            .arg("--manifest-path")
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+            .arg("--target-dir")
+            .arg(profile_dir.parent().unwrap());
+        if profile_dir.file_name().unwrap() == "release" {
+            command.arg("--release");
+        }
+
+        assert!(command.status().unwrap().success());
+        profile_dir.join("libvfs_preload.so")
+    })
+}
+
+fn probe() -> &'static Path {
+    static PROBE: OnceLock<PathBuf> = OnceLock::new();
+    PROBE.get_or_init(|| {
+        let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("vfs-preload-probe");
+        let status = Command::new("cc")
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/probe.c"))
+            .arg("-o")
+            .arg(&output)
+            .status()
+            .expect("a C compiler (cc) is required to build the test program");
+        assert!(status.success());
+        output
+    })
+}
+
+/// Builds tests/reenter.c, a library to load after the shim.
+fn reenter_library() -> &'static Path {
+    static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("libvfs-preload-reenter.so");
+        let status = Command::new("cc")
+            .args(["-shared", "-fPIC"])
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reenter.c"))
+            .arg("-o")
+            .arg(&output)
+            .arg("-ldl")
+            .status()
+            .expect("a C compiler (cc) is required to build the test library");
+        assert!(status.success());
+        output
+    })
+}
+
+const CONFIG: &str = "
+# Tier 1
+file {root}/game/data/player.png => {root}/mod/player.png
+folder-as-files {root}/game/data => {root}/mod/data
+# Tier 2
+folder {root}/game/saves => {root}/mod/saves
+virtual {root}/game/data/extra.bin => {root}/mod/extra.bin
+";
+
+#[test]
+fn file_redirect_applies_to_open_and_fopen() {
+    let fixture = Fixture::new(CONFIG);
+    let player = fixture.path("game/data/player.png");
+    assert_eq!(fixture.output(&["open", &player]), "modded");
+    assert_eq!(fixture.output(&["fopen", &player]), "modded");
+    assert_eq!(
+        fixture.output(&["openat", &fixture.path("game"), "data/../data/PLAYER.png"]),
+        "modded"
+    );
+    assert_eq!(
+        fixture.output(&["open", &fixture.path("game/data/untouched.txt")]),
+        "untouched"
+    );
+}
+
+#[test]
+fn folder_as_files_makes_new_files_visible() {
+    let fixture = Fixture::new(CONFIG);
+    assert_eq!(
+        fixture.output(&["open", &fixture.path("game/data/new.txt")]),
+        "new file"
+    );
+    assert_eq!(
+        fixture.output(&["open", &fixture.path("game/data/sub/deep.txt")]),
+        "deep"
+    );
+    assert_eq!(
+        fixture.output(&["stat", &fixture.path("game/data/new.txt")]),
+        "size=8 regular=1\n"
+    );
+}
+
+#[test]
+fn folder_redirect_falls_back_to_original() {
+    let fixture = Fixture::new(CONFIG);
+    assert_eq!(
+        fixture.output(&["open", &fixture.path("game/saves/override.sav")]),
+        "override save"
+    );
+    assert_eq!(
+        fixture.output(&["open", &fixture.path("game/saves/original.sav")]),
+        "old save"
+    );
+
+    // New files are created in the redirected folder.
+    fixture.output(&["write", &fixture.path("game/saves/new.sav"), "created"]);
+    assert_eq!(
+        fs::read_to_string(fixture.path("mod/saves/new.sav")).unwrap(),
+        "created"
+    );
+    assert!(!Path::new(&fixture.path("game/saves/new.sav")).exists());
+}
+
+#[test]
+fn virtual_file_is_readable_and_reports_metadata() {
+    let fixture = Fixture::new(CONFIG);
+    let extra = fixture.path("game/data/extra.bin");
+    assert_eq!(fixture.output(&["open", &extra]), "virtual content");
+    assert_eq!(fixture.output(&["fopen", &extra]), "virtual content");
+    assert_eq!(fixture.output(&["stat", &extra]), "size=15 regular=1\n");
+    assert_eq!(fixture.output(&["statx", &extra]), "size=15 regular=1\n");
+    assert_eq!(fixture.output(&["access", &extra, "r"]), "ok\n");
+    assert_eq!(
+        fixture.run(&["access", &extra, "w"]),
+        (format!("errno={}\n", libc::EACCES), false)
+    );
+    assert_eq!(
+        fixture.run(&["write", &extra, "x"]),
+        (format!("errno={}\n", libc::EACCES), false)
+    );
+}
+
+#[test]
+fn large_virtual_file_is_filled_on_demand() {
+    // Over the size which is copied up front, so content arrives as it's read.
+    let content: Vec<u8> = (0..5 * 1024 * 1024 + 123)
+        .map(|x| (x % 251) as u8)
+        .collect();
+    let fixture = Fixture::new(&format!(
+        "{CONFIG}virtual {{root}}/game/data/large.bin => {{root}}/mod/large.bin\n"
+    ));
+    fs::write(fixture.path("mod/large.bin"), &content).unwrap();
+    let large = fixture.path("game/data/large.bin");
+    for command in ["open", "mmap", "sendfile", "dup", "fork"] {
+        let output = Command::new(probe())
+            .args([command, &large])
+            .env("LD_PRELOAD", library())
+            .env("VFS_CONFIG", fixture.path("vfs.conf"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{command} failed");
+        assert!(output.stdout == content, "{command} read the wrong content");
+    }
+}
+
+#[test]
+fn directory_listing_includes_redirected_and_virtual_files() {
+    let fixture = Fixture::new(CONFIG);
+    let mut names: Vec<String> = fixture
+        .output(&["ls", &fixture.path("game/data")])
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["extra.bin", "new.txt", "player.png", "untouched.txt"]
+    );
+}
+
+#[test]
+fn passes_through_without_config() {
+    let fixture = Fixture::new("");
+    let player = fixture.path("game/data/player.png");
+    assert_eq!(fixture.output(&["open", &player]), "original");
+    assert_eq!(
+        fixture.run(&["stat", &fixture.path("game/data/extra.bin")]),
+        (format!("errno={}\n", libc::ENOENT), false)
+    );
+}
+
+#[test]
+fn child_processes_inherit_vfs_through_scrubbed_environments() {
+    let fixture = Fixture::new(CONFIG);
+    let probe = probe().to_str().unwrap();
+    let player = fixture.path("game/data/player.png");
+
+    // Launcher -> game, both with empty environments.
+    assert_eq!(fixture.output(&["spawn", probe, "open", &player]), "modded");
+    assert_eq!(fixture.output(&["exec", probe, "open", &player]), "modded");
+
+    // Launcher -> `env -i` (clears `environ`, then `execvp`) -> game.
+    assert_eq!(
+        fixture.output(&["exec", "/usr/bin/env", "env", "-i", probe, "open", &player]),
+        "modded"
+    );
+}
+
+#[test]
+fn child_processes_respect_propagation_lists() {
+    let probe = probe().to_str().unwrap();
+    let name = Path::new(probe).file_name().unwrap().to_str().unwrap();
+    let launch = |fixture: &Fixture| {
+        let player = fixture.path("game/data/player.png");
+        fixture.output(&["spawn", probe, "open", &player])
+    };
+
+    let denied = Fixture::new(&format!("{CONFIG}propagate-deny {name}\n"));
+    assert_eq!(launch(&denied), "original");
+    let allowed = Fixture::new(&format!("{CONFIG}propagate-allow {name}\n"));
+    assert_eq!(launch(&allowed), "modded");
+    let not_allowed = Fixture::new(&format!("{CONFIG}propagate-allow game.x86_64\n"));
+    assert_eq!(launch(&not_allowed), "original");
+    let disabled = Fixture::new(&format!("{CONFIG}propagate off\n"));
+    assert_eq!(launch(&disabled), "original");
+
+    // The launcher itself is still redirected.
+    let player = disabled.path("game/data/player.png");
+    assert_eq!(disabled.output(&["open", &player]), "modded");
+}
+
+#[test]
+fn hooks_reentered_from_inside_a_hook_pass_through() {
+    // The shim redirects `open` to mod/player.png through the next `openat`, whose definition
+    // opens game/data/player.png through the exported `open` again. That nested call must go
+    // straight to the real `openat` once, unredirected.
+    let fixture = Fixture::new(CONFIG);
+    let player = fixture.path("game/data/player.png");
+    let preload = format!("{} {}", library().display(), reenter_library().display());
+    let mut command = fixture.command(&["open", &player]);
+    command
+        .env("LD_PRELOAD", preload)
+        .env("REENTER_TRIGGER", fixture.path("mod/player.png"))
+        .env("REENTER_PATH", &player);
+    let (output, success) = run(command);
+    assert!(success, "{output}");
+    assert_eq!(output, "nested: original, 1 openat\nmodded");
+}
@@ -0,0 +1,176 @@
+// Test program run under the shim; performs one file operation and prints the result.
+
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <spawn.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/sendfile.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int print_fd(int fd) {
+    char buffer[4096];
+    ssize_t count;
+    while ((count = read(fd, buffer, sizeof buffer)) > 0)
+        fwrite(buffer, 1, count, stdout);
+    close(fd);
+    return 0;
+}
+
+static int fail(void) {
+    printf("errno=%d\n", errno);
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 3)
+        return 2;
+
+    const char *command = argv[1];
+    const char *path = argv[2];
+    if (strcmp(command, "open") == 0) {
+        int fd = open(path, O_RDONLY);
+        return fd < 0 ? fail() : print_fd(fd);
+    }
+
+    if (strcmp(command, "openat") == 0) {
+        int dir = open(path, O_RDONLY | O_DIRECTORY);
+        int fd = openat(dir, argv[3], O_RDONLY);
+        return fd < 0 ? fail() : print_fd(fd);
+    }
+
+    if (strcmp(command, "fopen") == 0) {
+        FILE *file = fopen(path, "rb");
+        if (!file)
+            return fail();
+        int c;
+        while ((c = fgetc(file)) != EOF)
+            putchar(c);
+        fclose(file);
+        return 0;
+    }
+
+    if (strcmp(command, "mmap") == 0) {
+        int fd = open(path, O_RDONLY);
+        struct stat st;
+        if (fd < 0 || fstat(fd, &st) != 0)
+            return fail();
+        void *data = mmap(NULL, st.st_size, PROT_READ, MAP_SHARED, fd, 0);
+        if (data == MAP_FAILED)
+            return fail();
+        fwrite(data, 1, st.st_size, stdout);
+        munmap(data, st.st_size);
+        close(fd);
+        return 0;
+    }
+
+    // Reads through a duplicate, after closing the original descriptor.
+    if (strcmp(command, "dup") == 0) {
+        int fd = open(path, O_RDONLY);
+        if (fd < 0)
+            return fail();
+        int duplicate = fcntl(fd, F_DUPFD_CLOEXEC, 100);
+        if (duplicate < 0 || dup2(duplicate, 200) < 0)
+            return fail();
+        close(fd);
+        close(duplicate);
+        return print_fd(200);
+    }
+
+    // Reads in a forked child.
+    if (strcmp(command, "fork") == 0) {
+        int fd = open(path, O_RDONLY);
+        if (fd < 0)
+            return fail();
+        fflush(stdout);
+        pid_t child = fork();
+        if (child == 0) {
+            int result = print_fd(fd);
+            fflush(stdout);
+            _exit(result);
+        }
+        int status;
+        if (child < 0 || waitpid(child, &status, 0) < 0)
+            return fail();
+        return WIFEXITED(status) ? WEXITSTATUS(status) : 1;
+    }
+
+    if (strcmp(command, "sendfile") == 0) {
+        int fd = open(path, O_RDONLY);
+        if (fd < 0)
+            return fail();
+        fflush(stdout);
+        while (sendfile(STDOUT_FILENO, fd, NULL, 1 << 20) > 0)
+            ;
+        close(fd);
+        return 0;
+    }
+
+    if (strcmp(command, "write") == 0) {
+        int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+        if (fd < 0)
+            return fail();
+        write(fd, argv[3], strlen(argv[3]));
+        close(fd);
+        return 0;
+    }
+
+    if (strcmp(command, "stat") == 0) {
+        struct stat st;
+        if (stat(path, &st) != 0)
+            return fail();
+        printf("size=%lld regular=%d\n", (long long)st.st_size, S_ISREG(st.st_mode));
+        return 0;
+    }
+
+    if (strcmp(command, "statx") == 0) {
+        struct statx stx;
+        if (statx(AT_FDCWD, path, 0, STATX_BASIC_STATS, &stx) != 0)
+            return fail();
+        printf("size=%llu regular=%d\n", (unsigned long long)stx.stx_size, S_ISREG(stx.stx_mode));
+        return 0;
+    }
+
+    if (strcmp(command, "access") == 0) {
+        int mode = strcmp(argv[3], "w") == 0 ? W_OK : R_OK;
+        if (access(path, mode) != 0)
+            return fail();
+        printf("ok\n");
+        return 0;
+    }
+
+    if (strcmp(command, "ls") == 0) {
+        DIR *dir = opendir(path);
+        if (!dir)
+            return fail();
+        struct dirent *entry;
+        while ((entry = readdir(dir)) != NULL)
+            if (strcmp(entry->d_name, ".") != 0 && strcmp(entry->d_name, "..") != 0)
+                printf("%s\n", entry->d_name);
+        closedir(dir);
+        return 0;
+    }
+
+    // Launchers: start `argv[2..]` with an empty environment, like a launcher which scrubs it.
+    char *empty_environment[] = {NULL};
+    if (strcmp(command, "exec") == 0) {
+        execve(path, argv + 2, empty_environment);
+        return fail();
+    }
+
+    if (strcmp(command, "spawn") == 0) {
+        pid_t child;
+        int status;
+        errno = posix_spawn(&child, path, NULL, NULL, argv + 2, empty_environment);
+        if (errno != 0 || waitpid(child, &status, 0) < 0)
+            return fail();
+        return WIFEXITED(status) ? WEXITSTATUS(status) : 1;
+    }
+
+    return 2;
+}
@@ -0,0 +1,55 @@
+// Library loaded after the shim, whose `openat` calls back into the exported `open` like
+// libc functions built on other hooked functions do. The shim's `openat` passes through to it.
+
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <fcntl.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+typedef int (*openat_fn)(int, const char *, int, ...);
+typedef int (*open_fn)(const char *, int, ...);
+
+static __thread int depth;
+static int nested_calls;
+
+int openat(int dirfd, const char *path, int flags, ...) {
+    openat_fn real_openat = (openat_fn)dlsym(RTLD_NEXT, "openat");
+    mode_t mode = 0;
+    if (flags & (O_CREAT | O_TMPFILE)) {
+        va_list args;
+        va_start(args, flags);
+        mode = va_arg(args, mode_t);
+        va_end(args);
+    }
+
+    if (depth > 0) {
+        nested_calls++;
+        return real_openat(dirfd, path, flags, mode);
+    }
+
+    // When opening REENTER_TRIGGER, open REENTER_PATH through the exported `open` first, and
+    // print what it read and how many times that came back here.
+    const char *trigger = getenv("REENTER_TRIGGER");
+    const char *nested = getenv("REENTER_PATH");
+    if (trigger && nested && strcmp(path, trigger) == 0) {
+        open_fn hooked_open = (open_fn)dlsym(RTLD_DEFAULT, "open");
+        char content[64] = {0};
+        depth++;
+        int fd = hooked_open(nested, O_RDONLY);
+        ssize_t count = fd < 0 ? -1 : read(fd, content, sizeof content - 1);
+        if (fd >= 0)
+            close(fd);
+        depth--;
+
+        char line[128];
+        int length = snprintf(line, sizeof line, "nested: %s, %d openat\n",
+                              count < 0 ? "failed" : content, nested_calls);
+        write(STDOUT_FILENO, line, length);
+    }
+
+    return real_openat(dirfd, path, flags, mode);
+}
@@ -0,0 +1,51 @@
+// The seccomp filter installed in the target process
+
+use libc::{sock_filter, BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+use vfs_linux::syscalls::PATH_SYSCALLS;
+
+/// `AUDIT_ARCH_*` value of the syscall ABI handled by the supervisor.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+// Offsets into `struct seccomp_data`
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+
+/// Builds a filter which notifies the supervisor of the syscalls in `PATH_SYSCALLS`.
+///
+/// Syscalls made through other ABIs (e.g. 32-bit programs on x86_64) use different numbers
+/// and are allowed unchanged.
+pub(crate) fn program() -> Vec<sock_filter> {
+    let count = PATH_SYSCALLS.len();
+    let mut program = vec![
+        statement(BPF_LD | BPF_W | BPF_ABS, ARCH_OFFSET),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+        statement(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW),
+        statement(BPF_LD | BPF_W | BPF_ABS, NR_OFFSET),
+    ];
+
+    // Each match jumps over the remaining comparisons and the 'allow' to the 'notify'.
+    for (index, number) in PATH_SYSCALLS.iter().enumerate() {
+        let skip = (count - index) as u8;
+        program.push(jump(BPF_JMP | BPF_JEQ | BPF_K, *number as u32, skip, 0));
+    }
+
+    program.push(statement(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+    program.push(statement(BPF_RET | BPF_K, libc::SECCOMP_RET_USER_NOTIF));
+    program
+}
+
+fn statement(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
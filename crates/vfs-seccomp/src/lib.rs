@@ -0,0 +1,28 @@
+//! Linux backend which doesn't inject anything into the target process.
+//!
+//! For programs which can't be injected: setuid helpers, anti-tamper, etc. The program is
+//! started under a seccomp filter which hands file related syscalls to a supervisor
+//! (`SECCOMP_RET_USER_NOTIF`). The supervisor resolves the path through the same Layer 1/2
+//! logic as the other backends (see `vfs-linux`) and then either:
+//!
+//! - Lets the kernel run the syscall unchanged, if the path isn't redirected.
+//! - Opens the redirected file, or a memory file holding a virtual file's content, and
+//!   installs the descriptor into the target (`SECCOMP_IOCTL_NOTIF_ADDFD`).
+//! - Performs `stat`/`access` on the redirected path itself and writes back the result.
+//!
+//! Works unprivileged: the filter is installed with `no_new_privs` set. Requires Linux 5.14
+//! or newer. The filter is inherited by child processes, so they are covered too.
+//!
+//! The supervisor reads and writes the target's memory, which needs the same permission as
+//! `ptrace`; targets are children of the supervisor, which Yama's default policy allows.
+#![cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+mod filter;
+mod listener;
+mod memory;
+mod supervisor;
+
+pub use supervisor::Supervisor;
@@ -0,0 +1,104 @@
+// Installing the filter in the target and passing its notification descriptor back
+
+use libc::{c_int, c_void, sock_filter};
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd};
+
+/// Installs `program` in the calling process, returning the notification descriptor.
+///
+/// Runs between `fork` and `exec`, so must not allocate.
+pub(crate) fn install(program: &[sock_filter]) -> io::Result<c_int> {
+    let program = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_ptr() as *mut sock_filter,
+    };
+
+    // SAFETY: `program` points to a valid filter, which the kernel copies.
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let listener = libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+            &program,
+        );
+        if listener < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(listener as c_int)
+    }
+}
+
+// Large enough for a control message carrying one descriptor
+type ControlBuffer = [u64; 4];
+
+/// Sends `fd` over the unix socket `socket`.
+///
+/// Runs between `fork` and `exec`, so must not allocate.
+pub(crate) fn send(socket: c_int, fd: c_int) -> io::Result<()> {
+    let mut byte = 0u8;
+    let mut data = libc::iovec {
+        iov_base: (&mut byte as *mut u8).cast::<c_void>(),
+        iov_len: 1,
+    };
+    let mut control: ControlBuffer = [0; 4];
+
+    // SAFETY: The message only points to the locals above, which outlive the call.
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut data;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = libc::CMSG_SPACE(size_of::<c_int>() as u32) as usize;
+
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(size_of::<c_int>() as u32) as usize;
+        libc::CMSG_DATA(header).cast::<c_int>().write_unaligned(fd);
+
+        if libc::sendmsg(socket, &message, 0) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Receives a descriptor sent with [`send`].
+pub(crate) fn receive(socket: c_int) -> io::Result<OwnedFd> {
+    let mut byte = 0u8;
+    let mut data = libc::iovec {
+        iov_base: (&mut byte as *mut u8).cast::<c_void>(),
+        iov_len: 1,
+    };
+    let mut control: ControlBuffer = [0; 4];
+
+    // SAFETY: The message only points to the locals above, which outlive the call.
+    unsafe {
+        let mut message: libc::msghdr = std::mem::zeroed();
+        message.msg_iov = &mut data;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr().cast();
+        message.msg_controllen = size_of::<ControlBuffer>();
+
+        if libc::recvmsg(socket, &mut message, libc::MSG_CMSG_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let header = libc::CMSG_FIRSTHDR(&message);
+        if header.is_null() || (*header).cmsg_type != libc::SCM_RIGHTS {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the target did not send its notification descriptor",
+            ));
+        }
+
+        let fd = libc::CMSG_DATA(header).cast::<c_int>().read_unaligned();
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
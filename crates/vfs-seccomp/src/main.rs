@@ -0,0 +1,64 @@
+// Command line front end: runs a program under the supervisor
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn main() {
+    use std::path::PathBuf;
+    use std::process::{exit, Command};
+    use vfs_linux::state::Vfs;
+    use vfs_seccomp::Supervisor;
+
+    const USAGE: &str = "usage: vfs-supervise [--config <path>] -- <program> [args...]";
+
+    let mut args = std::env::args_os().skip(1);
+    let mut config = std::env::var_os("VFS_CONFIG").map(PathBuf::from);
+    loop {
+        match args.next() {
+            Some(x) if x == "--config" => config = args.next().map(PathBuf::from),
+            Some(x) if x == "--" => break,
+            _ => {
+                eprintln!("{USAGE}");
+                exit(2);
+            }
+        }
+    }
+
+    let Some(program) = args.next() else {
+        eprintln!("{USAGE}");
+        exit(2);
+    };
+
+    let Some(config) = config else {
+        eprintln!("vfs-supervise: no config; pass --config or set VFS_CONFIG");
+        exit(2);
+    };
+
+    let vfs = match Vfs::load(&config) {
+        Ok(x) => x,
+        Err(error) => {
+            eprintln!("vfs-supervise: cannot read {}: {error}", config.display());
+            exit(2);
+        }
+    };
+
+    let mut command = Command::new(&program);
+    command.args(args);
+    match Supervisor::new(vfs).run(&mut command) {
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(error) => {
+            eprintln!("vfs-supervise: {}: {error}", program.to_string_lossy());
+            exit(1);
+        }
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn main() {
+    eprintln!("vfs-supervise only supports Linux on x86_64 and AArch64");
+    std::process::exit(1);
+}
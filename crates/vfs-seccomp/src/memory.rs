@@ -0,0 +1,91 @@
+// Access to the target process's memory
+
+use std::io;
+
+/// Longest path read from the target, including the terminator.
+const PATH_MAX: usize = libc::PATH_MAX as usize;
+
+/// Reads a nul terminated string from the target's memory.
+pub(crate) fn read_string(pid: u32, address: u64) -> io::Result<Vec<u8>> {
+    let page_size = page_size();
+    let mut result = Vec::new();
+    let mut address = address as usize;
+    while result.len() < PATH_MAX {
+        // Read up to the end of the page, since the next one may not be mapped.
+        let chunk = page_size - address % page_size;
+        let start = result.len();
+        result.resize(start + chunk, 0);
+        read(pid, address, &mut result[start..])?;
+        if let Some(end) = result[start..].iter().position(|x| *x == 0) {
+            result.truncate(start + end);
+            return Ok(result);
+        }
+
+        address += chunk;
+    }
+
+    Err(io::Error::from_raw_os_error(libc::ENAMETOOLONG))
+}
+
+/// Reads a plain old data value from the target's memory.
+///
+/// # Safety
+///
+/// `T` must be valid for any bit pattern.
+pub(crate) unsafe fn read_value<T>(pid: u32, address: u64) -> io::Result<T> {
+    let mut value = std::mem::zeroed::<T>();
+    let bytes = std::slice::from_raw_parts_mut(
+        (&mut value as *mut T).cast::<u8>(),
+        std::mem::size_of::<T>(),
+    );
+    read(pid, address as usize, bytes)?;
+    Ok(value)
+}
+
+/// Writes a plain old data value into the target's memory.
+pub(crate) fn write_value<T: Copy>(pid: u32, address: u64, value: &T) -> io::Result<()> {
+    // SAFETY: Reading `T`'s bytes; it is `Copy`, so has no drop glue or interior references.
+    let bytes = unsafe {
+        std::slice::from_raw_parts((value as *const T).cast::<u8>(), std::mem::size_of::<T>())
+    };
+    let local = libc::iovec {
+        iov_base: bytes.as_ptr() as *mut _,
+        iov_len: bytes.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut _,
+        iov_len: bytes.len(),
+    };
+
+    // SAFETY: Both iovecs describe valid memory of the stated length, in their processes.
+    let written = unsafe { libc::process_vm_writev(pid as i32, &local, 1, &remote, 1, 0) };
+    check_length(written, bytes.len())
+}
+
+fn read(pid: u32, address: usize, buffer: &mut [u8]) -> io::Result<()> {
+    let local = libc::iovec {
+        iov_base: buffer.as_mut_ptr().cast(),
+        iov_len: buffer.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut _,
+        iov_len: buffer.len(),
+    };
+
+    // SAFETY: Both iovecs describe valid memory of the stated length, in their processes.
+    let read = unsafe { libc::process_vm_readv(pid as i32, &local, 1, &remote, 1, 0) };
+    check_length(read, buffer.len())
+}
+
+fn check_length(result: isize, expected: usize) -> io::Result<()> {
+    match result {
+        -1 => Err(io::Error::last_os_error()),
+        x if x as usize != expected => Err(io::Error::from_raw_os_error(libc::EFAULT)),
+        _ => Ok(()),
+    }
+}
+
+fn page_size() -> usize {
+    // SAFETY: Always safe to call.
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
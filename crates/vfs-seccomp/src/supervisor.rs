@@ -0,0 +1,418 @@
+// The supervisor: starts the target under the filter and answers its notifications
+
+use crate::{filter, listener, memory};
+use libc::{c_int, seccomp_notif, seccomp_notif_addfd, seccomp_notif_resp};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use vfs_linux::paths::{self, Target};
+use vfs_linux::state::Vfs;
+use vfs_linux::syscalls::{path_syscall, PathSyscall, SyscallKind};
+use vfs_linux::virtual_file::{self, MemfdOptions};
+
+/// Runs programs with their file syscalls routed through a [`Vfs`].
+pub struct Supervisor {
+    vfs: Vfs,
+}
+
+/// How to answer a notification.
+enum Reply {
+    /// Let the kernel run the syscall with the original arguments.
+    Continue,
+    /// Fail the syscall with this `errno`.
+    Error(c_int),
+    /// Return this value.
+    Value(i64),
+    /// Install this descriptor in the target and return its number.
+    Fd { fd: OwnedFd, cloexec: bool },
+}
+
+impl Supervisor {
+    pub fn new(vfs: Vfs) -> Self {
+        Self { vfs }
+    }
+
+    /// Starts `command` under the filter, and serves it until it and all of its descendants
+    /// have exited. Returns the exit status of `command`.
+    ///
+    /// Descendants which outlive `command` keep being served; once the supervisor stops,
+    /// their file syscalls would fail with `ENOSYS`.
+    pub fn run(&self, command: &mut Command) -> io::Result<ExitStatus> {
+        let (ours, theirs) = socket_pair()?;
+        let program = filter::program();
+        let socket = theirs.as_raw_fd();
+
+        // SAFETY: The closure only makes async-signal-safe syscalls and doesn't allocate.
+        unsafe {
+            command.pre_exec(move || {
+                let listener = listener::install(&program)?;
+                let result = listener::send(socket, listener);
+                libc::close(listener);
+                result
+            });
+        }
+
+        let mut child = command.spawn()?;
+        drop(theirs);
+        let notifications = listener::receive(ours.as_raw_fd())?;
+        let root = std::fs::read_link(format!("/proc/{}/exe", child.id())).ok();
+        self.serve(&notifications, root.as_deref())?;
+        child.wait()
+    }
+
+    /// `root` is the executable of the started program.
+    fn serve(&self, notifications: &OwnedFd, root: Option<&Path>) -> io::Result<()> {
+        let fd = notifications.as_raw_fd();
+        loop {
+            let mut poll = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            // SAFETY: Polling a single valid descriptor.
+            if unsafe { libc::poll(&mut poll, 1, -1) } < 0 {
+                match io::Error::last_os_error() {
+                    x if x.kind() == io::ErrorKind::Interrupted => continue,
+                    x => return Err(x),
+                }
+            }
+
+            // Set once no process uses the filter any more.
+            if poll.revents & libc::POLLIN == 0 {
+                return Ok(());
+            }
+
+            // SAFETY: The kernel requires the notification to be zeroed.
+            let mut notification: seccomp_notif = unsafe { std::mem::zeroed() };
+            // SAFETY: `notification` is the structure this request fills.
+            if unsafe { libc::ioctl(fd, libc::SECCOMP_IOCTL_NOTIF_RECV, &mut notification) } < 0 {
+                // The target was killed or interrupted before we received the notification.
+                match io::Error::last_os_error().raw_os_error() {
+                    Some(libc::ENOENT | libc::EINTR) => continue,
+                    _ => return Err(io::Error::last_os_error()),
+                }
+            }
+
+            let reply = self.handle(fd, &notification, root);
+            respond(fd, notification.id, reply);
+        }
+    }
+
+    fn handle(
+        &self,
+        notifications: RawFd,
+        notification: &seccomp_notif,
+        root: Option<&Path>,
+    ) -> Reply {
+        let Some(syscall) = path_syscall(i64::from(notification.data.nr)) else {
+            return Reply::Continue;
+        };
+
+        let pid = notification.pid;
+        if !self.covers(pid, root) {
+            return Reply::Continue;
+        }
+
+        let args = notification.data.args;
+        let Ok(path) = memory::read_string(pid, args[syscall.path]) else {
+            return Reply::Continue;
+        };
+
+        let open = match syscall.kind {
+            SyscallKind::Open { flags, mode } => {
+                // SAFETY: `open_how` is plain old data.
+                let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+                how.flags = u64::from(args[flags] as u32);
+                how.mode = args[mode] & 0o7777;
+                Some(how)
+            }
+            // SAFETY: `open_how` is plain old data.
+            SyscallKind::Openat2 { how } => match unsafe { memory::read_value(pid, args[how]) } {
+                Ok(how) => Some(how),
+                Err(_) => return Reply::Continue,
+            },
+            _ => None,
+        };
+
+        // Everything read from the target (and its /proc entries) must be checked against pid
+        // reuse before use.
+        if !is_valid(notifications, notification.id) {
+            return Reply::Continue;
+        }
+
+        let dirfd = syscall.dirfd.map_or(libc::AT_FDCWD, |x| args[x] as c_int);
+        let Some(path) = absolute(pid, dirfd, path) else {
+            return Reply::Continue;
+        };
+
+        let create = open.is_some_and(|x| x.flags & libc::O_CREAT as u64 != 0);
+        match paths::resolve_absolute(&self.vfs, path, create) {
+            Target::Original => Reply::Continue,
+            Target::Redirected(target) => redirected(pid, &syscall, &args, open, &target),
+            Target::Virtual(path) => self.virtual_syscall(pid, &syscall, &args, open, &path),
+        }
+    }
+
+    /// Whether process `pid` should see the VFS, per the config's propagation settings.
+    ///
+    /// The filter can't be removed from a child, so children which shouldn't inherit the VFS
+    /// have their syscalls passed through instead. Processes running the started program's
+    /// executable, like its forks, are always covered.
+    fn covers(&self, pid: u32, root: Option<&Path>) -> bool {
+        let propagation = &self.vfs.propagation;
+        if propagation.is_unrestricted() {
+            return true;
+        }
+
+        let Ok(exe) = std::fs::read_link(format!("/proc/{pid}/exe")) else {
+            return true;
+        };
+
+        root == Some(exe.as_path()) || propagation.applies_to(&exe.to_string_lossy())
+    }
+
+    fn virtual_syscall(
+        &self,
+        pid: u32,
+        syscall: &PathSyscall,
+        args: &[u64; 6],
+        open: Option<libc::open_how>,
+        path: &str,
+    ) -> Reply {
+        if let Some(how) = open {
+            let flags = how.flags as c_int;
+            // The child reads the descriptor without us, so the content must be there up front.
+            let options = MemfdOptions::eager();
+            return match virtual_file::open(&self.vfs, path, flags | libc::O_CLOEXEC, &options) {
+                // SAFETY: `open` returned a new descriptor which we own.
+                Ok(fd) => Reply::Fd {
+                    fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                    cloexec: flags & libc::O_CLOEXEC != 0,
+                },
+                Err(errno) => Reply::Error(errno),
+            };
+        }
+
+        if let SyscallKind::Access { mode, .. } = syscall.kind {
+            return match virtual_file::check_access(args[mode] as c_int) {
+                Ok(()) => Reply::Value(0),
+                Err(errno) => Reply::Error(errno),
+            };
+        }
+
+        let Some(entry) = self.vfs.framework.virtual_files().get(path) else {
+            return Reply::Error(libc::ENOENT);
+        };
+
+        let written = match syscall.kind {
+            SyscallKind::Stat { buf, .. } => {
+                // SAFETY: `stat` is plain old data.
+                let mut stat = unsafe { std::mem::zeroed() };
+                virtual_file::fill_stat(&entry, &mut stat);
+                memory::write_value(pid, args[buf], &stat)
+            }
+            SyscallKind::Statx { buf, .. } => {
+                // SAFETY: `statx` is plain old data.
+                let mut statx = unsafe { std::mem::zeroed() };
+                virtual_file::fill_statx(&entry, &mut statx);
+                memory::write_value(pid, args[buf], &statx)
+            }
+            _ => unreachable!(),
+        };
+
+        value_or_errno(written.map(|_| 0))
+    }
+}
+
+/// Performs a syscall on a redirected path in the supervisor, on behalf of the target.
+///
+/// Files are created with the supervisor's umask.
+fn redirected(
+    pid: u32,
+    syscall: &PathSyscall,
+    args: &[u64; 6],
+    open: Option<libc::open_how>,
+    target: &CString,
+) -> Reply {
+    let path = target.as_ptr();
+    if let Some(mut how) = open {
+        let cloexec = how.flags & libc::O_CLOEXEC as u64 != 0;
+        how.flags |= libc::O_CLOEXEC as u64;
+        // SAFETY: `path` is a valid C string and `how` a valid `open_how`.
+        let fd = unsafe {
+            match syscall.kind {
+                // Unlike `openat2`, `open` ignores unknown flags and a mode without `O_CREAT`.
+                SyscallKind::Open { .. } => i64::from(libc::open(
+                    path,
+                    how.flags as c_int,
+                    how.mode as libc::c_uint,
+                )),
+                _ => libc::syscall(
+                    libc::SYS_openat2,
+                    libc::AT_FDCWD,
+                    path,
+                    &how,
+                    size_of::<libc::open_how>(),
+                ),
+            }
+        };
+
+        return match fd {
+            -1 => Reply::Error(errno()),
+            // SAFETY: The open call returned a new descriptor which we own.
+            fd => Reply::Fd {
+                fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) },
+                cloexec,
+            },
+        };
+    }
+
+    // SAFETY: `path` is a valid C string; the buffers are valid for writes.
+    unsafe {
+        match syscall.kind {
+            SyscallKind::Stat {
+                buf,
+                flags,
+                no_follow,
+            } => {
+                let mut flags = flags.map_or(0, |x| args[x] as c_int);
+                if no_follow {
+                    flags |= libc::AT_SYMLINK_NOFOLLOW;
+                }
+
+                let mut stat = std::mem::zeroed();
+                if libc::fstatat(libc::AT_FDCWD, path, &mut stat, flags) != 0 {
+                    return Reply::Error(errno());
+                }
+
+                value_or_errno(memory::write_value(pid, args[buf], &stat).map(|_| 0))
+            }
+            SyscallKind::Statx { flags, mask, buf } => {
+                let mut statx = std::mem::zeroed();
+                let result = libc::statx(
+                    libc::AT_FDCWD,
+                    path,
+                    args[flags] as c_int,
+                    args[mask] as u32,
+                    &mut statx,
+                );
+                if result != 0 {
+                    return Reply::Error(errno());
+                }
+
+                value_or_errno(memory::write_value(pid, args[buf], &statx).map(|_| 0))
+            }
+            SyscallKind::Access { mode, flags } => {
+                let flags = flags.map_or(0, |x| args[x] as c_int);
+                match libc::faccessat(libc::AT_FDCWD, path, args[mode] as c_int, flags) {
+                    0 => Reply::Value(0),
+                    _ => Reply::Error(errno()),
+                }
+            }
+            SyscallKind::Open { .. } | SyscallKind::Openat2 { .. } => unreachable!(),
+        }
+    }
+}
+
+/// Converts a path read from the target into an absolute path, like [`paths::absolute`]
+/// does for the calling process.
+fn absolute(pid: u32, dirfd: c_int, path: Vec<u8>) -> Option<String> {
+    let path = String::from_utf8(path).ok()?;
+    if path.is_empty() {
+        return None;
+    }
+
+    if path.starts_with('/') {
+        return Some(paths::normalize_lexically(&path));
+    }
+
+    let base = if dirfd == libc::AT_FDCWD {
+        std::fs::read_link(format!("/proc/{pid}/cwd")).ok()?
+    } else {
+        std::fs::read_link(format!("/proc/{pid}/fd/{dirfd}")).ok()?
+    };
+
+    Some(paths::normalize_lexically(
+        &base.join(path).to_string_lossy(),
+    ))
+}
+
+/// Sends `reply` for notification `id`.
+///
+/// Errors are ignored; they mean the target was killed while we handled the syscall.
+fn respond(notifications: RawFd, id: u64, reply: Reply) {
+    let mut response = seccomp_notif_resp {
+        id,
+        val: 0,
+        error: 0,
+        flags: 0,
+    };
+
+    match reply {
+        Reply::Continue => response.flags = libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+        Reply::Error(errno) => response.error = -errno,
+        Reply::Value(value) => response.val = value,
+        Reply::Fd { fd, cloexec } => {
+            // Installs the descriptor and answers the notification with its number.
+            let add = seccomp_notif_addfd {
+                id,
+                flags: libc::SECCOMP_ADDFD_FLAG_SEND as u32,
+                srcfd: fd.as_raw_fd() as u32,
+                newfd: 0,
+                newfd_flags: if cloexec { libc::O_CLOEXEC as u32 } else { 0 },
+            };
+
+            // SAFETY: `add` is the structure this request reads.
+            if unsafe { libc::ioctl(notifications, libc::SECCOMP_IOCTL_NOTIF_ADDFD, &add) } >= 0 {
+                return;
+            }
+
+            response.error = -errno();
+        }
+    }
+
+    // SAFETY: `response` is the structure this request reads.
+    unsafe { libc::ioctl(notifications, libc::SECCOMP_IOCTL_NOTIF_SEND, &response) };
+}
+
+/// Whether notification `id` is still pending, i.e. its process hasn't been replaced.
+fn is_valid(notifications: RawFd, id: u64) -> bool {
+    // SAFETY: The request reads the `u64` id.
+    unsafe { libc::ioctl(notifications, libc::SECCOMP_IOCTL_NOTIF_ID_VALID, &id) == 0 }
+}
+
+fn socket_pair() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for both descriptors.
+    let result = unsafe {
+        libc::socketpair(
+            libc::AF_UNIX,
+            libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+            0,
+            fds.as_mut_ptr(),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `socketpair` returned two new descriptors which we own.
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+fn value_or_errno(result: io::Result<i64>) -> Reply {
+    match result {
+        Ok(value) => Reply::Value(value),
+        Err(error) => Reply::Error(error.raw_os_error().unwrap_or(libc::EIO)),
+    }
+}
+
+fn errno() -> c_int {
+    io::Error::last_os_error()
+        .raw_os_error()
+        .unwrap_or(libc::EIO)
+}
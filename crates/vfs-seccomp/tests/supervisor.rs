@@ -0,0 +1,139 @@
+#![cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+// Runs ordinary programs (coreutils, the shell) under `vfs-supervise` and checks what they see.
+// Nothing is injected, so unmodified system binaries work.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+struct Fixture {
+    root: TempDir,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("game/saves")).unwrap();
+        fs::create_dir_all(root.path().join("mod/saves")).unwrap();
+        fs::write(root.path().join("game/player.png"), "original").unwrap();
+        fs::write(root.path().join("game/untouched.txt"), "untouched").unwrap();
+        fs::write(root.path().join("mod/player.png"), "modded").unwrap();
+        fs::write(root.path().join("mod/extra.bin"), "virtual content").unwrap();
+
+        let config = "
+            file {root}/game/player.png => {root}/mod/player.png
+            folder {root}/game/saves => {root}/mod/saves
+            virtual {root}/game/extra.bin => {root}/mod/extra.bin
+        ";
+        let config = config.replace("{root}", &root.path().to_string_lossy());
+        fs::write(root.path().join("vfs.conf"), config).unwrap();
+        Self { root }
+    }
+
+    fn path(&self, relative: &str) -> String {
+        self.root
+            .path()
+            .join(relative)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Runs `args` under the supervisor, in the game folder.
+    fn run(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_vfs-supervise"))
+            .arg("--config")
+            .arg(self.root.path().join("vfs.conf"))
+            .arg("--")
+            .args(args)
+            .current_dir(self.root.path().join("game"))
+            .output()
+            .unwrap();
+        assert!(
+            output.stderr.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    fn output(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(output.status.success(), "{args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+#[test]
+fn redirects_files() {
+    let fixture = Fixture::new();
+    assert_eq!(
+        fixture.output(&["cat", &fixture.path("game/player.png")]),
+        "modded"
+    );
+    assert_eq!(fixture.output(&["cat", "player.png"]), "modded");
+    assert_eq!(fixture.output(&["stat", "-c", "%s", "player.png"]), "6\n");
+    assert_eq!(fixture.output(&["cat", "untouched.txt"]), "untouched");
+}
+
+#[test]
+fn serves_virtual_files() {
+    let fixture = Fixture::new();
+    assert_eq!(fixture.output(&["cat", "extra.bin"]), "virtual content");
+    assert_eq!(
+        fixture.output(&["stat", "-c", "%s %F", "extra.bin"]),
+        "15 regular file\n"
+    );
+    assert!(fixture.run(&["test", "-r", "extra.bin"]).status.success());
+    assert!(!fixture.run(&["test", "-w", "extra.bin"]).status.success());
+}
+
+#[test]
+fn creates_files_in_folder_redirect() {
+    let fixture = Fixture::new();
+    fixture.output(&["sh", "-c", "echo created > saves/new.sav"]);
+    assert_eq!(
+        fs::read_to_string(fixture.path("mod/saves/new.sav")).unwrap(),
+        "created\n"
+    );
+    assert!(!Path::new(&fixture.path("game/saves/new.sav")).exists());
+}
+
+#[test]
+fn covers_child_processes() {
+    let fixture = Fixture::new();
+    let script = "cat player.png; sh -c 'cat extra.bin'";
+    assert_eq!(
+        fixture.output(&["sh", "-c", script]),
+        "moddedvirtual content"
+    );
+}
+
+#[test]
+fn returns_exit_code() {
+    let fixture = Fixture::new();
+    assert_eq!(fixture.run(&["sh", "-c", "exit 3"]).status.code(), Some(3));
+}
+
+#[test]
+fn respects_propagation_lists() {
+    let fixture = Fixture::new();
+    let config = fixture.path("vfs.conf");
+    let mut text = fs::read_to_string(&config).unwrap();
+    text.push_str("propagate-deny cat\n");
+    fs::write(&config, text).unwrap();
+
+    // `sh` is the started program and still covered; the `cat` it runs passes through.
+    assert_eq!(
+        fixture.output(&[
+            "sh",
+            "-c",
+            "cat player.png; test -r extra.bin && echo found"
+        ]),
+        "originalfound\n"
+    );
+}
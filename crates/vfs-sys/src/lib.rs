@@ -0,0 +1,276 @@
+//! Internal I/O: raw syscalls which bypass the libc entry points the backends hook.
+//!
+//! The VFS's own file access goes through here rather than std or libc: the Linux backends
+//! (through `vfs-linux`, which re-exports this as `vfs_linux::sys`) and Layer 2's memory files.
+//! A hooked `pread64` reached from inside a hook only passes through because of the
+//! re-entrancy guard; a raw syscall never reaches the hook at all, and doesn't depend on the
+//! guard being held (for example on threads the VFS starts itself).
+#![cfg(target_os = "linux")]
+
+use libc::c_int;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+extern "C" {
+    /// Makes a syscall directly, returning the result or a negated `errno`.
+    ///
+    /// Deliberately not written with `asm!`: with a constant number, the compiler emits
+    /// `mov eax, imm` before the `syscall`, which would make our own instruction a candidate
+    /// for syscall patching.
+    fn vfs_raw_syscall(number: i64, a0: u64, a1: u64, a2: u64, a3: u64, a4: u64, a5: u64) -> i64;
+}
+
+#[cfg(target_arch = "x86_64")]
+std::arch::global_asm!(
+    ".globl vfs_raw_syscall",
+    ".hidden vfs_raw_syscall",
+    ".type vfs_raw_syscall, @function",
+    "vfs_raw_syscall:",
+    "mov rax, rdi",
+    "mov rdi, rsi",
+    "mov rsi, rdx",
+    "mov rdx, rcx",
+    "mov r10, r8",
+    "mov r8, r9",
+    "mov r9, [rsp + 8]",
+    "syscall",
+    "ret",
+    ".size vfs_raw_syscall, . - vfs_raw_syscall",
+);
+
+#[cfg(target_arch = "aarch64")]
+std::arch::global_asm!(
+    ".globl vfs_raw_syscall",
+    ".hidden vfs_raw_syscall",
+    ".type vfs_raw_syscall, %function",
+    "vfs_raw_syscall:",
+    "mov x8, x0",
+    "mov x0, x1",
+    "mov x1, x2",
+    "mov x2, x3",
+    "mov x3, x4",
+    "mov x4, x5",
+    "mov x5, x6",
+    "svc #0",
+    "ret",
+    ".size vfs_raw_syscall, . - vfs_raw_syscall",
+);
+
+/// Makes a syscall without going through libc, returning the result or a negated `errno`.
+///
+/// # Safety
+///
+/// The arguments must be valid for the syscall, as when the program makes it itself.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub unsafe fn raw_syscall(number: i64, args: [u64; 6]) -> i64 {
+    vfs_raw_syscall(number, args[0], args[1], args[2], args[3], args[4], args[5])
+}
+
+/// Makes a syscall through libc's generic `syscall`, which no backend hooks.
+///
+/// # Safety
+///
+/// The arguments must be valid for the syscall, as when the program makes it itself.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub unsafe fn raw_syscall(number: i64, args: [u64; 6]) -> i64 {
+    let [a0, a1, a2, a3, a4, a5] = args;
+    match libc::syscall(number, a0, a1, a2, a3, a4, a5) {
+        -1 => {
+            -(io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(libc::EIO) as i64)
+        }
+        result => result,
+    }
+}
+
+/// Reads from `fd` at `offset`, retrying if interrupted.
+pub fn pread(fd: BorrowedFd<'_>, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    let args = [
+        fd.as_raw_fd() as u64,
+        buffer.as_mut_ptr() as u64,
+        buffer.len() as u64,
+        offset,
+        0,
+        0,
+    ];
+    loop {
+        // SAFETY: `buffer` is valid for writes of its length.
+        match result(unsafe { raw_syscall(libc::SYS_pread64, args) }) {
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            read => return read.map(|x| x as usize),
+        }
+    }
+}
+
+/// Reads from `fd` at its file position, retrying if interrupted.
+pub fn read(fd: BorrowedFd<'_>, buffer: &mut [u8]) -> io::Result<usize> {
+    let args = [
+        fd.as_raw_fd() as u64,
+        buffer.as_mut_ptr() as u64,
+        buffer.len() as u64,
+        0,
+        0,
+        0,
+    ];
+    loop {
+        // SAFETY: `buffer` is valid for writes of its length.
+        match result(unsafe { raw_syscall(libc::SYS_read, args) }) {
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            read => return read.map(|x| x as usize),
+        }
+    }
+}
+
+/// Opens `path`, relative to the working directory. Files are never created.
+pub fn open(path: &CStr, flags: c_int) -> io::Result<OwnedFd> {
+    let flags = flags & !(libc::O_CREAT | libc::O_TMPFILE);
+    let args = [
+        libc::AT_FDCWD as u64,
+        path.as_ptr() as u64,
+        flags as u64,
+        0,
+        0,
+        0,
+    ];
+    // SAFETY: `path` is a valid C string.
+    let fd = result(unsafe { raw_syscall(libc::SYS_openat, args) })?;
+    // SAFETY: `openat` returned a new descriptor which we own.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as c_int) })
+}
+
+/// Moves the file position of `fd`, as `lseek`. Returns the new position.
+pub fn lseek(fd: BorrowedFd<'_>, offset: i64, whence: c_int) -> io::Result<u64> {
+    let args = [fd.as_raw_fd() as u64, offset as u64, whence as u64, 0, 0, 0];
+    // SAFETY: Only takes integers.
+    result(unsafe { raw_syscall(libc::SYS_lseek, args) })
+}
+
+/// `fcntl` with an integer argument, such as `F_ADD_SEALS` or `F_DUPFD_CLOEXEC`.
+pub fn fcntl(fd: BorrowedFd<'_>, command: c_int, argument: u64) -> io::Result<u64> {
+    let args = [fd.as_raw_fd() as u64, command as u64, argument, 0, 0, 0];
+    // SAFETY: Only takes integers.
+    result(unsafe { raw_syscall(libc::SYS_fcntl, args) })
+}
+
+/// Maps memory, as `mmap`. Returns the address of the mapping.
+///
+/// # Safety
+///
+/// As for `mmap`: with `MAP_FIXED`, whatever was mapped at `address` is replaced.
+pub unsafe fn mmap(
+    address: usize,
+    length: usize,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: u64,
+) -> io::Result<usize> {
+    let args = [
+        address as u64,
+        length as u64,
+        prot as u64,
+        flags as u64,
+        fd as u64,
+        offset,
+    ];
+    result(raw_syscall(libc::SYS_mmap, args)).map(|x| x as usize)
+}
+
+/// Returns the metadata of `fd`.
+pub fn fstat(fd: BorrowedFd<'_>) -> io::Result<libc::stat> {
+    // SAFETY: `stat` is plain old data, filled by the syscall.
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    let args = [
+        fd.as_raw_fd() as u64,
+        &mut stat as *mut libc::stat as u64,
+        0,
+        0,
+        0,
+        0,
+    ];
+    // SAFETY: `stat` is valid for writes.
+    result(unsafe { raw_syscall(libc::SYS_fstat, args) })?;
+    Ok(stat)
+}
+
+/// Returns the metadata of `path`, following symbolic links.
+pub fn stat(path: &CStr) -> io::Result<libc::stat> {
+    // SAFETY: `stat` is plain old data, filled by the syscall.
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    let args = [
+        libc::AT_FDCWD as u64,
+        path.as_ptr() as u64,
+        &mut stat as *mut libc::stat as u64,
+        0,
+        0,
+        0,
+    ];
+    // SAFETY: `path` is a valid C string; `stat` is valid for writes.
+    result(unsafe { raw_syscall(libc::SYS_newfstatat, args) })?;
+    Ok(stat)
+}
+
+/// Whether anything exists at `path`, like [`Path::exists`](std::path::Path::exists).
+pub fn exists(path: &str) -> bool {
+    CString::new(path).is_ok_and(|x| stat(&x).is_ok())
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub fn read_link(path: &CStr) -> io::Result<String> {
+    let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+    let args = [
+        libc::AT_FDCWD as u64,
+        path.as_ptr() as u64,
+        buffer.as_mut_ptr() as u64,
+        buffer.len() as u64,
+        0,
+        0,
+    ];
+    // SAFETY: `path` is a valid C string; `buffer` is valid for writes of its length.
+    let length = result(unsafe { raw_syscall(libc::SYS_readlinkat, args) })?;
+    buffer.truncate(length as usize);
+    String::from_utf8(buffer).map_err(|_| io::ErrorKind::InvalidData.into())
+}
+
+/// Returns the working directory.
+pub fn current_dir() -> io::Result<String> {
+    let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+    let args = [buffer.as_mut_ptr() as u64, buffer.len() as u64, 0, 0, 0, 0];
+    // SAFETY: `buffer` is valid for writes of its length.
+    let length = result(unsafe { raw_syscall(libc::SYS_getcwd, args) })?;
+    // The length includes the terminating nul.
+    buffer.truncate((length as usize).saturating_sub(1));
+    String::from_utf8(buffer).map_err(|_| io::ErrorKind::InvalidData.into())
+}
+
+/// Writes a line to stderr, for diagnostics. Failures are ignored.
+pub fn report(message: &str) {
+    let line = format!("{message}\n");
+    let mut remaining = line.as_bytes();
+    while !remaining.is_empty() {
+        let args = [
+            libc::STDERR_FILENO as u64,
+            remaining.as_ptr() as u64,
+            remaining.len() as u64,
+            0,
+            0,
+            0,
+        ];
+        // SAFETY: `remaining` is valid for reads of its length.
+        match result(unsafe { raw_syscall(libc::SYS_write, args) }) {
+            Ok(written) if written > 0 => remaining = &remaining[written as usize..],
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            _ => return,
+        }
+    }
+}
+
+fn result(value: i64) -> io::Result<u64> {
+    match value {
+        -4095..=-1 => Err(io::Error::from_raw_os_error(-value as i32)),
+        value => Ok(value as u64),
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(target_os = "linux")]
+
+// Checks that the raw syscall wrappers agree with std.
+
+use std::ffi::CString;
+use std::fs;
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::fs::MetadataExt;
+
+#[test]
+fn reads_match_std() {
+    let root = tempfile::tempdir().unwrap();
+    let player = root.path().join("player.png");
+    fs::write(&player, "modded").unwrap();
+
+    let path = CString::new(player.to_str().unwrap()).unwrap();
+    let file = vfs_sys::open(&path, libc::O_RDONLY | libc::O_CLOEXEC).unwrap();
+    let mut buffer = [0u8; 16];
+    let read = vfs_sys::pread(file.as_fd(), &mut buffer, 1).unwrap();
+    assert_eq!(&buffer[..read], b"odded");
+
+    assert_eq!(vfs_sys::lseek(file.as_fd(), 2, libc::SEEK_SET).unwrap(), 2);
+    let read = vfs_sys::read(file.as_fd(), &mut buffer).unwrap();
+    assert_eq!(&buffer[..read], b"dded");
+    assert_eq!(vfs_sys::lseek(file.as_fd(), 0, libc::SEEK_CUR).unwrap(), 6);
+
+    let metadata = fs::metadata(&player).unwrap();
+    let stat = vfs_sys::fstat(file.as_fd()).unwrap();
+    assert_eq!((stat.st_dev, stat.st_ino), (metadata.dev(), metadata.ino()));
+    assert_eq!(stat.st_size, 6);
+
+    let missing = CString::new(root.path().join("missing").to_str().unwrap()).unwrap();
+    let error = vfs_sys::open(&missing, libc::O_RDONLY | libc::O_CREAT).unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(libc::ENOENT));
+}
+
+#[test]
+fn descriptors_and_mappings_match_std() {
+    let root = tempfile::tempdir().unwrap();
+    let data = root.path().join("data.bin");
+    fs::write(&data, "mapped content").unwrap();
+    let file = fs::File::open(&data).unwrap();
+
+    let duplicate = vfs_sys::fcntl(file.as_fd(), libc::F_DUPFD_CLOEXEC, 0).unwrap();
+    assert_ne!(duplicate, file.as_raw_fd() as u64);
+    let flags = vfs_sys::fcntl(file.as_fd(), libc::F_GETFD, 0).unwrap();
+    assert_eq!(flags, libc::FD_CLOEXEC as u64);
+    // SAFETY: Closing the duplicate made above.
+    unsafe { libc::close(duplicate as libc::c_int) };
+
+    // SAFETY: A new mapping, unmapped below.
+    let address = unsafe {
+        vfs_sys::mmap(
+            0,
+            14,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    }
+    .unwrap();
+    // SAFETY: The mapping covers the whole file.
+    let mapped = unsafe { std::slice::from_raw_parts(address as *const u8, 14) };
+    assert_eq!(mapped, b"mapped content");
+    // SAFETY: Unmapping the mapping made above.
+    unsafe { libc::munmap(address as *mut libc::c_void, 14) };
+}
+
+#[test]
+fn paths_match_std() {
+    let root = tempfile::tempdir().unwrap();
+    let player = root.path().join("player.png");
+    fs::write(&player, "modded").unwrap();
+
+    assert!(vfs_sys::exists(player.to_str().unwrap()));
+    assert!(!vfs_sys::exists(
+        root.path().join("missing").to_str().unwrap()
+    ));
+
+    let path = CString::new(player.to_str().unwrap()).unwrap();
+    assert_eq!(vfs_sys::stat(&path).unwrap().st_size, 6);
+
+    let current_dir = std::env::current_dir().unwrap();
+    assert_eq!(
+        vfs_sys::current_dir().unwrap(),
+        current_dir.to_str().unwrap()
+    );
+
+    let link = CString::new("/proc/self/exe").unwrap();
+    let exe = fs::read_link("/proc/self/exe").unwrap();
+    assert_eq!(vfs_sys::read_link(&link).unwrap(), exe.to_str().unwrap());
+}
@@ -0,0 +1,103 @@
+// Handling of intercepted syscalls
+
+use crate::entry::SyscallFrame;
+use libc::{c_char, c_int};
+use vfs_linux::paths::{self, Target};
+use vfs_linux::propagation;
+use vfs_linux::state::{self, Vfs};
+use vfs_linux::sys::raw_syscall;
+use vfs_linux::syscalls::{exec_syscall, path_syscall, ExecSyscall, PathSyscall, SyscallKind};
+use vfs_linux::virtual_file::{self, MemfdOptions};
+
+/// Called from `vfs_syscall_entry` for every intercepted syscall.
+///
+/// Returns the syscall result: the value, or a negated `errno`.
+pub(crate) unsafe extern "C" fn dispatch(frame: &SyscallFrame) -> i64 {
+    let number = frame.number;
+    let args = frame.args();
+
+    // Nested syscalls made by the VFS itself (std, libc) go straight to the kernel.
+    let Some((vfs, _guard)) = state::enter() else {
+        return raw_syscall(number, args);
+    };
+
+    if let Some(syscall) = exec_syscall(number) {
+        return exec(vfs, &syscall, number, args);
+    }
+
+    let Some(syscall) = path_syscall(number) else {
+        return raw_syscall(number, args);
+    };
+
+    let dirfd = syscall.dirfd.map_or(libc::AT_FDCWD, |x| args[x] as c_int);
+    let create = open_flags(&syscall, &args).is_some_and(|x| x & libc::O_CREAT != 0);
+    match paths::resolve(vfs, dirfd, args[syscall.path] as *const c_char, create) {
+        Target::Original => raw_syscall(number, args),
+        Target::Redirected(target) => {
+            let mut args = args;
+            args[syscall.path] = target.as_ptr() as u64;
+            if let Some(index) = syscall.dirfd {
+                args[index] = libc::AT_FDCWD as u64;
+            }
+
+            raw_syscall(number, args)
+        }
+        Target::Virtual(path) => virtual_syscall(vfs, &syscall, &args, &path),
+    }
+}
+
+/// Starts a new program with the VFS carried over in its environment (or removed from it).
+unsafe fn exec(vfs: &Vfs, syscall: &ExecSyscall, number: i64, args: [u64; 6]) -> i64 {
+    let dirfd = syscall.dirfd.map_or(libc::AT_FDCWD, |x| args[x] as c_int);
+    let program = propagation::program_path(dirfd, args[syscall.path] as *const c_char);
+    let environment = vfs
+        .propagation
+        .child_environment(&program, args[syscall.environment] as *const *const c_char);
+
+    let mut args = args;
+    args[syscall.environment] = environment.as_ptr() as u64;
+    raw_syscall(number, args)
+}
+
+unsafe fn virtual_syscall(vfs: &Vfs, syscall: &PathSyscall, args: &[u64; 6], path: &str) -> i64 {
+    if let Some(flags) = open_flags(syscall, args) {
+        // Reads aren't patched, so the content must be there up front.
+        let options = MemfdOptions::eager();
+        return result(virtual_file::open(vfs, path, flags, &options).map(i64::from));
+    }
+
+    if let SyscallKind::Access { mode, .. } = syscall.kind {
+        return result(virtual_file::check_access(args[mode] as c_int).map(|_| 0));
+    }
+
+    let Some(entry) = vfs.framework.virtual_files().get(path) else {
+        return -i64::from(libc::ENOENT);
+    };
+
+    match syscall.kind {
+        SyscallKind::Stat { buf, .. } => {
+            virtual_file::fill_stat(&entry, &mut *(args[buf] as *mut libc::stat))
+        }
+        SyscallKind::Statx { buf, .. } => {
+            virtual_file::fill_statx(&entry, &mut *(args[buf] as *mut libc::statx))
+        }
+        _ => unreachable!(),
+    }
+
+    0
+}
+
+/// Open flags of an `open` family syscall, `None` for other syscalls.
+unsafe fn open_flags(syscall: &PathSyscall, args: &[u64; 6]) -> Option<c_int> {
+    match syscall.kind {
+        SyscallKind::Open { flags, .. } => Some(args[flags] as c_int),
+        SyscallKind::Openat2 { how } => (args[how] as *const libc::open_how)
+            .as_ref()
+            .map(|x| x.flags as c_int),
+        _ => None,
+    }
+}
+
+fn result(result: Result<i64, c_int>) -> i64 {
+    result.unwrap_or_else(|errno| -i64::from(errno))
+}
@@ -0,0 +1,102 @@
+// Assembly glue between patched code and the Rust dispatcher
+
+use std::arch::global_asm;
+
+/// Registers of an intercepted syscall, as pushed by `vfs_syscall_entry`.
+#[repr(C)]
+pub(crate) struct SyscallFrame {
+    pub(crate) number: i64,
+    pub(crate) r9: u64,
+    pub(crate) r8: u64,
+    pub(crate) r10: u64,
+    pub(crate) rdx: u64,
+    pub(crate) rsi: u64,
+    pub(crate) rdi: u64,
+}
+
+impl SyscallFrame {
+    /// Syscall arguments in order.
+    pub(crate) fn args(&self) -> [u64; 6] {
+        [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9]
+    }
+}
+
+extern "C" {
+    /// Called by trampolines in place of the `syscall` instruction.
+    ///
+    /// Behaves like `syscall` towards the patched code: the result is returned in `rax` and
+    /// every other register is preserved, including flags and SSE registers (the kernel
+    /// clobbers `rcx` and `r11`, so those aren't preserved). Trampolines step over the red zone
+    /// before calling, since the patched code may keep data there.
+    pub(crate) fn vfs_syscall_entry();
+}
+
+global_asm!(
+    ".globl vfs_syscall_entry",
+    ".hidden vfs_syscall_entry",
+    ".type vfs_syscall_entry, @function",
+    "vfs_syscall_entry:",
+    "pushfq",
+    "cld",
+    // Build a `SyscallFrame`
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push r10",
+    "push r8",
+    "push r9",
+    "push rax",
+    "mov rdi, rsp",
+    // Align the stack and save SSE registers, which the kernel would have preserved
+    "push rbp",
+    "mov rbp, rsp",
+    "and rsp, -16",
+    "sub rsp, 256",
+    "movdqu [rsp + 0x00], xmm0",
+    "movdqu [rsp + 0x10], xmm1",
+    "movdqu [rsp + 0x20], xmm2",
+    "movdqu [rsp + 0x30], xmm3",
+    "movdqu [rsp + 0x40], xmm4",
+    "movdqu [rsp + 0x50], xmm5",
+    "movdqu [rsp + 0x60], xmm6",
+    "movdqu [rsp + 0x70], xmm7",
+    "movdqu [rsp + 0x80], xmm8",
+    "movdqu [rsp + 0x90], xmm9",
+    "movdqu [rsp + 0xa0], xmm10",
+    "movdqu [rsp + 0xb0], xmm11",
+    "movdqu [rsp + 0xc0], xmm12",
+    "movdqu [rsp + 0xd0], xmm13",
+    "movdqu [rsp + 0xe0], xmm14",
+    "movdqu [rsp + 0xf0], xmm15",
+    "call {dispatch}",
+    "movdqu xmm0, [rsp + 0x00]",
+    "movdqu xmm1, [rsp + 0x10]",
+    "movdqu xmm2, [rsp + 0x20]",
+    "movdqu xmm3, [rsp + 0x30]",
+    "movdqu xmm4, [rsp + 0x40]",
+    "movdqu xmm5, [rsp + 0x50]",
+    "movdqu xmm6, [rsp + 0x60]",
+    "movdqu xmm7, [rsp + 0x70]",
+    "movdqu xmm8, [rsp + 0x80]",
+    "movdqu xmm9, [rsp + 0x90]",
+    "movdqu xmm10, [rsp + 0xa0]",
+    "movdqu xmm11, [rsp + 0xb0]",
+    "movdqu xmm12, [rsp + 0xc0]",
+    "movdqu xmm13, [rsp + 0xd0]",
+    "movdqu xmm14, [rsp + 0xe0]",
+    "movdqu xmm15, [rsp + 0xf0]",
+    "mov rsp, rbp",
+    "pop rbp",
+    // Discard the syscall number; the result is in rax
+    "add rsp, 8",
+    "pop r9",
+    "pop r8",
+    "pop r10",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "popfq",
+    "ret",
+    ".size vfs_syscall_entry, . - vfs_syscall_entry",
+    dispatch = sym crate::dispatch::dispatch,
+);
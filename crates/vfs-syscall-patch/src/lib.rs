@@ -0,0 +1,55 @@
+//! Syscall patching backend for x86_64 Linux.
+//!
+//! Covers programs which don't go through glibc's exported functions, so `vfs-preload`
+//! can't see their file I/O: statically linked binaries (glibc or `musl`), Zig programs, etc.
+//!
+//! On load, the executable regions of every loaded module are disassembled. Each `syscall`
+//! instruction that makes a file related syscall (`open`, `openat`, `stat`, `statx`, ...) is
+//! overwritten with a jump to a trampoline. The trampoline runs the displaced instructions and
+//! calls into the VFS instead of the kernel, which resolves the path through the same
+//! Layer 1/2 logic as `vfs-preload` (see `vfs-linux`, including the `VFS_CONFIG` format).
+//!
+//! Only `syscall` instructions whose syscall number can be read from a preceding
+//! `mov eax, imm` are patched. Generic wrappers such as `syscall(2)` take the number in a
+//! register and are left alone; this also ensures `clone`, `vfork` and `rt_sigreturn`, which
+//! must not run on a different stack frame, are never intercepted.
+//!
+//! Loading: dynamically linked programs can use `LD_PRELOAD=libvfs_syscall_patch.so`.
+//! Statically linked programs can't load libraries, so the static library is linked in
+//! instead (with `--whole-archive`, so the constructor is kept).
+//!
+//! Patching happens once, in the library constructor, before other threads exist.
+//! Code loaded later (`dlopen`) is not patched.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+mod dispatch;
+mod entry;
+mod maps;
+mod patch;
+mod trampoline;
+
+/// Loads the config and patches all loaded modules.
+///
+/// Runs automatically when the library is loaded. Calling it again has no effect.
+#[no_mangle]
+pub extern "C" fn vfs_syscall_patch_init() {
+    use std::sync::Once;
+
+    static INITIALISED: Once = Once::new();
+    INITIALISED.call_once(|| {
+        vfs_linux::state::initialise();
+        // Not configured, nothing to redirect.
+        let Some((_vfs, _guard)) = vfs_linux::state::enter() else {
+            return;
+        };
+
+        if let Err(error) = patch::patch_loaded_modules() {
+            vfs_linux::sys::report(&format!("vfs-syscall-patch: {error}"));
+        }
+    });
+}
+
+// Runs when the library is loaded, before the program's `main`.
+#[used]
+#[link_section = ".init_array"]
+static INITIALISE: extern "C" fn() = vfs_syscall_patch_init;
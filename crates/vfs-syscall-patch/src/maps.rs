@@ -0,0 +1,55 @@
+// Enumeration of executable memory regions
+
+use std::fs;
+use std::io;
+
+/// An executable mapping of a loaded module.
+pub(crate) struct Region {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    /// Protection flags to restore after patching.
+    pub(crate) protection: i32,
+    pub(crate) path: String,
+}
+
+/// Returns the executable, file backed regions of the process that should be patched.
+///
+/// Skips anonymous memory (JIT code, our own trampolines), kernel provided regions
+/// (`[vdso]`, `[vsyscall]`) and the dynamic loader, which runs with its lock held.
+pub(crate) fn executable_regions() -> io::Result<Vec<Region>> {
+    let maps = fs::read_to_string("/proc/self/maps")?;
+    Ok(maps.lines().filter_map(parse_line).collect())
+}
+
+// Line format: `start-end perms offset dev inode path`
+fn parse_line(line: &str) -> Option<Region> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let permissions = fields.next()?.as_bytes();
+    let path = fields.nth(3)?;
+    // Execute-only regions can't be disassembled.
+    if permissions.len() < 3 || permissions[0] != b'r' || permissions[2] != b'x' {
+        return None;
+    }
+
+    if !path.starts_with('/') || is_loader(path) {
+        return None;
+    }
+
+    let mut protection = libc::PROT_READ | libc::PROT_EXEC;
+    if permissions[1] == b'w' {
+        protection |= libc::PROT_WRITE;
+    }
+
+    Some(Region {
+        start: usize::from_str_radix(start, 16).ok()?,
+        end: usize::from_str_radix(end, 16).ok()?,
+        protection,
+        path: path.to_owned(),
+    })
+}
+
+fn is_loader(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.starts_with("ld-linux") || name.starts_with("ld-musl")
+}
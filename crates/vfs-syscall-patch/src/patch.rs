@@ -0,0 +1,309 @@
+// Finding patchable `syscall` instructions and redirecting them to trampolines
+
+use crate::maps;
+use crate::trampoline::Trampolines;
+use iced_x86::{
+    BlockEncoder, BlockEncoderOptions, Code, Decoder, DecoderOptions, FlowControl, Instruction,
+    InstructionBlock, InstructionInfoFactory, Mnemonic, OpAccess, OpKind, Register,
+};
+use std::collections::HashSet;
+use std::io;
+use std::ptr;
+use vfs_linux::sys::report;
+use vfs_linux::syscalls::{EXEC_SYSCALLS, PATH_SYSCALLS};
+
+/// Size of the `jmp rel32` written over each site.
+const JMP_SIZE: usize = 5;
+
+/// How many instructions to look back from a `syscall` for the `mov` setting its number.
+const MAX_LOOKBACK: usize = 16;
+
+/// `lea rsp, [rsp - 128]`: steps over the red zone.
+const SKIP_RED_ZONE: [u8; 5] = [0x48, 0x8D, 0x64, 0x24, 0x80];
+
+/// `lea rsp, [rsp + 128]`
+const RESTORE_RED_ZONE: [u8; 8] = [0x48, 0x8D, 0xA4, 0x24, 0x80, 0x00, 0x00, 0x00];
+
+/// Size of `call [rip + disp32]`.
+const CALL_INDIRECT_SIZE: usize = 6;
+
+/// A `syscall` instruction to patch, with the neighbouring instructions the jump displaces.
+pub(crate) struct Site {
+    instructions: Vec<Instruction>,
+    syscall_index: usize,
+}
+
+impl Site {
+    fn start(&self) -> usize {
+        self.instructions[0].ip() as usize
+    }
+
+    fn end(&self) -> usize {
+        self.instructions.last().unwrap().next_ip() as usize
+    }
+}
+
+/// Patches the file related syscalls of every loaded module. Returns the number of sites patched.
+///
+/// Regions which can't be made writable, and sites which can't be patched, are reported and
+/// skipped; the constructor must not fail the program.
+pub(crate) fn patch_loaded_modules() -> io::Result<usize> {
+    let mut trampolines = Trampolines::new();
+    let mut patched = 0;
+    for region in maps::executable_regions()? {
+        let sites = {
+            // SAFETY: The region is mapped readable, and nothing writes to it while we read.
+            let code = unsafe {
+                std::slice::from_raw_parts(region.start as *const u8, region.end - region.start)
+            };
+            find_sites(code, region.start as u64)
+        };
+
+        if sites.is_empty() {
+            continue;
+        }
+
+        let writable = region.protection | libc::PROT_WRITE;
+        if let Err(error) = protect(region.start, region.end, writable) {
+            report(&format!("vfs-syscall-patch: {}: {error}", region.path));
+            continue;
+        }
+
+        for site in &sites {
+            match patch_site(site, &mut trampolines) {
+                Ok(()) => patched += 1,
+                Err(error) => report(&format!(
+                    "vfs-syscall-patch: {} at {:#x}: {error}",
+                    region.path,
+                    site.start()
+                )),
+            }
+        }
+
+        if let Err(error) = protect(region.start, region.end, region.protection) {
+            report(&format!("vfs-syscall-patch: {}: {error}", region.path));
+        }
+    }
+
+    trampolines.seal()?;
+    Ok(patched)
+}
+
+/// Disassembles `code` (loaded at `ip`) and returns the sites which should be patched.
+///
+/// Uses a linear sweep; data embedded in executable sections may decode as instructions,
+/// but would also have to look like a `mov eax, <file syscall>` followed by `syscall`.
+pub(crate) fn find_sites(code: &[u8], ip: u64) -> Vec<Site> {
+    let mut decoder = Decoder::with_ip(64, code, ip, DecoderOptions::NONE);
+    let mut instructions = Vec::new();
+    // Direct branch targets; control may arrive there from elsewhere
+    let mut targets = HashSet::new();
+    while decoder.can_decode() {
+        let instruction = decoder.decode();
+        if matches!(
+            instruction.op0_kind(),
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64
+        ) {
+            targets.insert(instruction.near_branch_target());
+        }
+
+        instructions.push(instruction);
+    }
+
+    let mut info_factory = InstructionInfoFactory::new();
+    let mut sites = Vec::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        if instruction.mnemonic() != Mnemonic::Syscall {
+            continue;
+        }
+
+        let number = syscall_number(&instructions, index, &targets, &mut info_factory);
+        if !number.is_some_and(|x| PATH_SYSCALLS.contains(&x) || EXEC_SYSCALLS.contains(&x)) {
+            continue;
+        }
+
+        // A site grown forwards may take in the `mov` of the next one; patching both would
+        // overwrite part of the first `jmp`.
+        let site = displaced_instructions(&instructions, index, &targets);
+        if let Some(site) = site.filter(|x| !overlaps_any(x, &sites)) {
+            sites.push(site);
+        }
+    }
+
+    sites
+}
+
+fn overlaps_any(site: &Site, sites: &[Site]) -> bool {
+    sites
+        .iter()
+        .any(|x| x.start() < site.end() && site.start() < x.end())
+}
+
+/// Finds the syscall number loaded into `eax` before `instructions[index]`.
+///
+/// Walks back within the basic block; gives up at branch targets, calls and anything else
+/// which writes to `rax`.
+fn syscall_number(
+    instructions: &[Instruction],
+    index: usize,
+    targets: &HashSet<u64>,
+    info_factory: &mut InstructionInfoFactory,
+) -> Option<i64> {
+    let mut current = index;
+    for _ in 0..MAX_LOOKBACK {
+        if current == 0 || targets.contains(&instructions[current].ip()) {
+            return None;
+        }
+
+        let previous = &instructions[current - 1];
+        if previous.code() == Code::INVALID
+            || !matches!(
+                previous.flow_control(),
+                FlowControl::Next | FlowControl::ConditionalBranch
+            )
+        {
+            return None;
+        }
+
+        let is_mov_immediate = matches!(
+            previous.code(),
+            Code::Mov_r32_imm32 | Code::Mov_rm32_imm32 | Code::Mov_rm64_imm32 | Code::Mov_r64_imm64
+        );
+        if is_mov_immediate && matches!(previous.op0_register(), Register::EAX | Register::RAX) {
+            return Some(previous.immediate(1) as i64);
+        }
+
+        let writes_rax = info_factory
+            .info(previous)
+            .used_registers()
+            .iter()
+            .any(|x| {
+                x.register().full_register() == Register::RAX
+                    && !matches!(x.access(), OpAccess::Read | OpAccess::CondRead)
+            });
+        if writes_rax {
+            return None;
+        }
+
+        current -= 1;
+    }
+
+    None
+}
+
+/// Picks the instructions around `instructions[index]` which the `jmp` will overwrite.
+///
+/// They must be at least `JMP_SIZE` bytes, contain no other control flow, and nothing may
+/// branch into the middle of them.
+fn displaced_instructions(
+    instructions: &[Instruction],
+    index: usize,
+    targets: &HashSet<u64>,
+) -> Option<Site> {
+    let is_relocatable =
+        |x: &Instruction| x.code() != Code::INVALID && x.flow_control() == FlowControl::Next;
+    let (mut start, mut end) = (index, index + 1);
+    while instructions[start..end]
+        .iter()
+        .map(Instruction::len)
+        .sum::<usize>()
+        < JMP_SIZE
+    {
+        if start > 0
+            && !targets.contains(&instructions[start].ip())
+            && is_relocatable(&instructions[start - 1])
+        {
+            start -= 1;
+        } else if end < instructions.len()
+            && !targets.contains(&instructions[end].ip())
+            && is_relocatable(&instructions[end])
+        {
+            end += 1;
+        } else {
+            return None;
+        }
+    }
+
+    Some(Site {
+        instructions: instructions[start..end].to_vec(),
+        syscall_index: index - start,
+    })
+}
+
+/// Writes the trampoline for `site`, then the jump to it.
+///
+/// Trampoline layout: displaced instructions before the `syscall`, a call to
+/// `vfs_syscall_entry` in its place, displaced instructions after it, then a jump back.
+fn patch_site(site: &Site, trampolines: &mut Trampolines) -> io::Result<()> {
+    let (before, rest) = site.instructions.split_at(site.syscall_index);
+    let after = &rest[1..];
+
+    // Relocated instructions keep their size, except rare cases which grow by a few bytes.
+    let size = (before.len() + after.len()) * 16
+        + SKIP_RED_ZONE.len()
+        + CALL_INDIRECT_SIZE
+        + RESTORE_RED_ZONE.len()
+        + JMP_SIZE;
+    let (address, entry_slot) = trampolines.allocate(site.start(), size)?;
+
+    let mut code = encode(before, address)?;
+    code.extend_from_slice(&SKIP_RED_ZONE);
+    let call_end = address + code.len() + CALL_INDIRECT_SIZE;
+    code.extend_from_slice(&[0xFF, 0x15]);
+    code.extend_from_slice(&rel32(call_end, entry_slot)?.to_le_bytes());
+    code.extend_from_slice(&RESTORE_RED_ZONE);
+    code.extend(encode(after, address + code.len())?);
+    let jmp_end = address + code.len() + JMP_SIZE;
+    code.push(0xE9);
+    code.extend_from_slice(&rel32(jmp_end, site.end())?.to_le_bytes());
+    if code.len() > size {
+        return Err(io::Error::other(
+            "relocated instructions outgrew the trampoline",
+        ));
+    }
+
+    // Anything left over is filled with `int3`, so a missed jump into the middle traps.
+    let mut patch = vec![0xCC; site.end() - site.start()];
+    patch[0] = 0xE9;
+    let jmp = rel32(site.start() + JMP_SIZE, address)?;
+    patch[1..JMP_SIZE].copy_from_slice(&jmp.to_le_bytes());
+
+    // SAFETY: The trampoline memory was allocated with room for `size` bytes, and the site's
+    // region was made writable by the caller. The trampoline is complete before it is reachable.
+    unsafe {
+        ptr::copy_nonoverlapping(code.as_ptr(), address as *mut u8, code.len());
+        ptr::copy_nonoverlapping(patch.as_ptr(), site.start() as *mut u8, patch.len());
+    }
+
+    Ok(())
+}
+
+/// Re-encodes `instructions` to run at `ip`, fixing up `rip` relative operands.
+fn encode(instructions: &[Instruction], ip: usize) -> io::Result<Vec<u8>> {
+    if instructions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    BlockEncoder::encode(
+        64,
+        InstructionBlock::new(instructions, ip as u64),
+        BlockEncoderOptions::NONE,
+    )
+    .map(|x| x.code_buffer)
+    .map_err(|x| io::Error::other(x.to_string()))
+}
+
+/// Displacement from the end of an instruction (`from`) to `to`.
+fn rel32(from: usize, to: usize) -> io::Result<i32> {
+    let displacement = to as i64 - from as i64;
+    i32::try_from(displacement).map_err(|_| io::Error::other("trampoline out of rel32 range"))
+}
+
+fn protect(start: usize, end: usize, protection: i32) -> io::Result<()> {
+    // SAFETY: Changing protection of an existing mapping of a loaded module.
+    if unsafe { libc::mprotect(start as *mut _, end - start, protection) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+// Allocation of executable memory for trampolines, within jump range of patched code
+
+use crate::entry::vfs_syscall_entry;
+use std::io;
+use std::ptr;
+
+/// Size of each block of trampoline memory.
+const ARENA_SIZE: usize = 64 * 1024;
+
+/// Maximum distance between an arena and the code jumping to it.
+///
+/// `jmp rel32` reaches +-2 GiB; staying within 1 GiB leaves room for the arena and region size.
+const MAX_DISTANCE: usize = 1 << 30;
+
+/// Step between addresses tried when looking for free memory near a region.
+const SEARCH_STEP: usize = 16 * 1024 * 1024;
+
+/// A block of memory holding trampolines.
+///
+/// The first 8 bytes hold the address of `vfs_syscall_entry`, which trampolines call through
+/// (`call [rip + x]`), since our library may be mapped too far away for a direct call.
+struct Arena {
+    base: usize,
+    used: usize,
+}
+
+/// Hands out trampoline memory close to the code being patched.
+pub(crate) struct Trampolines {
+    arenas: Vec<Arena>,
+}
+
+impl Trampolines {
+    pub(crate) fn new() -> Self {
+        Self { arenas: Vec::new() }
+    }
+
+    /// Reserves `size` bytes within jump range of `near`.
+    ///
+    /// Returns the address of the reserved memory and of the slot holding the entry address.
+    pub(crate) fn allocate(&mut self, near: usize, size: usize) -> io::Result<(usize, usize)> {
+        let existing = self
+            .arenas
+            .iter_mut()
+            .find(|x| x.base.abs_diff(near) < MAX_DISTANCE && x.used + size <= ARENA_SIZE);
+        let arena = match existing {
+            Some(arena) => arena,
+            None => {
+                let arena = Arena {
+                    base: map_near(near)?,
+                    used: 8,
+                };
+
+                // SAFETY: The arena was just mapped writable and is at least 8 bytes.
+                unsafe {
+                    ptr::write(
+                        arena.base as *mut usize,
+                        vfs_syscall_entry as *const () as usize,
+                    )
+                };
+                self.arenas.push(arena);
+                self.arenas.last_mut().unwrap()
+            }
+        };
+
+        let address = arena.base + arena.used;
+        // Keep trampolines 16 byte aligned.
+        arena.used = (arena.used + size).next_multiple_of(16);
+        Ok((address, arena.base))
+    }
+
+    /// Makes all trampoline memory read-only and executable.
+    pub(crate) fn seal(&self) -> io::Result<()> {
+        for arena in &self.arenas {
+            // SAFETY: `arena` is a mapping we own.
+            let result = unsafe {
+                libc::mprotect(
+                    arena.base as *mut _,
+                    ARENA_SIZE,
+                    libc::PROT_READ | libc::PROT_EXEC,
+                )
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Maps a new writable arena within `MAX_DISTANCE` of `near`, searching below it first.
+fn map_near(near: usize) -> io::Result<usize> {
+    let near = near & !(SEARCH_STEP - 1);
+    let candidates = (1..MAX_DISTANCE / SEARCH_STEP).flat_map(|x| {
+        let offset = x * SEARCH_STEP;
+        [near.checked_sub(offset), near.checked_add(offset)]
+    });
+
+    for hint in candidates.flatten() {
+        // SAFETY: `MAP_FIXED_NOREPLACE` never replaces existing mappings.
+        let address = unsafe {
+            libc::mmap(
+                hint as *mut _,
+                ARENA_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if address == libc::MAP_FAILED {
+            continue;
+        }
+
+        // Kernels before 4.17 ignore MAP_FIXED_NOREPLACE and may place the mapping elsewhere.
+        if address as usize != hint {
+            // SAFETY: We just mapped this.
+            unsafe { libc::munmap(address, ARENA_SIZE) };
+            continue;
+        }
+
+        return Ok(hint);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        "no free memory within jump range for trampolines",
+    ))
+}
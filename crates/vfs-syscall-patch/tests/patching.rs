@@ -0,0 +1,318 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+// Runs a small C program with the patcher, statically linked in or preloaded, and checks
+// what it sees.
+//
+// The static program is built twice: against glibc's static libc, and with `musl-gcc` against
+// musl, with the patcher built for the musl target. musl's calls go through its own syscall
+// helpers (`__syscall_cp_asm` and the inline `__syscall*` functions) rather than glibc's. The
+// musl program is skipped, with a message, if `musl-gcc` or the Rust musl target is missing.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+#[derive(Clone, Copy)]
+enum Probe {
+    /// Statically linked against glibc, with the patcher's static library linked in.
+    Static,
+    /// Statically linked against musl, with the patcher's static library linked in.
+    Musl,
+    /// Dynamically linked, with the patcher loaded through `LD_PRELOAD`.
+    Preloaded,
+}
+
+struct Fixture {
+    root: TempDir,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("game/saves")).unwrap();
+        fs::create_dir_all(root.path().join("mod/saves")).unwrap();
+        fs::write(root.path().join("game/player.png"), "original").unwrap();
+        fs::write(root.path().join("game/untouched.txt"), "untouched").unwrap();
+        fs::write(root.path().join("mod/player.png"), "modded").unwrap();
+        fs::write(root.path().join("mod/extra.bin"), "virtual content").unwrap();
+
+        let config = "
+            file {root}/game/player.png => {root}/mod/player.png
+            folder {root}/game/saves => {root}/mod/saves
+            virtual {root}/game/extra.bin => {root}/mod/extra.bin
+        ";
+        let config = config.replace("{root}", &root.path().to_string_lossy());
+        fs::write(root.path().join("vfs.conf"), config).unwrap();
+        Self { root }
+    }
+
+    fn path(&self, relative: &str) -> String {
+        self.root
+            .path()
+            .join(relative)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Runs the probe; returns its output and whether it succeeded.
+    fn run(&self, probe: Probe, args: &[&str]) -> (String, bool) {
+        let mut command = match probe {
+            Probe::Static => Command::new(&probes().0),
+            Probe::Musl => Command::new(musl_probe().unwrap()),
+            Probe::Preloaded => {
+                let mut command = Command::new(&probes().1);
+                command.env("LD_PRELOAD", libraries().join("libvfs_syscall_patch.so"));
+                command
+            }
+        };
+
+        let output = command
+            .args(args)
+            .env("VFS_CONFIG", self.root.path().join("vfs.conf"))
+            .output()
+            .unwrap();
+        assert!(
+            output.stderr.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            output.status.success(),
+        )
+    }
+
+    fn output(&self, probe: Probe, args: &[&str]) -> String {
+        let (output, success) = self.run(probe, args);
+        assert!(success, "{args:?} failed: {output}");
+        output
+    }
+}
+
+/// Builds the libraries and returns the directory containing them.
+fn libraries() -> &'static Path {
+    static LIBRARIES: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARIES.get_or_init(|| {
+        assert!(build_libraries(None).status().unwrap().success());
+        profile_dir().to_owned()
+    })
+}
+
+/// target/<profile>: test binaries live in target/<profile>/deps.
+fn profile_dir() -> &'static Path {
+    static PROFILE_DIR: OnceLock<PathBuf> = OnceLock::new();
+    PROFILE_DIR.get_or_init(|| {
+        let exe = std::env::current_exe().unwrap();
+        exe.parent().unwrap().parent().unwrap().to_owned()
+    })
+}
+
+/// The command building the libraries for `target`, or the host.
+fn build_libraries(target: Option<&str>) -> Command {
+    // `cargo test` doesn't build staticlibs or cdylibs, so build them here, next to the test
+    // binaries.
+    let profile_dir = profile_dir();
+    let mut command = Command::new(env!("CARGO"));
+    command
+        .args(["build", "--lib", "--package", env!("CARGO_PKG_NAME")])
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(profile_dir.parent().unwrap());
+    if let Some(target) = target {
+        command.args(["--target", target]);
+    }
+    if profile_dir.file_name().unwrap() == "release" {
+        command.arg("--release");
+    }
+
+    command
+}
+
+/// Compiles the static and dynamic probes.
+fn probes() -> &'static (PathBuf, PathBuf) {
+    static PROBES: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+    PROBES.get_or_init(|| {
+        let source = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/probe.c");
+        let directory = Path::new(env!("CARGO_TARGET_TMPDIR"));
+        let static_probe = directory.join("vfs-syscall-patch-probe-static");
+        let dynamic_probe = directory.join("vfs-syscall-patch-probe");
+
+        // Warnings about glibc functions std references but never calls here (getaddrinfo, ...)
+        // are expected when linking statically.
+        let status = Command::new("cc")
+            .args(["-static", source, "-Wl,--whole-archive"])
+            .arg(libraries().join("libvfs_syscall_patch.a"))
+            .args(["-Wl,--no-whole-archive", "-o"])
+            .arg(&static_probe)
+            .stderr(std::process::Stdio::null())
+            .status()
+            .expect("a C compiler (cc) is required to build the test program");
+        assert!(status.success(), "linking the static probe failed");
+
+        let status = Command::new("cc")
+            .arg(source)
+            .arg("-o")
+            .arg(&dynamic_probe)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        (static_probe, dynamic_probe)
+    })
+}
+
+/// Compiles the musl probe, or returns `None` (saying why, past the test harness's output
+/// capture) if `musl-gcc` or the Rust musl target is missing.
+fn musl_probe() -> Option<&'static Path> {
+    static PROBE: OnceLock<Option<PathBuf>> = OnceLock::new();
+    let probe = PROBE.get_or_init(|| {
+        let probe = build_musl_probe();
+        if let Err(reason) = &probe {
+            let message = format!("skipping the musl static binary: {reason}\n");
+            io::stderr().write_all(message.as_bytes()).unwrap();
+        }
+        probe.ok()
+    });
+    probe.as_deref()
+}
+
+fn build_musl_probe() -> Result<PathBuf, String> {
+    const TARGET: &str = "x86_64-unknown-linux-musl";
+    let compiler = Command::new("musl-gcc").arg("--version").output();
+    if !compiler.is_ok_and(|x| x.status.success()) {
+        return Err("musl-gcc isn't installed".to_owned());
+    }
+
+    let build = build_libraries(Some(TARGET)).output().unwrap();
+    if !build.status.success() {
+        return Err(format!(
+            "building for {TARGET} failed; is the target installed (rustup target add {TARGET})?"
+        ));
+    }
+
+    let source = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/probe.c");
+    let profile = profile_dir().file_name().unwrap();
+    let library = profile_dir().parent().unwrap().join(TARGET).join(profile);
+    let probe = Path::new(env!("CARGO_TARGET_TMPDIR")).join("vfs-syscall-patch-probe-musl");
+    let status = Command::new("musl-gcc")
+        .args(["-static", source, "-Wl,--whole-archive"])
+        .arg(library.join("libvfs_syscall_patch.a"))
+        .args(["-Wl,--no-whole-archive", "-o"])
+        .arg(&probe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "linking the musl probe failed");
+    Ok(probe)
+}
+
+/// The statically linked probes which could be built.
+fn static_probes() -> Vec<Probe> {
+    match musl_probe() {
+        Some(_) => vec![Probe::Static, Probe::Musl],
+        None => vec![Probe::Static],
+    }
+}
+
+#[test]
+fn static_binary_is_redirected() {
+    let fixture = Fixture::new();
+    let player = fixture.path("game/player.png");
+    let untouched = fixture.path("game/untouched.txt");
+    for probe in static_probes() {
+        assert_eq!(fixture.output(probe, &["open", &player]), "modded");
+        assert_eq!(fixture.output(probe, &["fopen", &player]), "modded");
+        assert_eq!(
+            fixture.output(probe, &["stat", &player]),
+            "size=6 regular=1\n"
+        );
+        assert_eq!(fixture.output(probe, &["open", &untouched]), "untouched");
+    }
+}
+
+#[test]
+fn static_binary_sees_virtual_files() {
+    let fixture = Fixture::new();
+    let extra = fixture.path("game/extra.bin");
+    let denied = (format!("errno={}\n", libc::EACCES), false);
+    for probe in static_probes() {
+        assert_eq!(fixture.output(probe, &["open", &extra]), "virtual content");
+        assert_eq!(
+            fixture.output(probe, &["stat", &extra]),
+            "size=15 regular=1\n"
+        );
+        assert_eq!(
+            fixture.output(probe, &["statx", &extra]),
+            "size=15 regular=1\n"
+        );
+        assert_eq!(fixture.output(probe, &["access", &extra, "r"]), "ok\n");
+        assert_eq!(fixture.run(probe, &["access", &extra, "w"]), denied);
+    }
+}
+
+#[test]
+fn overlapping_sites_are_patched_once() {
+    let fixture = Fixture::new();
+    let extra = fixture.path("game/extra.bin");
+    // The first site is patched and sees the virtual file; the second, overlapping it, goes
+    // to the kernel, which doesn't.
+    let output = format!("0 {}\n", -libc::ENOENT);
+    for probe in static_probes() {
+        assert_eq!(fixture.output(probe, &["adjacent", &extra]), output);
+    }
+}
+
+#[test]
+fn static_binary_creates_files_in_folder_redirect() {
+    let fixture = Fixture::new();
+    fixture.output(
+        Probe::Static,
+        &["write", &fixture.path("game/saves/new.sav"), "created"],
+    );
+    assert_eq!(
+        fs::read_to_string(fixture.path("mod/saves/new.sav")).unwrap(),
+        "created"
+    );
+    assert!(!Path::new(&fixture.path("game/saves/new.sav")).exists());
+}
+
+#[test]
+fn preloaded_into_dynamic_binary_patches_libc() {
+    let fixture = Fixture::new();
+    let player = fixture.path("game/player.png");
+    let extra = fixture.path("game/extra.bin");
+    assert_eq!(
+        fixture.output(Probe::Preloaded, &["open", &player]),
+        "modded"
+    );
+    assert_eq!(
+        fixture.output(Probe::Preloaded, &["fopen", &extra]),
+        "virtual content"
+    );
+    assert_eq!(
+        fixture.output(Probe::Preloaded, &["stat", &extra]),
+        "size=15 regular=1\n"
+    );
+}
+
+#[test]
+fn child_processes_inherit_vfs_through_scrubbed_environments() {
+    let fixture = Fixture::new();
+    let player = fixture.path("game/player.png");
+
+    // Static launcher -> static game: `VFS_CONFIG` is put back by the patched `execve`.
+    let game = probes().0.to_str().unwrap();
+    assert_eq!(
+        fixture.output(Probe::Static, &["spawn", game, "open", &player]),
+        "modded"
+    );
+
+    // Dynamic launcher -> dynamic game: `LD_PRELOAD` is put back too.
+    let game = probes().1.to_str().unwrap();
+    assert_eq!(
+        fixture.output(Probe::Preloaded, &["spawn", game, "open", &player]),
+        "modded"
+    );
+}
@@ -0,0 +1,121 @@
+// Test program linked statically with the patcher; performs one file operation and prints
+// the result. glibc's internal calls in a static binary go straight to `syscall`, with no
+// exported functions for `LD_PRELOAD` to interpose.
+
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <spawn.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int fail(void) {
+    printf("errno=%d\n", errno);
+    return 1;
+}
+
+// Two `access(path, R_OK)` syscalls back to back. The first `syscall` can't take in the `jz`
+// before it, so its patch site grows forwards over the second's `mov eax`; that second site
+// must be left alone, or patching it would overwrite the first site's jump.
+static void adjacent_access(const char *path, int results[2]) {
+    int first = -1, second;
+    __asm__ volatile("mov $21, %%eax\n\t"
+                     "test %%rdi, %%rdi\n\t"
+                     "jz 1f\n\t"
+                     "syscall\n\t"
+                     "mov %%eax, %%edx\n\t"
+                     "mov $21, %%eax\n\t"
+                     "syscall\n\t"
+                     "1:\n\t"
+                     : "=&a"(second), "+d"(first)
+                     : "D"(path), "S"(R_OK)
+                     : "rcx", "r11", "memory");
+    results[0] = first;
+    results[1] = second;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 3)
+        return 2;
+
+    const char *command = argv[1];
+    const char *path = argv[2];
+    if (strcmp(command, "open") == 0) {
+        int fd = open(path, O_RDONLY);
+        if (fd < 0)
+            return fail();
+        char buffer[4096];
+        ssize_t count;
+        while ((count = read(fd, buffer, sizeof buffer)) > 0)
+            fwrite(buffer, 1, count, stdout);
+        close(fd);
+        return 0;
+    }
+
+    if (strcmp(command, "fopen") == 0) {
+        FILE *file = fopen(path, "rb");
+        if (!file)
+            return fail();
+        int c;
+        while ((c = fgetc(file)) != EOF)
+            putchar(c);
+        fclose(file);
+        return 0;
+    }
+
+    if (strcmp(command, "write") == 0) {
+        int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+        if (fd < 0)
+            return fail();
+        write(fd, argv[3], strlen(argv[3]));
+        close(fd);
+        return 0;
+    }
+
+    if (strcmp(command, "stat") == 0) {
+        struct stat st;
+        if (stat(path, &st) != 0)
+            return fail();
+        printf("size=%lld regular=%d\n", (long long)st.st_size, S_ISREG(st.st_mode));
+        return 0;
+    }
+
+    if (strcmp(command, "statx") == 0) {
+        struct statx stx;
+        if (statx(AT_FDCWD, path, 0, STATX_BASIC_STATS, &stx) != 0)
+            return fail();
+        printf("size=%llu regular=%d\n", (unsigned long long)stx.stx_size, S_ISREG(stx.stx_mode));
+        return 0;
+    }
+
+    if (strcmp(command, "access") == 0) {
+        int mode = strcmp(argv[3], "w") == 0 ? W_OK : R_OK;
+        if (access(path, mode) != 0)
+            return fail();
+        printf("ok\n");
+        return 0;
+    }
+
+    if (strcmp(command, "adjacent") == 0) {
+        int results[2];
+        adjacent_access(path, results);
+        printf("%d %d\n", results[0], results[1]);
+        return 0;
+    }
+
+    // Launcher: start `argv[2..]` with an empty environment, like a launcher which scrubs it.
+    if (strcmp(command, "spawn") == 0) {
+        char *empty_environment[] = {NULL};
+        pid_t child;
+        int status;
+        errno = posix_spawn(&child, path, NULL, NULL, argv + 2, empty_environment);
+        if (errno != 0 || waitpid(child, &status, 0) < 0)
+            return fail();
+        return WIFEXITED(status) ? WEXITSTATUS(status) : 1;
+    }
+
+    return 2;
+}
@@ -0,0 +1,141 @@
+// Asynchronous reads: completion based handler API and the adapters around it
+
+use crate::handler::FileHandler;
+use std::io;
+use std::sync::mpsc;
+
+/// Called exactly once when an asynchronous read finishes.
+///
+/// Receives the buffer back, together with the number of bytes read into it.
+pub type ReadCompletion = Box<dyn FnOnce(ReadBuffer, io::Result<usize>) + Send>;
+
+/// Destination of an asynchronous read.
+///
+/// Either owned by the request, or borrowed from the caller the way overlapped I/O (`NtReadFile`
+/// with an `OVERLAPPED`/APC) and io_uring (SQE buffer pointers) borrow it: the caller keeps the
+/// memory alive until the completion runs.
+pub struct ReadBuffer {
+    ptr: *mut u8,
+    len: usize,
+    owned: Option<Vec<u8>>,
+}
+
+// Only one request touches the buffer at a time; `from_raw` callers guarantee exclusive access.
+unsafe impl Send for ReadBuffer {}
+
+impl ReadBuffer {
+    /// Allocates a zeroed buffer of `len` bytes.
+    pub fn new(len: usize) -> Self {
+        Self::from_vec(vec![0; len])
+    }
+
+    /// Reads into an owned vector, using its full length.
+    pub fn from_vec(mut vec: Vec<u8>) -> Self {
+        Self {
+            ptr: vec.as_mut_ptr(),
+            len: vec.len(),
+            owned: Some(vec),
+        }
+    }
+
+    /// Reads into caller owned memory.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writes of `len` bytes, and not accessed by anything else,
+    /// until the read's [`ReadCompletion`] has run.
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> Self {
+        Self {
+            ptr,
+            len,
+            owned: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Shortens the visible part of the buffer. Has no effect if `len` is not smaller.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    /// Returns the vector for owned buffers (with its original length), `None` for raw ones.
+    pub fn into_vec(self) -> Option<Vec<u8>> {
+        self.owned
+    }
+}
+
+/// A file handler that produces content asynchronously, e.g. by submitting its own
+/// IoRing/io_uring requests or by decompressing on a dedicated thread.
+///
+/// Register it wrapped in a [`BlockingAdapter`], so synchronous hooks can use it too.
+pub trait AsyncFileHandler: Send + Sync {
+    /// Starts reading `buffer.len()` bytes at `offset`, and returns without waiting.
+    ///
+    /// `completion` must be called exactly once, from any thread. Unlike
+    /// [`FileHandler::read_at`], all requested bytes must be returned unless end of file is
+    /// reached; Layer 2 does not resubmit short asynchronous reads.
+    fn read_at_async(&self, offset: u64, buffer: ReadBuffer, completion: ReadCompletion);
+
+    /// Size of the file in bytes. `None` uses the size from the registered metadata.
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Exposes an [`AsyncFileHandler`] as a [`FileHandler`].
+///
+/// Synchronous reads (`NtReadFile` on a synchronous handle, `read`) submit the request and
+/// block the calling thread until it completes. Asynchronous reads go to the wrapped
+/// handler directly.
+pub struct BlockingAdapter<H: AsyncFileHandler>(pub H);
+
+impl<H: AsyncFileHandler> BlockingAdapter<H> {
+    pub fn new(handler: H) -> Self {
+        Self(handler)
+    }
+}
+
+impl<H: AsyncFileHandler> FileHandler for BlockingAdapter<H> {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+
+        // Safe: we block below until the completion has run.
+        let request = unsafe { ReadBuffer::from_raw(buffer.as_mut_ptr(), buffer.len()) };
+        self.0.read_at_async(
+            offset,
+            request,
+            Box::new(move |_, result| {
+                let _ = sender.send(result);
+            }),
+        );
+
+        receiver.recv().unwrap_or_else(|_| {
+            Err(io::Error::other(
+                "asynchronous read was dropped without completing",
+            ))
+        })
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.0.size()
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncFileHandler> {
+        Some(&self.0)
+    }
+}
@@ -0,0 +1,413 @@
+// Virtual file registration and read dispatch
+
+use crate::async_io::{ReadBuffer, ReadCompletion};
+use crate::handler::{FileHandler, ReadSegment};
+use crate::handles::{DescriptorTable, OpenFile, RawHandle};
+use crate::workers::WorkerPool;
+use std::collections::HashMap;
+use std::io::{self, IoSliceMut, SeekFrom};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use virtual_filesystem::{VfsError, VirtualFileHandle, VirtualFileMetadata, VirtualFiles};
+
+/// A virtual file registered by an extension.
+pub(crate) struct RegisteredFile {
+    pub(crate) metadata: VirtualFileMetadata,
+    pub(crate) handler: Box<dyn FileHandler>,
+    // Number of open files (not handles) referring to this file
+    open_count: AtomicUsize,
+}
+
+impl RegisteredFile {
+    pub(crate) fn size(&self) -> u64 {
+        self.handler.size().unwrap_or(self.metadata.size())
+    }
+
+    pub(crate) fn acquire(&self) {
+        if self.open_count.fetch_add(1, Ordering::AcqRel) == 0 {
+            self.handler.on_open();
+        }
+    }
+
+    pub(crate) fn release(&self) {
+        if self.open_count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.handler.on_close();
+        }
+    }
+
+    /// Reads at `offset`, clamped to the file size.
+    /// Keeps calling the handler until `buffer` is full or end of file is reached.
+    pub(crate) fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size().saturating_sub(offset);
+        let length = buffer.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let buffer = &mut buffer[..length];
+
+        let mut total = 0;
+        while total < buffer.len() {
+            match self
+                .handler
+                .read_at(offset + total as u64, &mut buffer[total..])
+            {
+                Ok(0) => break,
+                Ok(read) => total += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // Report what we have; the error resurfaces on the next read.
+                Err(_) if total > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Fills `segments` with a single call into the handler where possible.
+    ///
+    /// Segments are clamped to the file size first; the handler only sees the in-bounds part.
+    /// Segments the handler leaves partially filled are completed with [`Self::read_at`].
+    pub(crate) fn read_segments(&self, segments: &mut [ReadSegment<'_>]) -> io::Result<()> {
+        let size = self.size();
+        let mut clamped: Vec<ReadSegment<'_>> = segments
+            .iter_mut()
+            .map(|segment| {
+                let remaining = size.saturating_sub(segment.offset);
+                let length = segment
+                    .buffer
+                    .len()
+                    .min(remaining.try_into().unwrap_or(usize::MAX));
+                ReadSegment::new(segment.offset, &mut segment.buffer[..length])
+            })
+            .collect();
+
+        if clamped.iter().any(|x| !x.buffer.is_empty()) {
+            self.handler.read_segments(&mut clamped)?;
+        }
+
+        for segment in &mut clamped {
+            segment.filled = segment.filled.min(segment.buffer.len());
+            if segment.filled < segment.buffer.len() {
+                let offset = segment.offset + segment.filled as u64;
+                segment.filled += self.read_at(offset, &mut segment.buffer[segment.filled..])?;
+            }
+        }
+
+        let filled: Vec<usize> = clamped.iter().map(|x| x.filled).collect();
+        for (segment, filled) in segments.iter_mut().zip(filled) {
+            segment.filled = filled;
+        }
+
+        Ok(())
+    }
+}
+
+/// Layer 2: Virtual File Framework.
+///
+/// Extensions register virtual files here together with a [`FileHandler`] that provides their
+/// content. Registration is forwarded to Layer 1 so the files show up in directory searches.
+///
+/// The hooks (`NtCreateFile`, `NtReadFile`, `NtSetInformationFile`, `NtClose`, `NtDuplicateObject`
+/// on Windows; `open`, `read`, `lseek`, `close`, `dup` on Linux) call the dispatch methods below
+/// with the OS handle they are operating on. None of the dispatch logic depends on hooks being
+/// installed.
+pub struct VirtualFileFramework {
+    virtual_files: Arc<VirtualFiles>,
+    // Maps Layer 1 handle -> registered file
+    files: RwLock<HashMap<VirtualFileHandle, Arc<RegisteredFile>>>,
+    handles: DescriptorTable<OpenFile>,
+    // Runs synchronous handlers for asynchronous reads. Started on first use.
+    workers: OnceLock<WorkerPool>,
+    worker_count: usize,
+}
+
+/// Default number of threads serving asynchronous reads of synchronous handlers.
+const DEFAULT_ASYNC_WORKERS: usize = 4;
+
+impl VirtualFileFramework {
+    /// Creates the framework on top of the given Layer 1 registry.
+    pub fn new(virtual_files: Arc<VirtualFiles>) -> Self {
+        Self::with_async_workers(virtual_files, DEFAULT_ASYNC_WORKERS)
+    }
+
+    /// Creates the framework, using `worker_count` threads to run synchronous handlers
+    /// for asynchronous reads.
+    pub fn with_async_workers(virtual_files: Arc<VirtualFiles>, worker_count: usize) -> Self {
+        Self {
+            virtual_files,
+            files: RwLock::new(HashMap::new()),
+            handles: DescriptorTable::new(),
+            workers: OnceLock::new(),
+            worker_count,
+        }
+    }
+
+    /// The Layer 1 registry this framework registers files with.
+    pub fn virtual_files(&self) -> &Arc<VirtualFiles> {
+        &self.virtual_files
+    }
+
+    /// Creates a virtual file at `path`, whose content is provided by `file_handler`.
+    ///
+    /// Internally calls Layer 1's [`VirtualFiles::register_virtual_file`].
+    pub fn register_virtual_file(
+        &self,
+        path: &str,
+        metadata: VirtualFileMetadata,
+        file_handler: Box<dyn FileHandler>,
+    ) -> Result<VirtualFileHandle, VfsError> {
+        let mut files = self.files.write().unwrap();
+        let handle = self.virtual_files.register_virtual_file(path, metadata)?;
+        files.insert(
+            handle,
+            Arc::new(RegisteredFile {
+                metadata,
+                handler: file_handler,
+                open_count: AtomicUsize::new(0),
+            }),
+        );
+
+        Ok(handle)
+    }
+
+    /// Removes a virtual file registered earlier.
+    ///
+    /// Handles that are already open remain valid until closed.
+    pub fn unregister_virtual_file(&self, handle: VirtualFileHandle) -> Result<(), VfsError> {
+        let mut files = self.files.write().unwrap();
+        if files.remove(&handle).is_none() {
+            return Err(VfsError::InvalidHandle);
+        }
+
+        self.virtual_files.unregister_virtual_file(handle)
+    }
+
+    /// Returns true if `path` refers to a virtual file registered through this framework.
+    pub fn is_virtual_file(&self, path: &str) -> bool {
+        self.find(path).is_some()
+    }
+
+    /// Starts tracking `handle`, which the OS (or hook) returned for opening `path`.
+    ///
+    /// Called from `NtCreateFile`/`NtOpenFile`/`open`. The file position starts at 0.
+    /// Returns [`VfsError::NotFound`] if `path` is not a virtual file.
+    pub fn open(&self, path: &str, handle: RawHandle) -> Result<(), VfsError> {
+        let file = self.find(path).ok_or(VfsError::NotFound)?;
+        self.handles.insert(handle, OpenFile::new(file), false);
+        Ok(())
+    }
+
+    /// Makes `target` share state (file position, etc.) with `source`.
+    ///
+    /// Called from `NtDuplicateObject`/`dup`. The file is only closed once both are closed.
+    pub fn duplicate(&self, source: RawHandle, target: RawHandle) -> Result<(), VfsError> {
+        match self.handles.duplicate(source, target, false) {
+            true => Ok(()),
+            false => Err(VfsError::InvalidHandle),
+        }
+    }
+
+    /// Stops tracking `handle`. Called from `NtClose`/`close`.
+    pub fn close(&self, handle: RawHandle) -> Result<(), VfsError> {
+        match self.handles.remove(handle) {
+            Some(_) => Ok(()),
+            None => Err(VfsError::InvalidHandle),
+        }
+    }
+
+    /// Returns true if `handle` was opened for a virtual file.
+    ///
+    /// Hooks use this to decide whether to handle an operation or call the original function.
+    pub fn is_virtual_file_handle(&self, handle: RawHandle) -> bool {
+        self.handles.contains(handle)
+    }
+
+    /// Reads from the current file position of `handle`, advancing it by the number of bytes read.
+    ///
+    /// Fills `buffer` entirely unless end of file is reached. Returns `0` at end of file.
+    pub fn read(&self, handle: RawHandle, buffer: &mut [u8]) -> io::Result<usize> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        let mut position = open_file.position.lock().unwrap();
+        let read = open_file.file.read_at(*position, buffer)?;
+        *position += read as u64;
+        Ok(read)
+    }
+
+    /// Reads at an explicit `offset`, without changing the file position (`pread` semantics).
+    ///
+    /// `NtReadFile` with a `ByteOffset` on a synchronous handle also moves the file pointer;
+    /// the hook should [`seek`](Self::seek) then [`read`](Self::read) in that case.
+    pub fn read_at(&self, handle: RawHandle, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        open_file.file.read_at(offset, buffer)
+    }
+
+    /// Scatter read: fills each segment from its own offset (`NtReadFileScatter`).
+    ///
+    /// Does not change the file position. On return, [`ReadSegment::filled`] holds the bytes read
+    /// into each segment. A segment is only short if it extends past end of file; segments that
+    /// start at or past end of file read `0` bytes. Returns the total number of bytes read.
+    ///
+    /// `NtReadFileScatter` reads one contiguous file range into page sized buffers; build the
+    /// segments with consecutive offsets (see [`Self::read_vectored_at`]) and seek afterwards
+    /// if the handle is synchronous.
+    pub fn read_scatter(
+        &self,
+        handle: RawHandle,
+        segments: &mut [ReadSegment<'_>],
+    ) -> io::Result<usize> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        open_file.file.read_segments(segments)?;
+        Ok(segments.iter().map(|x| x.filled).sum())
+    }
+
+    /// Vectored read at `offset` into consecutive buffers (`preadv`, `preadv2`).
+    ///
+    /// Buffers cover one contiguous range of the file, in order. Does not change the file position.
+    /// Returns the total bytes read, which is only less than the combined buffer length if end of
+    /// file was reached. Buffers after the end of file are left untouched.
+    pub fn read_vectored_at(
+        &self,
+        handle: RawHandle,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        Self::read_contiguous(&open_file.file, offset, buffers)
+    }
+
+    /// Vectored read from the current file position (`readv`), advancing it by the bytes read.
+    ///
+    /// Same end of file behaviour as [`Self::read_vectored_at`].
+    pub fn read_vectored(
+        &self,
+        handle: RawHandle,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        let mut position = open_file.position.lock().unwrap();
+        let read = Self::read_contiguous(&open_file.file, *position, buffers)?;
+        *position += read as u64;
+        Ok(read)
+    }
+
+    /// Starts an asynchronous read at `offset` and returns without waiting for it.
+    ///
+    /// For overlapped `NtReadFile` calls, IoRing and io_uring style submissions. `completion`
+    /// runs exactly once with the buffer and the number of bytes read; reads are clamped to the
+    /// file size like synchronous ones. Reads at or past end of file complete immediately, on
+    /// the calling thread, with `0` bytes. Does not change the file position.
+    ///
+    /// Handlers with a native asynchronous implementation ([`FileHandler::as_async`]) are called
+    /// directly. Otherwise the handler runs on a Layer 2 worker thread, so a slow handler does
+    /// not block the thread that asked for asynchronous I/O; if it panics, the read completes
+    /// with an error.
+    ///
+    /// Returns [`VfsError::InvalidHandle`] without calling `completion` if `handle` is unknown.
+    pub fn read_at_async(
+        &self,
+        handle: RawHandle,
+        offset: u64,
+        mut buffer: ReadBuffer,
+        completion: ReadCompletion,
+    ) -> Result<(), VfsError> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        let file = open_file.file.clone();
+
+        let remaining = file.size().saturating_sub(offset);
+        buffer.truncate(remaining.try_into().unwrap_or(usize::MAX));
+        if buffer.is_empty() {
+            completion(buffer, Ok(0));
+            return Ok(());
+        }
+
+        if let Some(handler) = file.handler.as_async() {
+            handler.read_at_async(offset, buffer, completion);
+            return Ok(());
+        }
+
+        self.workers().submit(Box::new(move || {
+            let read = || file.read_at(offset, buffer.as_mut_slice());
+            let result = panic::catch_unwind(AssertUnwindSafe(read))
+                .unwrap_or_else(|_| Err(io::Error::other("file handler panicked")));
+            completion(buffer, result);
+        }));
+
+        Ok(())
+    }
+
+    /// Moves the file position of `handle`, returning the new position.
+    ///
+    /// Called from `NtSetInformationFile` (`FilePositionInformation`) and `lseek`.
+    /// Seeking past the end of the file is allowed; reads there return `0`.
+    pub fn seek(&self, handle: RawHandle, position: SeekFrom) -> io::Result<u64> {
+        let open_file = self.handles.get(handle).ok_or(VfsError::InvalidHandle)?;
+        let mut current = open_file.position.lock().unwrap();
+        let new_position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => current.checked_add_signed(delta),
+            SeekFrom::End(delta) => open_file.file.size().checked_add_signed(delta),
+        };
+
+        let new_position = new_position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+
+        *current = new_position;
+        Ok(new_position)
+    }
+
+    /// Current file position of `handle`.
+    pub fn position(&self, handle: RawHandle) -> Option<u64> {
+        let open_file = self.handles.get(handle)?;
+        let position = *open_file.position.lock().unwrap();
+        Some(position)
+    }
+
+    /// Metadata of the file behind `handle`, for `NtQueryInformationFile`/`fstat`.
+    ///
+    /// The size reflects [`FileHandler::size`] if the handler overrides it.
+    pub fn metadata(&self, handle: RawHandle) -> Option<VirtualFileMetadata> {
+        let open_file = self.handles.get(handle)?;
+        let mut metadata = open_file.file.metadata;
+        metadata.end_of_file = open_file.file.size() as i64;
+        Some(metadata)
+    }
+
+    fn read_contiguous(
+        file: &RegisteredFile,
+        offset: u64,
+        buffers: &mut [IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        let mut next_offset = offset;
+        let mut segments: Vec<ReadSegment<'_>> = buffers
+            .iter_mut()
+            .map(|buffer| {
+                let segment = ReadSegment::new(next_offset, buffer);
+                next_offset += segment.buffer.len() as u64;
+                segment
+            })
+            .collect();
+
+        file.read_segments(&mut segments)?;
+
+        // Like `readv`, the result covers a contiguous range: stop counting at the first short segment.
+        let mut total = 0;
+        for segment in &segments {
+            total += segment.filled;
+            if segment.filled < segment.buffer.len() {
+                break;
+            }
+        }
+
+        Ok(total)
+    }
+
+    fn workers(&self) -> &WorkerPool {
+        self.workers
+            .get_or_init(|| WorkerPool::new(self.worker_count))
+    }
+
+    pub(crate) fn find(&self, path: &str) -> Option<Arc<RegisteredFile>> {
+        let entry = self.virtual_files.get(path)?;
+        self.files.read().unwrap().get(&entry.handle).cloned()
+    }
+}
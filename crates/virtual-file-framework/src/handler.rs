@@ -0,0 +1,76 @@
+// Interface implemented by Layer 3 extensions to provide file content
+
+use crate::async_io::AsyncFileHandler;
+use std::io;
+
+/// Provides the content of a virtual file.
+///
+/// Implemented by extensions (Layer 3), e.g. an archive emulator or Nx2VFS.
+/// Layer 2 owns everything else: handle state, file position, seeks and clamping reads to the file size.
+pub trait FileHandler: Send + Sync {
+    /// Reads up to `buffer.len()` bytes starting at `offset` within the file.
+    ///
+    /// Returns the number of bytes written into `buffer`. Returning less than requested is allowed;
+    /// Layer 2 calls again for the remainder. Returning `0` is treated as end of file.
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Reads several segments in one call (`NtReadFileScatter`, `readv`/`preadv`).
+    ///
+    /// Each segment arrives clamped to the file size. Set [`ReadSegment::filled`] to the number
+    /// of bytes written into each segment; Layer 2 completes any segment left partially filled
+    /// with [`read_at`](Self::read_at).
+    ///
+    /// The default implementation calls [`read_at`](Self::read_at) once per segment. Override it
+    /// if the handler can serve multiple segments more cheaply together, e.g. by decompressing
+    /// a shared block once.
+    fn read_segments(&self, segments: &mut [ReadSegment<'_>]) -> io::Result<()> {
+        for segment in segments {
+            segment.filled = self.read_at(segment.offset, segment.buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Size of the file in bytes.
+    ///
+    /// `None` uses the size from the metadata the file was registered with.
+    fn size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the native asynchronous implementation of this handler, if it has one.
+    ///
+    /// Asynchronous reads of handlers without one run [`read_at`](Self::read_at) on a
+    /// Layer 2 worker thread. See [`BlockingAdapter`](crate::BlockingAdapter).
+    fn as_async(&self) -> Option<&dyn AsyncFileHandler> {
+        None
+    }
+
+    /// Called when the first handle to the file is opened.
+    ///
+    /// Called again if the file is reopened after all previous handles were closed.
+    fn on_open(&self) {}
+
+    /// Called when the last handle to the file (including duplicates) is closed.
+    fn on_close(&self) {}
+}
+
+/// One buffer of a scatter/vectored read, and where in the file it is read from.
+#[derive(Debug)]
+pub struct ReadSegment<'a> {
+    /// Offset within the file.
+    pub offset: u64,
+    pub buffer: &'a mut [u8],
+    /// Number of bytes of `buffer` that have been filled.
+    pub filled: usize,
+}
+
+impl<'a> ReadSegment<'a> {
+    pub fn new(offset: u64, buffer: &'a mut [u8]) -> Self {
+        Self {
+            offset,
+            buffer,
+            filled: 0,
+        }
+    }
+}
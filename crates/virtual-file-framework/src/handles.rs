@@ -0,0 +1,205 @@
+// Handle/descriptor tracking table shared by the hook backends
+
+use crate::framework::RegisteredFile;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Raw OS handle value: a `HANDLE` on Windows, a file descriptor on POSIX.
+pub type RawHandle = usize;
+
+/// Tracks which handles (file descriptors) refer to files the VFS cares about.
+///
+/// Follows the OS model on both platforms: a handle refers to a shared *description*
+/// (Windows file object, POSIX open file description) holding state such as the file position.
+/// Duplicates share the description; it is dropped once its last handle is closed.
+/// Flags such as close-on-exec belong to the individual handle.
+///
+/// | Operation        | Windows                                   | POSIX                          |
+/// |------------------|-------------------------------------------|--------------------------------|
+/// | [`insert`]       | `NtCreateFile`, `NtOpenFile`              | `open`, `openat`               |
+/// | [`duplicate`]    | `NtDuplicateObject`                       | `dup`, `dup2`, `dup3`, `F_DUPFD` |
+/// | [`remove`]       | `NtClose`, `DUPLICATE_CLOSE_SOURCE`       | `close`, target of `dup2`      |
+/// | close-on-exec    | inverse of `HANDLE_FLAG_INHERIT`          | `FD_CLOEXEC`                   |
+/// | [`inherited`]    | `CreateProcess` with inherited handles    | `exec`                         |
+/// | [`fork`]         | -                                         | `fork`                         |
+///
+/// The table doesn't choose handle values; the hooks record the value the OS returned.
+///
+/// [`insert`]: Self::insert
+/// [`duplicate`]: Self::duplicate
+/// [`remove`]: Self::remove
+/// [`inherited`]: Self::inherited
+/// [`fork`]: Self::fork
+pub struct DescriptorTable<T> {
+    descriptors: RwLock<HashMap<RawHandle, Descriptor<T>>>,
+}
+
+struct Descriptor<T> {
+    description: Arc<T>,
+    close_on_exec: bool,
+}
+
+impl<T> Clone for Descriptor<T> {
+    fn clone(&self) -> Self {
+        Self {
+            description: self.description.clone(),
+            close_on_exec: self.close_on_exec,
+        }
+    }
+}
+
+impl<T> Default for DescriptorTable<T> {
+    fn default() -> Self {
+        Self {
+            descriptors: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T> DescriptorTable<T> {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking `handle` as a new description.
+    ///
+    /// The OS reuses handle values. If `handle` is still tracked, its close was missed; the
+    /// old entry is replaced and returned.
+    pub fn insert(&self, handle: RawHandle, description: T, close_on_exec: bool) -> Option<Arc<T>> {
+        let descriptor = Descriptor {
+            description: Arc::new(description),
+            close_on_exec,
+        };
+
+        let previous = self.descriptors.write().unwrap().insert(handle, descriptor);
+        previous.map(|x| x.description)
+    }
+
+    /// Makes `target` refer to the same description as `source`, with its own close-on-exec
+    /// flag. Anything tracked as `target` before is closed, as `dup2` does.
+    ///
+    /// Returns `false` (and leaves `target` alone) if `source` isn't tracked. `source` and
+    /// `target` being the same handle only updates the flag.
+    pub fn duplicate(&self, source: RawHandle, target: RawHandle, close_on_exec: bool) -> bool {
+        let mut descriptors = self.descriptors.write().unwrap();
+        let Some(description) = descriptors.get(&source).map(|x| x.description.clone()) else {
+            return false;
+        };
+
+        let descriptor = Descriptor {
+            description,
+            close_on_exec,
+        };
+        let previous = descriptors.insert(target, descriptor);
+        // Dropping a description may call into a handler; not with the table locked.
+        drop(descriptors);
+        drop(previous);
+        true
+    }
+
+    /// Stops tracking `handle`, returning its description if it was tracked.
+    ///
+    /// The description is dropped once no other handle (or caller) refers to it.
+    pub fn remove(&self, handle: RawHandle) -> Option<Arc<T>> {
+        let removed = self.descriptors.write().unwrap().remove(&handle);
+        removed.map(|x| x.description)
+    }
+
+    /// Returns the description `handle` refers to.
+    pub fn get(&self, handle: RawHandle) -> Option<Arc<T>> {
+        let descriptors = self.descriptors.read().unwrap();
+        descriptors.get(&handle).map(|x| x.description.clone())
+    }
+
+    /// Returns true if `handle` is tracked.
+    pub fn contains(&self, handle: RawHandle) -> bool {
+        self.descriptors.read().unwrap().contains_key(&handle)
+    }
+
+    /// Returns the close-on-exec flag of `handle`, or `None` if it isn't tracked.
+    pub fn close_on_exec(&self, handle: RawHandle) -> Option<bool> {
+        let descriptors = self.descriptors.read().unwrap();
+        descriptors.get(&handle).map(|x| x.close_on_exec)
+    }
+
+    /// Sets the close-on-exec flag of `handle` (`F_SETFD`, `SetHandleInformation`).
+    ///
+    /// Returns `false` if `handle` isn't tracked.
+    pub fn set_close_on_exec(&self, handle: RawHandle, close_on_exec: bool) -> bool {
+        let mut descriptors = self.descriptors.write().unwrap();
+        match descriptors.get_mut(&handle) {
+            Some(descriptor) => {
+                descriptor.close_on_exec = close_on_exec;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns every tracked handle, in no particular order.
+    pub fn handles(&self) -> Vec<RawHandle> {
+        self.descriptors.read().unwrap().keys().copied().collect()
+    }
+
+    /// Returns the number of tracked handles.
+    pub fn len(&self) -> usize {
+        self.descriptors.read().unwrap().len()
+    }
+
+    /// Returns true if no handles are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the table a new process starts with: the handles without close-on-exec,
+    /// sharing their descriptions with this table.
+    pub fn inherited(&self) -> Self {
+        let descriptors = self.descriptors.read().unwrap();
+        let inherited = descriptors
+            .iter()
+            .filter(|(_, x)| !x.close_on_exec)
+            .map(|(handle, x)| (*handle, x.clone()))
+            .collect();
+
+        Self {
+            descriptors: RwLock::new(inherited),
+        }
+    }
+
+    /// Runs `fork` (the real `fork`) with the table locked, and returns its result.
+    ///
+    /// The child gets a copy of the process memory, including this table. Holding the lock
+    /// across the fork means the copy is a consistent snapshot, rather than one caught halfway
+    /// through an update by another thread (whose lock would never be released in the child).
+    /// Parent and child each unlock their own copy afterwards. Descriptions are copied along
+    /// with the table; from then on each process has its own.
+    pub fn fork<R>(&self, fork: impl FnOnce() -> R) -> R {
+        let _descriptors = self.descriptors.write().unwrap();
+        fork()
+    }
+}
+
+/// State shared by a Layer 2 handle and all of its duplicates.
+pub(crate) struct OpenFile {
+    pub(crate) file: Arc<RegisteredFile>,
+    // Current file pointer. Held for the duration of a read so that position-relative
+    // reads on the same handle are serialised, as they are for real files.
+    pub(crate) position: Mutex<u64>,
+}
+
+impl OpenFile {
+    pub(crate) fn new(file: Arc<RegisteredFile>) -> Self {
+        file.acquire();
+        Self {
+            file,
+            position: Mutex::new(0),
+        }
+    }
+}
+
+impl Drop for OpenFile {
+    fn drop(&mut self) {
+        self.file.release();
+    }
+}
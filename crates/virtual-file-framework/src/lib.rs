@@ -0,0 +1,33 @@
+//! Layer 2: Virtual File Framework.
+//!
+//! Handles the 'what' problem: providing the content of virtual files once they are opened.
+//! Extensions (Layer 3) register files with a [`FileHandler`]; Layer 2 tracks handles,
+//! file positions and seeks, and dispatches reads to the handler.
+//!
+//! The [`DescriptorTable`] behind handle tracking is shared with the hook backends, which use it
+//! to remember which handles (file descriptors) refer to virtual or redirected files.
+//!
+//! Handlers are synchronous by default. Asynchronous reads of synchronous handlers run on
+//! Layer 2 worker threads; handlers that can produce data asynchronously implement
+//! [`AsyncFileHandler`] and are registered through a [`BlockingAdapter`].
+//!
+//! On Linux, virtual files can also be materialised as memory file descriptors
+//! ([`VirtualFileFramework::open_memfd`]), for code which only deals in real descriptors.
+
+mod async_io;
+mod framework;
+mod handler;
+mod handles;
+#[cfg(target_os = "linux")]
+mod memfd;
+#[cfg(target_os = "linux")]
+mod userfaultfd;
+mod workers;
+
+pub use async_io::{AsyncFileHandler, BlockingAdapter, ReadBuffer, ReadCompletion};
+pub use framework::VirtualFileFramework;
+pub use handler::{FileHandler, ReadSegment};
+pub use handles::{DescriptorTable, RawHandle};
+#[cfg(target_os = "linux")]
+pub use memfd::{populate_memfd, register_memfd_mapping, MemfdOptions};
+pub use virtual_filesystem::{VfsError, VirtualFileHandle, VirtualFileMetadata};
@@ -0,0 +1,442 @@
+// Virtual files as real file descriptors (Linux memory files)
+
+use crate::framework::{RegisteredFile, VirtualFileFramework};
+use crate::userfaultfd::{UserFaultFd, UFFD_EVENT_PAGEFAULT};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::FileExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once, OnceLock, RwLock};
+use virtual_filesystem::{path::file_name, VfsError};
+
+/// Size of the chunks in which content is copied into eagerly filled files.
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Not in the libc crate yet: blocks writes except through mappings made before sealing.
+const F_SEAL_FUTURE_WRITE: libc::c_int = 0x10;
+
+/// How [`VirtualFileFramework::open_memfd`] fills a memory file.
+///
+/// Defaults to [`eager`](Self::eager).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemfdOptions {
+    /// Files up to this size are copied in full when opened. Larger files are filled a page at
+    /// a time, as they are accessed.
+    pub eager_limit: u64,
+}
+
+impl MemfdOptions {
+    /// Always copies the whole file when opened.
+    ///
+    /// For descriptors which may be read without a hook seeing it first: raw syscalls,
+    /// `io_uring`, descriptors kept across `exec` or sent to other processes. A lazily filled
+    /// file reads as zeros wherever it wasn't filled yet.
+    pub const fn eager() -> Self {
+        Self {
+            eager_limit: u64::MAX,
+        }
+    }
+
+    /// Copies files up to `limit` bytes when opened, and fills larger ones on demand.
+    ///
+    /// Only for descriptors whose every read and mapping goes through a hook calling
+    /// [`populate_memfd`] or [`register_memfd_mapping`] first.
+    pub const fn lazy_above(limit: u64) -> Self {
+        Self { eager_limit: limit }
+    }
+}
+
+impl Default for MemfdOptions {
+    fn default() -> Self {
+        Self::eager()
+    }
+}
+
+impl VirtualFileFramework {
+    /// Materialises the virtual file at `path` as a read-only, sealed memory file
+    /// (`memfd_create`), so the descriptor works with every API: `read`, `mmap`, `sendfile`, ...
+    ///
+    /// Files are filled up front, unless `options` opt in to lazy filling, in which case files
+    /// above the limit are sized up front and filled on demand:
+    /// page faults in mappings registered with [`register_memfd_mapping`] are served through
+    /// `userfaultfd`, and the range a `read`-like call will access is filled by
+    /// [`populate_memfd`]. The kernel itself never asks for missing content, so hooks must
+    /// call these before passing such calls through; a missing page otherwise reads as zeros.
+    /// Falls back to filling up front if `userfaultfd` is unavailable, or the file can't be
+    /// set up to be filled on demand.
+    ///
+    /// Lazily filled files stay registered, and the handler open, for the life of the process.
+    /// The returned descriptor is close-on-exec.
+    pub fn open_memfd(&self, path: &str, options: &MemfdOptions) -> io::Result<OwnedFd> {
+        let file = self.find(path).ok_or(VfsError::NotFound)?;
+        let size = file.size();
+        let name = CString::new(file_name(path)).unwrap_or_default();
+        // SAFETY: `name` is a valid C string.
+        let fd = unsafe {
+            libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: `memfd_create` returned a new descriptor which we own.
+        let memory_file = unsafe { File::from_raw_fd(fd) };
+        let lazy = size > options.eager_limit
+            && lazy_files().is_some_and(|x| fill_lazily(x, &memory_file, &file, size).is_ok());
+        if !lazy {
+            file.acquire();
+            let result = copy_content(&file, &memory_file);
+            file.release();
+            result?;
+            seal(&memory_file, libc::F_SEAL_WRITE)?;
+        }
+
+        // Reopen read-only, matching the access virtual files allow.
+        let reopen_path = CString::new(format!("/proc/self/fd/{fd}")).unwrap();
+        vfs_sys::open(&reopen_path, libc::O_RDONLY | libc::O_CLOEXEC)
+    }
+}
+
+/// Sets `memory_file` up to be filled on demand. On failure, undoes everything, so the file can
+/// be filled up front instead.
+fn fill_lazily(
+    lazy_files: &LazyFiles,
+    memory_file: &File,
+    file: &Arc<RegisteredFile>,
+    size: u64,
+) -> io::Result<()> {
+    memory_file.set_len(size)?;
+    lazy_files.add(memory_file, file.clone(), size)?;
+    if let Err(error) = seal(memory_file, F_SEAL_FUTURE_WRITE) {
+        lazy_files.remove(memory_file);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Fills `length` bytes at `offset` of `fd`, if it is a lazily filled memory file from
+/// [`VirtualFileFramework::open_memfd`]. Does nothing for other descriptors.
+///
+/// `None` stands for the descriptor's file position, as used by `read` and friends. Call
+/// before passing through calls which read the file's content in the kernel: `read`, `pread`,
+/// `readv`, `sendfile`, `splice`, `copy_file_range`.
+pub fn populate_memfd(fd: BorrowedFd<'_>, offset: Option<u64>, length: u64) -> io::Result<()> {
+    if !LAZY_FILES_OPENED.load(Ordering::Acquire) {
+        return Ok(());
+    }
+
+    let Some(lazy_files) = lazy_files() else {
+        return Ok(());
+    };
+
+    let Some(file) = lazy_files.find(fd) else {
+        return Ok(());
+    };
+
+    let offset = match offset {
+        Some(offset) => offset,
+        None => vfs_sys::lseek(fd, 0, libc::SEEK_CUR)?,
+    };
+
+    file.populate(&lazy_files.userfaultfd, offset, length)
+}
+
+/// Makes page faults in a mapping of `fd` fill the memory file on demand, if it is a lazily
+/// filled memory file. Does nothing for other descriptors.
+///
+/// Call right after `mmap` maps `length` bytes of `fd` at `address` with protection `prot`,
+/// starting at file `offset`, before anything accesses the mapping: touching a missing page of
+/// an unregistered mapping fills it with zeros for good. The mapping is replaced with an
+/// equivalent private one, since mappings of the read-only descriptor can't be registered with
+/// `userfaultfd`; the content never changes, so the two can't be told apart.
+///
+/// Where `userfaultfd` only handles faults from user mode (unprivileged), the mapped range is
+/// filled immediately instead, so the kernel can read the mapping too.
+pub fn register_memfd_mapping(
+    fd: BorrowedFd<'_>,
+    address: usize,
+    length: usize,
+    offset: u64,
+    prot: libc::c_int,
+) -> io::Result<()> {
+    if !LAZY_FILES_OPENED.load(Ordering::Acquire) {
+        return Ok(());
+    }
+
+    let Some(lazy_files) = lazy_files() else {
+        return Ok(());
+    };
+
+    let Some(file) = lazy_files.find(fd) else {
+        return Ok(());
+    };
+
+    if !lazy_files.userfaultfd.handles_kernel_faults {
+        return file.populate(&lazy_files.userfaultfd, offset, length as u64);
+    }
+
+    // `mmap` lengths needn't be page aligned, but the mapping always is.
+    let length = length.next_multiple_of(page_size());
+    // SAFETY: Replaces the caller's mapping of the same file, with the same content.
+    let remapped = unsafe {
+        vfs_sys::mmap(
+            address,
+            length,
+            prot,
+            libc::MAP_PRIVATE | libc::MAP_FIXED,
+            file.memory_file.as_raw_fd(),
+            offset,
+        )
+    };
+    if remapped.is_err() {
+        return file.populate(&lazy_files.userfaultfd, offset, length as u64);
+    }
+
+    if lazy_files.userfaultfd.register(address, length).is_err() {
+        return file.populate(&lazy_files.userfaultfd, offset, length as u64);
+    }
+
+    lazy_files.add_region(address, length, file, offset);
+    Ok(())
+}
+
+// Set once a lazily filled file exists, so unrelated descriptors skip the lookup
+static LAZY_FILES_OPENED: AtomicBool = AtomicBool::new(false);
+
+/// Process wide state of lazily filled memory files.
+struct LazyFiles {
+    userfaultfd: UserFaultFd,
+    // Keyed by (device, inode), which every descriptor of the file shares
+    files: RwLock<HashMap<(u64, u64), Arc<LazyFile>>>,
+    // Registered mappings of the files made by the program, keyed by start address
+    regions: RwLock<BTreeMap<usize, Region>>,
+    service: Once,
+}
+
+/// A lazily filled memory file.
+struct LazyFile {
+    file: Arc<RegisteredFile>,
+    /// Writable descriptor of the memory file, for mappings which can be registered.
+    memory_file: File,
+    size: u64,
+    /// Our own shared mapping of the whole file, registered with `userfaultfd`.
+    /// Content is installed through it; the page cache is shared with every other mapping.
+    base: usize,
+}
+
+struct Region {
+    length: usize,
+    file: Arc<LazyFile>,
+    offset: u64,
+}
+
+/// Returns the process wide state, or `None` if `userfaultfd` isn't available.
+fn lazy_files() -> Option<&'static LazyFiles> {
+    static LAZY_FILES: OnceLock<Option<LazyFiles>> = OnceLock::new();
+    LAZY_FILES
+        .get_or_init(|| {
+            let userfaultfd = UserFaultFd::new().ok()?;
+            Some(LazyFiles {
+                userfaultfd,
+                files: RwLock::new(HashMap::new()),
+                regions: RwLock::new(BTreeMap::new()),
+                service: Once::new(),
+            })
+        })
+        .as_ref()
+}
+
+impl LazyFiles {
+    fn add(&self, memory_file: &File, file: Arc<RegisteredFile>, size: u64) -> io::Result<()> {
+        let key = identity(memory_file.as_fd())?;
+        let own_file = duplicate(memory_file)?;
+        let length = (size as usize).next_multiple_of(page_size());
+        // SAFETY: Maps the memory file we own; the mapping is never unmapped.
+        let base = unsafe {
+            vfs_sys::mmap(
+                0,
+                length,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                memory_file.as_raw_fd(),
+                0,
+            )?
+        };
+
+        if let Err(error) = self.userfaultfd.register(base, length) {
+            // SAFETY: Unmapping the mapping made above, which nothing else knows about.
+            unsafe { libc::munmap(base as *mut libc::c_void, length) };
+            return Err(error);
+        }
+
+        file.acquire();
+        let lazy_file = Arc::new(LazyFile {
+            file,
+            memory_file: own_file,
+            size,
+            base,
+        });
+        self.files.write().unwrap().insert(key, lazy_file);
+        LAZY_FILES_OPENED.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Undoes [`add`](Self::add), before the file is handed out.
+    fn remove(&self, memory_file: &File) {
+        let Ok(key) = identity(memory_file.as_fd()) else {
+            return;
+        };
+
+        let Some(lazy_file) = self.files.write().unwrap().remove(&key) else {
+            return;
+        };
+
+        let length = (lazy_file.size as usize).next_multiple_of(page_size());
+        // SAFETY: Unmapping the file's own mapping, which nothing else knows about yet. This
+        // also unregisters it from `userfaultfd`.
+        unsafe { libc::munmap(lazy_file.base as *mut libc::c_void, length) };
+        lazy_file.file.release();
+    }
+
+    fn find(&self, fd: BorrowedFd<'_>) -> Option<Arc<LazyFile>> {
+        let key = identity(fd).ok()?;
+        self.files.read().unwrap().get(&key).cloned()
+    }
+
+    fn add_region(&'static self, address: usize, length: usize, file: Arc<LazyFile>, offset: u64) {
+        let mut regions = self.regions.write().unwrap();
+        // Anything overlapping was unmapped, or it couldn't have been mapped here.
+        let overlapping: Vec<usize> = regions
+            .range(..address + length)
+            .filter(|(start, region)| **start + region.length > address)
+            .map(|(start, _)| *start)
+            .collect();
+        for start in overlapping {
+            regions.remove(&start);
+        }
+
+        regions.insert(
+            address,
+            Region {
+                length,
+                file,
+                offset,
+            },
+        );
+        drop(regions);
+
+        self.service.call_once(|| {
+            std::thread::Builder::new()
+                .name("vfs-memfd-faults".into())
+                .spawn(move || self.serve())
+                .expect("failed to start the memory file fault thread");
+        });
+    }
+
+    /// Serves page faults in the program's mappings of lazily filled files.
+    fn serve(&self) {
+        let page_size = page_size();
+        while let Ok(message) = self.userfaultfd.read() {
+            if message.event != UFFD_EVENT_PAGEFAULT {
+                continue;
+            }
+
+            let address = message.address as usize & !(page_size - 1);
+            let region = {
+                let regions = self.regions.read().unwrap();
+                regions
+                    .range(..=address)
+                    .next_back()
+                    .filter(|(start, region)| address < **start + region.length)
+                    .map(|(start, region)| (*start, region.file.clone(), region.offset))
+            };
+
+            // Faults in our own mappings never happen: content is installed before it is read.
+            if let Some((start, file, offset)) = region {
+                let file_offset = offset + (address - start) as u64;
+                let _ = file.populate(&self.userfaultfd, file_offset, page_size as u64);
+            }
+
+            self.userfaultfd.wake(address, page_size);
+        }
+    }
+}
+
+impl LazyFile {
+    /// Installs the content of every missing page in the given range.
+    fn populate(&self, userfaultfd: &UserFaultFd, offset: u64, length: u64) -> io::Result<()> {
+        let page_size = page_size() as u64;
+        let end = offset.saturating_add(length).min(self.size);
+        if offset >= end {
+            return Ok(());
+        }
+
+        let first = offset / page_size * page_size;
+        let last = end.div_ceil(page_size) * page_size;
+        let mut resident = vec![0u8; ((last - first) / page_size) as usize];
+        // SAFETY: `resident` has one byte per page of the range, which is inside our mapping.
+        let result = unsafe {
+            libc::mincore(
+                (self.base + first as usize) as *mut libc::c_void,
+                (last - first) as usize,
+                resident.as_mut_ptr(),
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut page = vec![0u8; page_size as usize];
+        for (index, _) in resident.iter().enumerate().filter(|(_, x)| **x & 1 == 0) {
+            let page_offset = first + index as u64 * page_size;
+            page.fill(0);
+            // A failed read leaves zeros; the fault must be resolved either way.
+            let _ = self.file.read_at(page_offset, &mut page);
+            userfaultfd.copy(self.base + page_offset as usize, &page)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn copy_content(file: &RegisteredFile, memory_file: &File) -> io::Result<()> {
+    let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+    let mut offset = 0u64;
+    loop {
+        let read = file.read_at(offset, &mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        memory_file.write_all_at(&buffer[..read], offset)?;
+        offset += read as u64;
+    }
+}
+
+/// Prevents resizing, writes of kind `write_seal` and further sealing.
+fn seal(memory_file: &File, write_seal: libc::c_int) -> io::Result<()> {
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | write_seal | libc::F_SEAL_SEAL;
+    vfs_sys::fcntl(memory_file.as_fd(), libc::F_ADD_SEALS, seals as u64)?;
+    Ok(())
+}
+
+/// Duplicates `memory_file`, close-on-exec, as [`File::try_clone`] without going through libc.
+fn duplicate(memory_file: &File) -> io::Result<File> {
+    let fd = vfs_sys::fcntl(memory_file.as_fd(), libc::F_DUPFD_CLOEXEC, 0)?;
+    // SAFETY: `fcntl` returned a new descriptor which we own.
+    Ok(unsafe { File::from_raw_fd(fd as libc::c_int) })
+}
+
+fn identity(fd: BorrowedFd<'_>) -> io::Result<(u64, u64)> {
+    let stat = vfs_sys::fstat(fd)?;
+    Ok((stat.st_dev, stat.st_ino))
+}
+
+fn page_size() -> usize {
+    // SAFETY: Always safe to call.
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
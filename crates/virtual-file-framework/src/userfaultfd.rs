@@ -0,0 +1,170 @@
+// Minimal userfaultfd bindings (linux/userfaultfd.h); the libc crate doesn't provide them
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+
+const UFFD_API: u64 = 0xAA;
+const UFFD_USER_MODE_ONLY: libc::c_int = 1;
+const UFFD_FEATURE_MISSING_SHMEM: u64 = 1 << 5;
+const UFFDIO_REGISTER_MODE_MISSING: u64 = 1;
+pub(crate) const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+
+// _IOWR/_IOR(0xAA, nr, struct); the same encoding on x86_64 and AArch64. `ioctl` takes the
+// request as an unsigned long on glibc and an int on musl, so the bits are cast to either.
+const UFFDIO_API: libc::Ioctl = 0xC018_AA3F_u32 as libc::Ioctl;
+const UFFDIO_REGISTER: libc::Ioctl = 0xC020_AA00_u32 as libc::Ioctl;
+const UFFDIO_WAKE: libc::Ioctl = 0x8010_AA02_u32 as libc::Ioctl;
+const UFFDIO_COPY: libc::Ioctl = 0xC028_AA03_u32 as libc::Ioctl;
+
+#[repr(C)]
+struct UffdioApi {
+    api: u64,
+    features: u64,
+    ioctls: u64,
+}
+
+#[repr(C)]
+struct UffdioRange {
+    start: u64,
+    len: u64,
+}
+
+#[repr(C)]
+struct UffdioRegister {
+    range: UffdioRange,
+    mode: u64,
+    ioctls: u64,
+}
+
+#[repr(C)]
+struct UffdioCopy {
+    dst: u64,
+    src: u64,
+    len: u64,
+    mode: u64,
+    copy: i64,
+}
+
+/// A message read from the descriptor; only page faults are requested.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct UffdMsg {
+    pub(crate) event: u8,
+    _reserved: [u8; 7],
+    pub(crate) flags: u64,
+    pub(crate) address: u64,
+    _pagefault_rest: u64,
+}
+
+/// A userfaultfd descriptor set up for missing page faults on memory files.
+pub(crate) struct UserFaultFd {
+    fd: OwnedFd,
+    /// Whether faults from kernel mode (e.g. `write` from a mapping) are delivered too.
+    pub(crate) handles_kernel_faults: bool,
+}
+
+impl UserFaultFd {
+    /// Opens a descriptor, falling back to user mode faults only when unprivileged.
+    pub(crate) fn new() -> io::Result<Self> {
+        let (fd, handles_kernel_faults) = match open(0) {
+            Ok(fd) => (fd, true),
+            Err(_) => (open(UFFD_USER_MODE_ONLY)?, false),
+        };
+
+        let mut api = UffdioApi {
+            api: UFFD_API,
+            features: UFFD_FEATURE_MISSING_SHMEM,
+            ioctls: 0,
+        };
+        // SAFETY: `api` is the structure this request reads and fills.
+        if unsafe { libc::ioctl(fd.as_raw_fd(), UFFDIO_API, &mut api) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd,
+            handles_kernel_faults,
+        })
+    }
+
+    /// Delivers missing page faults in `length` bytes at `address` to this descriptor.
+    pub(crate) fn register(&self, address: usize, length: usize) -> io::Result<()> {
+        let mut register = UffdioRegister {
+            range: UffdioRange {
+                start: address as u64,
+                len: length as u64,
+            },
+            mode: UFFDIO_REGISTER_MODE_MISSING,
+            ioctls: 0,
+        };
+        // SAFETY: `register` is the structure this request reads and fills.
+        match unsafe { libc::ioctl(self.fd.as_raw_fd(), UFFDIO_REGISTER, &mut register) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Atomically installs `page` at `address` (in a registered range), waking faulting threads.
+    ///
+    /// Returns `false` if the page was already present.
+    pub(crate) fn copy(&self, address: usize, page: &[u8]) -> io::Result<bool> {
+        let mut copy = UffdioCopy {
+            dst: address as u64,
+            src: page.as_ptr() as u64,
+            len: page.len() as u64,
+            mode: 0,
+            copy: 0,
+        };
+        loop {
+            // SAFETY: `copy` is the structure this request reads and fills; `src` is readable.
+            if unsafe { libc::ioctl(self.fd.as_raw_fd(), UFFDIO_COPY, &mut copy) } == 0 {
+                return Ok(true);
+            }
+
+            match io::Error::last_os_error().raw_os_error() {
+                Some(libc::EEXIST) => return Ok(false),
+                // The address space changed under us; try again.
+                Some(libc::EAGAIN) => continue,
+                _ => return Err(io::Error::last_os_error()),
+            }
+        }
+    }
+
+    /// Wakes threads waiting on a fault in `length` bytes at `address`.
+    pub(crate) fn wake(&self, address: usize, length: usize) {
+        let range = UffdioRange {
+            start: address as u64,
+            len: length as u64,
+        };
+        // SAFETY: `range` is the structure this request reads.
+        unsafe { libc::ioctl(self.fd.as_raw_fd(), UFFDIO_WAKE, &range) };
+    }
+
+    /// Blocks until the next fault.
+    pub(crate) fn read(&self) -> io::Result<UffdMsg> {
+        let mut message = UffdMsg::default();
+        // SAFETY: `UffdMsg` has the kernel's layout, and any bytes are a valid `UffdMsg`.
+        let buffer = unsafe {
+            std::slice::from_raw_parts_mut(
+                (&mut message as *mut UffdMsg).cast::<u8>(),
+                size_of::<UffdMsg>(),
+            )
+        };
+        // A raw read: this runs on our own thread, where no hook guard is held.
+        match vfs_sys::read(self.fd.as_fd(), buffer)? {
+            read if read == size_of::<UffdMsg>() => Ok(message),
+            _ => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+fn open(flags: libc::c_int) -> io::Result<OwnedFd> {
+    // SAFETY: The syscall only takes flags.
+    let fd = unsafe { libc::syscall(libc::SYS_userfaultfd, libc::O_CLOEXEC | flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `userfaultfd` returned a new descriptor which we own.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+}
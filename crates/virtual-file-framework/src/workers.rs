@@ -0,0 +1,51 @@
+// Worker threads that run synchronous handlers on behalf of asynchronous callers
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads executing queued jobs in order of submission.
+///
+/// A job which panics only ends itself; the thread goes on to the next one.
+///
+/// Used so that a slow synchronous handler (e.g. a decompressor) serving an asynchronous
+/// read ties up one of these threads, rather than the game thread that issued the read.
+pub(crate) struct WorkerPool {
+    sender: Mutex<Sender<Job>>,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for index in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("vfs-async-{index}"))
+                .spawn(move || loop {
+                    // Hold the lock only while waiting for a job, not while running it.
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => {
+                            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                        }
+                        Err(_) => break, // Pool dropped
+                    }
+                })
+                .expect("failed to spawn async I/O worker");
+        }
+
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    pub(crate) fn submit(&self, job: Job) {
+        // Workers only exit once the sender is dropped, so this cannot fail.
+        let _ = self.sender.lock().unwrap().send(job);
+    }
+}
@@ -0,0 +1,227 @@
+// Asynchronous reads: worker dispatch for synchronous handlers, the native path for
+// asynchronous handlers, and the blocking adapter used by synchronous hooks.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use virtual_file_framework::{
+    AsyncFileHandler, BlockingAdapter, FileHandler, ReadBuffer, ReadCompletion,
+    VirtualFileFramework, VirtualFileMetadata,
+};
+use virtual_filesystem::{VfsError, VirtualFiles};
+
+fn content(size: usize) -> Vec<u8> {
+    (0..size).map(|x| (x % 253) as u8).collect()
+}
+
+// A synchronous handler that takes a long time, like a decompressor: each read waits until
+// the barrier's count of reads are running at once.
+struct SlowHandler {
+    data: Vec<u8>,
+    barrier: Barrier,
+}
+
+impl FileHandler for SlowHandler {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let worker = thread::current()
+            .name()
+            .unwrap_or("")
+            .starts_with("vfs-async-");
+        assert!(worker, "read on the thread which submitted it");
+        self.barrier.wait();
+
+        let start = offset as usize;
+        let length = buffer.len().min(self.data.len() - start);
+        buffer[..length].copy_from_slice(&self.data[start..start + length]);
+        Ok(length)
+    }
+}
+
+struct PanickingHandler;
+
+impl FileHandler for PanickingHandler {
+    fn read_at(&self, _offset: u64, _buffer: &mut [u8]) -> io::Result<usize> {
+        panic!("handler bug");
+    }
+}
+
+// Completes every request from its own thread.
+struct ThreadedHandler {
+    data: Arc<Vec<u8>>,
+    submissions: Arc<AtomicUsize>,
+}
+
+impl AsyncFileHandler for ThreadedHandler {
+    fn read_at_async(&self, offset: u64, mut buffer: ReadBuffer, completion: ReadCompletion) {
+        self.submissions.fetch_add(1, Ordering::SeqCst);
+        let data = self.data.clone();
+        thread::spawn(move || {
+            let start = offset as usize;
+            let length = buffer.len().min(data.len() - start);
+            buffer.as_mut_slice()[..length].copy_from_slice(&data[start..start + length]);
+            completion(buffer, Ok(length));
+        });
+    }
+}
+
+fn completion_channel() -> (
+    ReadCompletion,
+    mpsc::Receiver<(ReadBuffer, io::Result<usize>)>,
+) {
+    let (sender, receiver) = mpsc::channel();
+    let completion: ReadCompletion = Box::new(move |buffer, result| {
+        sender.send((buffer, result)).unwrap();
+    });
+    (completion, receiver)
+}
+
+#[test]
+fn slow_synchronous_handler_does_not_block_caller() {
+    let data = content(8192);
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    framework
+        .register_virtual_file(
+            "slow.bin",
+            VirtualFileMetadata::with_size(data.len() as u64),
+            Box::new(SlowHandler {
+                data: data.clone(),
+                barrier: Barrier::new(4),
+            }),
+        )
+        .unwrap();
+    framework.open("slow.bin", 1).unwrap();
+
+    // The reads only complete once all four run at once, on the worker threads.
+    let mut receivers = Vec::new();
+    for index in 0..4u64 {
+        let (completion, receiver) = completion_channel();
+        framework
+            .read_at_async(1, index * 1000, ReadBuffer::new(1000), completion)
+            .unwrap();
+        receivers.push((index, receiver));
+    }
+
+    for (index, receiver) in receivers {
+        let (buffer, result) = receiver.recv().unwrap();
+        assert_eq!(result.unwrap(), 1000);
+        let offset = index as usize * 1000;
+        assert_eq!(buffer.into_vec().unwrap(), &data[offset..offset + 1000]);
+    }
+
+    assert_eq!(framework.position(1), Some(0));
+}
+
+#[test]
+fn native_async_handler_is_called_directly() {
+    let data = Arc::new(content(5000));
+    let submissions = Arc::new(AtomicUsize::new(0));
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    framework
+        .register_virtual_file(
+            "async.bin",
+            VirtualFileMetadata::with_size(data.len() as u64),
+            Box::new(BlockingAdapter::new(ThreadedHandler {
+                data: data.clone(),
+                submissions: submissions.clone(),
+            })),
+        )
+        .unwrap();
+    framework.open("async.bin", 1).unwrap();
+
+    // Caller owned memory, as with overlapped I/O. Clamped to the end of the file.
+    let mut destination = vec![0u8; 100];
+    let (completion, receiver) = completion_channel();
+    let buffer = unsafe { ReadBuffer::from_raw(destination.as_mut_ptr(), destination.len()) };
+    framework
+        .read_at_async(1, 4950, buffer, completion)
+        .unwrap();
+    let (buffer, result) = receiver.recv().unwrap();
+    assert_eq!(result.unwrap(), 50);
+    assert_eq!(buffer.len(), 50);
+    assert!(buffer.into_vec().is_none());
+    assert_eq!(&destination[..50], &data[4950..]);
+    assert_eq!(submissions.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn blocking_adapter_serves_synchronous_reads() {
+    let data = Arc::new(content(5000));
+    let submissions = Arc::new(AtomicUsize::new(0));
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    framework
+        .register_virtual_file(
+            "async.bin",
+            VirtualFileMetadata::with_size(data.len() as u64),
+            Box::new(BlockingAdapter::new(ThreadedHandler {
+                data: data.clone(),
+                submissions: submissions.clone(),
+            })),
+        )
+        .unwrap();
+    framework.open("async.bin", 1).unwrap();
+
+    let mut buffer = vec![0u8; 3000];
+    assert_eq!(framework.read(1, &mut buffer).unwrap(), 3000);
+    assert_eq!(buffer, &data[..3000]);
+    assert_eq!(framework.read(1, &mut buffer).unwrap(), 2000);
+    assert_eq!(&buffer[..2000], &data[3000..]);
+    assert_eq!(framework.position(1), Some(5000));
+    assert_eq!(submissions.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn end_of_file_completes_immediately() {
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    framework
+        .register_virtual_file(
+            "slow.bin",
+            VirtualFileMetadata::with_size(10),
+            Box::new(SlowHandler {
+                data: content(10),
+                barrier: Barrier::new(1),
+            }),
+        )
+        .unwrap();
+    framework.open("slow.bin", 1).unwrap();
+
+    let (completion, receiver) = completion_channel();
+    framework
+        .read_at_async(1, 10, ReadBuffer::new(64), completion)
+        .unwrap();
+    let (_, result) = receiver.try_recv().unwrap();
+    assert_eq!(result.unwrap(), 0);
+
+    let (completion, receiver) = completion_channel();
+    assert_eq!(
+        framework
+            .read_at_async(2, 0, ReadBuffer::new(1), completion)
+            .unwrap_err(),
+        VfsError::InvalidHandle
+    );
+    assert!(receiver.recv().is_err(), "completion must not run");
+}
+
+#[test]
+fn panicking_handler_completes_with_an_error() {
+    let framework = VirtualFileFramework::with_async_workers(Arc::new(VirtualFiles::new()), 1);
+    framework
+        .register_virtual_file(
+            "panics.bin",
+            VirtualFileMetadata::with_size(10),
+            Box::new(PanickingHandler),
+        )
+        .unwrap();
+    framework.open("panics.bin", 1).unwrap();
+
+    // The only worker serves the second read too.
+    for _ in 0..2 {
+        let (completion, receiver) = completion_channel();
+        framework
+            .read_at_async(1, 0, ReadBuffer::new(10), completion)
+            .unwrap();
+        let (_, result) = receiver.recv().unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,147 @@
+// Exercises the descriptor table as the hooks drive it, for both handle and descriptor semantics.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use virtual_file_framework::DescriptorTable;
+
+// Counts drops, to check when the last handle to a description goes away.
+struct Description {
+    name: &'static str,
+    drops: Arc<AtomicUsize>,
+}
+
+impl Drop for Description {
+    fn drop(&mut self) {
+        self.drops.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn description(name: &'static str) -> (Description, Arc<AtomicUsize>) {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let description = Description {
+        name,
+        drops: drops.clone(),
+    };
+    (description, drops)
+}
+
+#[test]
+fn duplicates_share_description_until_last_close() {
+    let table = DescriptorTable::new();
+    let (file, drops) = description("file");
+    table.insert(3, file, false);
+
+    // `dup`, then `dup3(..., O_CLOEXEC)`.
+    assert!(table.duplicate(3, 4, false));
+    assert!(table.duplicate(4, 10, true));
+    assert!(Arc::ptr_eq(&table.get(3).unwrap(), &table.get(10).unwrap()));
+    assert_eq!(table.len(), 3);
+
+    drop(table.remove(3));
+    drop(table.remove(4));
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    assert_eq!(table.get(10).unwrap().name, "file");
+
+    drop(table.remove(10));
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(table.is_empty());
+}
+
+#[test]
+fn close_on_exec_is_per_descriptor() {
+    let table = DescriptorTable::new();
+    table.insert(3, description("file").0, true);
+    table.duplicate(3, 4, false);
+    assert_eq!(table.close_on_exec(3), Some(true));
+    assert_eq!(table.close_on_exec(4), Some(false));
+
+    // `fcntl(F_SETFD)` on one doesn't affect the other.
+    assert!(table.set_close_on_exec(4, true));
+    assert!(table.set_close_on_exec(3, false));
+    assert_eq!(table.close_on_exec(3), Some(false));
+    assert_eq!(table.close_on_exec(4), Some(true));
+    assert!(!table.set_close_on_exec(5, true));
+    assert_eq!(table.close_on_exec(5), None);
+}
+
+#[test]
+fn duplicate_replaces_target() {
+    let table = DescriptorTable::new();
+    let (first, first_drops) = description("first");
+    let (second, second_drops) = description("second");
+    table.insert(3, first, false);
+    table.insert(4, second, false);
+
+    // `dup2(3, 4)` closes what 4 referred to.
+    assert!(table.duplicate(3, 4, false));
+    assert_eq!(table.get(4).unwrap().name, "first");
+    assert_eq!(second_drops.load(Ordering::SeqCst), 1);
+    assert_eq!(first_drops.load(Ordering::SeqCst), 0);
+
+    // Untracked sources leave the target alone; the hook forgets it separately.
+    assert!(!table.duplicate(7, 4, false));
+    assert_eq!(table.get(4).unwrap().name, "first");
+}
+
+#[test]
+fn reused_handle_value_replaces_missed_close() {
+    let table = DescriptorTable::new();
+    let (old, old_drops) = description("old");
+    table.insert(3, old, false);
+
+    let previous = table.insert(3, description("new").0, false);
+    assert_eq!(previous.unwrap().name, "old");
+    assert_eq!(old_drops.load(Ordering::SeqCst), 1);
+    assert_eq!(table.get(3).unwrap().name, "new");
+}
+
+#[test]
+fn inherited_keeps_descriptors_without_close_on_exec() {
+    let table = DescriptorTable::new();
+    table.insert(3, description("kept").0, false);
+    table.insert(4, description("closed").0, true);
+    table.duplicate(3, 5, true);
+
+    let inherited = table.inherited();
+    let mut handles = inherited.handles();
+    handles.sort();
+    assert_eq!(handles, [3]);
+    assert!(Arc::ptr_eq(
+        &inherited.get(3).unwrap(),
+        &table.get(3).unwrap()
+    ));
+
+    // The parent's table is unchanged.
+    assert_eq!(table.len(), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn fork_gives_child_a_snapshot() {
+    let table = DescriptorTable::new();
+    table.insert(3, description("file").0, true);
+    table.duplicate(3, 4, false);
+
+    // SAFETY: The child only reads the table and exits, without returning to the harness.
+    let child = table.fork(|| unsafe { libc::fork() });
+    if child == 0 {
+        let ok = table.get(4).is_some_and(|x| x.name == "file")
+            && Arc::ptr_eq(&table.get(3).unwrap(), &table.get(4).unwrap())
+            && table.close_on_exec(3) == Some(true)
+            && table.close_on_exec(4) == Some(false);
+        // Changes in the child aren't seen by the parent.
+        table.remove(3);
+        // SAFETY: Exits the child without running the parent's cleanup.
+        unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+    }
+
+    let mut status = 0;
+    // SAFETY: Waits for the child forked above.
+    assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+    assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+    assert!(table.contains(3));
+
+    // The table is usable again in the parent.
+    table.remove(4);
+    assert_eq!(table.len(), 1);
+}
@@ -0,0 +1,258 @@
+// Exercises the Layer 2 dispatch path directly, as the hooks would, without installing any hooks.
+
+use std::io::{self, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use virtual_file_framework::{FileHandler, VirtualFileFramework, VirtualFileMetadata};
+use virtual_filesystem::{VfsError, VirtualFiles};
+
+// Serves bytes from memory, at most `chunk` bytes per call to exercise short reads.
+struct MemoryHandler {
+    data: Vec<u8>,
+    chunk: usize,
+    opens: Arc<AtomicUsize>,
+    closes: Arc<AtomicUsize>,
+}
+
+impl MemoryHandler {
+    fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            chunk: usize::MAX,
+            opens: Arc::default(),
+            closes: Arc::default(),
+        }
+    }
+}
+
+impl FileHandler for MemoryHandler {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let start = (offset as usize).min(self.data.len());
+        let length = buffer.len().min(self.data.len() - start).min(self.chunk);
+        buffer[..length].copy_from_slice(&self.data[start..start + length]);
+        Ok(length)
+    }
+
+    fn on_open(&self) {
+        self.opens.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_close(&self) {
+        self.closes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn setup(data: &[u8]) -> (VirtualFileFramework, Arc<VirtualFiles>) {
+    let layer1 = Arc::new(VirtualFiles::new());
+    let framework = VirtualFileFramework::new(layer1.clone());
+    framework
+        .register_virtual_file(
+            "game/data/file.bin",
+            VirtualFileMetadata::with_size(data.len() as u64),
+            Box::new(MemoryHandler::new(data.to_vec())),
+        )
+        .unwrap();
+    (framework, layer1)
+}
+
+#[test]
+fn registration_is_forwarded_to_layer1() {
+    let layer1 = Arc::new(VirtualFiles::new());
+    let framework = VirtualFileFramework::new(layer1.clone());
+    let handle = framework
+        .register_virtual_file(
+            "game/file.bin",
+            VirtualFileMetadata::with_size(4),
+            Box::new(MemoryHandler::new(vec![0; 4])),
+        )
+        .unwrap();
+
+    assert!(layer1.contains("game/file.bin"));
+    assert_eq!(layer1.get("GAME/File.bin").unwrap().metadata.size(), 4);
+    assert_eq!(
+        framework
+            .register_virtual_file(
+                "game/file.bin",
+                VirtualFileMetadata::with_size(4),
+                Box::new(MemoryHandler::new(vec![0; 4]))
+            )
+            .unwrap_err(),
+        VfsError::AlreadyExists
+    );
+
+    framework.unregister_virtual_file(handle).unwrap();
+    assert!(!layer1.contains("game/file.bin"));
+    assert!(!framework.is_virtual_file("game/file.bin"));
+    assert_eq!(
+        framework.unregister_virtual_file(handle).unwrap_err(),
+        VfsError::InvalidHandle
+    );
+}
+
+#[test]
+fn sequential_reads_advance_position() {
+    let data: Vec<u8> = (0..=255).collect();
+    let (framework, _) = setup(&data);
+    framework.open("game/data/file.bin", 10).unwrap();
+
+    let mut buffer = [0u8; 100];
+    assert_eq!(framework.read(10, &mut buffer).unwrap(), 100);
+    assert_eq!(&buffer[..], &data[..100]);
+    assert_eq!(framework.read(10, &mut buffer).unwrap(), 100);
+    assert_eq!(&buffer[..], &data[100..200]);
+
+    // Clamped to end of file, then EOF.
+    assert_eq!(framework.read(10, &mut buffer).unwrap(), 56);
+    assert_eq!(&buffer[..56], &data[200..]);
+    assert_eq!(framework.read(10, &mut buffer).unwrap(), 0);
+    assert_eq!(framework.position(10), Some(256));
+}
+
+#[test]
+fn short_handler_reads_are_completed() {
+    let data: Vec<u8> = (0..200).map(|x| x as u8).collect();
+    let layer1 = Arc::new(VirtualFiles::new());
+    let framework = VirtualFileFramework::new(layer1);
+    let mut handler = MemoryHandler::new(data.clone());
+    handler.chunk = 7;
+    framework
+        .register_virtual_file(
+            "file.bin",
+            VirtualFileMetadata::with_size(200),
+            Box::new(handler),
+        )
+        .unwrap();
+    framework.open("file.bin", 1).unwrap();
+
+    let mut buffer = [0u8; 150];
+    assert_eq!(framework.read(1, &mut buffer).unwrap(), 150);
+    assert_eq!(&buffer[..], &data[..150]);
+}
+
+#[test]
+fn seek_and_positional_reads() {
+    let data: Vec<u8> = (0..=255).collect();
+    let (framework, _) = setup(&data);
+    framework.open("game/data/file.bin", 3).unwrap();
+
+    assert_eq!(framework.seek(3, SeekFrom::Start(50)).unwrap(), 50);
+    assert_eq!(framework.seek(3, SeekFrom::Current(-10)).unwrap(), 40);
+    assert_eq!(framework.seek(3, SeekFrom::End(-6)).unwrap(), 250);
+    assert!(framework.seek(3, SeekFrom::Current(-251)).is_err());
+    assert_eq!(framework.position(3), Some(250));
+
+    // pread semantics: position is untouched.
+    let mut buffer = [0u8; 4];
+    assert_eq!(framework.read_at(3, 4, &mut buffer).unwrap(), 4);
+    assert_eq!(buffer, [4, 5, 6, 7]);
+    assert_eq!(framework.position(3), Some(250));
+
+    // Past the end is allowed, but reads nothing.
+    framework.seek(3, SeekFrom::Start(1000)).unwrap();
+    assert_eq!(framework.read(3, &mut buffer).unwrap(), 0);
+}
+
+#[test]
+fn duplicated_handles_share_state() {
+    let data: Vec<u8> = (0..=255).collect();
+    let layer1 = Arc::new(VirtualFiles::new());
+    let framework = VirtualFileFramework::new(layer1);
+    let handler = MemoryHandler::new(data);
+    let (opens, closes) = (handler.opens.clone(), handler.closes.clone());
+    framework
+        .register_virtual_file(
+            "file.bin",
+            VirtualFileMetadata::with_size(256),
+            Box::new(handler),
+        )
+        .unwrap();
+
+    framework.open("file.bin", 1).unwrap();
+    framework.duplicate(1, 2).unwrap();
+    assert_eq!(opens.load(Ordering::SeqCst), 1);
+
+    let mut buffer = [0u8; 16];
+    framework.read(1, &mut buffer).unwrap();
+    assert_eq!(framework.position(2), Some(16));
+    framework.read(2, &mut buffer).unwrap();
+    assert_eq!(buffer[0], 16);
+
+    // File stays open until both handles are closed.
+    framework.close(1).unwrap();
+    assert!(!framework.is_virtual_file_handle(1));
+    assert_eq!(closes.load(Ordering::SeqCst), 0);
+    assert_eq!(framework.read(2, &mut buffer).unwrap(), 16);
+    framework.close(2).unwrap();
+    assert_eq!(closes.load(Ordering::SeqCst), 1);
+
+    assert_eq!(framework.close(2).unwrap_err(), VfsError::InvalidHandle);
+    assert_eq!(
+        framework.duplicate(2, 3).unwrap_err(),
+        VfsError::InvalidHandle
+    );
+}
+
+#[test]
+fn independent_opens_have_independent_positions() {
+    let data: Vec<u8> = (0..=255).collect();
+    let (framework, _) = setup(&data);
+    framework.open("game/data/file.bin", 1).unwrap();
+    framework.open("GAME/DATA/FILE.BIN", 2).unwrap();
+
+    let mut buffer = [0u8; 8];
+    framework.read(1, &mut buffer).unwrap();
+    assert_eq!(framework.position(1), Some(8));
+    assert_eq!(framework.position(2), Some(0));
+}
+
+#[test]
+fn open_handles_survive_unregistration() {
+    let layer1 = Arc::new(VirtualFiles::new());
+    let framework = VirtualFileFramework::new(layer1);
+    let handle = framework
+        .register_virtual_file(
+            "file.bin",
+            VirtualFileMetadata::with_size(3),
+            Box::new(MemoryHandler::new(vec![1, 2, 3])),
+        )
+        .unwrap();
+    framework.open("file.bin", 7).unwrap();
+    framework.unregister_virtual_file(handle).unwrap();
+
+    assert_eq!(
+        framework.open("file.bin", 8).unwrap_err(),
+        VfsError::NotFound
+    );
+    let mut buffer = [0u8; 3];
+    assert_eq!(framework.read(7, &mut buffer).unwrap(), 3);
+    assert_eq!(buffer, [1, 2, 3]);
+}
+
+#[test]
+fn handler_size_overrides_metadata() {
+    struct Sized;
+    impl FileHandler for Sized {
+        fn read_at(&self, _offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+            buffer.fill(0xAA);
+            Ok(buffer.len())
+        }
+
+        fn size(&self) -> Option<u64> {
+            Some(10)
+        }
+    }
+
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    framework
+        .register_virtual_file(
+            "file.bin",
+            VirtualFileMetadata::with_size(0),
+            Box::new(Sized),
+        )
+        .unwrap();
+    framework.open("file.bin", 1).unwrap();
+
+    let mut buffer = [0u8; 32];
+    assert_eq!(framework.read(1, &mut buffer).unwrap(), 10);
+    assert_eq!(framework.metadata(1).unwrap().end_of_file, 10);
+}
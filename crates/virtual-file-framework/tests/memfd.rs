@@ -0,0 +1,178 @@
+// Checks that virtual files materialised as memory files read the same through `read`, `mmap`
+// and `sendfile`, whether they were filled up front or on demand.
+#![cfg(target_os = "linux")]
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use virtual_file_framework::{
+    populate_memfd, register_memfd_mapping, FileHandler, MemfdOptions, VirtualFileFramework,
+    VirtualFileMetadata,
+};
+use virtual_filesystem::VirtualFiles;
+
+// Spans several pages, with a partial last page.
+const FILE_SIZE: usize = 5 * 4096 + 123;
+const PATH: &str = "/game/data/big.bin";
+
+// Serves generated content and counts the bytes requested.
+struct PatternHandler {
+    requested: Arc<AtomicUsize>,
+}
+
+impl FileHandler for PatternHandler {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let start = (offset as usize).min(FILE_SIZE);
+        let length = buffer.len().min(FILE_SIZE - start);
+        for (index, byte) in buffer[..length].iter_mut().enumerate() {
+            *byte = pattern(start + index);
+        }
+
+        self.requested.fetch_add(length, Ordering::SeqCst);
+        Ok(length)
+    }
+}
+
+fn pattern(offset: usize) -> u8 {
+    (offset * 31 % 251) as u8 + 1
+}
+
+fn expected() -> Vec<u8> {
+    (0..FILE_SIZE).map(pattern).collect()
+}
+
+fn open(options: MemfdOptions) -> (OwnedFd, Arc<AtomicUsize>) {
+    let requested = Arc::new(AtomicUsize::new(0));
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    framework
+        .register_virtual_file(
+            PATH,
+            VirtualFileMetadata::with_size(FILE_SIZE as u64),
+            Box::new(PatternHandler {
+                requested: requested.clone(),
+            }),
+        )
+        .unwrap();
+
+    let fd = framework.open_memfd(PATH, &options).unwrap();
+    (fd, requested)
+}
+
+const LAZY: MemfdOptions = MemfdOptions::lazy_above(0);
+
+fn pread(fd: &OwnedFd, offset: usize, length: usize) -> Vec<u8> {
+    let mut buffer = vec![0u8; length];
+    // SAFETY: `buffer` is valid for `length` bytes.
+    let read = unsafe {
+        libc::pread(
+            fd.as_raw_fd(),
+            buffer.as_mut_ptr().cast(),
+            length,
+            offset as i64,
+        )
+    };
+    buffer.truncate(read as usize);
+    buffer
+}
+
+/// Maps the whole file read-only; returns the mapping's bytes.
+fn map(fd: &OwnedFd) -> &'static [u8] {
+    // SAFETY: Maps a read-only file; the mapping is leaked for the rest of the test.
+    unsafe {
+        let address = libc::mmap(
+            std::ptr::null_mut(),
+            FILE_SIZE,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            fd.as_raw_fd(),
+            0,
+        );
+        assert_ne!(address, libc::MAP_FAILED);
+        std::slice::from_raw_parts(address.cast(), FILE_SIZE)
+    }
+}
+
+fn sendfile(fd: &OwnedFd) -> Vec<u8> {
+    let mut out = tempfile::tempfile().unwrap();
+    let mut offset = 0;
+    // SAFETY: Copies between two valid descriptors.
+    let sent = unsafe { libc::sendfile(out.as_raw_fd(), fd.as_raw_fd(), &mut offset, FILE_SIZE) };
+    assert_eq!(sent, FILE_SIZE as isize);
+    let mut content = Vec::new();
+    io::Seek::rewind(&mut out).unwrap();
+    io::Read::read_to_end(&mut out, &mut content).unwrap();
+    content
+}
+
+#[test]
+fn eager_file_reads_natively() {
+    let (fd, requested) = open(MemfdOptions::eager());
+    assert_eq!(requested.load(Ordering::SeqCst), FILE_SIZE);
+    assert_eq!(pread(&fd, 0, FILE_SIZE + 10), expected());
+    assert_eq!(map(&fd), expected());
+    assert_eq!(sendfile(&fd), expected());
+}
+
+#[test]
+fn files_are_filled_up_front_by_default() {
+    // Reads no hook sees, such as raw syscalls or another process's, must not find zeros.
+    assert_eq!(MemfdOptions::default(), MemfdOptions::eager());
+    let (fd, requested) = open(MemfdOptions::default());
+    assert_eq!(requested.load(Ordering::SeqCst), FILE_SIZE);
+    assert_eq!(pread(&fd, 0, FILE_SIZE), expected());
+}
+
+#[test]
+fn lazy_file_fills_read_ranges_on_demand() {
+    let (fd, requested) = open(LAZY);
+    assert_eq!(requested.load(Ordering::SeqCst), 0);
+
+    // Only the page containing the range is filled.
+    populate_memfd(fd.as_fd(), Some(4096 + 10), 100).unwrap();
+    assert_eq!(requested.load(Ordering::SeqCst), 4096);
+    assert_eq!(
+        pread(&fd, 4096 + 10, 100),
+        expected()[4096 + 10..4096 + 110]
+    );
+
+    // Filled pages aren't requested again; `None` starts at the file position.
+    populate_memfd(fd.as_fd(), None, FILE_SIZE as u64).unwrap();
+    assert_eq!(requested.load(Ordering::SeqCst), FILE_SIZE);
+    assert_eq!(sendfile(&fd), expected());
+}
+
+#[test]
+fn lazy_file_fills_mappings_on_fault() {
+    let (fd, requested) = open(LAZY);
+    let mapping = map(&fd);
+    let address = mapping.as_ptr() as usize;
+    register_memfd_mapping(fd.as_fd(), address, FILE_SIZE, 0, libc::PROT_READ).unwrap();
+    assert_eq!(requested.load(Ordering::SeqCst), 0);
+
+    assert_eq!(mapping[3 * 4096 + 5], expected()[3 * 4096 + 5]);
+    assert_eq!(mapping, expected());
+    assert_eq!(requested.load(Ordering::SeqCst), FILE_SIZE);
+}
+
+#[test]
+fn memory_file_is_read_only() {
+    for options in [MemfdOptions::eager(), LAZY] {
+        let (fd, _) = open(options);
+        // SAFETY: Writes from a valid buffer.
+        let written = unsafe { libc::pwrite(fd.as_raw_fd(), [0u8].as_ptr().cast(), 1, 0) };
+        assert_eq!(written, -1);
+        // SAFETY: Only checks whether a writable mapping can be made.
+        let address = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                4096,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        assert_eq!(address, libc::MAP_FAILED);
+    }
+}
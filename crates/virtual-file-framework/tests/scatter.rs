@@ -0,0 +1,255 @@
+// Compares scatter/vectored reads of virtual files against `preadv`/`readv` on a real file
+// with the same content.
+#![cfg(unix)]
+
+use std::fs::File;
+use std::io::{self, IoSliceMut, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use virtual_file_framework::{FileHandler, ReadSegment, VirtualFileFramework, VirtualFileMetadata};
+use virtual_filesystem::VirtualFiles;
+
+const FILE_SIZE: usize = 10_000;
+const SENTINEL: u8 = 0xCD;
+
+// Serves a real file through the framework.
+struct FileBackedHandler(File);
+
+impl FileHandler for FileBackedHandler {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.0.read_at(buffer, offset)
+    }
+}
+
+// Serves memory, in small chunks, and counts calls to `read_segments`.
+struct ChunkedHandler {
+    data: Vec<u8>,
+    segment_calls: Arc<AtomicUsize>,
+}
+
+impl FileHandler for ChunkedHandler {
+    fn read_at(&self, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        let start = (offset as usize).min(self.data.len());
+        let length = buffer.len().min(self.data.len() - start).min(7);
+        buffer[..length].copy_from_slice(&self.data[start..start + length]);
+        Ok(length)
+    }
+
+    fn read_segments(&self, segments: &mut [ReadSegment<'_>]) -> io::Result<()> {
+        self.segment_calls.fetch_add(1, Ordering::SeqCst);
+        for segment in segments {
+            segment.filled = self.read_at(segment.offset, segment.buffer)?;
+        }
+        Ok(())
+    }
+}
+
+struct Fixture {
+    real: File,
+    framework: VirtualFileFramework,
+    segment_calls: Arc<AtomicUsize>,
+}
+
+// Virtual handles: 1 is served from the real file, 2 from memory.
+const HANDLES: [usize; 2] = [1, 2];
+
+fn setup() -> Fixture {
+    let data: Vec<u8> = (0..FILE_SIZE).map(|x| (x * 31 % 251) as u8).collect();
+    let mut real = tempfile::tempfile().unwrap();
+    real.write_all(&data).unwrap();
+
+    let segment_calls = Arc::new(AtomicUsize::new(0));
+    let framework = VirtualFileFramework::new(Arc::new(VirtualFiles::new()));
+    let metadata = VirtualFileMetadata::with_size(FILE_SIZE as u64);
+    framework
+        .register_virtual_file(
+            "file_backed.bin",
+            metadata,
+            Box::new(FileBackedHandler(real.try_clone().unwrap())),
+        )
+        .unwrap();
+    framework
+        .register_virtual_file(
+            "memory.bin",
+            metadata,
+            Box::new(ChunkedHandler {
+                data,
+                segment_calls: segment_calls.clone(),
+            }),
+        )
+        .unwrap();
+    framework.open("file_backed.bin", 1).unwrap();
+    framework.open("memory.bin", 2).unwrap();
+
+    Fixture {
+        real,
+        framework,
+        segment_calls,
+    }
+}
+
+fn buffers(lengths: &[usize]) -> Vec<Vec<u8>> {
+    lengths.iter().map(|&x| vec![SENTINEL; x]).collect()
+}
+
+fn preadv(file: &File, offset: u64, buffers: &mut [Vec<u8>]) -> usize {
+    let iovecs: Vec<libc::iovec> = buffers
+        .iter_mut()
+        .map(|x| libc::iovec {
+            iov_base: x.as_mut_ptr().cast(),
+            iov_len: x.len(),
+        })
+        .collect();
+    let result = unsafe {
+        libc::preadv(
+            file.as_raw_fd(),
+            iovecs.as_ptr(),
+            iovecs.len() as i32,
+            offset as libc::off_t,
+        )
+    };
+    assert!(result >= 0, "{}", io::Error::last_os_error());
+    result as usize
+}
+
+fn readv(file: &File, buffers: &mut [Vec<u8>]) -> usize {
+    let iovecs: Vec<libc::iovec> = buffers
+        .iter_mut()
+        .map(|x| libc::iovec {
+            iov_base: x.as_mut_ptr().cast(),
+            iov_len: x.len(),
+        })
+        .collect();
+    let result = unsafe { libc::readv(file.as_raw_fd(), iovecs.as_ptr(), iovecs.len() as i32) };
+    assert!(result >= 0, "{}", io::Error::last_os_error());
+    result as usize
+}
+
+fn as_slices(buffers: &mut [Vec<u8>]) -> Vec<IoSliceMut<'_>> {
+    buffers.iter_mut().map(|x| IoSliceMut::new(x)).collect()
+}
+
+#[test]
+fn preadv_matches_real_file() {
+    let fixture = setup();
+    let cases: &[(u64, &[usize])] = &[
+        (0, &[4096, 4096]),
+        (123, &[1, 2, 3, 500]),
+        (9_000, &[512, 512, 512]), // Crosses end of file in the second buffer
+        (9_990, &[5, 0, 5, 100]),  // Empty buffer in the middle, then EOF
+        (FILE_SIZE as u64, &[16, 16]), // At end of file
+        (20_000, &[16]),           // Past end of file
+        (4_000, &[]),              // No buffers
+    ];
+
+    for &(offset, lengths) in cases {
+        let mut expected = buffers(lengths);
+        let expected_read = preadv(&fixture.real, offset, &mut expected);
+
+        for handle in HANDLES {
+            let mut actual = buffers(lengths);
+            let read = fixture
+                .framework
+                .read_vectored_at(handle, offset, &mut as_slices(&mut actual))
+                .unwrap();
+            assert_eq!(read, expected_read, "offset {offset}, handle {handle}");
+            assert_eq!(actual, expected, "offset {offset}, handle {handle}");
+            assert_eq!(fixture.framework.position(handle), Some(0));
+        }
+    }
+}
+
+#[test]
+fn readv_matches_real_file_and_advances_position() {
+    let fixture = setup();
+    let lengths = [1000, 24, 2048];
+
+    for handle in HANDLES {
+        let mut real = fixture.real.try_clone().unwrap();
+        io::Seek::rewind(&mut real).unwrap();
+
+        loop {
+            let mut expected = buffers(&lengths);
+            let expected_read = readv(&real, &mut expected);
+
+            let mut actual = buffers(&lengths);
+            let read = fixture
+                .framework
+                .read_vectored(handle, &mut as_slices(&mut actual))
+                .unwrap();
+            assert_eq!(read, expected_read);
+            assert_eq!(actual, expected);
+
+            let real_position = io::Seek::stream_position(&mut real).unwrap();
+            assert_eq!(fixture.framework.position(handle), Some(real_position));
+
+            if read == 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn scatter_segments_match_positional_reads() {
+    let fixture = setup();
+    let layout: &[(u64, usize)] = &[
+        (8192, 4096), // Last page, crosses end of file
+        (0, 4096),
+        (5000, 10),
+        (5000, 10),   // Same range twice
+        (12_000, 64), // Past end of file
+    ];
+
+    for handle in HANDLES {
+        let mut storage = buffers(&layout.iter().map(|x| x.1).collect::<Vec<_>>());
+        let mut segments: Vec<ReadSegment<'_>> = storage
+            .iter_mut()
+            .zip(layout)
+            .map(|(buffer, &(offset, _))| ReadSegment::new(offset, buffer))
+            .collect();
+
+        let total = fixture
+            .framework
+            .read_scatter(handle, &mut segments)
+            .unwrap();
+
+        let mut expected_total = 0;
+        for segment in &segments {
+            let mut expected = vec![SENTINEL; segment.buffer.len()];
+            let expected_read = fixture.real.read_at(&mut expected, segment.offset).unwrap();
+            assert_eq!(segment.filled, expected_read, "offset {}", segment.offset);
+            assert_eq!(
+                &segment.buffer[..],
+                &expected[..],
+                "offset {}",
+                segment.offset
+            );
+            expected_total += expected_read;
+        }
+        assert_eq!(total, expected_total);
+    }
+}
+
+#[test]
+fn segments_are_served_with_one_handler_call() {
+    let fixture = setup();
+    let mut storage = buffers(&[100, 200, 300]);
+    fixture
+        .framework
+        .read_vectored_at(2, 50, &mut as_slices(&mut storage))
+        .unwrap();
+
+    // The chunked handler returns short reads; those are completed with `read_at`, not
+    // additional `read_segments` calls.
+    assert_eq!(fixture.segment_calls.load(Ordering::SeqCst), 1);
+
+    // Fully out of bounds requests don't reach the handler at all.
+    fixture
+        .framework
+        .read_vectored_at(2, 50_000, &mut as_slices(&mut storage))
+        .unwrap();
+    assert_eq!(fixture.segment_calls.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,58 @@
+// Error type shared by the VFS layers
+
+use std::fmt;
+
+/// Errors returned by VFS APIs.
+///
+/// Discriminants match the `R3VfsResult` codes of the C API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum VfsError {
+    NotInitialized = -1,
+    InvalidPath = -2,
+    PathTooLong = -3,
+    OutOfMemory = -4,
+    AlreadyExists = -5,
+    NotFound = -6,
+    InvalidHandle = -7,
+}
+
+impl VfsError {
+    /// Returns the C API (`R3VfsResult`) code for this error.
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl fmt::Display for VfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            VfsError::NotInitialized => "VFS is not initialized",
+            VfsError::InvalidPath => "invalid path",
+            VfsError::PathTooLong => "path is too long",
+            VfsError::OutOfMemory => "out of memory",
+            VfsError::AlreadyExists => "entry already exists",
+            VfsError::NotFound => "entry not found",
+            VfsError::InvalidHandle => "invalid handle",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for VfsError {}
+
+impl From<VfsError> for std::io::Error {
+    fn from(error: VfsError) -> Self {
+        use std::io::ErrorKind;
+        let kind = match error {
+            VfsError::InvalidPath | VfsError::PathTooLong | VfsError::InvalidHandle => {
+                ErrorKind::InvalidInput
+            }
+            VfsError::OutOfMemory => ErrorKind::OutOfMemory,
+            VfsError::AlreadyExists => ErrorKind::AlreadyExists,
+            VfsError::NotFound => ErrorKind::NotFound,
+            VfsError::NotInitialized => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, error)
+    }
+}
@@ -0,0 +1,18 @@
+//! Layer 1: Virtual FileSystem.
+//!
+//! Handles the 'where' problem: path redirection ([`Redirector`]), virtual file visibility and
+//! metadata ([`VirtualFiles`]).
+//! This layer does not care about file contents; see `virtual-file-framework` (Layer 2) for that.
+
+mod error;
+mod metadata;
+pub mod path;
+mod redirector;
+mod virtual_files;
+
+pub use error::VfsError;
+pub use metadata::*;
+pub use redirector::{
+    FolderFilesHandle, FolderRedirectHandle, RedirectHandle, Redirector, Resolution,
+};
+pub use virtual_files::{VirtualFileEntry, VirtualFileHandle, VirtualFiles};
@@ -0,0 +1,43 @@
+// Metadata reported for virtual files
+
+/// Platform specific file attribute flags.
+///
+/// On Windows these are the Win32 `FILE_ATTRIBUTE_*` flags, on Unix a `mode_t` equivalent.
+pub type FileAttributes = u32;
+
+pub const FILE_ATTRIBUTE_READONLY: u32 = 0x01;
+pub const FILE_ATTRIBUTE_HIDDEN: u32 = 0x02;
+pub const FILE_ATTRIBUTE_SYSTEM: u32 = 0x04;
+pub const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+pub const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+pub const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+
+/// Immutable metadata of a virtual file, set once at registration time.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VirtualFileMetadata {
+    pub creation_time: i64,
+    pub last_access_time: i64,
+    pub last_write_time: i64,
+    pub change_time: i64,
+    pub end_of_file: i64,     // File size in bytes
+    pub allocation_size: i64, // Allocated size (usually rounded to block size)
+    pub file_attributes: FileAttributes,
+}
+
+impl VirtualFileMetadata {
+    /// Creates metadata for a plain read-only file of `size` bytes with zeroed timestamps.
+    pub fn with_size(size: u64) -> Self {
+        Self {
+            end_of_file: size as i64,
+            allocation_size: size.next_multiple_of(4096) as i64,
+            file_attributes: FILE_ATTRIBUTE_NORMAL,
+            ..Default::default()
+        }
+    }
+
+    /// File size in bytes.
+    pub fn size(&self) -> u64 {
+        self.end_of_file as u64
+    }
+}
@@ -0,0 +1,109 @@
+// Path normalisation used for all lookups
+
+/// Normalises a path into the form used as a lookup key.
+///
+/// - Comparisons are case-insensitive, so the path is lowercased.
+/// - On Windows both `/` and `\` are accepted and converted to `\`.
+///   On Linux only `/` is a separator, backslashes are valid file name characters.
+/// - Repeated and trailing separators are removed.
+///
+/// Unicode normalisation is not performed; paths must otherwise match byte-for-byte.
+pub fn normalize_path(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    for c in path.chars() {
+        let c = if is_separator(c) { SEPARATOR } else { c };
+        if c == SEPARATOR && result.ends_with(SEPARATOR) {
+            continue;
+        }
+        result.extend(c.to_lowercase());
+    }
+
+    if result.len() > 1 && result.ends_with(SEPARATOR) {
+        result.pop();
+    }
+
+    result
+}
+
+/// Splits a normalised path into its parent directory and file name.
+///
+/// Returns `None` for the parent if the path has no separator.
+pub fn split_parent(path: &str) -> (Option<&str>, &str) {
+    match path.rfind(SEPARATOR) {
+        Some(0) => (Some(&path[..1]), &path[1..]),
+        Some(index) => (Some(&path[..index]), &path[index + 1..]),
+        None => (None, path),
+    }
+}
+
+/// Returns the last component of a (not necessarily normalised) path.
+pub fn file_name(path: &str) -> &str {
+    let path = path.trim_end_matches(is_separator);
+    match path.rfind(is_separator) {
+        Some(index) => &path[index + 1..],
+        None => path,
+    }
+}
+
+/// Returns the part of `path` below `folder`, if `path` is inside `folder`.
+///
+/// Components are compared the same way as [`normalize_path`] compares them, but the returned
+/// remainder keeps its original casing. Returns an empty string if `path` is `folder` itself.
+pub fn strip_folder<'a>(path: &'a str, folder: &str) -> Option<&'a str> {
+    let mut path_components = components(path);
+    for folder_component in components(folder).map(|x| x.1) {
+        let (_, path_component) = path_components.next()?;
+        if !eq_ignore_case(path_component, folder_component) {
+            return None;
+        }
+    }
+
+    Some(match path_components.next() {
+        Some((index, _)) => path[index..].trim_end_matches(is_separator),
+        None => "",
+    })
+}
+
+/// Joins `relative` onto `base` with a single separator.
+pub fn join(base: &str, relative: &str) -> String {
+    if relative.is_empty() {
+        return base.to_owned();
+    }
+
+    let mut result = base.trim_end_matches(is_separator).to_owned();
+    result.push(SEPARATOR);
+    result.push_str(relative.trim_start_matches(is_separator));
+    result
+}
+
+/// Iterates the non-empty components of a path, with their byte offsets.
+fn components(path: &str) -> impl Iterator<Item = (usize, &str)> {
+    path.split(is_separator)
+        .scan(0, |offset, component| {
+            let start = *offset;
+            *offset += component.len() + 1;
+            Some((start, component))
+        })
+        .filter(|(_, component)| !component.is_empty())
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+#[cfg(windows)]
+const SEPARATOR: char = '\\';
+#[cfg(not(windows))]
+const SEPARATOR: char = '/';
+
+#[cfg(windows)]
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+#[cfg(not(windows))]
+fn is_separator(c: char) -> bool {
+    c == '/'
+}
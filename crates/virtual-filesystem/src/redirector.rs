@@ -0,0 +1,291 @@
+// Path redirection: Tier 1 file redirects and Tier 2 folder fallback redirects
+
+use crate::error::VfsError;
+use crate::path::{file_name, join, normalize_path, split_parent, strip_folder};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Handle to an individual file redirect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RedirectHandle(u64);
+
+/// Handle to a set of file redirects created from a folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FolderFilesHandle(u64);
+
+/// Handle to a folder fallback redirect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FolderRedirectHandle(u64);
+
+/// Where a path should be opened from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Tier 1: always use this path, whether or not the file exists.
+    File(String),
+    /// Tier 2: use this path if it exists, otherwise fall back to the original one.
+    /// New files are created here.
+    Folder(String),
+}
+
+impl Resolution {
+    /// The redirected path, regardless of tier.
+    pub fn path(&self) -> &str {
+        match self {
+            Resolution::File(path) | Resolution::Folder(path) => path,
+        }
+    }
+}
+
+struct FileRedirect {
+    handle: RedirectHandle,
+    source: String,
+    target: String,
+}
+
+struct FolderRedirect {
+    handle: FolderRedirectHandle,
+    source: String,
+    target: String,
+}
+
+#[derive(Default)]
+struct RedirectorState {
+    // Maps normalised source path -> redirects for that path, latest last
+    files: HashMap<String, Vec<FileRedirect>>,
+    // Maps redirect handle -> normalised source path
+    file_handles: HashMap<RedirectHandle, String>,
+    // Folder fallback redirects, latest last
+    folders: Vec<FolderRedirect>,
+    // Maps folder-as-files handle -> the file redirects it created
+    folder_files: HashMap<FolderFilesHandle, Vec<RedirectHandle>>,
+}
+
+/// Redirects file and folder paths (Layer 1 public API).
+///
+/// Lookup checks Tier 1 file redirects first, then Tier 2 folder redirects.
+/// Within a tier, later additions take precedence over earlier ones.
+#[derive(Default)]
+pub struct Redirector {
+    state: RwLock<RedirectorState>,
+    next_handle: AtomicU64,
+}
+
+impl Redirector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redirects `source_path` (original game path) to `target_path` (mod file path).
+    pub fn add_file(
+        &self,
+        source_path: &str,
+        target_path: &str,
+    ) -> Result<RedirectHandle, VfsError> {
+        let mut state = self.state.write().unwrap();
+        self.add_file_locked(&mut state, source_path, target_path)
+    }
+
+    /// Removes an individual file redirect.
+    pub fn remove_file(&self, handle: RedirectHandle) -> Result<(), VfsError> {
+        let mut state = self.state.write().unwrap();
+        Self::remove_file_locked(&mut state, handle)
+    }
+
+    /// Creates a file redirect for every file currently inside `target_folder`.
+    ///
+    /// `source_folder/x/y.bin` is redirected to `target_folder/x/y.bin`.
+    /// The folder is scanned once; changes made to it afterwards are not tracked.
+    pub fn add_folder_as_files(
+        &self,
+        source_folder: &str,
+        target_folder: &str,
+    ) -> Result<FolderFilesHandle, VfsError> {
+        if source_folder.is_empty() {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let mut relative_paths = Vec::new();
+        collect_files(Path::new(target_folder), String::new(), &mut relative_paths)?;
+
+        let mut state = self.state.write().unwrap();
+        let mut handles = Vec::with_capacity(relative_paths.len());
+        for relative in relative_paths {
+            handles.push(self.add_file_locked(
+                &mut state,
+                &join(source_folder, &relative),
+                &join(target_folder, &relative),
+            )?);
+        }
+
+        let handle = FolderFilesHandle(self.allocate_handle());
+        state.folder_files.insert(handle, handles);
+        Ok(handle)
+    }
+
+    /// Removes folder-as-files and the file redirects it created.
+    pub fn remove_folder_as_files(&self, handle: FolderFilesHandle) -> Result<(), VfsError> {
+        let mut state = self.state.write().unwrap();
+        let handles = state
+            .folder_files
+            .remove(&handle)
+            .ok_or(VfsError::InvalidHandle)?;
+        for handle in handles {
+            // May already have been removed individually.
+            let _ = Self::remove_file_locked(&mut state, handle);
+        }
+
+        Ok(())
+    }
+
+    /// Adds a folder fallback redirect: `source_folder/x` resolves to `target_folder/x`.
+    pub fn add_folder(
+        &self,
+        source_folder: &str,
+        target_folder: &str,
+    ) -> Result<FolderRedirectHandle, VfsError> {
+        if source_folder.is_empty() || target_folder.is_empty() {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let handle = FolderRedirectHandle(self.allocate_handle());
+        self.state.write().unwrap().folders.push(FolderRedirect {
+            handle,
+            source: source_folder.to_owned(),
+            target: target_folder.to_owned(),
+        });
+        Ok(handle)
+    }
+
+    /// Removes a folder fallback redirect.
+    pub fn remove_folder(&self, handle: FolderRedirectHandle) -> Result<(), VfsError> {
+        let mut state = self.state.write().unwrap();
+        let index = state
+            .folders
+            .iter()
+            .position(|x| x.handle == handle)
+            .ok_or(VfsError::InvalidHandle)?;
+        state.folders.remove(index);
+        Ok(())
+    }
+
+    /// Resolves where `path` should be opened from, or `None` if it is not redirected.
+    pub fn resolve(&self, path: &str) -> Option<Resolution> {
+        let state = self.state.read().unwrap();
+        if let Some(redirect) = state
+            .files
+            .get(&normalize_path(path))
+            .and_then(|x| x.last())
+        {
+            return Some(Resolution::File(redirect.target.clone()));
+        }
+
+        // Most specific folder wins; among equally specific ones, the latest.
+        let mut best: Option<(usize, String)> = None;
+        for folder in &state.folders {
+            let Some(remainder) = strip_folder(path, &folder.source) else {
+                continue;
+            };
+
+            let specificity = normalize_path(&folder.source).len();
+            if best.as_ref().is_none_or(|(x, _)| specificity >= *x) {
+                best = Some((specificity, join(&folder.target, remainder)));
+            }
+        }
+
+        best.map(|(_, target)| Resolution::Folder(target))
+    }
+
+    /// Names of files that are redirected into `directory` (Tier 1 only).
+    ///
+    /// Used to make redirected files show up in directory listings when they don't exist
+    /// at the original location. Names keep the casing they were added with.
+    pub fn file_names_in_directory(&self, directory: &str) -> Vec<String> {
+        let directory = normalize_path(directory);
+        let state = self.state.read().unwrap();
+        state
+            .files
+            .iter()
+            .filter(|(key, _)| split_parent(key).0 == Some(directory.as_str()))
+            .filter_map(|(_, redirects)| redirects.last())
+            .map(|x| file_name(&x.source).to_owned())
+            .collect()
+    }
+
+    fn add_file_locked(
+        &self,
+        state: &mut RedirectorState,
+        source_path: &str,
+        target_path: &str,
+    ) -> Result<RedirectHandle, VfsError> {
+        if source_path.is_empty() || target_path.is_empty() {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let handle = RedirectHandle(self.allocate_handle());
+        let key = normalize_path(source_path);
+        state
+            .files
+            .entry(key.clone())
+            .or_default()
+            .push(FileRedirect {
+                handle,
+                source: source_path.to_owned(),
+                target: target_path.to_owned(),
+            });
+        state.file_handles.insert(handle, key);
+        Ok(handle)
+    }
+
+    fn remove_file_locked(
+        state: &mut RedirectorState,
+        handle: RedirectHandle,
+    ) -> Result<(), VfsError> {
+        let key = state
+            .file_handles
+            .remove(&handle)
+            .ok_or(VfsError::InvalidHandle)?;
+        if let Some(redirects) = state.files.get_mut(&key) {
+            redirects.retain(|x| x.handle != handle);
+            if redirects.is_empty() {
+                state.files.remove(&key);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn allocate_handle(&self) -> u64 {
+        self.next_handle.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Recursively collects paths of files under `folder`, relative to the scanned root.
+fn collect_files(
+    folder: &Path,
+    relative: String,
+    output: &mut Vec<String>,
+) -> Result<(), VfsError> {
+    let entries = fs::read_dir(folder).map_err(|_| VfsError::NotFound)?;
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let entry_relative = if relative.is_empty() {
+            name
+        } else {
+            join(&relative, &name)
+        };
+        if file_type.is_dir() {
+            collect_files(&entry.path(), entry_relative, output)?;
+        } else {
+            output.push(entry_relative);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+// Registry of virtual files (Layer 1 side)
+
+use crate::error::VfsError;
+use crate::metadata::VirtualFileMetadata;
+use crate::path::{normalize_path, split_parent};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Handle returned when registering a virtual file, used to unregister it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VirtualFileHandle(u64);
+
+impl VirtualFileHandle {
+    /// Raw value of the handle, for passing across FFI boundaries.
+    pub fn as_raw(self) -> u64 {
+        self.0
+    }
+}
+
+/// A virtual file as seen by Layer 1.
+#[derive(Debug, Clone)]
+pub struct VirtualFileEntry {
+    /// Path the file was registered with, before normalisation.
+    pub path: String,
+    pub metadata: VirtualFileMetadata,
+    pub handle: VirtualFileHandle,
+}
+
+/// Makes virtual files visible to path and metadata operations.
+///
+/// This is the private Layer 1 API for Layer 2. Extensions should register files through
+/// Layer 2 instead, which pairs each file with a handler that provides its content.
+#[derive(Debug, Default)]
+pub struct VirtualFiles {
+    // Maps normalised path -> entry
+    files: RwLock<HashMap<String, VirtualFileEntry>>,
+    // Maps handle -> normalised path
+    handles: RwLock<HashMap<VirtualFileHandle, String>>,
+    next_handle: AtomicU64,
+}
+
+impl VirtualFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a virtual file visible at `file_path`.
+    pub fn register_virtual_file(
+        &self,
+        file_path: &str,
+        metadata: VirtualFileMetadata,
+    ) -> Result<VirtualFileHandle, VfsError> {
+        if file_path.is_empty() {
+            return Err(VfsError::InvalidPath);
+        }
+
+        let key = normalize_path(file_path);
+        let mut files = self.files.write().unwrap();
+        if files.contains_key(&key) {
+            return Err(VfsError::AlreadyExists);
+        }
+
+        let handle = VirtualFileHandle(self.next_handle.fetch_add(1, Ordering::Relaxed) + 1);
+        self.handles.write().unwrap().insert(handle, key.clone());
+        files.insert(
+            key,
+            VirtualFileEntry {
+                path: file_path.to_owned(),
+                metadata,
+                handle,
+            },
+        );
+
+        Ok(handle)
+    }
+
+    /// Removes a virtual file registered earlier.
+    pub fn unregister_virtual_file(&self, handle: VirtualFileHandle) -> Result<(), VfsError> {
+        let mut files = self.files.write().unwrap();
+        let key = self
+            .handles
+            .write()
+            .unwrap()
+            .remove(&handle)
+            .ok_or(VfsError::InvalidHandle)?;
+        files.remove(&key);
+        Ok(())
+    }
+
+    /// Looks up the virtual file registered at `file_path`.
+    pub fn get(&self, file_path: &str) -> Option<VirtualFileEntry> {
+        self.files
+            .read()
+            .unwrap()
+            .get(&normalize_path(file_path))
+            .cloned()
+    }
+
+    /// Returns true if a virtual file is registered at `file_path`.
+    pub fn contains(&self, file_path: &str) -> bool {
+        self.files
+            .read()
+            .unwrap()
+            .contains_key(&normalize_path(file_path))
+    }
+
+    /// Returns the virtual files registered directly inside `directory`.
+    ///
+    /// Used to inject virtual files into directory listings.
+    pub fn entries_in_directory(&self, directory: &str) -> Vec<VirtualFileEntry> {
+        let directory = normalize_path(directory);
+        self.files
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| split_parent(key).0 == Some(directory.as_str()))
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
+}